@@ -0,0 +1,30 @@
+-- The sync server's database. It only ever holds what the apps encrypted before sending it, so
+-- nothing here can be read without the team's passphrase.
+
+-- The devices allowed to sync, each with a token of its own. Only a hash of the token is kept.
+CREATE TABLE IF NOT EXISTS devices (
+    name TEXT PRIMARY KEY NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_at INTEGER NOT NULL,
+    last_seen_at INTEGER NOT NULL DEFAULT 0
+);
+
+-- The salt the team's key is derived from, and a value encrypted with the key so that a device
+-- can tell whether it was given the right passphrase.
+CREATE TABLE IF NOT EXISTS team (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    salt TEXT NOT NULL,
+    key_check TEXT NOT NULL DEFAULT ''
+);
+
+-- The latest version of each entry or deletion: an encrypted blob and its version vector, which
+-- counts the changes each device made to it. `seq` goes up with every change, for devices to ask
+-- for what changed since they last synced.
+CREATE TABLE IF NOT EXISTS records (
+    uuid TEXT PRIMARY KEY NOT NULL,
+    version TEXT NOT NULL,
+    blob TEXT NOT NULL,
+    seq INTEGER NOT NULL UNIQUE,
+    device TEXT NOT NULL,
+    updated_at INTEGER NOT NULL
+);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS settings (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    min_master_password_score INTEGER NOT NULL DEFAULT 3
+);
+
+INSERT INTO settings (id, min_master_password_score)
+VALUES (1, 3);
@@ -0,0 +1,11 @@
+ALTER TABLE password_entries ADD COLUMN created_at INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE password_entries ADD COLUMN updated_at INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE password_entries ADD COLUMN password_changed_at INTEGER NOT NULL DEFAULT 0;
+
+-- Existing entries have no history, so treat them as created now.
+UPDATE password_entries
+SET created_at = CAST(strftime('%s', 'now') AS INTEGER),
+    updated_at = CAST(strftime('%s', 'now') AS INTEGER),
+    password_changed_at = CAST(strftime('%s', 'now') AS INTEGER);
+
+ALTER TABLE settings ADD COLUMN max_password_age_months INTEGER NOT NULL DEFAULT 12;
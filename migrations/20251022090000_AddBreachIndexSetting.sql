@@ -0,0 +1 @@
+ALTER TABLE settings ADD COLUMN breach_index_path TEXT NOT NULL DEFAULT '';
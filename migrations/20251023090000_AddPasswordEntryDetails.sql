@@ -0,0 +1,3 @@
+ALTER TABLE password_entries ADD COLUMN urls TEXT NOT NULL DEFAULT '';
+ALTER TABLE password_entries ADD COLUMN notes TEXT NOT NULL DEFAULT '';
+ALTER TABLE password_entries ADD COLUMN tags TEXT NOT NULL DEFAULT '';
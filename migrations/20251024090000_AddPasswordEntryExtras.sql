@@ -0,0 +1 @@
+ALTER TABLE password_entries ADD COLUMN encrypted_extras TEXT NOT NULL DEFAULT '';
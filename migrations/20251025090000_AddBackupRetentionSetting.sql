@@ -0,0 +1 @@
+ALTER TABLE settings ADD COLUMN backup_retention INTEGER NOT NULL DEFAULT 10;
@@ -0,0 +1,19 @@
+-- Entries the vault check found can't be read or decrypted, moved out of the vault so that the
+-- rest of it can be used. They're kept as they were in case they can be recovered, so the columns
+-- take whatever the entry had.
+CREATE TABLE IF NOT EXISTS quarantined_entries (
+    id INTEGER PRIMARY KEY NOT NULL,
+    entry_id INTEGER NOT NULL,
+    site,
+    username,
+    password_hash,
+    urls,
+    notes,
+    tags,
+    created_at,
+    updated_at,
+    password_changed_at,
+    encrypted_extras,
+    reason TEXT NOT NULL,
+    quarantined_at INTEGER NOT NULL
+);
@@ -0,0 +1,25 @@
+-- Security events, each encrypted with the log key and chained to the one before it with a MAC,
+-- so that records that were changed or removed can be found.
+CREATE TABLE IF NOT EXISTS audit_log (
+    sequence INTEGER PRIMARY KEY NOT NULL,
+    recorded_at INTEGER NOT NULL,
+    record TEXT NOT NULL,
+    mac TEXT NOT NULL
+);
+
+-- The log key, encrypted with the master password, and a MAC over the last record so that
+-- records removed from the end of the log are noticed too.
+CREATE TABLE IF NOT EXISTS audit_head (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    encrypted_key TEXT NOT NULL,
+    sequence INTEGER NOT NULL,
+    mac TEXT NOT NULL
+);
+
+-- Events recorded while the vault is locked, e.g. failed sign-ins. They're moved into the log
+-- once it can be encrypted again.
+CREATE TABLE IF NOT EXISTS audit_pending (
+    id INTEGER PRIMARY KEY NOT NULL,
+    recorded_at INTEGER NOT NULL,
+    action TEXT NOT NULL
+);
@@ -0,0 +1,40 @@
+-- What syncing a vault with other devices through a shared folder needs. Each entry gets a UUID
+-- that stays the same on every device and a revision that goes up each time it's changed.
+ALTER TABLE password_entries ADD COLUMN uuid TEXT NOT NULL DEFAULT '';
+ALTER TABLE password_entries ADD COLUMN revision INTEGER NOT NULL DEFAULT 1;
+
+UPDATE password_entries
+SET uuid = lower(
+    hex(randomblob(4)) || '-' || hex(randomblob(2)) || '-4' || substr(hex(randomblob(2)), 2) || '-'
+    || substr('89ab', 1 + abs(random()) % 4, 1) || substr(hex(randomblob(2)), 2) || '-'
+    || hex(randomblob(6))
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS password_entries_uuid ON password_entries (uuid);
+
+-- Deleted entries, so that the deletion reaches the other devices instead of the entry coming
+-- back from them.
+CREATE TABLE IF NOT EXISTS tombstones (
+    uuid TEXT PRIMARY KEY NOT NULL,
+    deleted_at INTEGER NOT NULL
+);
+
+-- The version of each entry last seen in each other device's sync file, the common ancestor the
+-- three-way merge compares both sides with. Encrypted like the entries.
+CREATE TABLE IF NOT EXISTS sync_bases (
+    device TEXT NOT NULL,
+    uuid TEXT NOT NULL,
+    entry TEXT NOT NULL,
+    PRIMARY KEY (device, uuid)
+);
+
+-- This device's name in the sync folder and a hash of the file it last wrote there, to notice
+-- when a copy of the vault on another device writes under the same name.
+CREATE TABLE IF NOT EXISTS sync_state (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    device TEXT NOT NULL,
+    last_written TEXT NOT NULL DEFAULT '',
+    synced_at INTEGER NOT NULL DEFAULT 0
+);
+
+ALTER TABLE settings ADD COLUMN sync_folder TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,20 @@
+-- The sync server this vault syncs with, if any. The device's token and the key derived from the
+-- team's passphrase are encrypted with the vault's key. `cursor` is the sequence number of the
+-- latest change on the server that's been pulled.
+CREATE TABLE IF NOT EXISTS sync_server (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    url TEXT NOT NULL,
+    device TEXT NOT NULL,
+    token TEXT NOT NULL,
+    team_key TEXT NOT NULL,
+    cursor INTEGER NOT NULL DEFAULT 0,
+    synced_at INTEGER NOT NULL DEFAULT 0
+);
+
+-- The server's records as they were pulled, still encrypted with the team's key, so that only
+-- what changed has to be pulled again.
+CREATE TABLE IF NOT EXISTS sync_server_records (
+    uuid TEXT PRIMARY KEY NOT NULL,
+    version TEXT NOT NULL,
+    blob TEXT NOT NULL
+);
@@ -0,0 +1,5 @@
+-- Notes are encrypted like the password. SQL can't encrypt them, so the notes already in the
+-- vault stay in the old column until the next sign-in moves them over, after which it's left
+-- empty.
+ALTER TABLE password_entries ADD COLUMN encrypted_notes TEXT NOT NULL DEFAULT '';
+ALTER TABLE quarantined_entries ADD COLUMN encrypted_notes;
@@ -0,0 +1,5 @@
+-- The quarantined entry's UUID and revision, so that restoring it puts it back as the same entry
+-- on the other synced devices. Entries quarantined before have neither and are restored with a
+-- new UUID.
+ALTER TABLE quarantined_entries ADD COLUMN uuid TEXT NOT NULL DEFAULT '';
+ALTER TABLE quarantined_entries ADD COLUMN revision INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,128 @@
+//! `git-credential-pw`, a git credential helper answering from the vault. Set it up with
+//! `git config --global credential.helper pw` and git runs it with `get`, `store` or `erase`,
+//! writing the credential to stdin. Standard input is git's, so the master password is asked for
+//! on the terminal when the unlock agent isn't unlocked.
+
+use std::{io, path::PathBuf, process::ExitCode};
+
+use clap::Parser;
+use password_manager::{
+    models::AuthState,
+    services::{
+        agent, authentication, data_dir,
+        database::DatabaseService,
+        git_credential::{self, StoreOutcome},
+        vaults,
+    },
+};
+use zeroize::Zeroizing;
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Git credential helper backed by the password manager vault"
+)]
+struct Cli {
+    /// `get`, `store` or `erase`. Git may add operations later, those are ignored.
+    operation: String,
+
+    /// Use the vault with this name instead of the default one, e.g.
+    /// `credential.helper "pw --vault Work"`
+    #[arg(long)]
+    vault: Option<String>,
+
+    /// The app's data directory, where the vaults are kept
+    #[arg(long)]
+    data_dir: Option<PathBuf>,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let result = tokio::runtime::Runtime::new()
+        .map_err(|err| err.to_string())
+        .and_then(|runtime| runtime.block_on(run(&cli)));
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("git-credential-pw: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: &Cli) -> Result<(), String> {
+    let operation = cli.operation.as_str();
+    if !matches!(operation, "get" | "store" | "erase") {
+        return Ok(());
+    }
+
+    let credential = git_credential::read_credential(io::stdin().lock())?;
+    let db_service = open_vault(cli).await?;
+    let auth_state = unlock(&db_service).await?;
+
+    match operation {
+        "get" => match git_credential::get(&credential, &auth_state, &db_service).await? {
+            Some(entry) => git_credential::write_credential(io::stdout().lock(), &entry),
+            // Saying nothing lets git try the next helper or ask for the credentials itself.
+            None => Ok(()),
+        },
+        "store" => {
+            match git_credential::store(&credential, &auth_state, &db_service).await? {
+                StoreOutcome::Created(id) => {
+                    eprintln!("git-credential-pw: Saved the credentials as entry {id}.")
+                }
+                StoreOutcome::Updated(id) => {
+                    eprintln!("git-credential-pw: Updated the password of entry {id}.")
+                }
+                StoreOutcome::Unchanged(_) => {}
+            }
+            Ok(())
+        }
+        _ => {
+            for id in git_credential::erase(&credential, &auth_state, &db_service).await? {
+                eprintln!(
+                    "git-credential-pw: Git rejected the password of entry {id}, it was kept in \
+                     the vault."
+                );
+            }
+            Ok(())
+        }
+    }
+}
+
+async fn open_vault(cli: &Cli) -> Result<DatabaseService, String> {
+    let data_dir = data_dir::resolve_data_dir(cli.data_dir.as_deref(), false)?;
+    vaults::open_named_vault(&data_dir, cli.vault.as_deref()).await
+}
+
+/// Signed in with the agent when it's unlocked, otherwise with the master password from the
+/// terminal.
+async fn unlock(db_service: &DatabaseService) -> Result<AuthState, String> {
+    let socket_path = agent::socket_path(db_service);
+    if let Some(auth_state) = agent::agent_auth_state(&socket_path).await {
+        return Ok(auth_state);
+    }
+
+    if !authentication::is_master_password_set(db_service).await? {
+        return Err("No master password has been set yet".into());
+    }
+
+    // rpassword reads from the terminal itself rather than from stdin.
+    let password = rpassword::prompt_password("Master password for git: ")
+        .map(Zeroizing::new)
+        .map_err(|err| {
+            format!(
+                "Could not ask for the master password, unlock the agent with `pw unlock`: {err}"
+            )
+        })?;
+
+    let auth_state = authentication::sign_in(password, AuthState::default(), db_service).await?;
+
+    if agent::status(&socket_path).await == Some(false) {
+        let _ = agent::unlock(&socket_path, auth_state.raw_master_password.clone()).await;
+    }
+
+    Ok(auth_state)
+}
@@ -0,0 +1,109 @@
+//! `pw-sync-server`, the optional server a team's vaults sync through. It keeps only what the
+//! apps encrypted before sending it and never sees a key, so it can run anywhere the devices can
+//! reach, including on the same machine as a stand-in for tests. Add a device with
+//! `pw-sync-server add-device <name>` and connect the vault with the token it prints.
+
+use std::{path::PathBuf, process::ExitCode, sync::Arc};
+
+use clap::{Parser, Subcommand};
+use password_manager::{
+    models::format_timestamp,
+    services::sync_server::{self, SyncServer},
+};
+use tokio::net::TcpListener;
+
+#[derive(Parser)]
+#[command(
+    name = "pw-sync-server",
+    version,
+    about = "Sync server that stores a team's encrypted vault entries"
+)]
+struct Cli {
+    /// The server's database file, created if it doesn't exist
+    #[arg(long, global = true, default_value = sync_server::DEFAULT_DATABASE_FILE_NAME)]
+    database: PathBuf,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Serve the devices until interrupted
+    Serve {
+        /// The address and port to listen on
+        #[arg(long, default_value = sync_server::DEFAULT_LISTEN_ADDRESS)]
+        listen: String,
+    },
+    /// Allow a device to sync and print the token it connects with
+    AddDevice {
+        /// The device's name, its changes are counted under it
+        name: String,
+    },
+    /// Stop a device from syncing
+    RevokeDevice { name: String },
+    /// List the devices that are allowed to sync
+    Devices,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let result = tokio::runtime::Runtime::new()
+        .map_err(|err| err.to_string())
+        .and_then(|runtime| runtime.block_on(run(cli)));
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("pw-sync-server: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), String> {
+    let server = SyncServer::open(&cli.database).await?;
+
+    match cli.command {
+        Command::Serve { listen } => {
+            let listener = TcpListener::bind(&listen)
+                .await
+                .map_err(|err| format!("Could not listen on {listen}: {err}"))?;
+            let address = listener.local_addr().map_err(|err| err.to_string())?;
+            eprintln!(
+                "pw-sync-server: Serving {} on http://{address}",
+                server.path.display()
+            );
+            sync_server::serve(Arc::new(server), listener).await
+        }
+        Command::AddDevice { name } => {
+            let token = server.add_device(&name).await?;
+            eprintln!(
+                "Added {}. Connect it with this token, it isn't shown again:",
+                name.trim()
+            );
+            println!("{token}");
+            Ok(())
+        }
+        Command::RevokeDevice { name } => {
+            server.revoke_device(&name).await?;
+            println!("Revoked {}.", name.trim());
+            Ok(())
+        }
+        Command::Devices => {
+            for device in server.list_devices().await? {
+                let last_seen = match device.last_seen_at {
+                    0 => "never".to_string(),
+                    last_seen_at => format_timestamp(last_seen_at),
+                };
+                println!(
+                    "{}  added {}, last seen {last_seen}",
+                    device.name,
+                    format_timestamp(device.created_at)
+                );
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,1245 @@
+//! `pw`, the vault from the terminal. It uses the same services as the desktop app and never
+//! starts the Dioxus runtime. The master password is asked for on every command unless the
+//! unlock agent started with `pw agent` is unlocked.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::{Args, Parser, Subcommand};
+use password_manager::{
+    models::{format_timestamp, unix_now, AuthState, PasswordEntryRaw, PasswordEntrySafe},
+    services::{
+        agent,
+        audit::{self, AuditAction, AuditEvent},
+        authentication::{self, AuthError},
+        clipboard, data_dir,
+        database::DatabaseService,
+        error::ServiceError,
+        integrity::{self, IntegrityReport, QuarantinedEntry},
+        password_entry,
+        password_generator::{self, GeneratorOptions},
+        password_strength,
+        query::{self, QueryContext},
+        settings,
+        sync::{self, SyncError, SyncSummary},
+        sync_client,
+        vaults::{self, VaultRegistry},
+    },
+};
+use serde::Serialize;
+use zeroize::Zeroizing;
+
+/// How long a copied value stays on the clipboard.
+const CLIPBOARD_TIMEOUT_SECS: u64 = 10;
+
+#[derive(Parser)]
+#[command(
+    name = "pw",
+    version,
+    about = "Use the password vault from the terminal"
+)]
+struct Cli {
+    /// Print results as JSON
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Use the vault with this name instead of the default one
+    #[arg(long, global = true)]
+    vault: Option<String>,
+
+    /// The app's data directory, where the vaults are kept
+    #[arg(long, global = true)]
+    data_dir: Option<PathBuf>,
+
+    /// Use the data directory next to the executable, like the app in portable mode
+    #[arg(long, global = true)]
+    portable: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List entries, or only those matching a search query
+    List {
+        /// A search query, e.g. `tag:work github`
+        query: Vec<String>,
+    },
+    /// Print an entry's password or another of its fields
+    Get {
+        /// The entry's id or a search query matching only it
+        #[arg(required = true)]
+        query: Vec<String>,
+        /// site, username, password, url, urls, notes, tags or the name of a custom field
+        #[arg(long)]
+        field: Option<String>,
+        /// Copy the value to the clipboard instead of printing it
+        #[arg(long)]
+        copy: bool,
+    },
+    /// Add an entry, asking for its password unless one is generated
+    Add {
+        site: String,
+        #[arg(long, short, default_value = "")]
+        username: String,
+        /// Can be given more than once
+        #[arg(long = "url")]
+        urls: Vec<String>,
+        #[arg(long, default_value = "")]
+        notes: String,
+        /// Can be given more than once
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        /// Generate the password instead of asking for it
+        #[arg(long)]
+        generate: bool,
+        #[command(flatten)]
+        generator: GeneratorArgs,
+    },
+    /// Change an entry, only the given fields are changed
+    Edit {
+        /// The entry's id or a search query matching only it
+        #[arg(required = true)]
+        query: Vec<String>,
+        #[arg(long)]
+        site: Option<String>,
+        #[arg(long, short)]
+        username: Option<String>,
+        /// Replaces all of the entry's URLs, can be given more than once
+        #[arg(long = "url")]
+        urls: Vec<String>,
+        #[arg(long)]
+        notes: Option<String>,
+        /// Replaces all of the entry's tags, can be given more than once
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        /// Ask for a new password
+        #[arg(long, conflicts_with = "generate")]
+        password: bool,
+        /// Generate a new password
+        #[arg(long)]
+        generate: bool,
+        #[command(flatten)]
+        generator: GeneratorArgs,
+    },
+    /// Delete an entry
+    Rm {
+        /// The entry's id or a search query matching only it
+        #[arg(required = true)]
+        query: Vec<String>,
+        /// Don't ask for confirmation
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// Print a random password
+    Generate {
+        #[command(flatten)]
+        generator: GeneratorArgs,
+    },
+    /// Set or change the master password
+    Passwd,
+    /// Run the unlock agent, which keeps the vault unlocked for other commands until it's locked
+    /// or idle
+    Agent,
+    /// Unlock the running agent
+    Unlock,
+    /// Lock the running agent
+    Lock,
+    /// List the vaults, the one the app opens first is marked
+    Vaults,
+    /// Check the vault file, its schema and that every entry can be decrypted
+    Check {
+        /// Move the entries that can't be read out of the vault, so that the rest can be used
+        #[arg(long)]
+        quarantine: bool,
+        /// Don't ask for confirmation
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// List the entries `pw check --quarantine` moved out of the vault, or restore or discard one
+    Quarantine {
+        /// Put the quarantined entry with this id back in the vault, it must decrypt with the
+        /// master password
+        #[arg(long, conflicts_with = "discard")]
+        restore: Option<i32>,
+        /// Delete the quarantined entry with this id for good
+        #[arg(long)]
+        discard: Option<i32>,
+        /// Don't ask for confirmation
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// Merge the changes made on other devices through the sync folder and the sync server, and
+    /// share this one's
+    Sync {
+        /// The folder to sync through, remembered for next time
+        folder: Option<PathBuf>,
+        /// Connect to the sync server at this http:// address, asking for the team's passphrase
+        #[arg(long, requires = "token")]
+        server: Option<String>,
+        /// The device's token, printed by `pw-sync-server add-device`
+        #[arg(long, requires = "server")]
+        token: Option<String>,
+        /// Stop syncing with the sync server
+        #[arg(long, conflicts_with = "server")]
+        disconnect: bool,
+    },
+}
+
+#[derive(Args)]
+struct GeneratorArgs {
+    /// How many characters a generated password has
+    #[arg(long, default_value_t = password_generator::DEFAULT_LENGTH)]
+    length: usize,
+    /// Leave lowercase letters out of generated passwords
+    #[arg(long)]
+    no_lowercase: bool,
+    /// Leave uppercase letters out of generated passwords
+    #[arg(long)]
+    no_uppercase: bool,
+    /// Leave digits out of generated passwords
+    #[arg(long)]
+    no_digits: bool,
+    /// Leave symbols out of generated passwords
+    #[arg(long)]
+    no_symbols: bool,
+}
+
+impl GeneratorArgs {
+    fn generate(&self) -> Result<Zeroizing<String>, String> {
+        password_generator::generate_password(&GeneratorOptions {
+            length: self.length,
+            lowercase: !self.no_lowercase,
+            uppercase: !self.no_uppercase,
+            digits: !self.no_digits,
+            symbols: !self.no_symbols,
+        })
+    }
+}
+
+/// The vault check as it's printed with `--json`.
+#[derive(Serialize)]
+struct CheckJson<'a> {
+    ok: bool,
+    database_problems: &'a [String],
+    schema_version: Option<i64>,
+    expected_schema_version: i64,
+    missing_migrations: &'a [i64],
+    unknown_migrations: &'a [i64],
+    broken_migrations: &'a [i64],
+    entries_checked: usize,
+    entry_problems: Vec<EntryProblemJson<'a>>,
+    /// Including the entries moved by this check.
+    quarantined: usize,
+}
+
+#[derive(Serialize)]
+struct EntryProblemJson<'a> {
+    id: i32,
+    site: &'a str,
+    username: &'a str,
+    code: &'static str,
+    message: String,
+}
+
+impl<'a> CheckJson<'a> {
+    fn new(report: &'a IntegrityReport, moved: usize) -> Self {
+        Self {
+            ok: report.is_ok(),
+            database_problems: &report.database_problems,
+            schema_version: report.schema.version,
+            expected_schema_version: report.schema.expected,
+            missing_migrations: &report.schema.missing,
+            unknown_migrations: &report.schema.unknown,
+            broken_migrations: &report.schema.broken,
+            entries_checked: report.entries_checked,
+            entry_problems: report
+                .entry_problems
+                .iter()
+                .map(|problem| EntryProblemJson {
+                    id: problem.id,
+                    site: &problem.site,
+                    username: &problem.username,
+                    code: problem.error.code(),
+                    message: problem.error.to_string(),
+                })
+                .collect(),
+            quarantined: report.quarantined.len() + moved,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct QuarantinedJson<'a> {
+    id: i32,
+    entry_id: i32,
+    uuid: &'a str,
+    site: &'a str,
+    username: &'a str,
+    reason: &'a str,
+    quarantined_at: i64,
+    restorable: bool,
+    in_vault: bool,
+}
+
+impl<'a> QuarantinedJson<'a> {
+    fn new(entry: &'a QuarantinedEntry) -> Self {
+        Self {
+            id: entry.id,
+            entry_id: entry.entry_id,
+            uuid: &entry.uuid,
+            site: &entry.site,
+            username: &entry.username,
+            reason: &entry.reason,
+            quarantined_at: entry.quarantined_at,
+            restorable: entry.restorable,
+            in_vault: entry.in_vault,
+        }
+    }
+}
+
+/// What syncing through the folder changed as it's printed with `--json`, and what syncing
+/// with the server did.
+#[derive(Serialize)]
+struct SyncOutputJson<'a> {
+    #[serde(flatten)]
+    folder: Option<SyncJson<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    server: Option<SyncJson<'a>>,
+}
+
+/// What syncing changed as it's printed with `--json`.
+#[derive(Serialize)]
+struct SyncJson<'a> {
+    device: &'a str,
+    devices: usize,
+    added: usize,
+    updated: usize,
+    deleted: usize,
+    skipped: &'a [String],
+    conflicts: Vec<ConflictJson<'a>>,
+}
+
+#[derive(Serialize)]
+struct ConflictJson<'a> {
+    uuid: &'a str,
+    site: &'a str,
+    username: &'a str,
+    device: &'a str,
+    fields: Vec<&'static str>,
+}
+
+impl<'a> SyncJson<'a> {
+    fn new(device: &'a str, summary: &'a SyncSummary) -> Self {
+        Self {
+            device,
+            devices: summary.devices,
+            added: summary.added,
+            updated: summary.updated,
+            deleted: summary.deleted,
+            skipped: &summary.skipped,
+            conflicts: summary
+                .conflicts
+                .iter()
+                .map(|conflict| ConflictJson {
+                    uuid: &conflict.local.uuid,
+                    site: &conflict.local.site,
+                    username: &conflict.local.username,
+                    device: &conflict.device,
+                    fields: conflict.fields.iter().map(|field| field.label()).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// An entry as it's printed with `--json`.
+#[derive(Serialize)]
+struct EntryJson<'a> {
+    id: i32,
+    site: &'a str,
+    username: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    password: Option<&'a str>,
+    urls: &'a [String],
+    /// Encrypted like the password, so only shown with it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    notes: Option<&'a str>,
+    tags: &'a [String],
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    custom_fields: BTreeMap<&'a str, &'a str>,
+    created_at: i64,
+    updated_at: i64,
+    password_changed_at: i64,
+}
+
+impl<'a> EntryJson<'a> {
+    fn summary(entry: &'a PasswordEntrySafe) -> Self {
+        Self {
+            id: entry.id,
+            site: &entry.site,
+            username: &entry.username,
+            password: None,
+            urls: &entry.urls,
+            notes: None,
+            tags: &entry.tags,
+            custom_fields: BTreeMap::new(),
+            created_at: entry.created_at,
+            updated_at: entry.updated_at,
+            password_changed_at: entry.password_changed_at,
+        }
+    }
+
+    fn full(entry: &'a PasswordEntryRaw) -> Self {
+        Self {
+            id: entry.id,
+            site: &entry.site,
+            username: &entry.username,
+            password: Some(&entry.raw_password),
+            urls: &entry.urls,
+            notes: Some(&entry.notes),
+            tags: &entry.tags,
+            custom_fields: entry
+                .extras
+                .custom_fields
+                .iter()
+                .map(|field| (field.name.as_str(), field.value.as_str()))
+                .collect(),
+            created_at: entry.created_at,
+            updated_at: entry.updated_at,
+            password_changed_at: entry.password_changed_at,
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let result = tokio::runtime::Runtime::new()
+        .map_err(|err| err.to_string())
+        .and_then(|runtime| runtime.block_on(run(cli)));
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("pw: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), String> {
+    let json = cli.json;
+    let data_dir = data_dir::resolve_data_dir(cli.data_dir.as_deref(), cli.portable)?;
+    let vault = cli.vault.as_deref();
+
+    match cli.command {
+        Command::List { query } => {
+            let db_service = open_vault(&data_dir, vault).await?;
+            let auth_state = unlock(&db_service).await?;
+            let entries = readable_entries(&auth_state, &db_service).await?;
+            let matches = search(&query.join(" "), &entries, &auth_state)?;
+
+            if json {
+                print_json(
+                    &matches
+                        .iter()
+                        .map(|entry| EntryJson::summary(entry))
+                        .collect::<Vec<_>>(),
+                )
+            } else {
+                print_table(&matches);
+                Ok(())
+            }
+        }
+        Command::Get { query, field, copy } => {
+            let db_service = open_vault(&data_dir, vault).await?;
+            let auth_state = unlock(&db_service).await?;
+            let entry = find_entry(&query.join(" "), &auth_state, &db_service).await?;
+
+            let shown = match (field.as_deref(), copy || !json) {
+                (Some(field), _) => field,
+                (None, true) => "password",
+                (None, false) => "every field",
+            };
+            let action = if copy {
+                AuditAction::Copy
+            } else {
+                AuditAction::Reveal
+            };
+            let event = AuditEvent::for_entry(action, entry.id, &entry.site)
+                .with_detail(format!("The {shown}, with pw"));
+            let _ = audit::record(event, &auth_state, &db_service).await;
+
+            if copy {
+                let field = field.as_deref().unwrap_or("password");
+                let value = field_value(&entry, field)?;
+                eprintln!(
+                    "Copied {field} of {} to the clipboard, it will be cleared in {CLIPBOARD_TIMEOUT_SECS} seconds.",
+                    entry.site
+                );
+                return clipboard::copy_and_wait(value, CLIPBOARD_TIMEOUT_SECS);
+            }
+
+            match (field, json) {
+                (None, true) => print_json(&EntryJson::full(&entry)),
+                (Some(field), true) => {
+                    let value = field_value(&entry, &field)?;
+                    print_json(&BTreeMap::from([(field.as_str(), value.as_str())]))
+                }
+                (field, false) => {
+                    let value = field_value(&entry, field.as_deref().unwrap_or("password"))?;
+                    println!("{}", value.as_str());
+                    Ok(())
+                }
+            }
+        }
+        Command::Add {
+            site,
+            username,
+            urls,
+            notes,
+            tags,
+            generate,
+            generator,
+        } => {
+            let db_service = open_vault(&data_dir, vault).await?;
+            let auth_state = unlock(&db_service).await?;
+
+            let raw_password = if generate {
+                generator.generate()?
+            } else {
+                prompt_new_password(&format!("Password for {site}: "))?
+            };
+
+            let now = unix_now();
+            let new_entry = PasswordEntryRaw {
+                id: 0,
+                uuid: String::new(),
+                revision: 0,
+                site,
+                username,
+                raw_password,
+                urls,
+                notes,
+                tags,
+                created_at: now,
+                updated_at: now,
+                password_changed_at: now,
+                extras: Default::default(),
+            };
+
+            let id =
+                password_entry::create_password_entry(new_entry.clone(), &auth_state, &db_service)
+                    .await?;
+
+            if json {
+                print_json(&EntryJson {
+                    id,
+                    ..EntryJson::full(&new_entry)
+                })
+            } else {
+                println!("Added {} ({id}).", new_entry.site);
+                Ok(())
+            }
+        }
+        Command::Edit {
+            query,
+            site,
+            username,
+            urls,
+            notes,
+            tags,
+            password,
+            generate,
+            generator,
+        } => {
+            let db_service = open_vault(&data_dir, vault).await?;
+            let auth_state = unlock(&db_service).await?;
+            let mut entry = find_entry(&query.join(" "), &auth_state, &db_service).await?;
+
+            if let Some(site) = site {
+                entry.site = site;
+            }
+            if let Some(username) = username {
+                entry.username = username;
+            }
+            if !urls.is_empty() {
+                entry.urls = urls;
+            }
+            if let Some(notes) = notes {
+                entry.notes = notes;
+            }
+            if !tags.is_empty() {
+                entry.tags = tags;
+            }
+
+            let now = unix_now();
+            if generate {
+                entry.raw_password = generator.generate()?;
+                entry.password_changed_at = now;
+            } else if password {
+                entry.raw_password =
+                    prompt_new_password(&format!("New password for {}: ", entry.site))?;
+                entry.password_changed_at = now;
+            }
+            entry.updated_at = now;
+
+            password_entry::save_updated_password(
+                entry.id,
+                entry.clone(),
+                &auth_state,
+                &db_service,
+            )
+            .await?;
+
+            if json {
+                print_json(&EntryJson {
+                    password: None,
+                    ..EntryJson::full(&entry)
+                })
+            } else {
+                println!("Updated {} ({}).", entry.site, entry.id);
+                Ok(())
+            }
+        }
+        Command::Rm { query, yes } => {
+            let db_service = open_vault(&data_dir, vault).await?;
+            let auth_state = unlock(&db_service).await?;
+            let entry = find_entry(&query.join(" "), &auth_state, &db_service).await?;
+
+            if !yes
+                && !confirm(&format!(
+                    "Delete {}?",
+                    describe(&entry.site, &entry.username)
+                ))?
+            {
+                return Err("Nothing was deleted".into());
+            }
+
+            password_entry::delete_password(entry.id, &auth_state, &db_service).await?;
+
+            if json {
+                print_json(&BTreeMap::from([("deleted", entry.id)]))
+            } else {
+                println!("Deleted {} ({}).", entry.site, entry.id);
+                Ok(())
+            }
+        }
+        Command::Generate { generator } => {
+            let password = generator.generate()?;
+
+            if json {
+                print_json(&BTreeMap::from([("password", password.as_str())]))
+            } else {
+                println!("{}", password.as_str());
+                Ok(())
+            }
+        }
+        Command::Passwd => {
+            let db_service = open_vault(&data_dir, vault).await?;
+
+            // There's nothing to sign in to before the first master password is set. The agent
+            // isn't used so that changing the password always takes the current one.
+            let auth_state = if authentication::is_master_password_set(&db_service).await? {
+                sign_in(&db_service).await?
+            } else {
+                AuthState::default()
+            };
+
+            let new_password = prompt_new_password("New master password: ")?;
+            match authentication::set_master_password(
+                new_password.clone(),
+                &auth_state,
+                &db_service,
+            )
+            .await
+            {
+                Ok(_) => {}
+                Err(err @ AuthError::TooWeak { .. }) => {
+                    let feedback = password_strength::estimate(&new_password).feedback;
+                    let mut message = err.to_string();
+                    for hint in feedback.warning.iter().chain(&feedback.suggestions) {
+                        message.push_str(&format!("\n  {hint}"));
+                    }
+                    return Err(message);
+                }
+                Err(err) => return Err(err.into()),
+            }
+
+            print_status(json, "changed", "The master password was changed.")
+        }
+        Command::Agent => {
+            let db_service = open_vault(&data_dir, vault).await?;
+            let socket_path = agent::socket_path(&db_service);
+            eprintln!(
+                "The agent is listening on {}, stop it with Ctrl+C.",
+                socket_path.display()
+            );
+            agent::run_agent(db_service, &socket_path).await
+        }
+        Command::Unlock => {
+            let db_service = open_vault(&data_dir, vault).await?;
+            let socket_path = agent::socket_path(&db_service);
+            if !agent::is_running(&socket_path).await {
+                return Err("The agent isn't running, start it with `pw agent`".into());
+            }
+
+            agent::unlock(&socket_path, prompt_password("Master password: ")?).await?;
+            print_status(json, "unlocked", "The agent is unlocked.")
+        }
+        Command::Lock => {
+            let db_service = open_vault(&data_dir, vault).await?;
+            if agent::lock(&agent::socket_path(&db_service)).await? {
+                print_status(json, "locked", "The agent is locked.")
+            } else {
+                Err("The agent isn't running".into())
+            }
+        }
+        Command::Check { quarantine, yes } => {
+            let db_service = open_vault(&data_dir, vault).await?;
+            let auth_state = unlock(&db_service).await?;
+            let report = integrity::check_vault(&auth_state, &db_service).await?;
+            if !json {
+                print_report(&report);
+            }
+
+            let problems = &report.entry_problems;
+            let moved = if quarantine
+                && !problems.is_empty()
+                && (yes
+                    || confirm(&format!(
+                        "Move {} entries out of the vault?",
+                        problems.len()
+                    ))?) {
+                integrity::quarantine_entries(problems, &auth_state, &db_service).await?
+            } else {
+                0
+            };
+
+            if json {
+                print_json(&CheckJson::new(&report, moved))?;
+            } else if moved > 0 {
+                println!("Moved {moved} entries out of the vault.");
+            } else if !problems.is_empty() {
+                println!("Move them out of the vault with `pw check --quarantine`.");
+            }
+
+            let entries_ok = problems.len() == moved;
+            if report.database_problems.is_empty() && report.schema.is_ok() && entries_ok {
+                Ok(())
+            } else {
+                Err("The vault has problems".into())
+            }
+        }
+        Command::Quarantine {
+            restore,
+            discard,
+            yes,
+        } => {
+            let db_service = open_vault(&data_dir, vault).await?;
+            let auth_state = unlock(&db_service).await?;
+            let quarantined = integrity::list_quarantined_entries(&auth_state, &db_service).await?;
+            let find = |id| {
+                quarantined
+                    .iter()
+                    .find(|entry| entry.id == id)
+                    .ok_or_else(|| format!("There is no quarantined entry {id}"))
+            };
+
+            if let Some(id) = restore {
+                let entry = find(id)?;
+                let new_id =
+                    integrity::restore_quarantined_entry(entry.id, &auth_state, &db_service)
+                        .await?;
+                return if json {
+                    print_json(&BTreeMap::from([("restored", new_id)]))
+                } else {
+                    println!("Restored {} as entry {new_id}.", entry.site);
+                    Ok(())
+                };
+            }
+
+            if let Some(id) = discard {
+                let entry = find(id)?;
+                if !yes
+                    && !confirm(&format!(
+                        "Delete the quarantined {} for good?",
+                        describe(&entry.site, &entry.username)
+                    ))?
+                {
+                    return Err("Nothing was deleted".into());
+                }
+                integrity::discard_quarantined_entry(entry.id, &auth_state, &db_service).await?;
+                return if json {
+                    print_json(&BTreeMap::from([("discarded", entry.id)]))
+                } else {
+                    println!("Discarded {} ({}).", entry.site, entry.id);
+                    Ok(())
+                };
+            }
+
+            if json {
+                print_json(
+                    &quarantined
+                        .iter()
+                        .map(QuarantinedJson::new)
+                        .collect::<Vec<_>>(),
+                )
+            } else {
+                print_quarantined(&quarantined);
+                Ok(())
+            }
+        }
+        Command::Sync {
+            folder,
+            server,
+            token,
+            disconnect,
+        } => {
+            let db_service = open_vault(&data_dir, vault).await?;
+            let mut settings = settings::get_settings(&db_service).await?;
+            let folder_given = folder.is_some();
+            if let Some(folder) = folder {
+                let folder = std::path::absolute(&folder).map_err(|err| err.to_string())?;
+                settings.sync_folder = folder.display().to_string();
+            }
+            // The sync files are encrypted with the master password, the agent only has the key.
+            // The server's key is kept in the vault, so the agent's is enough for it.
+            let auth_state = if settings.sync_folder.is_empty() {
+                unlock(&db_service).await?
+            } else {
+                sign_in(&db_service).await?
+            };
+            if folder_given {
+                settings::save_settings(&settings, &db_service).await?;
+            }
+
+            if disconnect {
+                sync_client::disconnect(&auth_state, &db_service).await?;
+                eprintln!("Disconnected from the sync server.");
+            }
+            if let (Some(url), Some(token)) = (server, token) {
+                let passphrase = prompt_password("Team passphrase: ")?;
+                let connection =
+                    sync_client::connect(&url, &token, &passphrase, &auth_state, &db_service)
+                        .await?;
+                eprintln!("Connected to {} as {}.", connection.url, connection.device);
+            }
+            let connection = sync_client::get_connection(&db_service).await?;
+            if settings.sync_folder.is_empty() && connection.is_none() {
+                if disconnect {
+                    return Ok(());
+                }
+                return Err(SyncError::NoFolder.into());
+            }
+
+            let folder_summary = if settings.sync_folder.is_empty() {
+                None
+            } else {
+                Some(
+                    sync::sync_folder(Path::new(&settings.sync_folder), &auth_state, &db_service)
+                        .await?,
+                )
+            };
+            let server_summary = match connection.as_ref() {
+                Some(_) => Some(sync_client::sync_server(&auth_state, &db_service).await?),
+                None => None,
+            };
+            let state = sync::get_state(&db_service).await?;
+
+            if json {
+                return print_json(&SyncOutputJson {
+                    folder: folder_summary
+                        .as_ref()
+                        .map(|summary| SyncJson::new(&state.device, summary)),
+                    server: server_summary
+                        .as_ref()
+                        .zip(connection.as_ref())
+                        .map(|(summary, connection)| SyncJson::new(&connection.device, summary)),
+                });
+            }
+            if let Some(summary) = folder_summary.as_ref() {
+                print_sync_summary(&format!("Synced as {}", state.device), summary);
+            }
+            if let (Some(summary), Some(connection)) = (server_summary.as_ref(), connection) {
+                print_sync_summary(
+                    &format!("Synced with {} as {}", connection.url, connection.device),
+                    summary,
+                );
+            }
+            Ok(())
+        }
+        Command::Vaults => {
+            let registry = VaultRegistry::load(&data_dir)?;
+            let current = registry.current();
+
+            if json {
+                return print_json(&registry.vaults);
+            }
+            for vault in &registry.vaults {
+                let marker = if vault == current { "*" } else { " " };
+                println!("{marker} {}  {}", vault.name, vault.path.display());
+            }
+            Ok(())
+        }
+    }
+}
+
+fn print_sync_summary(heading: &str, summary: &SyncSummary) {
+    for skipped in &summary.skipped {
+        eprintln!("pw: Skipped {skipped}");
+    }
+    println!("{heading}: {summary}.");
+    for conflict in &summary.conflicts {
+        let fields: Vec<&str> = conflict.fields.iter().map(|field| field.label()).collect();
+        println!(
+            "Conflict: {} was changed on {} too ({}), resolve it in the app.",
+            describe(&conflict.local.site, &conflict.local.username),
+            conflict.device,
+            fields.join(", ")
+        );
+    }
+}
+
+async fn open_vault(data_dir: &Path, name: Option<&str>) -> Result<DatabaseService, String> {
+    vaults::open_named_vault(data_dir, name).await
+}
+
+/// Signed in with the agent when it's unlocked, otherwise with the master password.
+async fn unlock(db_service: &DatabaseService) -> Result<AuthState, String> {
+    let socket_path = agent::socket_path(db_service);
+    if let Some(auth_state) = agent::agent_auth_state(&socket_path).await {
+        return Ok(auth_state);
+    }
+
+    let auth_state = sign_in(db_service).await?;
+
+    // A running agent that's locked is unlocked too, so that the next command doesn't ask again.
+    if agent::status(&socket_path).await == Some(false) {
+        let password = auth_state.raw_master_password.clone();
+        if let Err(err) = agent::unlock(&socket_path, password).await {
+            eprintln!("pw: The agent couldn't be unlocked: {err}");
+        }
+    }
+
+    Ok(auth_state)
+}
+
+/// Ask for the master password and sign in with it.
+async fn sign_in(db_service: &DatabaseService) -> Result<AuthState, String> {
+    if !authentication::is_master_password_set(db_service).await? {
+        return Err("No master password has been set yet, set one with `pw passwd`".into());
+    }
+
+    let password = prompt_password("Master password: ")?;
+    Ok(authentication::sign_in(password, AuthState::default(), db_service).await?)
+}
+
+/// Every entry that can be read. The others are left out with a warning, so that a damaged entry
+/// doesn't keep the rest of the vault from being used. Passwords are only decrypted when needed.
+async fn readable_entries(
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<Vec<PasswordEntrySafe>, String> {
+    let page =
+        password_entry::get_password_entry_summaries_page(auth_state, db_service, None, u32::MAX)
+            .await?;
+
+    let mut entries = Vec::with_capacity(page.len());
+    for result in page {
+        match result {
+            Ok(entry) => entries.push(entry),
+            Err(problem) => eprintln!(
+                "pw: Skipped: {} ({}), see `pw check`",
+                problem.error,
+                problem.error.code()
+            ),
+        }
+    }
+    Ok(entries)
+}
+
+/// Entries matching a search query, all of them when it's empty.
+fn search<'a>(
+    query: &str,
+    entries: &'a [PasswordEntrySafe],
+    auth_state: &AuthState,
+) -> Result<Vec<&'a PasswordEntrySafe>, String> {
+    let parsed = query::parse(query).map_err(|err| err.to_string())?;
+    if parsed.is_empty() {
+        return Ok(entries.iter().collect());
+    }
+
+    Ok(
+        query::execute(&parsed, entries, &QueryContext::default(), auth_state)
+            .into_iter()
+            .map(|result| &entries[result.index])
+            .collect(),
+    )
+}
+
+/// The one entry `query` refers to, by id or with a search query. When several entries match but
+/// only one's site is exactly the query, that one is used.
+async fn find_entry(
+    query: &str,
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<PasswordEntryRaw, String> {
+    let entries = readable_entries(auth_state, db_service).await?;
+
+    let by_id = query
+        .parse::<i32>()
+        .ok()
+        .and_then(|id| entries.iter().find(|entry| entry.id == id));
+
+    let entry = match by_id {
+        Some(entry) => entry,
+        None => {
+            let matches = search(query, &entries, auth_state)?;
+            let exact: Vec<&PasswordEntrySafe> = matches
+                .iter()
+                .copied()
+                .filter(|entry| entry.site.eq_ignore_ascii_case(query))
+                .collect();
+
+            match (matches.as_slice(), exact.as_slice()) {
+                ([], _) => return Err(format!("No entry matches \"{query}\"")),
+                ([entry], _) | (_, [entry]) => *entry,
+                _ => {
+                    let candidates: Vec<String> = matches
+                        .iter()
+                        .map(|entry| {
+                            format!("  {}  {}", entry.id, describe(&entry.site, &entry.username))
+                        })
+                        .collect();
+                    return Err(format!(
+                        "{} entries match \"{query}\", narrow the query or use an id:\n{}",
+                        matches.len(),
+                        candidates.join("\n")
+                    ));
+                }
+            }
+        }
+    };
+
+    Ok(password_entry::get_password_entry_by_id(entry.id, auth_state, db_service).await?)
+}
+
+fn field_value(entry: &PasswordEntryRaw, field: &str) -> Result<Zeroizing<String>, String> {
+    let value = match field.to_lowercase().as_str() {
+        "password" => entry.raw_password.to_string(),
+        "site" => entry.site.clone(),
+        "username" | "user" => entry.username.clone(),
+        "url" => entry.urls.first().cloned().unwrap_or_default(),
+        "urls" => password_entry::join_urls(&entry.urls),
+        "notes" => entry.notes.clone(),
+        "tags" => password_entry::join_tags(&entry.tags),
+        _ => entry
+            .extras
+            .custom_fields
+            .iter()
+            .find(|custom| custom.name == field)
+            .or_else(|| {
+                entry
+                    .extras
+                    .custom_fields
+                    .iter()
+                    .find(|custom| custom.name.eq_ignore_ascii_case(field))
+            })
+            .map(|custom| custom.value.to_string())
+            .ok_or_else(|| format!("{} has no field called \"{field}\"", entry.site))?,
+    };
+
+    Ok(Zeroizing::new(value))
+}
+
+fn print_table(entries: &[&PasswordEntrySafe]) {
+    let id_width = entries
+        .iter()
+        .map(|entry| entry.id.to_string().len())
+        .max()
+        .unwrap_or(0);
+    let site_width = entries
+        .iter()
+        .map(|entry| entry.site.chars().count())
+        .max()
+        .unwrap_or(0);
+    let username_width = entries
+        .iter()
+        .map(|entry| entry.username.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    for entry in entries {
+        let line = format!(
+            "{:>id_width$}  {:site_width$}  {:username_width$}  {}",
+            entry.id,
+            entry.site,
+            entry.username,
+            entry.urls.first().map(String::as_str).unwrap_or_default(),
+        );
+        println!("{}", line.trim_end());
+    }
+
+    if let Some(newest) = entries.iter().map(|entry| entry.updated_at).max() {
+        eprintln!(
+            "{} entries, last changed {}",
+            entries.len(),
+            format_timestamp(newest)
+        );
+    }
+}
+
+/// Print the outcome of a command that has no other output.
+fn print_status(json: bool, key: &str, message: &str) -> Result<(), String> {
+    if json {
+        print_json(&BTreeMap::from([(key, true)]))
+    } else {
+        println!("{message}");
+        Ok(())
+    }
+}
+
+fn print_report(report: &IntegrityReport) {
+    if report.database_problems.is_empty() {
+        println!("Database file: ok");
+    } else {
+        println!(
+            "Database file: {} problem(s) found",
+            report.database_problems.len()
+        );
+        for problem in &report.database_problems {
+            println!("  {problem}");
+        }
+    }
+
+    let schema = &report.schema;
+    let version = schema
+        .version
+        .map(|version| version.to_string())
+        .unwrap_or_else(|| "none".into());
+    if schema.is_ok() {
+        println!("Schema: ok, version {version}");
+    } else {
+        println!(
+            "Schema: version {version}, this app has {}",
+            schema.expected
+        );
+        for (label, versions) in [
+            ("Not applied", &schema.missing),
+            ("From a newer version", &schema.unknown),
+            ("Failed or changed", &schema.broken),
+        ] {
+            if !versions.is_empty() {
+                let versions: Vec<String> = versions.iter().map(i64::to_string).collect();
+                println!("  {label}: {}", versions.join(", "));
+            }
+        }
+    }
+
+    println!(
+        "Entries: {} checked, {} can't be read",
+        report.entries_checked,
+        report.entry_problems.len()
+    );
+    for problem in &report.entry_problems {
+        println!(
+            "  {}  {}: {} ({})",
+            problem.id,
+            describe(&problem.site, &problem.username),
+            problem.error,
+            problem.error.code()
+        );
+    }
+    if !report.quarantined.is_empty() {
+        println!(
+            "Quarantined: {} entries were moved out of the vault by earlier checks, see `pw quarantine`",
+            report.quarantined.len()
+        );
+    }
+}
+
+fn print_quarantined(quarantined: &[QuarantinedEntry]) {
+    if quarantined.is_empty() {
+        println!("No entries are quarantined.");
+        return;
+    }
+
+    for entry in quarantined {
+        let state = match (entry.restorable, entry.in_vault) {
+            (_, true) => "back in the vault through sync, can be discarded",
+            (true, false) => "can be restored with `pw quarantine --restore`",
+            // Changing the master password only re-encrypts the ones that decrypt.
+            (false, false) => "encrypted with another master password or damaged",
+        };
+        println!(
+            "{}  {}: {}, quarantined {}, {state}",
+            entry.id,
+            describe(&entry.site, &entry.username),
+            entry.reason,
+            format_timestamp(entry.quarantined_at)
+        );
+    }
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), String> {
+    let text = serde_json::to_string_pretty(value).map_err(|err| err.to_string())?;
+    println!("{text}");
+    Ok(())
+}
+
+fn describe(site: &str, username: &str) -> String {
+    if username.is_empty() {
+        site.to_string()
+    } else {
+        format!("{site} ({username})")
+    }
+}
+
+/// Read a password from the terminal without echoing it. When stdin isn't a terminal, e.g. in
+/// scripts, a line is read from it instead.
+fn prompt_password(prompt: &str) -> Result<Zeroizing<String>, String> {
+    if io::stdin().is_terminal() {
+        return rpassword::prompt_password(prompt)
+            .map(Zeroizing::new)
+            .map_err(|err| format!("Could not read the password: {err}"));
+    }
+
+    let mut line = Zeroizing::new(String::new());
+    io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .map_err(|err| format!("Could not read the password: {err}"))?;
+    Ok(Zeroizing::new(
+        line.trim_end_matches(['\r', '\n']).to_string(),
+    ))
+}
+
+/// Ask for a new password twice so that a typo isn't saved.
+fn prompt_new_password(prompt: &str) -> Result<Zeroizing<String>, String> {
+    let password = prompt_password(prompt)?;
+    let repeated = prompt_password("Repeat it: ")?;
+
+    if password != repeated {
+        return Err("The passwords don't match".into());
+    }
+    Ok(password)
+}
+
+fn confirm(question: &str) -> Result<bool, String> {
+    eprint!("{question} [y/N] ");
+    io::stderr().flush().map_err(|err| err.to_string())?;
+
+    let mut answer = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .map_err(|err| err.to_string())?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
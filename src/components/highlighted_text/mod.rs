@@ -0,0 +1,32 @@
+use dioxus::prelude::*;
+
+/// Renders `text` with the characters at `positions` highlighted, used to show which parts of a
+/// field matched a search.
+#[component]
+pub fn HighlightedText(text: String, positions: Vec<usize>) -> Element {
+    if positions.is_empty() {
+        return rsx! { "{text}" };
+    }
+
+    // Group the text into runs of highlighted and plain characters.
+    let mut runs: Vec<(bool, String)> = Vec::new();
+    for (index, c) in text.chars().enumerate() {
+        let highlighted = positions.contains(&index);
+        match runs.last_mut() {
+            Some((run_highlighted, run)) if *run_highlighted == highlighted => run.push(c),
+            _ => runs.push((highlighted, c.to_string())),
+        }
+    }
+
+    rsx! {
+        for (highlighted, run) in runs {
+            if highlighted {
+                mark { style: "background: none; color: var(--focused-border-color); font-weight: 700;",
+                    "{run}"
+                }
+            } else {
+                "{run}"
+            }
+        }
+    }
+}
@@ -34,6 +34,35 @@ pub fn Input(props: InputProps) -> Element {
     }
 }
 
+#[derive(Debug, Clone, Props, PartialEq)]
+pub struct TextAreaProps {
+    #[props(into, optional)]
+    value_changed: Callback<Event<FormData>>,
+    #[props(extends=GlobalAttributes)]
+    #[props(extends=textarea)]
+    attributes: Vec<Attribute>,
+}
+
+/// A multi-line version of [`Input`] sharing its styling.
+#[component]
+pub fn TextArea(props: TextAreaProps) -> Element {
+    let mut state = use_context::<Signal<AuthState>>();
+    let style = include_str!("./style.css");
+
+    rsx! {
+        style { {style} }
+        textarea {
+            class: "input",
+            style: "resize: vertical; font-family: inherit;",
+            oninput: move |evt| {
+                state.write().reset_idle_timer();
+                props.value_changed.call(evt);
+            },
+            ..props.attributes,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Props, PartialEq)]
 pub struct PasswordInputProps {
     #[props(into, optional)]
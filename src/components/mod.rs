@@ -28,3 +28,12 @@ pub use field::*;
 
 pub mod alert;
 pub use alert::*;
+
+pub mod strength_meter;
+pub use strength_meter::*;
+
+pub mod highlighted_text;
+pub use highlighted_text::*;
+
+pub mod virtual_list;
+pub use virtual_list::*;
@@ -0,0 +1,46 @@
+use dioxus::prelude::*;
+use zeroize::Zeroizing;
+
+use crate::services::password_strength::{self, MAX_SCORE};
+
+/// Shows how strong a password is while it is being typed, along with how long it would take to
+/// crack offline and suggestions for improving it.
+#[component]
+pub fn StrengthMeter(password: Zeroizing<String>) -> Element {
+    let style = include_str!("./style.css");
+
+    if password.is_empty() {
+        return rsx! {};
+    }
+
+    let estimate = password_strength::estimate(&password);
+    let crack_time = password_strength::display_time(estimate.crack_times.offline_slow_hashing);
+
+    rsx! {
+        style { {style} }
+        div { class: "strength-meter", "data-score": estimate.score,
+            div { class: "strength-meter-bar",
+                for segment in 0..=MAX_SCORE {
+                    div {
+                        class: "strength-meter-segment",
+                        "data-filled": segment <= estimate.score,
+                    }
+                }
+            }
+            div { class: "strength-meter-label",
+                span { {password_strength::score_label(estimate.score)} }
+                span { "Time to crack: {crack_time}" }
+            }
+            if let Some(warning) = estimate.feedback.warning {
+                div { class: "strength-meter-warning", "{warning}" }
+            }
+            if !estimate.feedback.suggestions.is_empty() {
+                ul { class: "strength-meter-suggestions",
+                    for suggestion in estimate.feedback.suggestions {
+                        li { "{suggestion}" }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,5 +1,15 @@
 use dioxus::prelude::*;
-use dioxus_primitives::toast::{self, ToastProviderProps};
+use dioxus_primitives::toast::{self, ToastOptions, ToastProviderProps};
+
+use crate::services::error::ServiceError;
+
+/// Options for an error toast about a service's error, to go with the error's title. The code
+/// follows the message so that the problem can be reported.
+pub fn error_options(err: &impl ServiceError) -> ToastOptions {
+    ToastOptions::new()
+        .description(format!("{err} ({})", err.code()))
+        .permanent(true)
+}
 
 #[component]
 pub fn ToastProvider(props: ToastProviderProps) -> Element {
@@ -0,0 +1,138 @@
+use dioxus::prelude::*;
+
+/// Rows rendered above and below the visible ones so that fast scrolling doesn't show gaps.
+const OVERSCAN_ROWS: usize = 4;
+
+/// Used until the list has been measured.
+const DEFAULT_VIEWPORT_HEIGHT: f64 = 800.0;
+
+#[derive(Debug, Clone, PartialEq, Props)]
+pub struct VirtualListProps {
+    /// Identifies the scroll container for [`scroll_to`], must be unique on the page.
+    #[props(into)]
+    pub id: String,
+    pub row_count: usize,
+    /// The height of every row in pixels, rows are clipped to it.
+    pub row_height: f64,
+    /// The row selected with the keyboard, highlighted by the row itself.
+    #[props(default)]
+    pub selected: Option<usize>,
+    /// Called with the new row when the selection is moved with the arrow, page, home or end keys.
+    #[props(optional)]
+    pub on_select: Callback<usize>,
+    /// Called with the selected row when Enter is pressed.
+    #[props(optional)]
+    pub on_activate: Callback<usize>,
+    /// Called with the scroll offset in pixels whenever the list is scrolled.
+    #[props(optional)]
+    pub on_scroll: Callback<f64>,
+    /// Called when the list is scrolled to within a screen of its last row, e.g. to load more.
+    #[props(optional)]
+    pub on_end_reached: Callback<()>,
+    /// Renders the row at an index.
+    pub render_row: Callback<usize, Element>,
+    #[props(extends = GlobalAttributes)]
+    pub attributes: Vec<Attribute>,
+}
+
+/// A scrolling list that only renders the rows in view, for lists too long to render in full.
+/// It scrolls within whatever height it is given, e.g. with a `style` attribute.
+#[component]
+pub fn VirtualList(props: VirtualListProps) -> Element {
+    let style = include_str!("./style.css");
+    let mut scroll_top = use_signal(|| 0.0);
+    let mut viewport_height = use_signal(|| DEFAULT_VIEWPORT_HEIGHT);
+
+    let row_count = props.row_count;
+    let row_height = props.row_height;
+    let total_height = row_count as f64 * row_height;
+
+    let first_row = ((scroll_top() / row_height).floor() as usize).saturating_sub(OVERSCAN_ROWS);
+    let last_row = (((scroll_top() + viewport_height()) / row_height).ceil() as usize
+        + OVERSCAN_ROWS)
+        .min(row_count);
+
+    let id = props.id.clone();
+    let selected = props.selected;
+
+    rsx! {
+        style { {style} }
+        div {
+            id: props.id.clone(),
+            class: "virtual-list",
+            tabindex: "0",
+            role: "listbox",
+            onscroll: move |evt: Event<ScrollData>| {
+                let top = evt.data().scroll_top();
+                scroll_top.set(top);
+                props.on_scroll.call(top);
+                if top + 2.0 * viewport_height() >= total_height {
+                    props.on_end_reached.call(());
+                }
+            },
+            onresize: move |evt: Event<ResizeData>| {
+                if let Ok(size) = evt.data().get_border_box_size() {
+                    viewport_height.set(size.height);
+                }
+            },
+            onkeydown: move |evt: KeyboardEvent| {
+                if row_count == 0 {
+                    return;
+                }
+
+                let page = ((viewport_height() / row_height).floor() as usize).max(1);
+                let last = row_count - 1;
+                let next = match evt.key() {
+                    Key::ArrowDown => selected.map_or(0, |row| (row + 1).min(last)),
+                    Key::ArrowUp => selected.map_or(0, |row| row.saturating_sub(1)),
+                    Key::PageDown => selected.map_or(0, |row| (row + page).min(last)),
+                    Key::PageUp => selected.map_or(0, |row| row.saturating_sub(page)),
+                    Key::Home => 0,
+                    Key::End => last,
+                    Key::Enter => {
+                        if let Some(row) = selected {
+                            evt.prevent_default();
+                            props.on_activate.call(row);
+                        }
+                        return;
+                    }
+                    _ => return,
+                };
+
+                evt.prevent_default();
+                props.on_select.call(next);
+
+                // Keep the selected row in view.
+                let row_top = next as f64 * row_height;
+                let row_bottom = row_top + row_height;
+                if row_top < scroll_top() {
+                    scroll_to(&id, row_top);
+                } else if row_bottom > scroll_top() + viewport_height() {
+                    scroll_to(&id, row_bottom - viewport_height());
+                }
+            },
+            ..props.attributes,
+            div {
+                class: "virtual-list-content",
+                style: "height: {total_height}px;",
+                for index in first_row..last_row {
+                    div {
+                        key: "{index}",
+                        class: "virtual-list-row",
+                        role: "option",
+                        "aria-selected": selected == Some(index),
+                        style: "top: {index as f64 * row_height}px; height: {row_height}px;",
+                        {props.render_row.call(index)}
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Scroll the [`VirtualList`] with `id` so that `top` pixels are above the visible area.
+pub fn scroll_to(id: &str, top: f64) {
+    let _ = document::eval(&format!(
+        "const list = document.getElementById({id:?}); if (list) {{ list.scrollTop = {top}; }}"
+    ));
+}
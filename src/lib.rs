@@ -0,0 +1,5 @@
+//! The vault's models and services, shared by the desktop app and the command-line tools in
+//! `src/bin`. Nothing here depends on Dioxus.
+
+pub mod models;
+pub mod services;
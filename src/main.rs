@@ -1,28 +1,54 @@
 use std::{
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{Arc, OnceLock},
 };
 
+use clap::Parser;
 use dioxus::{
     desktop::{Config, LogicalSize, WindowBuilder},
     prelude::*,
 };
 
 mod components;
-mod models;
 mod routes;
-mod services;
 mod views;
 
-use directories::UserDirs;
+use password_manager::{models, services};
 use routes::Route;
 
-use crate::{components::ToastProvider, models::AuthState, services::database::DatabaseService};
+use crate::{
+    components::ToastProvider,
+    models::AuthState,
+    services::{
+        data_dir,
+        database::DatabaseService,
+        instance::{self, Instance, InstanceGuard},
+        vaults::{self, VaultRegistry},
+    },
+    views::{Recovery, StartupFailure},
+};
 
 static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+/// Held for as long as the app runs, not set when the data directory couldn't be locked.
+static INSTANCE: OnceLock<InstanceGuard> = OnceLock::new();
+
+#[derive(Parser)]
+#[command(version, about = "A password manager")]
+struct Cli {
+    /// Keep the vaults and the app's other files in this directory
+    #[arg(long)]
+    data_dir: Option<PathBuf>,
+
+    /// Keep everything in a `data` directory next to the executable, e.g. to run the app from a
+    /// USB drive
+    #[arg(long)]
+    portable: bool,
+}
 
 fn main() {
-    init_data_directory();
+    let cli = Cli::parse();
+    init_data_directory(&cli);
+    init_instance();
 
     let launcher = tokio::runtime::Runtime::new()
         .unwrap()
@@ -35,6 +61,32 @@ fn main() {
 fn App() -> Element {
     provide_context(Signal::new(AuthState::default()));
 
+    // Launching the app again brings this window to the front.
+    use_future(|| async {
+        let Some(guard) = INSTANCE.get() else {
+            return;
+        };
+        let window = dioxus::desktop::window();
+        let result = guard
+            .listen_for_launches(|| {
+                window.set_minimized(false);
+                window.set_visible(true);
+                window.set_focus();
+            })
+            .await;
+        if let Err(err) = result {
+            eprintln!("Stopped listening for the app being launched again: {err}");
+        }
+    });
+
+    // The vault switcher and the recovery screen replace the vault in this signal.
+    let startup = use_context::<Result<Arc<DatabaseService>, StartupFailure>>();
+    let vault = use_context_provider(|| Signal::new(startup.clone().ok()));
+    let failure = match vault() {
+        Some(_) => None,
+        None => startup.err(),
+    };
+
     let main_css = include_str!("../assets/styling/main.css");
     let dx_component_theme = include_str!("../assets/styling/dx-components-theme.css");
 
@@ -42,15 +94,39 @@ fn App() -> Element {
         style { {main_css} }
         style { {dx_component_theme} }
 
-        ToastProvider { Router::<Route> {} }
+        ToastProvider {
+            // Keyed by the vault's file so that the router and every view below it start over on
+            // another vault instead of keeping state from the old one.
+            for db_service in vault() {
+                VaultRoutes { key: "{db_service.path.display()}" }
+            }
+            if let Some(failure) = failure {
+                Recovery { failure }
+            }
+        }
+    }
+}
+
+/// Provides the open vault to the views.
+#[component]
+fn VaultRoutes() -> Element {
+    let vault = use_context::<Signal<Option<Arc<DatabaseService>>>>();
+    use_context_provider(|| {
+        vault
+            .peek()
+            .clone()
+            .expect("the routes are only rendered while a vault is open")
+    });
+
+    rsx! {
+        Router::<Route> {}
     }
 }
 
 async fn init_launcher() -> LaunchBuilder {
     let app_name = "Password Manager";
 
-    let db_service =
-        Arc::new(DatabaseService::new(DATA_DIR.get().unwrap().join("passwords.sqlite")).await);
+    let startup = open_last_vault(DATA_DIR.get().unwrap()).await.map(Arc::new);
 
     dioxus::LaunchBuilder::desktop()
         .with_cfg(desktop! {
@@ -62,18 +138,52 @@ async fn init_launcher() -> LaunchBuilder {
             )
             .with_menu(None)
         })
-        .with_context(db_service)
+        .with_context(startup)
 }
 
-fn init_data_directory() {
-    if let Some(user_dirs) = UserDirs::new() {
-        let home_dir = user_dirs.home_dir();
-        let data_dir = home_dir.join(".password_manager");
+/// The vault that was open last. When it can't be opened the app shows the recovery screen
+/// instead.
+async fn open_last_vault(data_dir: &Path) -> Result<DatabaseService, StartupFailure> {
+    let registry = VaultRegistry::load(data_dir).unwrap_or_else(|err| {
+        eprintln!("{err}, only the default vault is available");
+        VaultRegistry::new(data_dir)
+    });
+
+    let vault = registry.current();
+    vaults::open_listed_vault(&registry, vault)
+        .await
+        .map_err(|error| StartupFailure {
+            vault: vault.clone(),
+            error,
+        })
+}
+
+/// Only one app runs with a data directory, launching another shows the first one's window.
+fn init_instance() {
+    let data_dir = DATA_DIR.get().unwrap();
+    match instance::acquire(data_dir) {
+        Ok(Instance::First(guard)) => INSTANCE.set(guard).unwrap(),
+        Ok(Instance::AlreadyRunning { shown: true }) => std::process::exit(0),
+        Ok(Instance::AlreadyRunning { shown: false }) => {
+            eprintln!(
+                "The app is already running with {}. Exiting...",
+                data_dir.display()
+            );
+            std::process::exit(1);
+        }
+        // When the data directory can't be used, the recovery screen says why.
+        Err(err) => eprintln!("{err}, the app can't tell whether it's already running"),
+    }
+}
 
-        std::fs::create_dir_all(&data_dir).expect("could not create application data directory");
-        DATA_DIR.set(data_dir.clone()).unwrap();
-    } else {
-        println!("Could not find user directories. Exiting...");
-        std::process::exit(1);
+fn init_data_directory(cli: &Cli) {
+    match data_dir::resolve_data_dir(cli.data_dir.as_deref(), cli.portable) {
+        // The directory is created along with the vault, and when that fails the recovery
+        // screen says why.
+        Ok(data_dir) => DATA_DIR.set(data_dir).unwrap(),
+        Err(err) => {
+            eprintln!("{err}. Exiting...");
+            std::process::exit(1);
+        }
     }
 }
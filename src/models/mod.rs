@@ -1,84 +1,449 @@
-use std::time::{Duration, Instant};
+use std::{
+    fmt,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use aes_gcm::{
-    aead::{Aead, OsRng},
+    aead::{rand_core::RngCore, Aead, OsRng},
     AeadCore, Aes256Gcm, Key, KeyInit, Nonce,
 };
 use argon2::Argon2;
 use base64::prelude::*;
-use zeroize::Zeroizing;
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::services::error::ServiceError;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct PasswordEntrySafe {
     pub id: i32,
+    pub uuid: String,
+    pub revision: i64,
     pub site: String,
     pub username: String,
     pub password_hash: String,
+    pub urls: Vec<String>,
+    /// The entry's notes, encrypted like the password. Empty when it has none.
+    pub encrypted_notes: String,
+    pub tags: Vec<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub password_changed_at: i64,
+    /// The entry's [`EntryExtras`], encrypted like the password. Empty when it has none.
+    pub encrypted_extras: String,
 }
 
 impl PasswordEntrySafe {
-    pub fn to_raw(&self, auth_state: &AuthState) -> Result<PasswordEntryRaw, String> {
+    pub fn to_raw(&self, auth_state: &AuthState) -> Result<PasswordEntryRaw, CryptoError> {
+        self.to_raw_with(&auth_state.cipher())
+    }
+
+    /// Like [`Self::to_raw`] but reusing a cipher, for decrypting many entries.
+    pub fn to_raw_with(&self, cipher: &Aes256Gcm) -> Result<PasswordEntryRaw, CryptoError> {
         Ok(PasswordEntryRaw {
             id: self.id,
+            uuid: self.uuid.clone(),
+            revision: self.revision,
             site: self.site.clone(),
             username: self.username.clone(),
-            raw_password: Zeroizing::new(self.decrypt_password(auth_state)?),
+            raw_password: self.decrypt_password_with(cipher)?,
+            urls: self.urls.clone(),
+            notes: self.decrypt_notes_with(cipher)?.to_string(),
+            tags: self.tags.clone(),
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            password_changed_at: self.password_changed_at,
+            extras: self.decrypt_extras_with(cipher)?,
         })
     }
 
-    fn decrypt_password(&self, auth_state: &AuthState) -> Result<String, String> {
-        let key_bits = auth_state.get_key_material();
-        let key = Key::<Aes256Gcm>::from_slice(&key_bits);
-        let cipher = Aes256Gcm::new(key);
+    /// Decrypt just the password, for when it is needed briefly such as to show or copy it.
+    pub fn decrypt_password(
+        &self,
+        auth_state: &AuthState,
+    ) -> Result<Zeroizing<String>, CryptoError> {
+        self.decrypt_password_with(&auth_state.cipher())
+    }
+
+    /// Like [`Self::decrypt_password`] but reusing a cipher, which avoids deriving the key again
+    /// when decrypting many entries.
+    pub fn decrypt_password_with(
+        &self,
+        cipher: &Aes256Gcm,
+    ) -> Result<Zeroizing<String>, CryptoError> {
+        let plaintext = decrypt(cipher, &self.password_hash)?;
 
-        let combined = BASE64_STANDARD
-            .decode(self.password_hash.clone())
-            .map_err(|err| err.to_string())?;
+        String::from_utf8(plaintext)
+            .map(Zeroizing::new)
+            .map_err(|err| {
+                // Don't leave the invalid plaintext behind in memory.
+                err.into_bytes().zeroize();
+                CryptoError::Malformed("the decrypted password is not valid UTF-8".into())
+            })
+    }
+
+    /// Decrypt just the notes, e.g. to search them.
+    pub fn decrypt_notes_with(&self, cipher: &Aes256Gcm) -> Result<Zeroizing<String>, CryptoError> {
+        if self.encrypted_notes.is_empty() {
+            return Ok(Zeroizing::new(String::new()));
+        }
 
-        let (nonce_bytes, ciphertext) = combined.split_at(12);
+        let plaintext = decrypt(cipher, &self.encrypted_notes)?;
 
-        let nonce: &Nonce<_> = Nonce::from_slice(nonce_bytes);
+        String::from_utf8(plaintext)
+            .map(Zeroizing::new)
+            .map_err(|err| {
+                err.into_bytes().zeroize();
+                CryptoError::Malformed("the decrypted notes are not valid UTF-8".into())
+            })
+    }
+
+    fn decrypt_extras_with(&self, cipher: &Aes256Gcm) -> Result<EntryExtras, CryptoError> {
+        if self.encrypted_extras.is_empty() {
+            return Ok(EntryExtras::default());
+        }
 
-        let plaintext = cipher
-            .decrypt(nonce, ciphertext)
-            .map_err(|err| err.to_string())?;
+        let plaintext = Zeroizing::new(decrypt(cipher, &self.encrypted_extras)?);
 
-        String::from_utf8(plaintext).map_err(|err| err.to_string())
+        serde_json::from_slice(&plaintext).map_err(|err| {
+            CryptoError::Malformed(format!("the extra details can't be read: {err}"))
+        })
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct PasswordEntryRaw {
     pub id: i32,
+    /// Identifies the entry on every device the vault is synced with. Empty for a new entry, one
+    /// is given to it when it's saved.
+    pub uuid: String,
+    /// Goes up by one each time the entry is changed, 0 for a new entry.
+    pub revision: i64,
     pub site: String,
     pub username: String,
     pub raw_password: Zeroizing<String>,
+    /// Addresses the credentials are used at, e.g. `https://github.com/login`.
+    pub urls: Vec<String>,
+    pub notes: String,
+    pub tags: Vec<String>,
+    /// Unix timestamp (seconds) of when the entry was created.
+    pub created_at: i64,
+    /// Unix timestamp (seconds) of the last time the entry was edited.
+    pub updated_at: i64,
+    /// Unix timestamp (seconds) of the last time the password itself was changed.
+    pub password_changed_at: i64,
+    pub extras: EntryExtras,
 }
 
 impl PasswordEntryRaw {
-    pub fn to_safe(&self, auth_state: &AuthState) -> Result<PasswordEntrySafe, String> {
+    pub fn to_safe(&self, auth_state: &AuthState) -> Result<PasswordEntrySafe, CryptoError> {
+        self.to_safe_with(&auth_state.cipher())
+    }
+
+    /// Like [`Self::to_safe`] but reusing a cipher, for encrypting many entries.
+    pub fn to_safe_with(&self, cipher: &Aes256Gcm) -> Result<PasswordEntrySafe, CryptoError> {
+        let encrypted_extras = if self.extras.is_empty() {
+            String::new()
+        } else {
+            let plaintext = Zeroizing::new(
+                serde_json::to_vec(&self.extras)
+                    .map_err(|err| CryptoError::Encrypt(err.to_string()))?,
+            );
+            encrypt(cipher, &plaintext)?
+        };
+        let encrypted_notes = if self.notes.is_empty() {
+            String::new()
+        } else {
+            encrypt(cipher, self.notes.as_bytes())?
+        };
+
         Ok(PasswordEntrySafe {
             id: self.id,
+            uuid: self.uuid.clone(),
+            revision: self.revision,
             site: self.site.clone(),
             username: self.username.clone(),
-            password_hash: self.encrypt_password(auth_state)?,
+            password_hash: encrypt(cipher, self.raw_password.as_bytes())?,
+            urls: self.urls.clone(),
+            encrypted_notes,
+            tags: self.tags.clone(),
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            password_changed_at: self.password_changed_at,
+            encrypted_extras,
         })
     }
-    fn encrypt_password(&self, auth_state: &AuthState) -> Result<String, String> {
-        let key_bits = auth_state.get_key_material();
-        let key = Key::<Aes256Gcm>::from_slice(&key_bits);
-        let cipher = Aes256Gcm::new(key);
-        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+}
 
-        let ciphertext = cipher
-            .encrypt(&nonce, self.raw_password.as_bytes())
-            .map_err(|err| err.to_string())?;
+/// Left behind by a deleted entry so that syncing deletes it on the other devices too.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tombstone {
+    pub uuid: String,
+    /// Unix timestamp (seconds) of when the entry was deleted.
+    pub deleted_at: i64,
+}
 
-        // concatenate nonce + ciphertext
-        let mut combined = nonce.to_vec();
-        combined.extend_from_slice(&ciphertext);
+/// Details of an entry beyond its main fields, encrypted with it. Most are ones that other password
+/// managers, KeePass in particular, keep and this app has no fields of its own for. They are kept
+/// with the entry so that exporting it again loses nothing.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EntryExtras {
+    /// The names of the groups the entry is in, from the top level down.
+    pub group: Vec<String>,
+    pub custom_fields: Vec<CustomField>,
+    pub attachments: Vec<Attachment>,
+    /// Earlier versions of the entry, oldest first.
+    pub history: Vec<EntryRevision>,
+    /// Unix timestamp (seconds) of when the entry expires, if it does.
+    pub expires_at: Option<i64>,
+    /// The entry's UUID in the KeePass database it was imported from.
+    pub keepass_uuid: Option<[u8; 16]>,
+    /// The SSH key the SSH agent serves for this entry.
+    pub ssh_key: Option<SshKey>,
+}
 
-        Ok(BASE64_STANDARD.encode(combined))
+impl EntryExtras {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CustomField {
+    pub name: String,
+    pub value: Zeroizing<String>,
+    /// Whether the value is secret and should be hidden like a password.
+    pub protected: bool,
+}
+
+/// An SSH private key kept in the vault. It never leaves it unencrypted, the SSH agent signs with
+/// it instead.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SshKey {
+    /// The private key in OpenSSH format, without a passphrase of its own.
+    pub private_key: Zeroizing<String>,
+    /// Whether every use of the key has to be allowed in the app first.
+    #[serde(default)]
+    pub confirm_use: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attachment {
+    pub name: String,
+    #[serde(with = "base64_bytes")]
+    pub data: Vec<u8>,
+}
+
+/// A previous version of an entry.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EntryRevision {
+    pub site: String,
+    pub username: String,
+    pub raw_password: Zeroizing<String>,
+    pub urls: Vec<String>,
+    pub notes: String,
+    pub tags: Vec<String>,
+    pub custom_fields: Vec<CustomField>,
+    pub attachments: Vec<Attachment>,
+    /// Unix timestamp (seconds) of when this version was saved.
+    pub updated_at: i64,
+}
+
+/// Serializes bytes as base64 rather than as an array of numbers.
+mod base64_bytes {
+    use base64::prelude::*;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&BASE64_STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        BASE64_STANDARD
+            .decode(encoded)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Why an entry couldn't be encrypted or decrypted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CryptoError {
+    /// The data doesn't decrypt with the key, because it was encrypted with another master
+    /// password or has been changed since.
+    WrongKey,
+    /// The stored data isn't in the format it was written in.
+    Malformed(String),
+    Encrypt(String),
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CryptoError::WrongKey => write!(
+                f,
+                "The data couldn't be decrypted with the master password, it was encrypted with another one or has been changed"
+            ),
+            CryptoError::Malformed(message) => write!(f, "The stored data is damaged, {message}"),
+            CryptoError::Encrypt(message) => write!(f, "Could not encrypt the entry: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for CryptoError {}
+
+impl ServiceError for CryptoError {
+    fn code(&self) -> &'static str {
+        match self {
+            CryptoError::WrongKey => "crypto.wrong_key",
+            CryptoError::Malformed(_) => "crypto.malformed",
+            CryptoError::Encrypt(_) => "crypto.encrypt",
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            CryptoError::WrongKey => "Wrong key",
+            CryptoError::Malformed(_) => "Damaged entry",
+            CryptoError::Encrypt(_) => "Encryption failed",
+        }
+    }
+}
+
+impl From<CryptoError> for String {
+    fn from(err: CryptoError) -> Self {
+        err.to_string()
+    }
+}
+
+/// Encrypt with a random nonce, returning the nonce and ciphertext together as base64.
+pub fn encrypt(cipher: &Aes256Gcm, plaintext: &[u8]) -> Result<String, CryptoError> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|err| CryptoError::Encrypt(err.to_string()))?;
+
+    // concatenate nonce + ciphertext
+    let mut combined = nonce.to_vec();
+    combined.extend_from_slice(&ciphertext);
+
+    Ok(BASE64_STANDARD.encode(combined))
+}
+
+/// Decrypt what [`encrypt`] returned.
+pub fn decrypt(cipher: &Aes256Gcm, encoded: &str) -> Result<Vec<u8>, CryptoError> {
+    let combined = BASE64_STANDARD
+        .decode(encoded)
+        .map_err(|err| CryptoError::Malformed(format!("the ciphertext isn't base64: {err}")))?;
+
+    if combined.len() < 12 {
+        return Err(CryptoError::Malformed("the ciphertext is truncated".into()));
+    }
+
+    let (nonce_bytes, ciphertext) = combined.split_at(12);
+
+    let nonce: &Nonce<_> = Nonce::from_slice(nonce_bytes);
+
+    // AES-GCM can't tell a wrong key from a changed ciphertext, both fail authentication.
+    cipher
+        .decrypt(nonce, ciphertext)
+        .map_err(|_| CryptoError::WrongKey)
+}
+
+/// The current time as a unix timestamp in seconds, the format used for entry timestamps.
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// A random (version 4) UUID, e.g. for a new entry.
+pub fn new_uuid() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian calendar date.
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The proleptic Gregorian calendar date `days` after 1970-01-01, the inverse of
+/// [`days_from_civil`].
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Format a unix timestamp in seconds as `YYYY-MM-DD HH:MM UTC`.
+pub fn format_timestamp(unix_time: i64) -> String {
+    let (year, month, day) = civil_from_days(unix_time.div_euclid(86_400));
+    let seconds_of_day = unix_time.rem_euclid(86_400);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02} UTC",
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60
+    )
+}
+
+/// User configurable application settings, stored as a single row in the `settings` table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// The minimum strength score (0-4) a new master password must reach.
+    pub min_master_password_score: u8,
+    /// Passwords that haven't changed in this many months are flagged by the security report.
+    pub max_password_age_months: u32,
+    /// Where the Pwned Passwords breach index was built, empty when breach checking is disabled.
+    pub breach_index_path: String,
+    /// How many automatic backups of the vault are kept, 0 turns them off.
+    pub backup_retention: u32,
+    /// The folder the vault is synced through, empty when it isn't synced.
+    pub sync_folder: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            min_master_password_score: 3,
+            max_password_age_months: 12,
+            breach_index_path: String::new(),
+            backup_retention: 10,
+            sync_folder: String::new(),
+        }
     }
 }
 
@@ -87,6 +452,9 @@ pub struct AuthState {
     pub signed_in: bool,
     pub raw_master_password: Zeroizing<String>,
     pub salt: Zeroizing<String>,
+    /// The key derived from the master password, set instead of the password and salt when the
+    /// unlock agent handed it over.
+    pub key_material: Option<Zeroizing<[u8; 32]>>,
     pub last_activity: Instant,
 }
 
@@ -96,13 +464,27 @@ impl Default for AuthState {
             signed_in: false,
             raw_master_password: Zeroizing::new(String::new()),
             salt: Zeroizing::new(String::new()),
+            key_material: None,
             last_activity: Instant::now(),
         }
     }
 }
 
 impl AuthState {
+    /// Signed in with a key that's already been derived from the master password.
+    pub fn from_key_material(key_material: Zeroizing<[u8; 32]>) -> Self {
+        Self {
+            signed_in: true,
+            key_material: Some(key_material),
+            ..Self::default()
+        }
+    }
+
     pub fn get_key_material(&self) -> [u8; 32] {
+        if let Some(key_material) = &self.key_material {
+            return **key_material;
+        }
+
         let mut output_key_material = [0u8; 32];
         Argon2::default()
             .hash_password_into(
@@ -115,6 +497,12 @@ impl AuthState {
         output_key_material
     }
 
+    /// The cipher password entries are encrypted with, keyed from the master password.
+    pub fn cipher(&self) -> Aes256Gcm {
+        let key_bits = Zeroizing::new(self.get_key_material());
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key_bits.as_slice()))
+    }
+
     pub fn reset_idle_timer(&mut self) {
         self.last_activity = Instant::now();
     }
@@ -20,8 +20,41 @@ pub enum Route {
         #[route("/vault/:id")]
         PasswordDetails { id: i32 },
 
+        #[route("/vault/:id/damaged")]
+        DamagedEntryDetails { id: i32 },
+
         #[route("/new_password_entry")]
-        NewPasswordEntry {}
+        NewPasswordEntry {},
+
+        #[route("/settings")]
+        AppSettings {},
+
+        #[route("/security_report")]
+        SecurityReport {},
+
+        #[route("/import")]
+        Import {},
+
+        #[route("/import/vault_file")]
+        ImportVaultFile {},
+
+        #[route("/export")]
+        Export {},
+
+        #[route("/backups")]
+        RestoreBackup {},
+
+        #[route("/check")]
+        CheckVault {},
+
+        #[route("/activity")]
+        Activity {},
+
+        #[route("/sync")]
+        SyncVault {},
+
+        #[route("/vaults/new")]
+        NewVault {}
 }
 
 impl Route {
@@ -45,7 +78,51 @@ impl Route {
         Route::PasswordDetails { id }
     }
 
+    pub fn damaged_entry(id: i32) -> Self {
+        Route::DamagedEntryDetails { id }
+    }
+
     pub fn new_password_entry() -> Self {
         Route::NewPasswordEntry {}
     }
+
+    pub fn app_settings() -> Self {
+        Route::AppSettings {}
+    }
+
+    pub fn security_report() -> Self {
+        Route::SecurityReport {}
+    }
+
+    pub fn import() -> Self {
+        Route::Import {}
+    }
+
+    pub fn import_vault_file() -> Self {
+        Route::ImportVaultFile {}
+    }
+
+    pub fn export() -> Self {
+        Route::Export {}
+    }
+
+    pub fn restore_backup() -> Self {
+        Route::RestoreBackup {}
+    }
+
+    pub fn check_vault() -> Self {
+        Route::CheckVault {}
+    }
+
+    pub fn activity() -> Self {
+        Route::Activity {}
+    }
+
+    pub fn sync_vault() -> Self {
+        Route::SyncVault {}
+    }
+
+    pub fn new_vault() -> Self {
+        Route::NewVault {}
+    }
 }
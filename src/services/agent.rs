@@ -0,0 +1,373 @@
+//! The unlock agent: a process that keeps the vault unlocked for the CLI and the desktop app so
+//! that the master password isn't asked for on every command.
+//!
+//! The agent listens on a Unix domain socket next to the vault that only its owner can use, and
+//! connections from other users are refused. Requests and responses are single lines of JSON.
+//! Once unlocked the agent holds the key derived from the master password, never the password
+//! itself, and hands the key to clients that ask for it. It locks itself after the same period
+//! of inactivity as [`AuthState::is_expired`], or when asked to.
+
+use std::path::{Path, PathBuf};
+
+use base64::prelude::*;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::{models::AuthState, services::database::DatabaseService};
+
+/// The socket is named after the vault's file, e.g. `passwords.agent.sock`, so that vaults in the
+/// same directory each have their own.
+pub const SOCKET_EXTENSION: &str = "agent.sock";
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum AgentRequest {
+    /// Unlock the agent, the password is checked against the vault.
+    Unlock {
+        password: Zeroizing<String>,
+    },
+    /// Get the vault key, which counts as activity.
+    Key,
+    Status,
+    Lock,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "response", rename_all = "snake_case")]
+pub enum AgentResponse {
+    Ok,
+    Locked,
+    /// The base64 encoded vault key.
+    Key {
+        key: Zeroizing<String>,
+    },
+    Status {
+        unlocked: bool,
+    },
+    Error {
+        message: String,
+    },
+}
+
+pub fn socket_path(db_service: &DatabaseService) -> PathBuf {
+    db_service.path.with_extension(SOCKET_EXTENSION)
+}
+
+/// Signed in with the agent's key, or `None` when the agent isn't running or is locked.
+pub async fn agent_auth_state(socket_path: &Path) -> Option<AuthState> {
+    match send(socket_path, &AgentRequest::Key).await {
+        Ok(AgentResponse::Key { key }) => {
+            let decoded = Zeroizing::new(BASE64_STANDARD.decode(key.as_bytes()).ok()?);
+            let key_material: [u8; 32] = decoded.as_slice().try_into().ok()?;
+            Some(AuthState::from_key_material(Zeroizing::new(key_material)))
+        }
+        _ => None,
+    }
+}
+
+/// Unlock the agent with the master password.
+pub async fn unlock(socket_path: &Path, password: Zeroizing<String>) -> Result<(), String> {
+    match send(socket_path, &AgentRequest::Unlock { password }).await? {
+        AgentResponse::Ok => Ok(()),
+        AgentResponse::Error { message } => Err(message),
+        _ => Err("Unexpected response from the agent".into()),
+    }
+}
+
+/// Lock the agent. Returns `false` when it isn't running.
+pub async fn lock(socket_path: &Path) -> Result<bool, String> {
+    if !is_running(socket_path).await {
+        return Ok(false);
+    }
+
+    match send(socket_path, &AgentRequest::Lock).await? {
+        AgentResponse::Ok => Ok(true),
+        AgentResponse::Error { message } => Err(message),
+        _ => Err("Unexpected response from the agent".into()),
+    }
+}
+
+/// Whether the agent is running and unlocked, `None` when it isn't running.
+pub async fn status(socket_path: &Path) -> Option<bool> {
+    match send(socket_path, &AgentRequest::Status).await {
+        Ok(AgentResponse::Status { unlocked }) => Some(unlocked),
+        _ => None,
+    }
+}
+
+pub async fn is_running(socket_path: &Path) -> bool {
+    status(socket_path).await.is_some()
+}
+
+#[cfg(unix)]
+pub use unix::{run_agent, send};
+
+#[cfg(not(unix))]
+pub async fn send(_socket_path: &Path, _request: &AgentRequest) -> Result<AgentResponse, String> {
+    Err("The agent is only available on Unix".into())
+}
+
+#[cfg(not(unix))]
+pub async fn run_agent(_db_service: DatabaseService, _socket_path: &Path) -> Result<(), String> {
+    Err("The agent is only available on Unix".into())
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::{
+        os::unix::fs::{MetadataExt, PermissionsExt},
+        path::Path,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use base64::prelude::*;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::{unix::OwnedWriteHalf, UnixListener, UnixStream},
+    };
+    use zeroize::Zeroizing;
+
+    use super::{AgentRequest, AgentResponse};
+    use crate::{
+        models::AuthState,
+        services::{authentication, database::DatabaseService},
+    };
+
+    /// How often the agent checks whether it's been idle for too long.
+    const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+    /// Requests are small, anything longer than this isn't one.
+    const MAX_REQUEST_LEN: usize = 64 * 1024;
+    /// The same goes for responses.
+    const MAX_RESPONSE_LEN: usize = 64 * 1024;
+
+    type SharedState = Arc<Mutex<Option<AuthState>>>;
+
+    /// Send one request to the agent and wait for its response.
+    pub async fn send(socket_path: &Path, request: &AgentRequest) -> Result<AgentResponse, String> {
+        let stream = UnixStream::connect(socket_path)
+            .await
+            .map_err(|err| format!("Could not connect to the agent: {err}"))?;
+        let (reader, mut writer) = stream.into_split();
+
+        let mut line =
+            Zeroizing::new(serde_json::to_string(request).map_err(|err| err.to_string())?);
+        line.push('\n');
+        writer
+            .write_all(line.as_bytes())
+            .await
+            .map_err(|err| err.to_string())?;
+
+        // Reading stops after the limit, so whatever is listening on the socket can't make the
+        // client buffer without end.
+        let mut response = Zeroizing::new(String::new());
+        BufReader::new(reader)
+            .take(MAX_RESPONSE_LEN as u64 + 1)
+            .read_line(&mut response)
+            .await
+            .map_err(|err| err.to_string())?;
+        if response.len() > MAX_RESPONSE_LEN {
+            return Err("The agent's response is too long".into());
+        }
+        serde_json::from_str(&response).map_err(|err| err.to_string())
+    }
+
+    /// Serve requests until interrupted. The socket is removed again when the agent stops.
+    pub async fn run_agent(db_service: DatabaseService, socket_path: &Path) -> Result<(), String> {
+        if socket_path.exists() {
+            if UnixStream::connect(socket_path).await.is_ok() {
+                return Err(format!(
+                    "An agent is already running on {}",
+                    socket_path.display()
+                ));
+            }
+            // Left behind by an agent that didn't stop cleanly.
+            std::fs::remove_file(socket_path).map_err(|err| err.to_string())?;
+        }
+
+        let listener = UnixListener::bind(socket_path).map_err(|err| err.to_string())?;
+        std::fs::set_permissions(socket_path, std::fs::Permissions::from_mode(0o600))
+            .map_err(|err| err.to_string())?;
+        let owner = std::fs::metadata(socket_path)
+            .map_err(|err| err.to_string())?
+            .uid();
+
+        let state: SharedState = Arc::new(Mutex::new(None));
+        let db_service = Arc::new(db_service);
+
+        let expiry_state = state.clone();
+        let expiry = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(EXPIRY_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                let mut state = expiry_state.lock().unwrap();
+                if state.as_ref().is_some_and(AuthState::is_expired) {
+                    *state = None;
+                }
+            }
+        });
+
+        let result = loop {
+            tokio::select! {
+                accepted = listener.accept() => {
+                    let stream = match accepted {
+                        Ok((stream, _)) => stream,
+                        Err(err) => break Err(err.to_string()),
+                    };
+
+                    // The socket's permissions should already keep other users out.
+                    let same_user = stream
+                        .peer_cred()
+                        .is_ok_and(|credentials| credentials.uid() == owner);
+                    if same_user {
+                        tokio::spawn(serve(stream, state.clone(), db_service.clone()));
+                    }
+                }
+                _ = tokio::signal::ctrl_c() => break Ok(()),
+            }
+        };
+
+        expiry.abort();
+        *state.lock().unwrap() = None;
+        let _ = std::fs::remove_file(socket_path);
+        result
+    }
+
+    async fn serve(stream: UnixStream, state: SharedState, db_service: Arc<DatabaseService>) {
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+
+        loop {
+            // Reading stops after the limit, so a client can't make the agent buffer without end.
+            let mut line = Zeroizing::new(String::new());
+            match (&mut reader)
+                .take(MAX_REQUEST_LEN as u64 + 1)
+                .read_line(&mut line)
+                .await
+            {
+                Ok(0) | Err(_) => return,
+                Ok(_) if line.len() > MAX_REQUEST_LEN => {
+                    // The rest of the request can't be told apart from the next one.
+                    let response = AgentResponse::Error {
+                        message: "The request is too long".into(),
+                    };
+                    let _ = write_response(&mut writer, &response).await;
+                    return;
+                }
+                Ok(_) => {}
+            }
+
+            let response = match serde_json::from_str::<AgentRequest>(&line) {
+                Ok(request) => respond(request, &state, &db_service).await,
+                Err(err) => AgentResponse::Error {
+                    message: format!("Invalid request: {err}"),
+                },
+            };
+
+            if write_response(&mut writer, &response).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    async fn write_response(
+        writer: &mut OwnedWriteHalf,
+        response: &AgentResponse,
+    ) -> Result<(), String> {
+        let encoded = serde_json::to_string(response).map_err(|err| err.to_string())?;
+        let mut encoded = Zeroizing::new(encoded);
+        encoded.push('\n');
+        writer
+            .write_all(encoded.as_bytes())
+            .await
+            .map_err(|err| err.to_string())
+    }
+
+    async fn respond(
+        request: AgentRequest,
+        state: &SharedState,
+        db_service: &DatabaseService,
+    ) -> AgentResponse {
+        match request {
+            AgentRequest::Unlock { password } => {
+                match authentication::sign_in(password, AuthState::default(), db_service).await {
+                    Ok(signed_in) => {
+                        // Keep the key rather than the password.
+                        let key_material = Zeroizing::new(signed_in.get_key_material());
+                        *state.lock().unwrap() = Some(AuthState::from_key_material(key_material));
+                        AgentResponse::Ok
+                    }
+                    Err(err) => AgentResponse::Error {
+                        message: err.to_string(),
+                    },
+                }
+            }
+            AgentRequest::Key => {
+                let mut state = state.lock().unwrap();
+                match state.as_mut() {
+                    Some(unlocked) if !unlocked.is_expired() => {
+                        unlocked.reset_idle_timer();
+                        let key_material = Zeroizing::new(unlocked.get_key_material());
+                        AgentResponse::Key {
+                            key: Zeroizing::new(BASE64_STANDARD.encode(key_material.as_slice())),
+                        }
+                    }
+                    _ => {
+                        *state = None;
+                        AgentResponse::Locked
+                    }
+                }
+            }
+            AgentRequest::Status => {
+                let state = state.lock().unwrap();
+                AgentResponse::Status {
+                    unlocked: state
+                        .as_ref()
+                        .is_some_and(|unlocked| !unlocked.is_expired()),
+                }
+            }
+            AgentRequest::Lock => {
+                *state.lock().unwrap() = None;
+                AgentResponse::Ok
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        async fn exchange(client: &mut UnixStream, request: &[u8]) -> String {
+            client.write_all(request).await.unwrap();
+            let mut response = String::new();
+            BufReader::new(client)
+                .read_line(&mut response)
+                .await
+                .unwrap();
+            response
+        }
+
+        #[tokio::test]
+        async fn long_requests_are_turned_away() {
+            let (_dir, db_service) = DatabaseService::temporary().await;
+            let (mut client, server) = UnixStream::pair().unwrap();
+            let state: SharedState = Arc::new(Mutex::new(None));
+            tokio::spawn(serve(server, state, Arc::new(db_service)));
+
+            let response = exchange(&mut client, b"{\"request\":\"status\"}\n").await;
+            assert_eq!(response, "{\"response\":\"status\",\"unlocked\":false}\n");
+
+            // Without a newline, as a client that never ends its request.
+            let response = exchange(&mut client, &vec![b' '; MAX_REQUEST_LEN + 1]).await;
+            assert_eq!(
+                response,
+                "{\"response\":\"error\",\"message\":\"The request is too long\"}\n"
+            );
+
+            // The agent hung up.
+            let mut rest = Vec::new();
+            client.read_to_end(&mut rest).await.unwrap();
+            assert!(rest.is_empty());
+        }
+    }
+}
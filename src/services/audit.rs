@@ -0,0 +1,740 @@
+//! The audit log, a record of security events such as unlocking the vault, showing and copying
+//! passwords and changing entries.
+//!
+//! Each record is encrypted with a random log key, which is kept encrypted with the master
+//! password, so changing the master password only encrypts the key again. Records are chained:
+//! each one's MAC covers the MAC of the record before it, and a MAC over the last record is kept
+//! with the key. A record that was changed, removed from the middle or the end of the log, or
+//! added without the key breaks the chain, which [`load_log`] reports. Replacing the whole vault
+//! with an older copy, e.g. by restoring a backup, can't be told apart from a log that ended there.
+//!
+//! Events that happen while the vault is locked, i.e. failed sign-ins, can't be encrypted. They
+//! are kept as they are until the next event is recorded, which moves them into the log.
+//!
+//! Recording is best effort for the callers: a log that can't be written doesn't keep the vault
+//! from being used, and the chain shows what went missing.
+
+use std::fmt;
+
+use aes_gcm::{
+    aead::{rand_core::RngCore, OsRng},
+    Aes256Gcm, Key, KeyInit,
+};
+use base64::prelude::*;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::{prelude::*, SqliteConnection};
+use zeroize::Zeroizing;
+
+use crate::{
+    models::{self, unix_now, AuthState, CryptoError},
+    services::{
+        database::{DatabaseService, QueryError},
+        error::ServiceError,
+    },
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// What happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Unlock,
+    FailedLogin,
+    MasterPasswordChange,
+    /// A password or other secret was shown or handed to another program.
+    Reveal,
+    Copy,
+    Create,
+    Edit,
+    Delete,
+    Export,
+    Import,
+    Sync,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 11] = [
+        AuditAction::Unlock,
+        AuditAction::FailedLogin,
+        AuditAction::MasterPasswordChange,
+        AuditAction::Reveal,
+        AuditAction::Copy,
+        AuditAction::Create,
+        AuditAction::Edit,
+        AuditAction::Delete,
+        AuditAction::Export,
+        AuditAction::Import,
+        AuditAction::Sync,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AuditAction::Unlock => "Unlocked",
+            AuditAction::FailedLogin => "Failed sign-in",
+            AuditAction::MasterPasswordChange => "Master password changed",
+            AuditAction::Reveal => "Revealed",
+            AuditAction::Copy => "Copied",
+            AuditAction::Create => "Created",
+            AuditAction::Edit => "Edited",
+            AuditAction::Delete => "Deleted",
+            AuditAction::Export => "Exported",
+            AuditAction::Import => "Imported",
+            AuditAction::Sync => "Synced",
+        }
+    }
+
+    /// The name the action is stored with, also used as the value of form options.
+    pub fn key(&self) -> &'static str {
+        match self {
+            AuditAction::Unlock => "unlock",
+            AuditAction::FailedLogin => "failed_login",
+            AuditAction::MasterPasswordChange => "master_password_change",
+            AuditAction::Reveal => "reveal",
+            AuditAction::Copy => "copy",
+            AuditAction::Create => "create",
+            AuditAction::Edit => "edit",
+            AuditAction::Delete => "delete",
+            AuditAction::Export => "export",
+            AuditAction::Import => "import",
+            AuditAction::Sync => "sync",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.key() == key)
+    }
+}
+
+/// An event to record, with the entry it's about if there is one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub action: AuditAction,
+    #[serde(default)]
+    pub entry_id: Option<i32>,
+    #[serde(default)]
+    pub site: String,
+    /// More about the event, e.g. which program a password was handed to.
+    #[serde(default)]
+    pub detail: String,
+}
+
+impl AuditEvent {
+    pub fn new(action: AuditAction) -> Self {
+        Self {
+            action,
+            entry_id: None,
+            site: String::new(),
+            detail: String::new(),
+        }
+    }
+
+    pub fn for_entry(action: AuditAction, entry_id: i32, site: &str) -> Self {
+        Self {
+            entry_id: Some(entry_id),
+            site: site.to_string(),
+            ..Self::new(action)
+        }
+    }
+
+    pub fn with_detail(self, detail: impl Into<String>) -> Self {
+        Self {
+            detail: detail.into(),
+            ..self
+        }
+    }
+}
+
+/// An event as it was recorded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditRecord {
+    /// The record's place in the log, counting from 1.
+    pub sequence: i64,
+    /// Unix timestamp (seconds) of when the event happened.
+    pub recorded_at: i64,
+    pub event: AuditEvent,
+}
+
+/// A break in the log's chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainProblem {
+    /// Records were removed from the middle of the log.
+    Missing { from: i64, to: i64 },
+    /// The record was changed or can't be decrypted.
+    Changed(i64),
+    /// Records were removed from the end of the log.
+    Truncated { from: i64, to: i64 },
+    /// Records were added without the log key.
+    Added { from: i64, to: i64 },
+    /// The MAC over the last record doesn't match it.
+    HeadChanged,
+}
+
+impl fmt::Display for ChainProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainProblem::Missing { from, to } if from == to => {
+                write!(f, "Record {from} was removed")
+            }
+            ChainProblem::Missing { from, to } => write!(f, "Records {from} to {to} were removed"),
+            ChainProblem::Changed(sequence) => write!(f, "Record {sequence} was changed"),
+            ChainProblem::Truncated { from, to } => write!(
+                f,
+                "Records {from} to {to} were removed from the end of the log"
+            ),
+            ChainProblem::Added { from, to } => {
+                write!(f, "Records {from} to {to} weren't written by the app")
+            }
+            ChainProblem::HeadChanged => write!(f, "The log's last record doesn't match its MAC"),
+        }
+    }
+}
+
+/// The readable records, oldest first, and what's wrong with the log.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditLog {
+    pub records: Vec<AuditRecord>,
+    pub problems: Vec<ChainProblem>,
+}
+
+impl AuditLog {
+    pub fn is_intact(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Which records to show, every one by default.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditFilter {
+    pub action: Option<AuditAction>,
+    /// Text the site or detail contains, ignoring case.
+    pub text: String,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, record: &AuditRecord) -> bool {
+        let text = self.text.trim().to_lowercase();
+        self.action
+            .is_none_or(|action| action == record.event.action)
+            && (text.is_empty()
+                || record.event.site.to_lowercase().contains(&text)
+                || record.event.detail.to_lowercase().contains(&text))
+    }
+}
+
+/// Why the log couldn't be written or read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditError {
+    NotSignedIn,
+    /// The log key doesn't decrypt with the master password.
+    WrongKey,
+    /// The log key is damaged or missing.
+    Corrupt(String),
+    Database(QueryError),
+}
+
+impl fmt::Display for AuditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditError::NotSignedIn => {
+                write!(f, "You must be signed in to access the activity log")
+            }
+            AuditError::WrongKey => write!(
+                f,
+                "The activity log's key couldn't be decrypted with the master password"
+            ),
+            AuditError::Corrupt(message) => write!(f, "The activity log is damaged: {message}"),
+            AuditError::Database(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for AuditError {}
+
+impl ServiceError for AuditError {
+    fn code(&self) -> &'static str {
+        match self {
+            AuditError::NotSignedIn => "audit.not_signed_in",
+            AuditError::WrongKey => "audit.wrong_key",
+            AuditError::Corrupt(_) => "audit.corrupt",
+            AuditError::Database(err) => err.code(),
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            AuditError::NotSignedIn => "Not signed in",
+            AuditError::WrongKey => "Wrong key",
+            AuditError::Corrupt(_) => "Damaged activity log",
+            AuditError::Database(err) => err.title(),
+        }
+    }
+}
+
+impl From<CryptoError> for AuditError {
+    fn from(err: CryptoError) -> Self {
+        match err {
+            CryptoError::WrongKey => AuditError::WrongKey,
+            CryptoError::Malformed(message) | CryptoError::Encrypt(message) => {
+                AuditError::Corrupt(message)
+            }
+        }
+    }
+}
+
+impl From<QueryError> for AuditError {
+    fn from(err: QueryError) -> Self {
+        AuditError::Database(err)
+    }
+}
+
+impl From<sqlx::Error> for AuditError {
+    fn from(err: sqlx::Error) -> Self {
+        AuditError::Database(err.into())
+    }
+}
+
+impl From<AuditError> for String {
+    fn from(err: AuditError) -> Self {
+        err.to_string()
+    }
+}
+
+/// Append an event to the log, after any that were kept while the vault was locked.
+pub async fn record(
+    event: AuditEvent,
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<(), AuditError> {
+    if !auth_state.signed_in {
+        return Err(AuditError::NotSignedIn);
+    }
+
+    // Appending reads the last record, so others have to wait until this one is written.
+    let mut tx = db_service.pool.begin_with("begin immediate").await?;
+    let keys = log_keys(auth_state, &mut tx).await?;
+
+    let pending = sqlx::query("select recorded_at, action from audit_pending order by id;")
+        .fetch_all(&mut *tx)
+        .await?;
+    for row in &pending {
+        let action: String = row.try_get("action")?;
+        if let Some(action) = AuditAction::from_key(&action) {
+            append(
+                &keys,
+                &AuditEvent::new(action),
+                row.try_get("recorded_at")?,
+                &mut tx,
+            )
+            .await?;
+        }
+    }
+    sqlx::query("delete from audit_pending;")
+        .execute(&mut *tx)
+        .await?;
+
+    append(&keys, &event, unix_now(), &mut tx).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Keep an event that happened while the vault is locked until it can be added to the log.
+pub async fn record_while_locked(
+    action: AuditAction,
+    db_service: &DatabaseService,
+) -> Result<(), AuditError> {
+    sqlx::query("insert into audit_pending (recorded_at, action) values (?, ?);")
+        .bind(unix_now())
+        .bind(action.key())
+        .execute(&db_service.pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Encrypt the log key with the new master password, in the transaction that changes it.
+pub async fn change_key_in(
+    auth_state: &AuthState,
+    new_auth_state: &AuthState,
+    conn: &mut SqliteConnection,
+) -> Result<(), AuditError> {
+    let Some(encrypted_key) =
+        sqlx::query_scalar::<_, String>("select encrypted_key from audit_head where id = 1;")
+            .fetch_optional(&mut *conn)
+            .await?
+    else {
+        return Ok(());
+    };
+
+    let log_key = Zeroizing::new(models::decrypt(&auth_state.cipher(), &encrypted_key)?);
+    sqlx::query("update audit_head set encrypted_key = ? where id = 1;")
+        .bind(models::encrypt(&new_auth_state.cipher(), &log_key)?)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Decrypt the log and check its chain.
+pub async fn load_log(
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<AuditLog, AuditError> {
+    if !auth_state.signed_in {
+        return Err(AuditError::NotSignedIn);
+    }
+
+    let mut conn = db_service.pool.acquire().await?;
+    let head = sqlx::query("select encrypted_key, sequence, mac from audit_head where id = 1;")
+        .fetch_optional(&mut *conn)
+        .await?;
+    let rows =
+        sqlx::query("select sequence, recorded_at, record, mac from audit_log order by sequence;")
+            .fetch_all(&mut *conn)
+            .await?;
+
+    let Some(head) = head else {
+        if rows.is_empty() {
+            return Ok(AuditLog::default());
+        }
+        return Err(AuditError::Corrupt("its key was removed".into()));
+    };
+    let keys = LogKeys::decrypt(auth_state, &head.try_get::<String, _>("encrypted_key")?)?;
+
+    let mut log = AuditLog::default();
+    let mut previous_mac = Vec::new();
+    let mut next_sequence = 1;
+
+    for row in &rows {
+        let sequence: i64 = row.try_get("sequence")?;
+        let recorded_at: i64 = row.try_get("recorded_at")?;
+        let record: String = row.try_get("record")?;
+        let mac = BASE64_STANDARD
+            .decode(row.try_get::<String, _>("mac")?)
+            .unwrap_or_default();
+
+        // The record after a gap can't be checked against the removed one before it, the chain
+        // picks up again from its MAC.
+        let intact = if sequence != next_sequence {
+            log.problems.push(ChainProblem::Missing {
+                from: next_sequence,
+                to: sequence - 1,
+            });
+            true
+        } else {
+            keys.record_mac(&previous_mac, sequence, recorded_at, &record) == mac
+        };
+
+        match keys.decrypt_event(&record).filter(|_| intact) {
+            Some(event) => log.records.push(AuditRecord {
+                sequence,
+                recorded_at,
+                event,
+            }),
+            None => log.problems.push(ChainProblem::Changed(sequence)),
+        }
+
+        previous_mac = mac;
+        next_sequence = sequence + 1;
+    }
+
+    let last_sequence = next_sequence - 1;
+    let head_sequence: i64 = head.try_get("sequence")?;
+    let head_mac = BASE64_STANDARD
+        .decode(head.try_get::<String, _>("mac")?)
+        .unwrap_or_default();
+
+    if head_sequence > last_sequence {
+        log.problems.push(ChainProblem::Truncated {
+            from: last_sequence + 1,
+            to: head_sequence,
+        });
+    } else if head_sequence < last_sequence {
+        log.problems.push(ChainProblem::Added {
+            from: head_sequence + 1,
+            to: last_sequence,
+        });
+    } else if keys.head_mac(head_sequence, &previous_mac) != head_mac {
+        log.problems.push(ChainProblem::HeadChanged);
+    }
+
+    Ok(log)
+}
+
+/// The keys records are encrypted and chained with, derived from the log key.
+struct LogKeys {
+    cipher: Aes256Gcm,
+    mac_key: Zeroizing<[u8; 32]>,
+}
+
+impl LogKeys {
+    fn derive(log_key: &[u8]) -> Self {
+        let cipher_key = hmac_sha256(log_key, &[b"audit record"]);
+        Self {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(cipher_key.as_slice())),
+            mac_key: hmac_sha256(log_key, &[b"audit chain"]),
+        }
+    }
+
+    fn decrypt(auth_state: &AuthState, encrypted_key: &str) -> Result<Self, AuditError> {
+        let log_key = Zeroizing::new(models::decrypt(&auth_state.cipher(), encrypted_key)?);
+        if log_key.len() != 32 {
+            return Err(AuditError::Corrupt("its key has the wrong length".into()));
+        }
+        Ok(Self::derive(&log_key))
+    }
+
+    fn record_mac(
+        &self,
+        previous_mac: &[u8],
+        sequence: i64,
+        recorded_at: i64,
+        record: &str,
+    ) -> Vec<u8> {
+        hmac_sha256(
+            self.mac_key.as_slice(),
+            &[
+                previous_mac,
+                &sequence.to_be_bytes(),
+                &recorded_at.to_be_bytes(),
+                record.as_bytes(),
+            ],
+        )
+        .to_vec()
+    }
+
+    fn head_mac(&self, sequence: i64, last_mac: &[u8]) -> Vec<u8> {
+        hmac_sha256(
+            self.mac_key.as_slice(),
+            &[b"head", &sequence.to_be_bytes(), last_mac],
+        )
+        .to_vec()
+    }
+
+    fn decrypt_event(&self, record: &str) -> Option<AuditEvent> {
+        let json = Zeroizing::new(models::decrypt(&self.cipher, record).ok()?);
+        serde_json::from_slice(&json).ok()
+    }
+}
+
+/// The log's keys, creating the log key the first time something is recorded.
+async fn log_keys(
+    auth_state: &AuthState,
+    conn: &mut SqliteConnection,
+) -> Result<LogKeys, AuditError> {
+    let encrypted_key =
+        sqlx::query_scalar::<_, String>("select encrypted_key from audit_head where id = 1;")
+            .fetch_optional(&mut *conn)
+            .await?;
+
+    if let Some(encrypted_key) = encrypted_key {
+        return LogKeys::decrypt(auth_state, &encrypted_key);
+    }
+
+    let mut log_key = Zeroizing::new([0u8; 32]);
+    OsRng.fill_bytes(log_key.as_mut_slice());
+    let keys = LogKeys::derive(log_key.as_slice());
+
+    sqlx::query("insert into audit_head (id, encrypted_key, sequence, mac) values (1, ?, 0, ?);")
+        .bind(models::encrypt(&auth_state.cipher(), log_key.as_slice())?)
+        .bind(BASE64_STANDARD.encode(keys.head_mac(0, &[])))
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(keys)
+}
+
+async fn append(
+    keys: &LogKeys,
+    event: &AuditEvent,
+    recorded_at: i64,
+    conn: &mut SqliteConnection,
+) -> Result<(), AuditError> {
+    let last = sqlx::query("select sequence, mac from audit_log order by sequence desc limit 1;")
+        .fetch_optional(&mut *conn)
+        .await?;
+    let (sequence, previous_mac) = match last {
+        Some(row) => (
+            row.try_get::<i64, _>("sequence")? + 1,
+            BASE64_STANDARD
+                .decode(row.try_get::<String, _>("mac")?)
+                .map_err(|err| AuditError::Corrupt(err.to_string()))?,
+        ),
+        None => (1, Vec::new()),
+    };
+
+    let json = Zeroizing::new(
+        serde_json::to_vec(event).map_err(|err| AuditError::Corrupt(err.to_string()))?,
+    );
+    let record = models::encrypt(&keys.cipher, &json)?;
+    let mac = keys.record_mac(&previous_mac, sequence, recorded_at, &record);
+
+    sqlx::query("insert into audit_log (sequence, recorded_at, record, mac) values (?, ?, ?, ?);")
+        .bind(sequence)
+        .bind(recorded_at)
+        .bind(record)
+        .bind(BASE64_STANDARD.encode(&mac))
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("update audit_head set sequence = ?, mac = ? where id = 1;")
+        .bind(sequence)
+        .bind(BASE64_STANDARD.encode(keys.head_mac(sequence, &mac)))
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> Zeroizing<[u8; 32]> {
+    let mut mac =
+        <HmacSha256 as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    for part in parts {
+        mac.update(part);
+    }
+    Zeroizing::new(mac.finalize().into_bytes().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A vault whose log has a failed sign-in, an unlock and a copy, in that order.
+    async fn logged_vault() -> (tempfile::TempDir, AuthState, DatabaseService) {
+        let (dir, db_service) = DatabaseService::temporary().await;
+        let auth_state = AuthState::from_key_material(Zeroizing::new([7; 32]));
+
+        record_while_locked(AuditAction::FailedLogin, &db_service)
+            .await
+            .unwrap();
+        record(
+            AuditEvent::new(AuditAction::Unlock),
+            &auth_state,
+            &db_service,
+        )
+        .await
+        .unwrap();
+        record(
+            AuditEvent::for_entry(AuditAction::Copy, 1, "example.com").with_detail("Password"),
+            &auth_state,
+            &db_service,
+        )
+        .await
+        .unwrap();
+
+        (dir, auth_state, db_service)
+    }
+
+    async fn execute(db_service: &DatabaseService, sql: &str) {
+        sqlx::query(sql).execute(&db_service.pool).await.unwrap();
+    }
+
+    async fn problems(auth_state: &AuthState, db_service: &DatabaseService) -> Vec<ChainProblem> {
+        load_log(auth_state, db_service).await.unwrap().problems
+    }
+
+    #[tokio::test]
+    async fn an_untouched_log_is_intact() {
+        let (_dir, auth_state, db_service) = logged_vault().await;
+
+        let log = load_log(&auth_state, &db_service).await.unwrap();
+        assert!(log.is_intact(), "{:?}", log.problems);
+        let actions: Vec<_> = log
+            .records
+            .iter()
+            .map(|record| record.event.action)
+            .collect();
+        assert_eq!(
+            actions,
+            [
+                AuditAction::FailedLogin,
+                AuditAction::Unlock,
+                AuditAction::Copy
+            ]
+        );
+        assert_eq!(log.records[2].sequence, 3);
+        assert_eq!(log.records[2].event.site, "example.com");
+        assert_eq!(log.records[2].event.detail, "Password");
+
+        // The log key is encrypted again with a new master password, the records aren't.
+        let new_auth_state = AuthState::from_key_material(Zeroizing::new([9; 32]));
+        let mut conn = db_service.pool.acquire().await.unwrap();
+        change_key_in(&auth_state, &new_auth_state, &mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+        assert_eq!(load_log(&new_auth_state, &db_service).await.unwrap(), log);
+    }
+
+    #[tokio::test]
+    async fn finds_removed_records() {
+        let (_dir, auth_state, db_service) = logged_vault().await;
+        execute(&db_service, "delete from audit_log where sequence = 2;").await;
+
+        let log = load_log(&auth_state, &db_service).await.unwrap();
+        assert_eq!(log.problems, [ChainProblem::Missing { from: 2, to: 2 }]);
+        let sequences: Vec<_> = log.records.iter().map(|record| record.sequence).collect();
+        assert_eq!(sequences, [1, 3]);
+
+        let (_dir, auth_state, db_service) = logged_vault().await;
+        execute(&db_service, "delete from audit_log where sequence = 3;").await;
+        assert_eq!(
+            problems(&auth_state, &db_service).await,
+            [ChainProblem::Truncated { from: 3, to: 3 }]
+        );
+    }
+
+    #[tokio::test]
+    async fn finds_changed_records() {
+        let (_dir, auth_state, db_service) = logged_vault().await;
+        execute(
+            &db_service,
+            "update audit_log set recorded_at = recorded_at - 3600 where sequence = 2;",
+        )
+        .await;
+        assert_eq!(
+            problems(&auth_state, &db_service).await,
+            [ChainProblem::Changed(2)]
+        );
+
+        // A record swapped for another one that was validly encrypted still breaks the chain.
+        let (_dir, auth_state, db_service) = logged_vault().await;
+        execute(
+            &db_service,
+            "update audit_log set record = (select record from audit_log where sequence = 3) where sequence = 2;",
+        )
+        .await;
+        let log = load_log(&auth_state, &db_service).await.unwrap();
+        assert_eq!(log.problems, [ChainProblem::Changed(2)]);
+        assert_eq!(log.records.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn finds_records_added_without_the_key() {
+        let (_dir, auth_state, db_service) = logged_vault().await;
+        execute(
+            &db_service,
+            "insert into audit_log (sequence, recorded_at, record, mac) select 4, recorded_at, record, mac from audit_log where sequence = 3;",
+        )
+        .await;
+        assert_eq!(
+            problems(&auth_state, &db_service).await,
+            [
+                ChainProblem::Changed(4),
+                ChainProblem::Added { from: 4, to: 4 }
+            ]
+        );
+
+        let (_dir, auth_state, db_service) = logged_vault().await;
+        execute(
+            &db_service,
+            "update audit_head set mac = (select mac from audit_log where sequence = 3);",
+        )
+        .await;
+        assert_eq!(
+            problems(&auth_state, &db_service).await,
+            [ChainProblem::HeadChanged]
+        );
+    }
+}
@@ -1,8 +1,19 @@
-use std::time::Instant;
+use std::{fmt, time::Instant};
 
 use crate::{
     models::AuthState,
-    services::{database::DatabaseService, password_entry},
+    services::{
+        agent,
+        audit::{self, AuditAction, AuditError, AuditEvent},
+        backup::{self, BackupReason},
+        database::{DatabaseService, QueryError},
+        error::ServiceError,
+        integrity,
+        password_entry::{self, EntryError, EntryProblem},
+        password_strength, settings,
+        sync::{self, SyncError},
+        sync_client, vault_lock,
+    },
 };
 use argon2::{
     password_hash::{rand_core::OsRng, SaltString},
@@ -16,6 +27,181 @@ use zeroize::Zeroizing;
 pub enum LoginError {
     IncorrectPassword,
     HashingError(String),
+    Database(QueryError),
+}
+
+impl fmt::Display for LoginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoginError::IncorrectPassword => write!(f, "Incorrect master password"),
+            LoginError::HashingError(message) => {
+                write!(f, "The saved master password can't be checked: {message}")
+            }
+            LoginError::Database(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for LoginError {}
+
+impl ServiceError for LoginError {
+    fn code(&self) -> &'static str {
+        match self {
+            LoginError::IncorrectPassword => "login.incorrect_password",
+            LoginError::HashingError(_) => "login.hashing",
+            LoginError::Database(err) => err.code(),
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            LoginError::IncorrectPassword => "Incorrect password",
+            LoginError::HashingError(_) => "Sign-in failed",
+            LoginError::Database(err) => err.title(),
+        }
+    }
+}
+
+impl From<LoginError> for String {
+    fn from(err: LoginError) -> Self {
+        err.to_string()
+    }
+}
+
+/// Why the master password couldn't be set or changed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuthError {
+    /// Changing the master password needs the current one.
+    NotSignedIn,
+    /// The new master password scored below the vault's minimum strength.
+    TooWeak {
+        score: u8,
+        required: u8,
+    },
+    /// The vault couldn't be backed up before its entries were re-encrypted.
+    Backup(String),
+    /// Entries that can't be decrypted would be lost, as they can't be re-encrypted with the new
+    /// password. They need to be quarantined first.
+    DamagedEntries(Vec<EntryProblem>),
+    Entry(EntryError),
+    Hashing(String),
+    /// The activity log's key couldn't be encrypted with the new password.
+    Audit(AuditError),
+    /// What's kept of the last sync couldn't be encrypted with the new password.
+    Sync(SyncError),
+    Database(QueryError),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::NotSignedIn => {
+                write!(f, "You must be signed in to change the master password")
+            }
+            AuthError::TooWeak { score, required } => write!(
+                f,
+                "The master password is {}, it needs to be at least {}",
+                password_strength::score_label(*score).to_lowercase(),
+                password_strength::score_label(*required).to_lowercase()
+            ),
+            AuthError::Backup(message) => write!(
+                f,
+                "Could not back up the vault before changing the master password: {message}"
+            ),
+            AuthError::DamagedEntries(problems) => {
+                let entries: Vec<String> = problems
+                    .iter()
+                    .map(|problem| {
+                        if problem.site.is_empty() {
+                            format!("entry {}", problem.id)
+                        } else {
+                            format!("{} (entry {})", problem.site, problem.id)
+                        }
+                    })
+                    .collect();
+                write!(
+                    f,
+                    "These entries can't be decrypted and would be lost with the old master password: {}. Quarantine them with `pw check --quarantine` or on the Check Vault page first",
+                    entries.join(", ")
+                )
+            }
+            AuthError::Entry(err) => err.fmt(f),
+            AuthError::Hashing(message) => {
+                write!(f, "Could not hash the master password: {message}")
+            }
+            AuthError::Audit(err) => err.fmt(f),
+            AuthError::Sync(err) => err.fmt(f),
+            AuthError::Database(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+impl ServiceError for AuthError {
+    fn code(&self) -> &'static str {
+        match self {
+            AuthError::NotSignedIn => "auth.not_signed_in",
+            AuthError::TooWeak { .. } => "auth.too_weak",
+            AuthError::Backup(_) => "auth.backup",
+            AuthError::DamagedEntries(_) => "auth.damaged_entries",
+            AuthError::Entry(err) => err.code(),
+            AuthError::Hashing(_) => "auth.hashing",
+            AuthError::Audit(err) => err.code(),
+            AuthError::Sync(err) => err.code(),
+            AuthError::Database(err) => err.code(),
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            AuthError::NotSignedIn => "Not signed in",
+            AuthError::TooWeak { .. } => "Master password too weak",
+            AuthError::Backup(_) => "Backup failed",
+            AuthError::DamagedEntries(_) => "Damaged entries",
+            AuthError::Entry(err) => err.title(),
+            AuthError::Hashing(_) => "Master password not set",
+            AuthError::Audit(err) => err.title(),
+            AuthError::Sync(err) => err.title(),
+            AuthError::Database(err) => err.title(),
+        }
+    }
+}
+
+impl From<EntryError> for AuthError {
+    fn from(err: EntryError) -> Self {
+        AuthError::Entry(err)
+    }
+}
+
+impl From<AuditError> for AuthError {
+    fn from(err: AuditError) -> Self {
+        AuthError::Audit(err)
+    }
+}
+
+impl From<SyncError> for AuthError {
+    fn from(err: SyncError) -> Self {
+        AuthError::Sync(err)
+    }
+}
+
+impl From<QueryError> for AuthError {
+    fn from(err: QueryError) -> Self {
+        AuthError::Database(err)
+    }
+}
+
+impl From<sqlx::Error> for AuthError {
+    fn from(err: sqlx::Error) -> Self {
+        AuthError::Database(err.into())
+    }
+}
+
+impl From<AuthError> for String {
+    fn from(err: AuthError) -> Self {
+        err.to_string()
+    }
 }
 
 pub async fn login(
@@ -25,7 +211,7 @@ pub async fn login(
 ) -> Result<AuthState, LoginError> {
     let (saved_hash, key_derivation_salt) = get_master_password_hash(&db_service.pool)
         .await
-        .expect("Could not get master password hash from the database");
+        .map_err(|err| LoginError::Database(err.into()))?;
 
     let parsed_hash = PasswordHash::new(&saved_hash)
         .map_err(|err| err.to_string())
@@ -38,69 +224,138 @@ pub async fn login(
         state.signed_in = true;
         state.raw_master_password = raw_pw;
         state.salt = Zeroizing::new(key_derivation_salt);
+        state.key_material = None;
         state.last_activity = Instant::now();
+
+        // Vaults from before notes were encrypted get them encrypted on the first sign-in.
+        password_entry::encrypt_plain_notes(&state, db_service)
+            .await
+            .map_err(|err| match err {
+                EntryError::Database(err) => LoginError::Database(err),
+                err => LoginError::Database(QueryError::Other(err.to_string())),
+            })?;
         Ok(state)
     } else {
         Err(LoginError::IncorrectPassword)
     }
 }
 
+/// [`login`] to use the vault, recording the attempt in the activity log. Reading another copy of
+/// the vault, e.g. a backup, uses [`login`] on its own.
+pub async fn sign_in(
+    raw_pw: Zeroizing<String>,
+    state: AuthState,
+    db_service: &DatabaseService,
+) -> Result<AuthState, LoginError> {
+    let result = login(raw_pw, state, db_service).await;
+
+    // Recording is best effort, a damaged log mustn't keep anyone out of the vault.
+    match &result {
+        Ok(auth_state) => {
+            let _ =
+                audit::record(AuditEvent::new(AuditAction::Unlock), auth_state, db_service).await;
+        }
+        Err(LoginError::IncorrectPassword) => {
+            let _ = audit::record_while_locked(AuditAction::FailedLogin, db_service).await;
+        }
+        Err(_) => {}
+    }
+
+    result
+}
+
 pub async fn set_master_password(
     raw_pw: Zeroizing<String>,
     auth_state: &AuthState,
     db_service: &DatabaseService,
-) -> Result<AuthState, String> {
-    let existing_password_entries =
-        password_entry::get_all_password_entries(auth_state, db_service, String::new()).await?;
+) -> Result<AuthState, AuthError> {
+    // Two processes changing the master password at once would leave the entries encrypted with
+    // either password.
+    let _lock = vault_lock::lock_vault(db_service).await?;
 
-    let hash = hash_new_master_password(&raw_pw).map_err(|err| err.to_string())?;
+    let required = settings::get_settings(db_service)
+        .await?
+        .min_master_password_score;
+    let score = password_strength::estimate(&raw_pw).score;
+    if score < required {
+        return Err(AuthError::TooWeak { score, required });
+    }
+
+    // Entries are re-encrypted with the new password, so keep a copy under the old one. Before
+    // the first master password is set there's no one signed in and nothing to re-encrypt.
+    if auth_state.signed_in {
+        backup::create_backup(BackupReason::MasterPasswordChange, db_service)
+            .await
+            .map_err(AuthError::Backup)?;
+    } else if is_master_password_set(db_service).await? {
+        return Err(AuthError::NotSignedIn);
+    }
+
+    let hash = hash_new_master_password(&raw_pw).map_err(AuthError::Hashing)?;
     let salt = SaltString::generate(&mut OsRng).to_string();
 
-    let mut tx = db_service
-        .pool
-        .begin()
-        .await
-        .map_err(|err| err.to_string())?;
+    // Taking the write lock straight away makes entries saved by others in the meantime wait
+    // until the entries are re-encrypted, instead of being left under the old password.
+    let mut tx = db_service.pool.begin_with("begin immediate").await?;
+    let existing_password_entries = if auth_state.signed_in {
+        // An entry that can't be decrypted can't be re-encrypted either, and would be left
+        // under a key that's gone.
+        let loaded = password_entry::load_all_password_entries_in(auth_state, &mut *tx).await?;
+        if !loaded.problems.is_empty() {
+            return Err(AuthError::DamagedEntries(loaded.problems));
+        }
+        loaded.entries
+    } else {
+        Vec::new()
+    };
 
     sqlx::query("update master_password set password_hash = ?, key_salt = ? where id = 1;")
         .bind(hash)
         .bind(salt.clone())
         .execute(&mut *tx)
-        .await
-        .map(|_| ())
-        .map_err(|err| err.to_string())?;
+        .await?;
 
     let new_auth_state = AuthState {
         signed_in: true,
         raw_master_password: raw_pw,
         salt: Zeroizing::new(salt),
+        key_material: None,
         last_activity: Instant::now(),
     };
 
+    let cipher = new_auth_state.cipher();
     for entry in existing_password_entries.iter() {
-        password_entry::save_updated_password(
-            entry.id,
-            entry.to_owned(),
-            &new_auth_state,
-            &mut *tx,
-        )
-        .await?;
+        let safe = entry.to_safe_with(&cipher).map_err(EntryError::from)?;
+        password_entry::reencrypt_password_entry(&safe, &mut *tx).await?;
     }
 
-    tx.commit()
-        .await
-        .map(|_| new_auth_state)
-        .map_err(|err| err.to_string())
+    if auth_state.signed_in {
+        audit::change_key_in(auth_state, &new_auth_state, &mut tx).await?;
+        sync::change_key_in(auth_state, &new_auth_state, &mut tx).await?;
+        sync_client::change_key_in(auth_state, &new_auth_state, &mut tx).await?;
+        integrity::change_key_in(auth_state, &new_auth_state, &mut tx).await?;
+    }
+
+    tx.commit().await?;
+
+    // The agent's key was derived from the old password.
+    let _ = agent::lock(&agent::socket_path(db_service)).await;
+    let _ = audit::record(
+        AuditEvent::new(AuditAction::MasterPasswordChange),
+        &new_auth_state,
+        db_service,
+    )
+    .await;
+
+    Ok(new_auth_state)
 }
 
 pub fn logout() -> AuthState {
     AuthState::default()
 }
 
-pub async fn is_master_password_set(db_service: &DatabaseService) -> Result<bool, String> {
-    let (hash, _) = get_master_password_hash(&db_service.pool)
-        .await
-        .map_err(|err| err.to_string())?;
+pub async fn is_master_password_set(db_service: &DatabaseService) -> Result<bool, QueryError> {
+    let (hash, _) = get_master_password_hash(&db_service.pool).await?;
 
     Ok(!hash.is_empty())
 }
@@ -0,0 +1,332 @@
+//! Automatic snapshots of the vault database.
+//!
+//! Snapshots are taken with `VACUUM INTO`, which writes a consistent copy of the database even
+//! while it's in use, into a `backups` directory next to the database file. They're still
+//! encrypted with the master password at the time they were taken. Only the newest
+//! [`Settings::backup_retention`](crate::models::Settings) snapshots are kept.
+
+use std::{
+    cmp::Reverse,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use sqlx::{sqlite::SqliteConnectOptions, ConnectOptions, Connection};
+use zeroize::Zeroizing;
+
+use crate::{
+    models::{AuthState, PasswordEntryRaw},
+    services::{
+        authentication::{self, LoginError},
+        database::DatabaseService,
+        password_entry, settings,
+        vault_file::{self, ImportMode, ImportSummary},
+        vault_lock,
+    },
+};
+
+pub const BACKUP_DIR_NAME: &str = "backups";
+
+const BACKUP_EXTENSION: &str = "sqlite";
+/// The files SQLite may keep next to a database file.
+const JOURNAL_SUFFIXES: [&str; 3] = ["-wal", "-shm", "-journal"];
+
+/// Why a backup was taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackupReason {
+    Unlock,
+    MasterPasswordChange,
+    Import,
+    Restore,
+    Sync,
+}
+
+impl BackupReason {
+    pub const ALL: [BackupReason; 5] = [
+        BackupReason::Unlock,
+        BackupReason::MasterPasswordChange,
+        BackupReason::Import,
+        BackupReason::Restore,
+        BackupReason::Sync,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            BackupReason::Unlock => "Unlocked",
+            BackupReason::MasterPasswordChange => "Before master password change",
+            BackupReason::Import => "Before import",
+            BackupReason::Restore => "Before restore",
+            BackupReason::Sync => "Before sync",
+        }
+    }
+
+    /// A stable name for the reason, used in backup file names.
+    pub fn key(&self) -> &'static str {
+        match self {
+            BackupReason::Unlock => "unlock",
+            BackupReason::MasterPasswordChange => "password-change",
+            BackupReason::Import => "import",
+            BackupReason::Restore => "restore",
+            BackupReason::Sync => "sync",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|reason| reason.key() == key)
+    }
+}
+
+/// A backup found in the backups directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackupInfo {
+    pub path: PathBuf,
+    /// Unix timestamp in seconds.
+    pub created_at: i64,
+    pub reason: BackupReason,
+    /// `None` when the backup couldn't be opened.
+    pub entry_count: Option<i64>,
+}
+
+pub fn backup_dir(db_service: &DatabaseService) -> PathBuf {
+    backup_dir_of(&db_service.path)
+}
+
+fn backup_dir_of(db_path: &Path) -> PathBuf {
+    db_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join(BACKUP_DIR_NAME)
+}
+
+/// Snapshot the vault and delete the oldest backups beyond the retention setting. Returns the
+/// new backup, or `None` when automatic backups are turned off.
+pub async fn create_backup(
+    reason: BackupReason,
+    db_service: &DatabaseService,
+) -> Result<Option<PathBuf>, String> {
+    let retention = settings::get_settings(db_service).await?.backup_retention;
+    if retention == 0 {
+        return Ok(None);
+    }
+
+    let dir = backup_dir(db_service);
+    std::fs::create_dir_all(&dir).map_err(|err| err.to_string())?;
+
+    // Milliseconds so that backups taken in quick succession, e.g. on unlock and then before an
+    // import, don't collide.
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let path = dir.join(format!(
+        "{}-{millis}-{}.{BACKUP_EXTENSION}",
+        database_stem(&db_service.path),
+        reason.key()
+    ));
+
+    sqlx::query("vacuum into ?;")
+        .bind(path.display().to_string())
+        .execute(&db_service.pool)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    for old in backup_files(&db_service.path)?
+        .into_iter()
+        .skip(retention as usize)
+    {
+        remove_database_file(&old.0)?;
+    }
+
+    Ok(Some(path))
+}
+
+/// Every backup of the vault, newest first.
+pub async fn list_backups(db_service: &DatabaseService) -> Result<Vec<BackupInfo>, String> {
+    list_backups_of(&db_service.path).await
+}
+
+/// Every backup of a vault file, newest first. The vault itself isn't opened, so this works when
+/// it's damaged.
+pub async fn list_backups_of(db_path: &Path) -> Result<Vec<BackupInfo>, String> {
+    let mut backups = Vec::new();
+
+    for (path, created_at_millis, reason) in backup_files(db_path)? {
+        let entry_count = count_entries(&path).await.ok();
+        backups.push(BackupInfo {
+            path,
+            created_at: (created_at_millis / 1000) as i64,
+            reason,
+            entry_count,
+        });
+    }
+
+    Ok(backups)
+}
+
+/// Unlock a backup with the master password it was taken with and decrypt its entries. The
+/// backup itself is left untouched.
+pub async fn read_backup(
+    path: &Path,
+    raw_pw: Zeroizing<String>,
+) -> Result<Vec<PasswordEntryRaw>, String> {
+    // Backups can be older than the latest migrations, so a copy is migrated and read instead.
+    let copy = path.with_extension("restoring");
+    std::fs::copy(path, &copy).map_err(|err| err.to_string())?;
+
+    let result = read_database_copy(&copy, raw_pw).await;
+    remove_database_file(&copy)?;
+    result
+}
+
+/// Replace the vault's entries with a backup's, re-encrypted with the current master password.
+/// The vault is backed up first so that restoring can be undone.
+pub async fn restore_backup(
+    path: &Path,
+    raw_pw: Zeroizing<String>,
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<ImportSummary, String> {
+    if !auth_state.signed_in {
+        return Err("You must be signed in to access these resources".into());
+    }
+
+    let entries = read_backup(path, raw_pw).await?;
+
+    let _lock = vault_lock::lock_vault(db_service).await?;
+    create_backup(BackupReason::Restore, db_service)
+        .await
+        .map_err(|err| format!("Could not back up the vault before restoring: {err}"))?;
+
+    vault_file::restore_entries(&entries, ImportMode::Replace, auth_state, db_service).await
+}
+
+/// Put a backup in place of a vault file that can't be opened anymore. The vault then has the
+/// master password the backup was taken with. The old file is kept next to it, renamed, in case
+/// anything can still be recovered from it. Returns where it was moved to.
+pub fn replace_with_backup(backup: &Path, db_path: &Path) -> Result<Option<PathBuf>, String> {
+    let mut moved_to = None;
+    if db_path.exists() {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        let damaged = with_suffix(db_path, &format!(".damaged-{millis}"));
+
+        std::fs::rename(db_path, &damaged)
+            .map_err(|err| format!("Could not move {} aside: {err}", db_path.display()))?;
+        // The journal files belong to the old file, SQLite would apply them to the backup.
+        for suffix in JOURNAL_SUFFIXES {
+            let _ = std::fs::remove_file(with_suffix(db_path, suffix));
+        }
+        moved_to = Some(damaged);
+    }
+
+    std::fs::copy(backup, db_path)
+        .map_err(|err| format!("Could not copy the backup to {}: {err}", db_path.display()))?;
+    Ok(moved_to)
+}
+
+async fn read_database_copy(
+    path: &Path,
+    raw_pw: Zeroizing<String>,
+) -> Result<Vec<PasswordEntryRaw>, String> {
+    let backup_db = DatabaseService::open(path.to_path_buf()).await?;
+
+    let result = match authentication::is_master_password_set(&backup_db).await {
+        Ok(false) => Err("The backup was taken before a master password was set".into()),
+        Ok(true) => match authentication::login(raw_pw, AuthState::default(), &backup_db).await {
+            Ok(backup_auth_state) => password_entry::get_all_password_entries(
+                &backup_auth_state,
+                &backup_db,
+                String::new(),
+            )
+            .await
+            .map_err(String::from),
+            Err(LoginError::IncorrectPassword) => {
+                Err("The password isn't the master password the backup was taken with".into())
+            }
+            Err(err) => Err(err.into()),
+        },
+        Err(err) => Err(err.into()),
+    };
+
+    backup_db.pool.close().await;
+    result
+}
+
+async fn count_entries(path: &Path) -> Result<i64, String> {
+    let mut connection = SqliteConnectOptions::new()
+        .filename(path)
+        .read_only(true)
+        .connect()
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let count = sqlx::query_scalar("select count(*) from password_entries;")
+        .fetch_one(&mut connection)
+        .await
+        .map_err(|err| err.to_string());
+
+    let _ = connection.close().await;
+    count
+}
+
+/// The vault's backup files with when and why they were taken, newest first. Files that don't
+/// look like this vault's backups are ignored.
+fn backup_files(db_path: &Path) -> Result<Vec<(PathBuf, u128, BackupReason)>, String> {
+    let dir = backup_dir_of(db_path);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let prefix = format!("{}-", database_stem(db_path));
+    let mut files = Vec::new();
+
+    for dir_entry in std::fs::read_dir(&dir).map_err(|err| err.to_string())? {
+        let path = dir_entry.map_err(|err| err.to_string())?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(BACKUP_EXTENSION) {
+            continue;
+        }
+
+        let parsed = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.strip_prefix(&prefix))
+            .and_then(|rest| rest.split_once('-'))
+            .and_then(|(millis, reason)| {
+                Some((
+                    millis.parse::<u128>().ok()?,
+                    BackupReason::from_key(reason)?,
+                ))
+            });
+
+        if let Some((millis, reason)) = parsed {
+            files.push((path, millis, reason));
+        }
+    }
+
+    files.sort_by_key(|(_, millis, _)| Reverse(*millis));
+    Ok(files)
+}
+
+fn database_stem(db_path: &Path) -> String {
+    db_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "vault".into())
+}
+
+/// Delete a database file along with any journal files SQLite left next to it.
+fn remove_database_file(path: &Path) -> Result<(), String> {
+    for suffix in JOURNAL_SUFFIXES {
+        let _ = std::fs::remove_file(with_suffix(path, suffix));
+    }
+
+    std::fs::remove_file(path).map_err(|err| err.to_string())
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
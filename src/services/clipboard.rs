@@ -4,7 +4,7 @@ use zeroize::Zeroizing;
 
 pub fn copy_with_timeout(secret: Zeroizing<String>, timeout_secs: u64) -> String {
     let mut clipboard = Clipboard::new().unwrap();
-    clipboard.set_text(secret.to_string()).unwrap();
+    clipboard.set_text(secret.as_str()).unwrap();
 
     // Spawn a background task to clear the clipboard after `timeout_secs`
     tokio::spawn(async move {
@@ -16,3 +16,18 @@ pub fn copy_with_timeout(secret: Zeroizing<String>, timeout_secs: u64) -> String
 
     format!("Copied to clipboard for {timeout_secs} seconds...")
 }
+
+/// Copy `secret` and block until it's cleared again after `timeout_secs`, for processes that
+/// exit when they're done such as the CLI. On some platforms the clipboard is only served while
+/// the process that set it is running, so returning early would lose it.
+pub fn copy_and_wait(secret: Zeroizing<String>, timeout_secs: u64) -> Result<(), String> {
+    let mut clipboard = Clipboard::new().map_err(|err| err.to_string())?;
+    clipboard
+        .set_text(secret.as_str())
+        .map_err(|err| err.to_string())?;
+
+    std::thread::sleep(std::time::Duration::from_secs(timeout_secs));
+    clipboard
+        .set_text("".to_string())
+        .map_err(|err| err.to_string())
+}
@@ -0,0 +1,73 @@
+//! Where the vaults, their backups and the app's other files are kept.
+//!
+//! The first of these is used:
+//! 1. The directory given on the command line with `--data-dir`.
+//! 2. The `PASSWORD_MANAGER_DATA_DIR` environment variable.
+//! 3. Portable mode, turned on with `--portable` or a file called `portable` next to the
+//!    executable, keeps everything in a `data` directory next to the executable, e.g. on a USB
+//!    drive.
+//! 4. `~/.password_manager` when it already exists, where earlier versions kept everything.
+//! 5. The platform's data directory, `$XDG_DATA_HOME/password-manager` on Linux.
+
+use std::path::{Path, PathBuf};
+
+use directories::{ProjectDirs, UserDirs};
+
+pub const DATA_DIR_ENV: &str = "PASSWORD_MANAGER_DATA_DIR";
+/// Portable mode is on when a file with this name is next to the executable.
+pub const PORTABLE_MARKER_FILE_NAME: &str = "portable";
+const PORTABLE_DATA_DIR_NAME: &str = "data";
+const LEGACY_DATA_DIR_NAME: &str = ".password_manager";
+const APPLICATION_NAME: &str = "password-manager";
+
+/// The data directory, from the command-line options when they set one. Nothing is created.
+pub fn resolve_data_dir(data_dir: Option<&Path>, portable: bool) -> Result<PathBuf, String> {
+    if let Some(data_dir) = data_dir {
+        return absolute(data_dir);
+    }
+
+    if let Some(data_dir) = std::env::var_os(DATA_DIR_ENV).filter(|value| !value.is_empty()) {
+        return absolute(Path::new(&data_dir));
+    }
+
+    let executable_dir = executable_dir();
+    if let Some(executable_dir) = &executable_dir {
+        if portable || executable_dir.join(PORTABLE_MARKER_FILE_NAME).is_file() {
+            return Ok(executable_dir.join(PORTABLE_DATA_DIR_NAME));
+        }
+    } else if portable {
+        return Err(
+            "Portable mode needs the location of the executable, which couldn't be found".into(),
+        );
+    }
+
+    if let Some(user_dirs) = UserDirs::new() {
+        let legacy = user_dirs.home_dir().join(LEGACY_DATA_DIR_NAME);
+        if legacy.is_dir() {
+            return Ok(legacy);
+        }
+    }
+
+    ProjectDirs::from("", "", APPLICATION_NAME)
+        .map(|project_dirs| project_dirs.data_dir().to_path_buf())
+        .ok_or_else(|| {
+            format!(
+                "Could not find the home directory, choose where to keep the vault with --data-dir or {DATA_DIR_ENV}"
+            )
+        })
+}
+
+/// The directory the running executable is in, with symlinks resolved so that a link on the
+/// `PATH` still finds the portable data.
+fn executable_dir() -> Option<PathBuf> {
+    let executable = std::env::current_exe().ok()?;
+    let executable = executable.canonicalize().unwrap_or(executable);
+    executable.parent().map(Path::to_path_buf)
+}
+
+/// Relative directories are relative to where the command was run, not to wherever the app is
+/// later.
+fn absolute(path: &Path) -> Result<PathBuf, String> {
+    std::path::absolute(path)
+        .map_err(|err| format!("Invalid data directory {}: {err}", path.display()))
+}
@@ -1,38 +1,449 @@
-use sqlx::migrate::Migrator;
-use sqlx::sqlite::SqlitePoolOptions;
-use sqlx::SqlitePool;
-use std::path::PathBuf;
+use sqlx::migrate::{MigrateError, Migrator};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous};
+use sqlx::{Row, SqlitePool};
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::services::error::ServiceError;
 
 static MIGRATOR: Migrator = sqlx::migrate!();
 
-async fn run_migrations(pool: &SqlitePool) -> Result<(), sqlx::Error> {
-    MIGRATOR.run(pool).await?;
-    Ok(())
+/// The vault's file inside the data directory.
+pub const DATABASE_FILE_NAME: &str = "passwords.sqlite";
+
+/// Every SQLite database file starts with this.
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+/// How long a query waits for another connection or process to finish writing before it gives
+/// up with [`QueryError::Locked`].
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn run_migrations(pool: &SqlitePool) -> Result<(), MigrateError> {
+    MIGRATOR.run(pool).await
+}
+
+/// Why a database file couldn't be opened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DatabaseError {
+    /// The directory for a new database file couldn't be created.
+    CreateDirectory {
+        path: PathBuf,
+        message: String,
+    },
+    NotFound {
+        path: PathBuf,
+    },
+    /// Another program is holding a lock on the file.
+    Locked {
+        path: PathBuf,
+    },
+    /// The file isn't a database or is damaged.
+    Corrupt {
+        path: PathBuf,
+        message: String,
+    },
+    /// The file or its directory can't be read or written.
+    AccessDenied {
+        path: PathBuf,
+        message: String,
+    },
+    /// The file was last opened by a newer version of the app.
+    NewerVersion {
+        path: PathBuf,
+        version: i64,
+    },
+    Migration {
+        path: PathBuf,
+        message: String,
+    },
+    Other {
+        path: PathBuf,
+        message: String,
+    },
+}
+
+impl DatabaseError {
+    /// The database file the error is about.
+    pub fn path(&self) -> &Path {
+        match self {
+            DatabaseError::CreateDirectory { path, .. }
+            | DatabaseError::NotFound { path }
+            | DatabaseError::Locked { path }
+            | DatabaseError::Corrupt { path, .. }
+            | DatabaseError::AccessDenied { path, .. }
+            | DatabaseError::NewerVersion { path, .. }
+            | DatabaseError::Migration { path, .. }
+            | DatabaseError::Other { path, .. } => path,
+        }
+    }
+
+    fn from_sqlx(path: &Path, err: sqlx::Error) -> Self {
+        let path = path.to_path_buf();
+
+        match QueryError::from(err) {
+            QueryError::Locked => DatabaseError::Locked { path },
+            QueryError::Corrupt(message) => DatabaseError::Corrupt { path, message },
+            QueryError::AccessDenied(_) if !path.exists() => DatabaseError::NotFound { path },
+            QueryError::AccessDenied(message) => DatabaseError::AccessDenied { path, message },
+            QueryError::Other(message) => DatabaseError::Other { path, message },
+        }
+    }
+
+    fn from_migrate(path: &Path, err: MigrateError) -> Self {
+        match err {
+            MigrateError::Execute(err) | MigrateError::ExecuteMigration(err, _) => {
+                match Self::from_sqlx(path, err) {
+                    DatabaseError::Other { path, message } => {
+                        DatabaseError::Migration { path, message }
+                    }
+                    err => err,
+                }
+            }
+            // A migration this version doesn't know about has been applied.
+            MigrateError::VersionMissing(version) => DatabaseError::NewerVersion {
+                path: path.to_path_buf(),
+                version,
+            },
+            err => DatabaseError::Migration {
+                path: path.to_path_buf(),
+                message: err.to_string(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = self.path().display();
+        match self {
+            DatabaseError::CreateDirectory { message, .. } => {
+                write!(f, "Could not create the folder for {path}: {message}")
+            }
+            DatabaseError::NotFound { .. } => write!(f, "{path} doesn't exist"),
+            DatabaseError::Locked { .. } => {
+                write!(f, "{path} is locked by another program")
+            }
+            DatabaseError::Corrupt { message, .. } => {
+                write!(f, "{path} is damaged or isn't a vault: {message}")
+            }
+            DatabaseError::AccessDenied { message, .. } => {
+                write!(f, "{path} can't be read or written: {message}")
+            }
+            DatabaseError::NewerVersion { version, .. } => write!(
+                f,
+                "{path} was opened by a newer version of the app, which upgraded it to version {version}"
+            ),
+            DatabaseError::Migration { message, .. } => {
+                write!(f, "Could not upgrade {path}: {message}")
+            }
+            DatabaseError::Other { message, .. } => {
+                write!(f, "Could not open {path}: {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {}
+
+impl ServiceError for DatabaseError {
+    fn code(&self) -> &'static str {
+        match self {
+            DatabaseError::CreateDirectory { .. } => "database.create_directory",
+            DatabaseError::NotFound { .. } => "database.not_found",
+            DatabaseError::Locked { .. } => "database.locked",
+            DatabaseError::Corrupt { .. } => "database.corrupt",
+            DatabaseError::AccessDenied { .. } => "database.access_denied",
+            DatabaseError::NewerVersion { .. } => "database.newer_version",
+            DatabaseError::Migration { .. } => "database.migration",
+            DatabaseError::Other { .. } => "database.open",
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            DatabaseError::CreateDirectory { .. } => "The vault's folder couldn't be created",
+            DatabaseError::NotFound { .. } => "The vault file is missing",
+            DatabaseError::Locked { .. } => "The vault is in use",
+            DatabaseError::Corrupt { .. } => "The vault file is damaged",
+            DatabaseError::AccessDenied { .. } => "The vault file can't be accessed",
+            DatabaseError::NewerVersion { .. } => "The vault needs a newer version of the app",
+            DatabaseError::Migration { .. } => "The vault couldn't be upgraded",
+            DatabaseError::Other { .. } => "The vault couldn't be opened",
+        }
+    }
+}
+
+impl From<DatabaseError> for String {
+    fn from(err: DatabaseError) -> Self {
+        err.to_string()
+    }
+}
+
+/// A query on an open vault that failed, told apart by what can be done about it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    /// Another program is holding a lock on the vault, trying again later may work.
+    Locked,
+    /// The vault file is damaged.
+    Corrupt(String),
+    /// The vault file can't be written, e.g. because it's read-only.
+    AccessDenied(String),
+    Other(String),
+}
+
+impl From<sqlx::Error> for QueryError {
+    fn from(err: sqlx::Error) -> Self {
+        // The primary result code is the low byte of SQLite's extended result codes.
+        let code = err
+            .as_database_error()
+            .and_then(|db_err| db_err.code())
+            .and_then(|code| code.parse::<i32>().ok())
+            .map(|code| code & 0xff);
+        let message = err.to_string();
+
+        match code {
+            // SQLITE_BUSY and SQLITE_LOCKED
+            Some(5 | 6) => QueryError::Locked,
+            // SQLITE_CORRUPT and SQLITE_NOTADB
+            Some(11 | 26) => QueryError::Corrupt(message),
+            // SQLITE_PERM, SQLITE_READONLY and SQLITE_CANTOPEN
+            Some(3 | 8 | 14) => QueryError::AccessDenied(message),
+            _ => QueryError::Other(message),
+        }
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::Locked => write!(
+                f,
+                "The vault is locked by another program, try again once it's done"
+            ),
+            QueryError::Corrupt(message) => write!(f, "The vault file is damaged: {message}"),
+            QueryError::AccessDenied(message) => {
+                write!(f, "The vault file can't be written: {message}")
+            }
+            QueryError::Other(message) => write!(f, "The vault couldn't be read: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl ServiceError for QueryError {
+    fn code(&self) -> &'static str {
+        match self {
+            QueryError::Locked => "database.locked",
+            QueryError::Corrupt(_) => "database.corrupt",
+            QueryError::AccessDenied(_) => "database.access_denied",
+            QueryError::Other(_) => "database.query",
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            QueryError::Locked => "The vault is in use",
+            QueryError::Corrupt(_) => "The vault file is damaged",
+            QueryError::AccessDenied(_) => "The vault file can't be written",
+            QueryError::Other(_) => "The vault couldn't be read",
+        }
+    }
+}
+
+impl From<QueryError> for String {
+    fn from(err: QueryError) -> Self {
+        err.to_string()
+    }
+}
+
+/// How a vault's schema compares to the migrations of this version of the app.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchemaStatus {
+    /// The newest migration applied to the vault.
+    pub version: Option<i64>,
+    /// The newest migration this version of the app has.
+    pub expected: i64,
+    /// Migrations this version has that aren't applied to the vault.
+    pub missing: Vec<i64>,
+    /// Applied migrations this version doesn't have, from a newer version.
+    pub unknown: Vec<i64>,
+    /// Applied migrations that failed part way or aren't the same as this version's.
+    pub broken: Vec<i64>,
+}
+
+impl SchemaStatus {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.unknown.is_empty() && self.broken.is_empty()
+    }
+}
+
+/// Facts about a database file that help to tell why it can't be opened.
+pub fn diagnose(path: &Path) -> Vec<(&'static str, String)> {
+    let mut facts = vec![("File", path.display().to_string())];
+
+    match std::fs::metadata(path) {
+        Ok(metadata) => {
+            facts.push(("Size", format!("{} bytes", metadata.len())));
+            let read_only = if metadata.permissions().readonly() {
+                "yes"
+            } else {
+                "no"
+            };
+            facts.push(("Read-only", read_only.into()));
+
+            let mut header = [0; SQLITE_HEADER.len()];
+            let header = std::fs::File::open(path)
+                .and_then(|mut file| file.read_exact(&mut header))
+                .map(|_| {
+                    if &header == SQLITE_HEADER {
+                        "SQLite database".to_string()
+                    } else {
+                        "not an SQLite database".to_string()
+                    }
+                })
+                .unwrap_or_else(|err| format!("couldn't be read: {err}"));
+            facts.push(("Contents", header));
+        }
+        Err(err) => facts.push(("Exists", format!("no ({err})"))),
+    }
+
+    let journals: Vec<&str> = ["-wal", "-shm", "-journal"]
+        .into_iter()
+        .filter(|suffix| {
+            let mut journal = path.as_os_str().to_owned();
+            journal.push(suffix);
+            Path::new(&journal).exists()
+        })
+        .collect();
+    facts.push((
+        "Journal files",
+        if journals.is_empty() {
+            "none".into()
+        } else {
+            journals.join(", ")
+        },
+    ));
+    facts.push(("App version", env!("CARGO_PKG_VERSION").into()));
+
+    facts
 }
 
 #[derive(Debug, Clone)]
 pub struct DatabaseService {
     pub pool: SqlitePool,
+    /// The database file, backups are kept next to it.
+    pub path: PathBuf,
 }
 
 impl DatabaseService {
-    pub async fn new(path: PathBuf) -> Self {
-        // Ensure parent directory exists
+    /// Open a database file and migrate it, creating it and its directory first when needed.
+    pub async fn create(path: PathBuf) -> Result<Self, DatabaseError> {
         if let Some(parent) = path.parent() {
-            std::fs::create_dir_all(parent).expect("Failed to create database directory");
+            std::fs::create_dir_all(parent).map_err(|err| DatabaseError::CreateDirectory {
+                path: path.clone(),
+                message: err.to_string(),
+            })?;
         }
 
-        let url = format!("sqlite://{}?mode=rwc", path.display());
+        Self::connect(path, true).await
+    }
+
+    /// Open an existing database file and migrate it, e.g. a copy of a backup.
+    pub async fn open(path: PathBuf) -> Result<Self, DatabaseError> {
+        if !path.is_file() {
+            return Err(DatabaseError::NotFound { path });
+        }
+
+        Self::connect(path, false).await
+    }
+
+    async fn connect(path: PathBuf, create: bool) -> Result<Self, DatabaseError> {
+        // With write-ahead logging, reading doesn't block writing, so the app, the CLI and the
+        // agent can have the vault open at the same time. Writers take turns, waiting for each
+        // other up to the busy timeout.
+        let options = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(create)
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal)
+            .busy_timeout(BUSY_TIMEOUT);
 
         let pool = SqlitePoolOptions::new()
-            .connect(&url)
+            .connect_with(options)
             .await
-            .expect("Cannot open or create the database file");
+            .map_err(|err| DatabaseError::from_sqlx(&path, err))?;
 
-        run_migrations(&pool)
-            .await
-            .expect("could not run database migrations");
+        // SQLite only reads the file on the first query, so a damaged or locked file is usually
+        // only noticed here.
+        if let Err(err) = run_migrations(&pool).await {
+            pool.close().await;
+            return Err(DatabaseError::from_migrate(&path, err));
+        }
+
+        Ok(Self { pool, path })
+    }
+
+    /// SQLite's own check of the file, empty when it found nothing wrong.
+    pub async fn integrity_problems(&self) -> Result<Vec<String>, QueryError> {
+        let messages: Vec<String> = sqlx::query_scalar("pragma integrity_check;")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(messages
+            .into_iter()
+            .filter(|message| message != "ok")
+            .collect())
+    }
 
-        Self { pool }
+    /// Compare the migrations applied to the vault with this version's.
+    pub async fn schema_status(&self) -> Result<SchemaStatus, QueryError> {
+        let rows = sqlx::query("select version, success, checksum from _sqlx_migrations;")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut status = SchemaStatus {
+            expected: MIGRATOR.iter().map(|m| m.version).max().unwrap_or_default(),
+            ..Default::default()
+        };
+
+        let mut applied = Vec::with_capacity(rows.len());
+        for row in &rows {
+            let version: i64 = row.try_get("version")?;
+            applied.push(version);
+            let success: bool = row.try_get("success")?;
+            let checksum: Vec<u8> = row.try_get("checksum")?;
+
+            status.version = status.version.max(Some(version));
+            match MIGRATOR.iter().find(|m| m.version == version) {
+                None => status.unknown.push(version),
+                Some(migration) if !success || *migration.checksum != *checksum => {
+                    status.broken.push(version)
+                }
+                Some(_) => {}
+            }
+        }
+
+        status.missing = MIGRATOR
+            .iter()
+            .map(|m| m.version)
+            .filter(|version| !applied.contains(version))
+            .collect();
+
+        Ok(status)
+    }
+}
+
+#[cfg(test)]
+impl DatabaseService {
+    /// A new, empty vault in a directory of its own, deleted when the
+    /// returned guard is dropped.
+    pub async fn temporary() -> (tempfile::TempDir, Self) {
+        let dir = tempfile::tempdir().unwrap();
+        let db_service = Self::create(dir.path().join(DATABASE_FILE_NAME))
+            .await
+            .unwrap();
+        (dir, db_service)
     }
 }
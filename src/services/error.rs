@@ -0,0 +1,16 @@
+//! What the services' error types have in common.
+//!
+//! Each service has an error enum of its own so that views can tell failures apart, e.g. an entry
+//! that was encrypted with another key from one that is damaged. Their `Display` text is the
+//! message for the user. Services that only report failures to be shown still return `String`,
+//! which every error type converts into.
+
+/// Implemented by the services' error types.
+pub trait ServiceError: std::error::Error {
+    /// A stable identifier such as `entry.wrong_key`, shown next to the message so that a
+    /// problem can be reported and looked up.
+    fn code(&self) -> &'static str;
+
+    /// A short heading for the message.
+    fn title(&self) -> &'static str;
+}
@@ -0,0 +1,552 @@
+//! The git credential helper protocol, see `gitcredentials(7)` and `git-credential(1)`.
+//!
+//! Git writes a credential description to the helper, one `key=value` line per attribute ended by
+//! a blank line, and the helper answers `get` with the same format. `store` and `erase` have no
+//! answer, and `erase` leaves the vault as it is. Entries are matched by comparing the protocol,
+//! host and path git asks about against their URLs.
+
+use std::io::{BufRead, Write};
+
+use zeroize::Zeroizing;
+
+use crate::{
+    models::{unix_now, AuthState, EntryExtras, PasswordEntryRaw, PasswordEntrySafe},
+    services::{
+        audit::{self, AuditAction, AuditEvent},
+        database::DatabaseService,
+        password_entry,
+    },
+};
+
+/// A credential as git describes it. Attributes git didn't send are empty.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Credential {
+    pub protocol: String,
+    /// The host name, with the port when it isn't the protocol's default.
+    pub host: String,
+    /// Only sent when `credential.useHttpPath` is set, or for protocols without a host.
+    pub path: String,
+    pub username: String,
+    pub password: Zeroizing<String>,
+}
+
+/// What `store` did with a credential.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreOutcome {
+    Created(i32),
+    /// An entry for the same account had a different password, which was replaced.
+    Updated(i32),
+    /// The vault already had exactly this credential.
+    Unchanged(i32),
+}
+
+/// Read a credential description, up to a blank line or the end of the input.
+pub fn read_credential<R: BufRead>(mut reader: R) -> Result<Credential, String> {
+    let mut credential = Credential::default();
+
+    loop {
+        let mut line = Zeroizing::new(String::new());
+        let read = reader
+            .read_line(&mut line)
+            .map_err(|err| format!("Could not read the credential: {err}"))?;
+        let line = line.trim_end_matches(['\r', '\n']);
+        if read == 0 || line.is_empty() {
+            break;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid credential attribute \"{line}\""))?;
+        match key {
+            "protocol" => credential.protocol = value.to_lowercase(),
+            "host" => credential.host = value.to_lowercase(),
+            "path" => credential.path = value.to_string(),
+            "username" => credential.username = value.to_string(),
+            "password" => credential.password = Zeroizing::new(value.to_string()),
+            // Sent by newer versions of git alongside the other attributes, which take precedence.
+            "url" => {
+                let parts = UrlParts::parse(value);
+                if credential.protocol.is_empty() {
+                    credential.protocol = parts.protocol.unwrap_or_default();
+                }
+                if credential.host.is_empty() {
+                    credential.host = parts.host;
+                }
+            }
+            // Capabilities, authentication challenges, OAuth tokens and anything added later.
+            _ => {}
+        }
+    }
+
+    credential.host = strip_default_port(&credential.protocol, &credential.host).to_string();
+    Ok(credential)
+}
+
+/// Write the username and password for git to use.
+pub fn write_credential<W: Write>(mut writer: W, entry: &PasswordEntryRaw) -> Result<(), String> {
+    // A value with a line break would be read by git as several attributes.
+    if [entry.username.as_str(), entry.raw_password.as_str()]
+        .iter()
+        .any(|value| value.contains(['\n', '\0']))
+    {
+        return Err(format!(
+            "The credentials for {} can't be passed to git because they contain a line break",
+            entry.site
+        ));
+    }
+
+    let response = Zeroizing::new(format!(
+        "username={}\npassword={}\n",
+        entry.username, *entry.raw_password
+    ));
+    writer
+        .write_all(response.as_bytes())
+        .and_then(|_| writer.flush())
+        .map_err(|err| err.to_string())
+}
+
+/// The best matching entry for a credential, if any.
+pub async fn get(
+    credential: &Credential,
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<Option<PasswordEntryRaw>, String> {
+    let entries = password_entry::get_all_password_entry_summaries(auth_state, db_service).await?;
+
+    let Some(entry) = best_match(credential, &entries, true) else {
+        return Ok(None);
+    };
+
+    let entry = password_entry::get_password_entry_by_id(entry.id, auth_state, db_service).await?;
+    let event = AuditEvent::for_entry(AuditAction::Reveal, entry.id, &entry.site)
+        .with_detail("Given to git");
+    let _ = audit::record(event, auth_state, db_service).await;
+    Ok(Some(entry))
+}
+
+/// Save a credential git has used successfully. An entry for the same account has its password
+/// replaced, otherwise a new entry is created for it.
+pub async fn store(
+    credential: &Credential,
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<StoreOutcome, String> {
+    if credential.host.is_empty() && credential.path.is_empty() {
+        return Err("Git didn't say which host the credential is for".into());
+    }
+    if credential.password.is_empty() {
+        return Err("Git didn't send a password to store".into());
+    }
+
+    let entries = password_entry::get_all_password_entry_summaries(auth_state, db_service).await?;
+    let now = unix_now();
+
+    if let Some(existing) = best_match(credential, &entries, false) {
+        let mut entry =
+            password_entry::get_password_entry_by_id(existing.id, auth_state, db_service).await?;
+        if entry.raw_password == credential.password {
+            return Ok(StoreOutcome::Unchanged(entry.id));
+        }
+
+        entry.raw_password = credential.password.clone();
+        entry.password_changed_at = now;
+        entry.updated_at = now;
+        password_entry::save_updated_password(entry.id, entry.clone(), auth_state, db_service)
+            .await?;
+        return Ok(StoreOutcome::Updated(entry.id));
+    }
+
+    let site = if credential.host.is_empty() {
+        credential.path.clone()
+    } else {
+        credential.host.clone()
+    };
+    let new_entry = PasswordEntryRaw {
+        id: 0,
+        uuid: String::new(),
+        revision: 0,
+        site,
+        username: credential.username.clone(),
+        raw_password: credential.password.clone(),
+        urls: vec![credential.url()],
+        notes: String::new(),
+        tags: Vec::new(),
+        created_at: now,
+        updated_at: now,
+        password_changed_at: now,
+        extras: EntryExtras::default(),
+    };
+
+    let id = password_entry::create_password_entry(new_entry, auth_state, db_service).await?;
+    Ok(StoreOutcome::Created(id))
+}
+
+/// The entries holding a credential git reports was rejected. They're left in the vault, a
+/// rejected password may only mean the server was down or the token lacks a scope, and deleting
+/// would also remove the entry from every synced device. Only entries with exactly the rejected
+/// username and password are returned.
+pub async fn erase(
+    credential: &Credential,
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<Vec<i32>, String> {
+    if credential.username.is_empty() || credential.password.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let entries = password_entry::get_all_password_entry_summaries(auth_state, db_service).await?;
+    let cipher = auth_state.cipher();
+    let mut rejected = Vec::new();
+
+    for entry in entries
+        .iter()
+        .filter(|entry| match_score(credential, entry).is_some())
+    {
+        if entry.decrypt_password_with(&cipher)? == credential.password {
+            rejected.push(entry.id);
+        }
+    }
+
+    Ok(rejected)
+}
+
+impl Credential {
+    /// The URL an entry created for this credential is saved with.
+    pub fn url(&self) -> String {
+        let protocol = if self.protocol.is_empty() {
+            "https"
+        } else {
+            &self.protocol
+        };
+        let path = self.path.trim_start_matches('/');
+
+        match (self.host.is_empty(), path.is_empty()) {
+            (_, true) => format!("{protocol}://{}", self.host),
+            (true, false) => format!("{protocol}:///{path}"),
+            (false, false) => format!("{protocol}://{}/{path}", self.host),
+        }
+    }
+}
+
+/// The matching entry with the most specific URL, the most recently changed one of those when
+/// there are several. Without `any_username` the entry must also be for the username git sent,
+/// even when that's empty.
+fn best_match<'a>(
+    credential: &Credential,
+    entries: &'a [PasswordEntrySafe],
+    any_username: bool,
+) -> Option<&'a PasswordEntrySafe> {
+    entries
+        .iter()
+        .filter_map(|entry| {
+            if !any_username && entry.username != credential.username {
+                return None;
+            }
+            match_score(credential, entry).map(|score| (score, entry.updated_at, entry))
+        })
+        .max_by_key(|(score, updated_at, _)| (*score, *updated_at))
+        .map(|(_, _, entry)| entry)
+}
+
+/// How specifically one of the entry's URLs matches the credential, or `None` when none of them
+/// do. When git sent a username the entry must be for it.
+fn match_score(credential: &Credential, entry: &PasswordEntrySafe) -> Option<(usize, bool)> {
+    if !credential.username.is_empty() && entry.username != credential.username {
+        return None;
+    }
+
+    let wanted_path = normalize_path(&credential.path);
+
+    entry
+        .urls
+        .iter()
+        .filter_map(|url| {
+            let parts = UrlParts::parse(url);
+
+            let same_protocol = match &parts.protocol {
+                Some(protocol) => *protocol == credential.protocol,
+                // An address without a scheme, e.g. `github.com`, is good for any protocol.
+                None => true,
+            };
+            let host =
+                strip_default_port(parts.protocol.as_deref().unwrap_or_default(), &parts.host);
+            if !same_protocol || host != credential.host {
+                return None;
+            }
+
+            // Paths are only compared when git sent one, and then the entry's path has to be the
+            // same repository or a directory containing it.
+            let path = normalize_path(&parts.path);
+            let path_matches = wanted_path.is_empty()
+                || path.is_empty()
+                || wanted_path == path
+                || wanted_path.starts_with(&format!("{path}/"));
+            if !path_matches {
+                return None;
+            }
+
+            let path_length = if wanted_path.is_empty() {
+                0
+            } else {
+                path.len()
+            };
+            Some((path_length, parts.protocol.is_some()))
+        })
+        .max()
+}
+
+struct UrlParts {
+    protocol: Option<String>,
+    host: String,
+    path: String,
+}
+
+impl UrlParts {
+    fn parse(url: &str) -> Self {
+        let url = url.trim();
+        let (protocol, rest) = match url.split_once("://") {
+            Some((protocol, rest)) => (Some(protocol.to_lowercase()), rest),
+            None => (None, url),
+        };
+        let rest = rest.split(['?', '#']).next().unwrap_or_default();
+        let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+        let host = authority
+            .rsplit_once('@')
+            .map_or(authority, |(_, host)| host);
+
+        Self {
+            protocol,
+            host: host.to_lowercase(),
+            path: path.to_string(),
+        }
+    }
+}
+
+/// `example.com:443` is the same host as `example.com` for https.
+fn strip_default_port<'a>(protocol: &str, host: &'a str) -> &'a str {
+    let default_port = match protocol {
+        "http" => "80",
+        "https" => "443",
+        "ssh" => "22",
+        "git" => "9418",
+        _ => return host,
+    };
+
+    match host.rsplit_once(':') {
+        Some((name, port)) if port == default_port => name,
+        _ => host,
+    }
+}
+
+/// Compare `org/repo.git/`, `/org/repo` and `org/repo` as the same path.
+fn normalize_path(path: &str) -> String {
+    let path = path.trim_matches('/');
+    path.strip_suffix(".git").unwrap_or(path).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credential(text: &str) -> Credential {
+        read_credential(text.as_bytes()).unwrap()
+    }
+
+    fn entry(username: &str, urls: &[&str]) -> PasswordEntrySafe {
+        PasswordEntrySafe {
+            id: 1,
+            uuid: String::new(),
+            revision: 0,
+            site: String::new(),
+            username: username.into(),
+            password_hash: String::new(),
+            urls: urls.iter().map(|url| url.to_string()).collect(),
+            encrypted_notes: String::new(),
+            tags: Vec::new(),
+            created_at: 0,
+            updated_at: 0,
+            password_changed_at: 0,
+            encrypted_extras: String::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn get_store_and_erase_go_through_the_vault() {
+        let (_dir, db_service) = DatabaseService::temporary().await;
+        let auth_state = AuthState::from_key_material(Zeroizing::new([7; 32]));
+        let pushed = "protocol=https\nhost=github.com\nusername=octocat\npassword=hunter2\n\n";
+
+        let asked = credential("protocol=https\nhost=github.com\n\n");
+        assert_eq!(get(&asked, &auth_state, &db_service).await.unwrap(), None);
+
+        let StoreOutcome::Created(id) = store(&credential(pushed), &auth_state, &db_service)
+            .await
+            .unwrap()
+        else {
+            panic!("the credential wasn't created");
+        };
+        let saved = password_entry::get_password_entry_by_id(id, &auth_state, &db_service)
+            .await
+            .unwrap();
+        assert_eq!(saved.site, "github.com");
+        assert_eq!(saved.urls, ["https://github.com"]);
+        assert_eq!(
+            store(&credential(pushed), &auth_state, &db_service)
+                .await
+                .unwrap(),
+            StoreOutcome::Unchanged(id)
+        );
+
+        let found = get(&asked, &auth_state, &db_service)
+            .await
+            .unwrap()
+            .unwrap();
+        let mut output = Vec::new();
+        write_credential(&mut output, &found).unwrap();
+        assert_eq!(output, b"username=octocat\npassword=hunter2\n");
+
+        let changed = pushed.replace("hunter2", "hunter3");
+        assert_eq!(
+            store(&credential(&changed), &auth_state, &db_service)
+                .await
+                .unwrap(),
+            StoreOutcome::Updated(id)
+        );
+
+        // Only the password git rejected is reported, and the entry stays either way.
+        let rejected = credential(&changed);
+        assert!(erase(&credential(pushed), &auth_state, &db_service)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            erase(&rejected, &auth_state, &db_service).await.unwrap(),
+            [id]
+        );
+        let kept = password_entry::get_all_password_entry_summaries(&auth_state, &db_service)
+            .await
+            .unwrap();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(
+            kept[0].decrypt_password_with(&auth_state.cipher()).unwrap(),
+            rejected.password
+        );
+    }
+
+    #[test]
+    fn reads_the_attributes_git_sends() {
+        let read = credential(
+            "protocol=HTTPS\r\nhost=Example.com:443\r\npath=org/repo.git\r\nusername=me\r\n\
+             capability[]=authtype\r\n\r\npassword=ignored\n",
+        );
+        assert_eq!(read.protocol, "https");
+        assert_eq!(read.host, "example.com");
+        assert_eq!(read.path, "org/repo.git");
+        assert_eq!(read.username, "me");
+        assert!(read.password.is_empty());
+
+        let from_url = credential("url=https://user@git.example.com:8443/org/repo\n");
+        assert_eq!(from_url.protocol, "https");
+        assert_eq!(from_url.host, "git.example.com:8443");
+
+        assert!(read_credential("no equals sign\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn refuses_to_write_a_line_break() {
+        let entry = PasswordEntryRaw {
+            id: 1,
+            uuid: String::new(),
+            revision: 0,
+            site: "example.com".into(),
+            username: "me".into(),
+            raw_password: Zeroizing::new("one\nprotocol=http".into()),
+            urls: Vec::new(),
+            notes: String::new(),
+            tags: Vec::new(),
+            created_at: 0,
+            updated_at: 0,
+            password_changed_at: 0,
+            extras: EntryExtras::default(),
+        };
+
+        assert!(write_credential(Vec::new(), &entry).is_err());
+    }
+
+    #[test]
+    fn matches_protocol_host_and_port() {
+        let asked = credential("protocol=https\nhost=example.com\n\n");
+
+        assert_eq!(
+            match_score(&asked, &entry("me", &["https://example.com/login"])),
+            Some((0, true))
+        );
+        assert_eq!(
+            match_score(&asked, &entry("me", &["https://EXAMPLE.com:443"])),
+            Some((0, true))
+        );
+        assert_eq!(
+            match_score(&asked, &entry("me", &["example.com"])),
+            Some((0, false))
+        );
+        for url in [
+            "http://example.com",
+            "https://example.com:8443",
+            "https://www.example.com",
+            "https://example.com.evil.test",
+        ] {
+            assert_eq!(match_score(&asked, &entry("me", &[url])), None, "{url}");
+        }
+
+        let with_port = credential("protocol=https\nhost=example.com:8443\n\n");
+        assert!(match_score(&with_port, &entry("me", &["https://example.com:8443"])).is_some());
+        assert!(match_score(&with_port, &entry("me", &["https://example.com"])).is_none());
+
+        let with_username = credential("protocol=https\nhost=example.com\nusername=me\n\n");
+        assert!(match_score(&with_username, &entry("you", &["https://example.com"])).is_none());
+    }
+
+    #[test]
+    fn matches_paths_by_repository_or_parent_directory() {
+        let asked = credential("protocol=https\nhost=github.com\npath=org/repo.git\n\n");
+
+        assert_eq!(
+            match_score(&asked, &entry("me", &["https://github.com/org/repo"])),
+            Some((8, true))
+        );
+        assert_eq!(
+            match_score(&asked, &entry("me", &["https://github.com/org/"])),
+            Some((3, true))
+        );
+        assert_eq!(
+            match_score(&asked, &entry("me", &["https://github.com"])),
+            Some((0, true))
+        );
+        for url in [
+            "https://github.com/org/repository",
+            "https://github.com/other/repo",
+            "https://github.com/or",
+        ] {
+            assert_eq!(match_score(&asked, &entry("me", &[url])), None, "{url}");
+        }
+
+        // Without a path from git, every URL for the host is as good as any other.
+        let without_path = credential("protocol=https\nhost=github.com\n\n");
+        assert_eq!(
+            match_score(
+                &without_path,
+                &entry("me", &["https://github.com/org/repo"])
+            ),
+            Some((0, true))
+        );
+
+        let entries = [
+            entry("me", &["https://github.com"]),
+            PasswordEntrySafe {
+                id: 2,
+                ..entry("me", &["https://github.com/org/repo.git"])
+            },
+        ];
+        assert_eq!(best_match(&asked, &entries, true).unwrap().id, 2);
+    }
+}
@@ -0,0 +1,302 @@
+//! Just enough HTTP/1.1 for the sync server and the app talking to it: one request per
+//! connection, bodies with a `Content-Length`, and no TLS. What's sent is either encrypted by
+//! the app before it leaves the device or a device's token, so the server is meant to be reached
+//! over a network that's already private, e.g. a VPN, an SSH tunnel or a TLS tunnel at both
+//! ends.
+
+use std::time::Duration;
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+};
+
+/// The request line and headers, anything longer isn't a request this app sends.
+const MAX_HEAD_LEN: usize = 16 * 1024;
+/// Large enough for an entry with its attachments, or all of a team's changes.
+const MAX_BODY_LEN: usize = 64 * 1024 * 1024;
+/// How long the app waits for the server before giving up.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    /// The path without the query string.
+    pub path: String,
+    pub query: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// The value of a header, matched without regard to case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// The value of a query string parameter, as it's sent. The app never sends values that
+    /// need decoding.
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+
+    /// The token from an `Authorization: Bearer` header.
+    pub fn bearer_token(&self) -> Option<&str> {
+        self.header("authorization")?
+            .strip_prefix("Bearer ")
+            .map(str::trim)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn json<T: serde::Serialize>(status: u16, value: &T) -> Self {
+        Self {
+            status,
+            body: serde_json::to_vec(value).unwrap_or_default(),
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// A server's base URL, e.g. `http://sync.example.lan:8787/pw`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerUrl {
+    /// The host and port to connect to.
+    pub address: String,
+    pub host: String,
+    /// The path every request's path is appended to, without a trailing slash.
+    pub base_path: String,
+}
+
+impl ServerUrl {
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let url = url.trim().trim_end_matches('/');
+        if url.starts_with("https://") {
+            return Err(
+                "The app talks to the sync server over plain HTTP, reach it through a tunnel on this device and use the tunnel's http:// address".into(),
+            );
+        }
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or("The sync server's address must start with http://")?;
+
+        let (host, base_path) = match rest.find('/') {
+            Some(index) => rest.split_at(index),
+            None => (rest, ""),
+        };
+        if host.is_empty() {
+            return Err("The sync server's address has no host".into());
+        }
+        let address = match host.rsplit_once(':') {
+            Some((_, port)) if !port.contains(']') => host.to_string(),
+            _ => format!("{host}:80"),
+        };
+
+        Ok(Self {
+            address,
+            host: host.to_string(),
+            base_path: base_path.to_string(),
+        })
+    }
+}
+
+/// Read a request from a connection. Anything malformed or too large is an error.
+pub async fn read_request<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Request, String> {
+    let (head, body) = read_message(reader).await?;
+    let mut lines = head.lines();
+
+    let request_line = lines.next().ok_or("The request is empty")?;
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(format!("Invalid request line: {request_line}"));
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Err(format!("Unsupported HTTP version: {version}"));
+    }
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    Ok(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: query.to_string(),
+        headers: parse_headers(lines)?,
+        body,
+    })
+}
+
+/// Write a JSON response and close the connection.
+pub async fn write_response<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    response: &Response,
+) -> Result<(), String> {
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason(response.status),
+        response.body.len()
+    );
+    writer
+        .write_all(head.as_bytes())
+        .await
+        .map_err(|err| err.to_string())?;
+    writer
+        .write_all(&response.body)
+        .await
+        .map_err(|err| err.to_string())?;
+    writer.shutdown().await.map_err(|err| err.to_string())
+}
+
+/// Send a request with a JSON body, if any, and wait for the response.
+pub async fn send(
+    server: &ServerUrl,
+    method: &str,
+    path: &str,
+    token: &str,
+    body: Option<&[u8]>,
+) -> Result<Response, String> {
+    tokio::time::timeout(CLIENT_TIMEOUT, exchange(server, method, path, token, body))
+        .await
+        .map_err(|_| format!("{} didn't answer in time", server.host))?
+}
+
+async fn exchange(
+    server: &ServerUrl,
+    method: &str,
+    path: &str,
+    token: &str,
+    body: Option<&[u8]>,
+) -> Result<Response, String> {
+    let mut stream = TcpStream::connect(&server.address)
+        .await
+        .map_err(|err| format!("Could not connect to {}: {err}", server.host))?;
+
+    let body = body.unwrap_or_default();
+    let head = format!(
+        "{method} {}{path} HTTP/1.1\r\nHost: {}\r\nAuthorization: Bearer {token}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        server.base_path,
+        server.host,
+        body.len()
+    );
+    stream
+        .write_all(head.as_bytes())
+        .await
+        .map_err(|err| err.to_string())?;
+    stream
+        .write_all(body)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let (head, body) = read_message(&mut stream).await?;
+    let mut lines = head.lines();
+    let status_line = lines.next().unwrap_or_default();
+    let status = status_line
+        .split(' ')
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| format!("{} didn't answer with HTTP", server.host))?;
+    let headers = parse_headers(lines)?;
+    if find_header(&headers, "transfer-encoding").is_some() {
+        return Err(format!(
+            "{} answered with a chunked response, which the app can't read",
+            server.host
+        ));
+    }
+
+    Ok(Response { status, body })
+}
+
+/// Read the head of a message and as much body as its `Content-Length` says.
+async fn read_message<R: AsyncRead + Unpin>(reader: &mut R) -> Result<(String, Vec<u8>), String> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 8192];
+    let head_end = loop {
+        if let Some(index) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break index;
+        }
+        if buffer.len() > MAX_HEAD_LEN {
+            return Err("The message's headers are too long".into());
+        }
+        let read = reader
+            .read(&mut chunk)
+            .await
+            .map_err(|err| err.to_string())?;
+        if read == 0 {
+            return Err("The connection was closed before the message ended".into());
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+
+    let head = String::from_utf8(buffer[..head_end].to_vec())
+        .map_err(|_| "The message's headers aren't text")?;
+    let length = match parse_headers(head.lines().skip(1))?
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+    {
+        Some((_, value)) => value
+            .parse::<usize>()
+            .map_err(|_| format!("Invalid Content-Length: {value}"))?,
+        None => 0,
+    };
+    if length > MAX_BODY_LEN {
+        return Err("The message is too large".into());
+    }
+
+    let mut body = buffer.split_off(head_end + 4);
+    if body.len() < length {
+        let mut rest = vec![0u8; length - body.len()];
+        reader
+            .read_exact(&mut rest)
+            .await
+            .map_err(|err| err.to_string())?;
+        body.extend_from_slice(&rest);
+    }
+    body.truncate(length);
+
+    Ok((head, body))
+}
+
+fn parse_headers<'a>(
+    lines: impl Iterator<Item = &'a str>,
+) -> Result<Vec<(String, String)>, String> {
+    lines
+        .map(|line| {
+            line.split_once(':')
+                .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+                .ok_or_else(|| format!("Invalid header: {line}"))
+        })
+        .collect()
+}
+
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    }
+}
@@ -0,0 +1,1139 @@
+//! Importing entries from other password managers' exports.
+//!
+//! Each supported export is parsed into [`PasswordEntryRaw`]s, compared against the vault to find
+//! duplicates, and shown to the user before anything is saved. The chosen entries are then saved
+//! together in a single transaction.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    io::{Cursor, Read},
+    path::Path,
+};
+
+use serde::Deserialize;
+use zeroize::Zeroizing;
+
+use crate::{
+    models::{
+        days_from_civil, unix_now, AuthState, CustomField, EntryExtras, PasswordEntryRaw,
+        PasswordEntrySafe,
+    },
+    services::{
+        audit::{self, AuditAction, AuditEvent},
+        backup::{self, BackupReason},
+        database::DatabaseService,
+        keepass, password_entry, query, vault_lock,
+    },
+};
+
+/// The file inside a 1Password 1PUX archive that holds the items.
+const ONE_PUX_DATA_FILE: &str = "export.data";
+const ONE_PUX_LOGIN_CATEGORY: &str = "001";
+const ONE_PUX_PASSWORD_CATEGORY: &str = "005";
+const BITWARDEN_LOGIN_TYPE: u8 = 1;
+/// A custom field whose value Bitwarden hides like a password.
+const BITWARDEN_HIDDEN_FIELD_TYPE: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    BitwardenJson,
+    BitwardenCsv,
+    ChromiumCsv,
+    FirefoxCsv,
+    OnePassword1pux,
+    OnePasswordCsv,
+    KeePassKdbx,
+}
+
+impl ImportFormat {
+    pub const ALL: [ImportFormat; 7] = [
+        ImportFormat::BitwardenJson,
+        ImportFormat::BitwardenCsv,
+        ImportFormat::ChromiumCsv,
+        ImportFormat::FirefoxCsv,
+        ImportFormat::OnePassword1pux,
+        ImportFormat::OnePasswordCsv,
+        ImportFormat::KeePassKdbx,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ImportFormat::BitwardenJson => "Bitwarden (JSON)",
+            ImportFormat::BitwardenCsv => "Bitwarden (CSV)",
+            ImportFormat::ChromiumCsv => "Chrome, Edge, Brave and other Chromium browsers (CSV)",
+            ImportFormat::FirefoxCsv => "Firefox (CSV)",
+            ImportFormat::OnePassword1pux => "1Password (1PUX)",
+            ImportFormat::OnePasswordCsv => "1Password (CSV)",
+            ImportFormat::KeePassKdbx => "KeePass or KeePassXC (KDBX 4)",
+        }
+    }
+
+    /// A stable name for the format, e.g. for form values.
+    pub fn key(&self) -> &'static str {
+        match self {
+            ImportFormat::BitwardenJson => "bitwarden_json",
+            ImportFormat::BitwardenCsv => "bitwarden_csv",
+            ImportFormat::ChromiumCsv => "chromium_csv",
+            ImportFormat::FirefoxCsv => "firefox_csv",
+            ImportFormat::OnePassword1pux => "1password_1pux",
+            ImportFormat::OnePasswordCsv => "1password_csv",
+            ImportFormat::KeePassKdbx => "keepass_kdbx",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| format.key() == key)
+    }
+
+    /// Work out which format an export is in from its file name and contents.
+    pub fn detect(path: &Path, data: &[u8]) -> Option<Self> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase)
+            .unwrap_or_default();
+
+        if extension == "kdbx" || keepass::is_kdbx(data) {
+            return Some(ImportFormat::KeePassKdbx);
+        }
+
+        // Zip archives start with "PK".
+        if extension == "1pux" || data.starts_with(b"PK\x03\x04") {
+            return Some(ImportFormat::OnePassword1pux);
+        }
+
+        let text = String::from_utf8_lossy(&data[..data.len().min(4096)]);
+        let text = text.trim_start_matches('\u{feff}').trim_start();
+        if extension == "json" || text.starts_with('{') {
+            return Some(ImportFormat::BitwardenJson);
+        }
+
+        let header = text.lines().next().unwrap_or_default().to_lowercase();
+        let columns: Vec<&str> = header
+            .split(',')
+            .map(|column| column.trim().trim_matches('"'))
+            .collect();
+        let has = |name: &str| columns.contains(&name);
+
+        if has("login_uri") || has("login_password") {
+            Some(ImportFormat::BitwardenCsv)
+        } else if has("httprealm") || has("formactionorigin") {
+            Some(ImportFormat::FirefoxCsv)
+        } else if has("title") && has("password") {
+            Some(ImportFormat::OnePasswordCsv)
+        } else if has("name") && has("url") && has("password") {
+            Some(ImportFormat::ChromiumCsv)
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for ImportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+/// Why an imported entry might not be wanted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Duplicate {
+    /// The vault already has an entry with this id for the same site and username.
+    InVault(i32),
+    /// An earlier entry in the same export, by its index, is for the same site and username.
+    InImport(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportItem {
+    pub entry: PasswordEntryRaw,
+    pub duplicate: Option<Duplicate>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportPreview {
+    pub format: ImportFormat,
+    pub items: Vec<ImportItem>,
+    /// Items in the export that can't be imported, such as credit cards, secure notes or
+    /// entries in KeePass's recycle bin.
+    pub skipped: usize,
+}
+
+/// Entries parsed from an export, before they are compared to the vault.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedImport {
+    pub format: ImportFormat,
+    pub entries: Vec<PasswordEntryRaw>,
+    pub skipped: usize,
+}
+
+/// Read and parse the export at `path`. The format is detected when `format` is `None`.
+/// `file_password` unlocks exports that are encrypted, KeePass databases, and is otherwise ignored.
+pub fn parse_file(
+    path: &Path,
+    format: Option<ImportFormat>,
+    file_password: &str,
+) -> Result<ParsedImport, String> {
+    let data = Zeroizing::new(std::fs::read(path).map_err(|err| err.to_string())?);
+    let format = match format {
+        Some(format) => format,
+        None => ImportFormat::detect(path, &data).ok_or_else(|| {
+            "Could not tell which password manager the file was exported from, please choose the format".to_string()
+        })?,
+    };
+
+    parse(format, &data, file_password)
+}
+
+/// Parse an export that's already been read into memory.
+pub fn parse(
+    format: ImportFormat,
+    data: &[u8],
+    file_password: &str,
+) -> Result<ParsedImport, String> {
+    let now = unix_now();
+    let (entries, skipped) = match format {
+        ImportFormat::BitwardenJson => parse_bitwarden_json(&utf8(data)?, now)?,
+        ImportFormat::OnePassword1pux => parse_1pux(data, now)?,
+        ImportFormat::KeePassKdbx => {
+            let database = keepass::read_database(data, file_password)?;
+            (database.entries, database.skipped)
+        }
+        ImportFormat::BitwardenCsv
+        | ImportFormat::ChromiumCsv
+        | ImportFormat::FirefoxCsv
+        | ImportFormat::OnePasswordCsv => parse_csv(format, data, now)?,
+    };
+
+    Ok(ParsedImport {
+        format,
+        entries,
+        skipped,
+    })
+}
+
+/// Parse the export at `path` and mark entries that duplicate ones already in the vault.
+pub async fn preview_import(
+    path: &Path,
+    format: Option<ImportFormat>,
+    file_password: Zeroizing<String>,
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<ImportPreview, String> {
+    let path = path.to_path_buf();
+    let parsed = tokio::task::spawn_blocking(move || parse_file(&path, format, &file_password))
+        .await
+        .map_err(|err| err.to_string())??;
+
+    let existing = password_entry::get_all_password_entry_summaries(auth_state, db_service).await?;
+    let duplicates = find_duplicates(&parsed.entries, &existing);
+
+    Ok(ImportPreview {
+        format: parsed.format,
+        items: parsed
+            .entries
+            .into_iter()
+            .zip(duplicates)
+            .map(|(entry, duplicate)| ImportItem { entry, duplicate })
+            .collect(),
+        skipped: parsed.skipped,
+    })
+}
+
+/// Save the chosen entries to the vault in a single transaction, returning how many were saved.
+pub async fn import_entries(
+    entries: &[PasswordEntryRaw],
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<usize, String> {
+    if !auth_state.signed_in {
+        return Err("You must be signed in to access these resources".into());
+    }
+
+    let _lock = vault_lock::lock_vault(db_service).await?;
+    backup::create_backup(BackupReason::Import, db_service)
+        .await
+        .map_err(|err| format!("Could not back up the vault before importing: {err}"))?;
+
+    let imported = password_entry::create_password_entries(entries, auth_state, db_service).await?;
+
+    let event = AuditEvent::new(AuditAction::Import)
+        .with_detail(format!("{imported} entries from another password manager"));
+    let _ = audit::record(event, auth_state, db_service).await;
+    Ok(imported)
+}
+
+/// Find entries that are for the same site and username as one already in the vault or earlier in
+/// `entries`. Sites are compared by the host of their first URL when they have one.
+pub fn find_duplicates(
+    entries: &[PasswordEntryRaw],
+    existing: &[PasswordEntrySafe],
+) -> Vec<Option<Duplicate>> {
+    let in_vault: HashMap<(String, String), i32> = existing
+        .iter()
+        .map(|entry| {
+            (
+                identity(&entry.site, &entry.username, &entry.urls),
+                entry.id,
+            )
+        })
+        .collect();
+    let mut in_import: HashMap<(String, String), usize> = HashMap::new();
+
+    entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            let key = identity(&entry.site, &entry.username, &entry.urls);
+            if let Some(id) = in_vault.get(&key) {
+                return Some(Duplicate::InVault(*id));
+            }
+            match in_import.get(&key) {
+                Some(first) => Some(Duplicate::InImport(*first)),
+                None => {
+                    in_import.insert(key, index);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// What two entries must share to be for the same account: the site, by its first URL's host when
+/// it has one, and the username.
+pub fn identity(site: &str, username: &str, urls: &[String]) -> (String, String) {
+    let site = match urls.first() {
+        Some(url) => query::url_host(&url.to_lowercase()).to_string(),
+        None => site.trim().to_lowercase(),
+    };
+    let site = site.strip_prefix("www.").map(String::from).unwrap_or(site);
+    (site, username.trim().to_lowercase())
+}
+
+fn utf8(data: &[u8]) -> Result<Zeroizing<String>, String> {
+    let text = std::str::from_utf8(data).map_err(|_| "The file is not valid UTF-8 text")?;
+    Ok(Zeroizing::new(
+        text.trim_start_matches('\u{feff}').to_string(),
+    ))
+}
+
+/// A login read from an export, before defaults are filled in.
+#[derive(Default)]
+struct ExportedLogin {
+    site: String,
+    username: String,
+    password: String,
+    urls: Vec<String>,
+    notes: String,
+    tags: Vec<String>,
+    custom_fields: Vec<CustomField>,
+    created_at: Option<i64>,
+    updated_at: Option<i64>,
+}
+
+impl ExportedLogin {
+    /// The site falls back to the first URL's host, and missing timestamps to `now`.
+    fn into_entry(self, now: i64) -> PasswordEntryRaw {
+        let site = match self.site.trim() {
+            "" => self
+                .urls
+                .first()
+                .map(|url| query::url_host(url).to_string())
+                .unwrap_or_default(),
+            site => site.to_string(),
+        };
+        let created_at = self.created_at.unwrap_or(now);
+        let updated_at = self.updated_at.unwrap_or(created_at);
+
+        PasswordEntryRaw {
+            id: 0,
+            uuid: String::new(),
+            revision: 0,
+            site,
+            username: self.username.trim().to_string(),
+            raw_password: Zeroizing::new(self.password),
+            urls: self.urls,
+            notes: self.notes.trim().to_string(),
+            tags: self.tags,
+            created_at,
+            updated_at,
+            password_changed_at: updated_at,
+            extras: EntryExtras {
+                custom_fields: self.custom_fields,
+                ..EntryExtras::default()
+            },
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenExport {
+    #[serde(default)]
+    encrypted: bool,
+    #[serde(default)]
+    folders: Vec<BitwardenFolder>,
+    #[serde(default)]
+    items: Vec<BitwardenItem>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenFolder {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenItem {
+    #[serde(rename = "type")]
+    item_type: u8,
+    #[serde(default)]
+    name: String,
+    notes: Option<String>,
+    folder_id: Option<String>,
+    login: Option<BitwardenLogin>,
+    fields: Option<Vec<BitwardenField>>,
+    creation_date: Option<String>,
+    revision_date: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenLogin {
+    username: Option<String>,
+    password: Option<String>,
+    uris: Option<Vec<BitwardenUri>>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenUri {
+    uri: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenField {
+    #[serde(rename = "type", default)]
+    field_type: u8,
+    name: Option<String>,
+    value: Option<String>,
+}
+
+fn parse_bitwarden_json(text: &str, now: i64) -> Result<(Vec<PasswordEntryRaw>, usize), String> {
+    let export: BitwardenExport = serde_json::from_str(text)
+        .map_err(|err| format!("The file is not a Bitwarden JSON export: {err}"))?;
+
+    if export.encrypted {
+        return Err("Encrypted Bitwarden exports can't be imported, export the vault as unencrypted JSON instead".into());
+    }
+
+    let folders: HashMap<String, String> = export
+        .folders
+        .into_iter()
+        .map(|folder| (folder.id, folder.name))
+        .collect();
+
+    let mut entries = Vec::new();
+    let mut skipped = 0;
+
+    for item in export.items {
+        let Some(login) = item
+            .login
+            .filter(|_| item.item_type == BITWARDEN_LOGIN_TYPE)
+        else {
+            skipped += 1;
+            continue;
+        };
+
+        let urls = login
+            .uris
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|uri| uri.uri)
+            .filter(|uri| !uri.trim().is_empty())
+            .collect();
+        let tags = item
+            .folder_id
+            .and_then(|id| folders.get(&id).cloned())
+            .into_iter()
+            .collect();
+
+        let custom_fields = item
+            .fields
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|field| {
+                custom_field(
+                    &field.name.unwrap_or_default(),
+                    &field.value.unwrap_or_default(),
+                    field.field_type == BITWARDEN_HIDDEN_FIELD_TYPE,
+                )
+            })
+            .collect();
+
+        entries.push(
+            ExportedLogin {
+                site: item.name,
+                username: login.username.unwrap_or_default(),
+                password: login.password.unwrap_or_default(),
+                urls,
+                notes: item.notes.unwrap_or_default(),
+                tags,
+                custom_fields,
+                created_at: item.creation_date.as_deref().and_then(parse_iso8601),
+                updated_at: item.revision_date.as_deref().and_then(parse_iso8601),
+            }
+            .into_entry(now),
+        );
+    }
+
+    Ok((entries, skipped))
+}
+
+#[derive(Deserialize)]
+struct OnePuxExport {
+    #[serde(default)]
+    accounts: Vec<OnePuxAccount>,
+}
+
+#[derive(Deserialize)]
+struct OnePuxAccount {
+    #[serde(default)]
+    vaults: Vec<OnePuxVault>,
+}
+
+#[derive(Deserialize)]
+struct OnePuxVault {
+    attrs: OnePuxVaultAttrs,
+    #[serde(default)]
+    items: Vec<OnePuxItem>,
+}
+
+#[derive(Deserialize)]
+struct OnePuxVaultAttrs {
+    #[serde(default)]
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OnePuxItem {
+    #[serde(default)]
+    category_uuid: String,
+    created_at: Option<i64>,
+    updated_at: Option<i64>,
+    #[serde(default)]
+    state: String,
+    #[serde(default)]
+    overview: OnePuxOverview,
+    #[serde(default)]
+    details: OnePuxDetails,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct OnePuxOverview {
+    title: String,
+    url: String,
+    urls: Vec<OnePuxUrl>,
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct OnePuxUrl {
+    #[serde(default)]
+    url: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct OnePuxDetails {
+    login_fields: Vec<OnePuxLoginField>,
+    notes_plain: Option<String>,
+    password: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OnePuxLoginField {
+    #[serde(default)]
+    designation: String,
+    #[serde(default)]
+    value: String,
+}
+
+fn parse_1pux(data: &[u8], now: i64) -> Result<(Vec<PasswordEntryRaw>, usize), String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data))
+        .map_err(|err| format!("The file is not a 1PUX archive: {err}"))?;
+    let mut file = archive
+        .by_name(ONE_PUX_DATA_FILE)
+        .map_err(|_| format!("The 1PUX archive has no {ONE_PUX_DATA_FILE}"))?;
+
+    let mut text = Zeroizing::new(String::new());
+    file.read_to_string(&mut text)
+        .map_err(|err| err.to_string())?;
+
+    let export: OnePuxExport = serde_json::from_str(&text)
+        .map_err(|err| format!("The 1PUX archive could not be read: {err}"))?;
+
+    let mut entries = Vec::new();
+    let mut skipped = 0;
+
+    for vault in export
+        .accounts
+        .into_iter()
+        .flat_map(|account| account.vaults)
+    {
+        for item in vault.items {
+            let is_login = [ONE_PUX_LOGIN_CATEGORY, ONE_PUX_PASSWORD_CATEGORY]
+                .contains(&item.category_uuid.as_str());
+            // Archived and deleted items stay behind.
+            if !is_login || !matches!(item.state.as_str(), "" | "active") {
+                skipped += 1;
+                continue;
+            }
+
+            let login_field = |designation: &str| {
+                item.details
+                    .login_fields
+                    .iter()
+                    .find(|field| field.designation == designation)
+                    .map(|field| field.value.clone())
+            };
+            let username = login_field("username").unwrap_or_default();
+            let password = login_field("password")
+                .or(item.details.password.clone())
+                .unwrap_or_default();
+
+            let mut urls: Vec<String> = item
+                .overview
+                .urls
+                .iter()
+                .map(|url| url.url.trim().to_string())
+                .filter(|url| !url.is_empty())
+                .collect();
+            if urls.is_empty() && !item.overview.url.trim().is_empty() {
+                urls.push(item.overview.url.trim().to_string());
+            }
+
+            let mut tags = item.overview.tags.clone();
+            if !vault.attrs.name.is_empty() && !tags.contains(&vault.attrs.name) {
+                tags.push(vault.attrs.name.clone());
+            }
+
+            entries.push(
+                ExportedLogin {
+                    site: item.overview.title.clone(),
+                    username,
+                    password,
+                    urls,
+                    notes: item.details.notes_plain.clone().unwrap_or_default(),
+                    tags,
+                    custom_fields: Vec::new(),
+                    created_at: item.created_at,
+                    updated_at: item.updated_at,
+                }
+                .into_entry(now),
+            );
+        }
+    }
+
+    Ok((entries, skipped))
+}
+
+/// The header names each format uses for the columns we import. The first one present is used.
+struct CsvColumns {
+    site: &'static [&'static str],
+    url: &'static [&'static str],
+    username: &'static [&'static str],
+    password: &'static [&'static str],
+    notes: &'static [&'static str],
+    tags: &'static [&'static str],
+    /// Extra `name: value` lines, imported as custom fields.
+    fields: &'static [&'static str],
+    /// Only rows with `login` in this column are imported, when the format has one.
+    item_type: &'static [&'static str],
+    /// Unix timestamps in milliseconds.
+    created_ms: &'static [&'static str],
+    changed_ms: &'static [&'static str],
+}
+
+impl CsvColumns {
+    fn for_format(format: ImportFormat) -> Self {
+        let none: &'static [&'static str] = &[];
+        match format {
+            ImportFormat::BitwardenCsv => CsvColumns {
+                site: &["name"],
+                url: &["login_uri"],
+                username: &["login_username"],
+                password: &["login_password"],
+                notes: &["notes"],
+                tags: &["folder"],
+                fields: &["fields"],
+                item_type: &["type"],
+                created_ms: none,
+                changed_ms: none,
+            },
+            ImportFormat::FirefoxCsv => CsvColumns {
+                site: none,
+                url: &["url"],
+                username: &["username"],
+                password: &["password"],
+                notes: none,
+                tags: none,
+                fields: none,
+                item_type: none,
+                created_ms: &["timecreated"],
+                changed_ms: &["timepasswordchanged"],
+            },
+            ImportFormat::OnePasswordCsv => CsvColumns {
+                site: &["title", "name"],
+                url: &["url", "website", "urls"],
+                username: &["username"],
+                password: &["password"],
+                notes: &["notes", "notesplain"],
+                tags: &["tags"],
+                fields: none,
+                item_type: none,
+                created_ms: none,
+                changed_ms: none,
+            },
+            // Chromium is also the fallback for the non-CSV formats, which never get here.
+            _ => CsvColumns {
+                site: &["name"],
+                url: &["url"],
+                username: &["username"],
+                password: &["password"],
+                notes: &["note", "notes"],
+                tags: none,
+                fields: none,
+                item_type: none,
+                created_ms: none,
+                changed_ms: none,
+            },
+        }
+    }
+}
+
+fn parse_csv(
+    format: ImportFormat,
+    data: &[u8],
+    now: i64,
+) -> Result<(Vec<PasswordEntryRaw>, usize), String> {
+    let text = utf8(data)?;
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(text.as_bytes());
+
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|err| format!("The file is not a valid CSV export: {err}"))?
+        .iter()
+        .map(|header| header.trim().to_lowercase())
+        .collect();
+
+    let columns = CsvColumns::for_format(format);
+    let position = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| headers.iter().position(|header| header == name))
+    };
+
+    let password_column = position(columns.password)
+        .ok_or_else(|| format!("The file has no password column, is it a {format} export?"))?;
+    let site_column = position(columns.site);
+    let url_column = position(columns.url);
+    let username_column = position(columns.username);
+    let notes_column = position(columns.notes);
+    let tags_column = position(columns.tags);
+    let fields_column = position(columns.fields);
+    let type_column = position(columns.item_type);
+    let created_column = position(columns.created_ms);
+    let changed_column = position(columns.changed_ms);
+
+    // Bitwarden puts several URIs in one cell separated by commas, elsewhere a comma can be part
+    // of the URL.
+    let url_separators: &[char] = if format == ImportFormat::BitwardenCsv {
+        &[',', '\n']
+    } else {
+        &['\n']
+    };
+
+    let mut entries = Vec::new();
+    let mut skipped = 0;
+
+    for (line, record) in reader.records().enumerate() {
+        // Line 1 is the header.
+        let record = record.map_err(|err| format!("Line {} could not be read: {err}", line + 2))?;
+        let get = |column: Option<usize>| {
+            column
+                .and_then(|column| record.get(column))
+                .unwrap_or_default()
+        };
+        let get_ms = |column: Option<usize>| {
+            get(column)
+                .trim()
+                .parse::<i64>()
+                .ok()
+                .filter(|ms| *ms > 0)
+                .map(|ms| ms / 1000)
+        };
+
+        if type_column.is_some() && !get(type_column).trim().eq_ignore_ascii_case("login") {
+            skipped += 1;
+            continue;
+        }
+
+        if record.iter().all(|value| value.trim().is_empty()) {
+            continue;
+        }
+
+        let urls = get(url_column)
+            .split(url_separators)
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(String::from)
+            .collect();
+
+        // The CSV doesn't say which fields were hidden, so they're all treated as secret.
+        let custom_fields = get(fields_column)
+            .lines()
+            .filter_map(|line| {
+                let (name, value) = line.split_once(": ").unwrap_or(("", line));
+                custom_field(name, value, true)
+            })
+            .collect();
+
+        entries.push(
+            ExportedLogin {
+                site: get(site_column).to_string(),
+                username: get(username_column).to_string(),
+                password: get(Some(password_column)).to_string(),
+                urls,
+                notes: get(notes_column).to_string(),
+                tags: password_entry::parse_tags(&get(tags_column).replace(';', ",")),
+                custom_fields,
+                created_at: get_ms(created_column),
+                updated_at: get_ms(changed_column),
+            }
+            .into_entry(now),
+        );
+    }
+
+    Ok((entries, skipped))
+}
+
+/// A custom field from an export, kept with the entry's encrypted extras. Fields with neither a
+/// name nor a value are left out.
+fn custom_field(name: &str, value: &str, protected: bool) -> Option<CustomField> {
+    let (name, value) = (name.trim(), value.trim());
+    if name.is_empty() && value.is_empty() {
+        return None;
+    }
+
+    Some(CustomField {
+        name: name.to_string(),
+        value: Zeroizing::new(value.to_string()),
+        protected,
+    })
+}
+
+/// Parse a UTC timestamp such as `2024-03-01T12:30:00.000Z` into unix seconds.
+pub fn parse_iso8601(timestamp: &str) -> Option<i64> {
+    let (date, time) = timestamp.split_once('T')?;
+    let mut date_parts = date.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (
+        date_parts.next()??,
+        date_parts.next()??,
+        date_parts.next()??,
+    );
+
+    let time = time.trim_end_matches('Z');
+    let time = time.split(['.', '+']).next()?;
+    let mut time_parts = time.splitn(3, ':').map(|part| part.parse::<i64>().ok());
+    let (hours, minutes, seconds) = (
+        time_parts.next()??,
+        time_parts.next()??,
+        time_parts.next().flatten().unwrap_or(0),
+    );
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    Some(days_from_civil(year, month, day) * 86_400 + hours * 3600 + minutes * 60 + seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(entry: &PasswordEntryRaw) -> Vec<(&str, &str, bool)> {
+        entry
+            .extras
+            .custom_fields
+            .iter()
+            .map(|field| (field.name.as_str(), field.value.as_str(), field.protected))
+            .collect()
+    }
+
+    #[test]
+    fn bitwarden_json_fields_are_kept_encrypted_with_the_entry() {
+        let export = r#"{
+            "encrypted": false,
+            "items": [{
+                "type": 1,
+                "name": "GitHub",
+                "notes": "Work account",
+                "login": { "username": "me", "password": "pw", "uris": [] },
+                "fields": [
+                    { "type": 0, "name": "Team", "value": "Platform" },
+                    { "type": 1, "name": "Recovery code", "value": "1234-5678" },
+                    { "type": 0, "name": " ", "value": "" }
+                ]
+            }]
+        }"#;
+
+        let parsed = parse(ImportFormat::BitwardenJson, export.as_bytes(), "").unwrap();
+        let entry = &parsed.entries[0];
+
+        assert_eq!(entry.notes, "Work account");
+        assert_eq!(
+            fields(entry),
+            vec![
+                ("Team", "Platform", false),
+                ("Recovery code", "1234-5678", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn bitwarden_csv_fields_are_treated_as_secret() {
+        let export = "folder,favorite,type,name,notes,fields,reprompt,login_uri,login_username,login_password,login_totp\n\
+            ,,login,GitHub,Work account,\"Team: Platform\nPIN: 0000\",0,https://github.com,me,pw,\n";
+
+        let parsed = parse(ImportFormat::BitwardenCsv, export.as_bytes(), "").unwrap();
+        let entry = &parsed.entries[0];
+
+        assert_eq!(entry.notes, "Work account");
+        assert_eq!(
+            fields(entry),
+            vec![("Team", "Platform", true), ("PIN", "0000", true)]
+        );
+    }
+
+    #[test]
+    fn detects_formats_by_extension_and_contents() {
+        let detect =
+            |name: &str, data: &str| ImportFormat::detect(Path::new(name), data.as_bytes());
+
+        assert_eq!(detect("vault.kdbx", ""), Some(ImportFormat::KeePassKdbx));
+        assert_eq!(
+            detect("export.1pux", ""),
+            Some(ImportFormat::OnePassword1pux)
+        );
+        assert_eq!(
+            detect("export", "PK\x03\x04"),
+            Some(ImportFormat::OnePassword1pux)
+        );
+        assert_eq!(detect("export.json", ""), Some(ImportFormat::BitwardenJson));
+        assert_eq!(
+            detect("export.txt", "\u{feff}  {\"items\": []}"),
+            Some(ImportFormat::BitwardenJson)
+        );
+        assert_eq!(
+            detect("export.csv", "folder,favorite,type,name,notes,fields,reprompt,login_uri,login_username,login_password,login_totp\n"),
+            Some(ImportFormat::BitwardenCsv)
+        );
+        assert_eq!(
+            detect("logins.csv", "\"url\",\"username\",\"password\",\"httpRealm\",\"formActionOrigin\",\"guid\",\"timeCreated\",\"timeLastUsed\",\"timePasswordChanged\"\n"),
+            Some(ImportFormat::FirefoxCsv)
+        );
+        assert_eq!(
+            detect(
+                "export.csv",
+                "Title,Url,Username,Password,OTPAuth,Favorite,Archived,Tags,Notes\n"
+            ),
+            Some(ImportFormat::OnePasswordCsv)
+        );
+        assert_eq!(
+            detect("Chrome Passwords.csv", "name,url,username,password,note\n"),
+            Some(ImportFormat::ChromiumCsv)
+        );
+        assert_eq!(detect("export.csv", "a,b,c\n1,2,3\n"), None);
+    }
+
+    #[test]
+    fn chromium_csv_falls_back_to_the_host_for_the_site() {
+        let export = "name,url,username,password,note\n\
+            GitHub,https://github.com/login,me,pw,Work account\n\
+            ,https://gitlab.com/users/sign_in,you,pw2,\n\
+            ,,,,\n";
+
+        let parsed = parse(ImportFormat::ChromiumCsv, export.as_bytes(), "").unwrap();
+        let [github, gitlab] = &parsed.entries[..] else {
+            panic!("{} entries", parsed.entries.len());
+        };
+
+        assert_eq!(github.site, "GitHub");
+        assert_eq!(github.username, "me");
+        assert_eq!(*github.raw_password, "pw");
+        assert_eq!(github.urls, ["https://github.com/login"]);
+        assert_eq!(github.notes, "Work account");
+        assert_eq!(gitlab.site, "gitlab.com");
+        assert_eq!(*gitlab.raw_password, "pw2");
+        assert_eq!(parsed.skipped, 0);
+    }
+
+    #[test]
+    fn firefox_csv_keeps_the_timestamps() {
+        let export = "\"url\",\"username\",\"password\",\"httpRealm\",\"formActionOrigin\",\"guid\",\"timeCreated\",\"timeLastUsed\",\"timePasswordChanged\"\n\
+            \"https://accounts.example.com\",\"me\",\"pw\",,\"https://accounts.example.com\",\"{1}\",\"1700000000000\",\"1700000500000\",\"1700001000000\"\n";
+
+        let parsed = parse(ImportFormat::FirefoxCsv, export.as_bytes(), "").unwrap();
+        let entry = &parsed.entries[0];
+
+        assert_eq!(entry.site, "accounts.example.com");
+        assert_eq!(entry.username, "me");
+        assert_eq!(*entry.raw_password, "pw");
+        assert_eq!(entry.created_at, 1_700_000_000);
+        assert_eq!(entry.updated_at, 1_700_001_000);
+        assert_eq!(entry.password_changed_at, 1_700_001_000);
+    }
+
+    #[test]
+    fn one_password_csv_splits_the_tags() {
+        let export = "Title,Url,Username,Password,OTPAuth,Favorite,Archived,Tags,Notes\n\
+            Bank,https://bank.example,me,pw,,false,false,finance;personal,PIN in the safe\n";
+
+        let parsed = parse(ImportFormat::OnePasswordCsv, export.as_bytes(), "").unwrap();
+        let entry = &parsed.entries[0];
+
+        assert_eq!(entry.site, "Bank");
+        assert_eq!(entry.urls, ["https://bank.example"]);
+        assert_eq!(entry.username, "me");
+        assert_eq!(*entry.raw_password, "pw");
+        assert_eq!(entry.tags, ["finance", "personal"]);
+        assert_eq!(entry.notes, "PIN in the safe");
+    }
+
+    #[test]
+    fn one_pux_imports_active_logins_and_passwords() {
+        let data = r#"{"accounts": [{"vaults": [{
+            "attrs": { "name": "Private" },
+            "items": [
+                {
+                    "categoryUuid": "001",
+                    "createdAt": 1600000000,
+                    "updatedAt": 1600000100,
+                    "state": "active",
+                    "overview": {
+                        "title": "GitHub",
+                        "url": "https://github.com",
+                        "urls": [{ "url": "https://github.com/login" }],
+                        "tags": ["work"]
+                    },
+                    "details": {
+                        "loginFields": [
+                            { "designation": "username", "value": "me" },
+                            { "designation": "password", "value": "pw" }
+                        ],
+                        "notesPlain": "2FA on the phone"
+                    }
+                },
+                {
+                    "categoryUuid": "005",
+                    "overview": { "title": "Wi-Fi", "url": "https://router.local" },
+                    "details": { "password": "wifi-pw" }
+                },
+                { "categoryUuid": "001", "state": "archived", "overview": { "title": "Old" } },
+                { "categoryUuid": "003", "overview": { "title": "Credit card" } }
+            ]
+        }]}]}"#;
+
+        let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        archive
+            .start_file(ONE_PUX_DATA_FILE, zip::write::SimpleFileOptions::default())
+            .unwrap();
+        std::io::Write::write_all(&mut archive, data.as_bytes()).unwrap();
+        let archive = archive.finish().unwrap().into_inner();
+
+        let parsed = parse(ImportFormat::OnePassword1pux, &archive, "").unwrap();
+        assert_eq!(parsed.skipped, 2);
+        let [github, wifi] = &parsed.entries[..] else {
+            panic!("{} entries", parsed.entries.len());
+        };
+
+        assert_eq!(github.site, "GitHub");
+        assert_eq!(github.username, "me");
+        assert_eq!(*github.raw_password, "pw");
+        assert_eq!(github.urls, ["https://github.com/login"]);
+        assert_eq!(github.tags, ["work", "Private"]);
+        assert_eq!(github.notes, "2FA on the phone");
+        assert_eq!(github.created_at, 1_600_000_000);
+        assert_eq!(github.updated_at, 1_600_000_100);
+
+        assert_eq!(*wifi.raw_password, "wifi-pw");
+        assert_eq!(wifi.urls, ["https://router.local"]);
+        assert_eq!(wifi.tags, ["Private"]);
+    }
+
+    #[test]
+    fn parses_iso8601_timestamps() {
+        assert_eq!(parse_iso8601("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(
+            parse_iso8601("2024-03-01T12:30:00.000Z"),
+            Some(1_709_296_200)
+        );
+        assert_eq!(
+            parse_iso8601("2024-03-01T12:30:00+00:00"),
+            Some(1_709_296_200)
+        );
+        assert_eq!(parse_iso8601("2024-03-01T12:30Z"), Some(1_709_296_200));
+        assert_eq!(parse_iso8601("2024-13-01T00:00:00Z"), None);
+        assert_eq!(parse_iso8601("2024-03-01"), None);
+        assert_eq!(parse_iso8601("yesterday"), None);
+    }
+
+    fn login(site: &str, username: &str, urls: &[&str]) -> PasswordEntryRaw {
+        ExportedLogin {
+            site: site.into(),
+            username: username.into(),
+            urls: urls.iter().map(|url| url.to_string()).collect(),
+            ..ExportedLogin::default()
+        }
+        .into_entry(0)
+    }
+
+    #[test]
+    fn finds_duplicates_in_the_vault_and_the_import() {
+        let auth_state = AuthState::from_key_material(Zeroizing::new([7; 32]));
+        let existing = [PasswordEntryRaw {
+            id: 5,
+            ..login("GitHub", "Me", &["https://www.github.com/login"])
+        }
+        .to_safe(&auth_state)
+        .unwrap()];
+
+        let entries = [
+            // The same host and username, whatever the site is called.
+            login("github.com", " me ", &["https://github.com"]),
+            login("Example", "me", &[]),
+            login("www.example", "ME", &[]),
+            login("Other name", "you", &["https://gitlab.com/a"]),
+            login("GitLab", "you", &["https://www.gitlab.com/b"]),
+            login("github.com", "someone else", &["https://github.com"]),
+        ];
+
+        assert_eq!(
+            find_duplicates(&entries, &existing),
+            [
+                Some(Duplicate::InVault(5)),
+                None,
+                Some(Duplicate::InImport(1)),
+                None,
+                Some(Duplicate::InImport(3)),
+                None,
+            ]
+        );
+    }
+}
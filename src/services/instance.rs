@@ -0,0 +1,125 @@
+//! Keeps the desktop app to one window per data directory.
+//!
+//! The first app to start holds a lock on `app.lock` in the data directory for as long as it
+//! runs and listens on `app.sock` next to it. A second launch finds the lock taken, asks the
+//! running app through the socket to bring its window to the front and exits.
+
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    path::Path,
+};
+
+const LOCK_FILE_NAME: &str = "app.lock";
+#[cfg(unix)]
+const SOCKET_FILE_NAME: &str = "app.sock";
+
+/// Whether this is the only app running with the data directory.
+#[derive(Debug)]
+pub enum Instance {
+    First(InstanceGuard),
+    /// Another app is running, `shown` is whether it was asked to show its window.
+    AlreadyRunning {
+        shown: bool,
+    },
+}
+
+/// Held by the first app for as long as it runs.
+#[derive(Debug)]
+pub struct InstanceGuard {
+    _lock: File,
+    #[cfg(unix)]
+    listener: std::os::unix::net::UnixListener,
+}
+
+/// Take the data directory for this app, or find the app that already has it.
+pub fn acquire(data_dir: &Path) -> Result<Instance, String> {
+    std::fs::create_dir_all(data_dir)
+        .map_err(|err| format!("Could not create {}: {err}", data_dir.display()))?;
+
+    let lock_path = data_dir.join(LOCK_FILE_NAME);
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .map_err(|err| format!("Could not open {}: {err}", lock_path.display()))?;
+
+    match lock.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            return Ok(Instance::AlreadyRunning {
+                shown: show_running(data_dir),
+            })
+        }
+        Err(TryLockError::Error(err)) => {
+            return Err(format!("Could not lock {}: {err}", lock_path.display()))
+        }
+    }
+
+    Ok(Instance::First(InstanceGuard {
+        #[cfg(unix)]
+        listener: unix::bind(&data_dir.join(SOCKET_FILE_NAME))?,
+        _lock: lock,
+    }))
+}
+
+impl InstanceGuard {
+    /// Call `on_launch` each time the app is launched again, until the socket fails.
+    #[cfg(unix)]
+    pub async fn listen_for_launches(&self, on_launch: impl FnMut()) -> Result<(), String> {
+        unix::listen(&self.listener, on_launch).await
+    }
+
+    /// Launching again is only noticed on Unix, elsewhere this waits forever.
+    #[cfg(not(unix))]
+    pub async fn listen_for_launches(&self, _on_launch: impl FnMut()) -> Result<(), String> {
+        std::future::pending().await
+    }
+}
+
+#[cfg(unix)]
+fn show_running(data_dir: &Path) -> bool {
+    std::os::unix::net::UnixStream::connect(data_dir.join(SOCKET_FILE_NAME)).is_ok()
+}
+
+#[cfg(not(unix))]
+fn show_running(_data_dir: &Path) -> bool {
+    false
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::{os::unix::fs::PermissionsExt, path::Path};
+
+    /// Listen on the socket. Whoever holds the instance lock owns it, so a socket that's
+    /// already there was left behind by an app that didn't stop cleanly.
+    pub fn bind(socket_path: &Path) -> Result<std::os::unix::net::UnixListener, String> {
+        if socket_path.exists() {
+            std::fs::remove_file(socket_path).map_err(|err| err.to_string())?;
+        }
+
+        let listener = std::os::unix::net::UnixListener::bind(socket_path)
+            .map_err(|err| format!("Could not listen on {}: {err}", socket_path.display()))?;
+        std::fs::set_permissions(socket_path, std::fs::Permissions::from_mode(0o600))
+            .map_err(|err| err.to_string())?;
+        Ok(listener)
+    }
+
+    pub async fn listen(
+        listener: &std::os::unix::net::UnixListener,
+        mut on_launch: impl FnMut(),
+    ) -> Result<(), String> {
+        let listener = listener.try_clone().map_err(|err| err.to_string())?;
+        listener
+            .set_nonblocking(true)
+            .map_err(|err| err.to_string())?;
+        let listener =
+            tokio::net::UnixListener::from_std(listener).map_err(|err| err.to_string())?;
+
+        loop {
+            // Connecting is the whole request, nothing is sent.
+            listener.accept().await.map_err(|err| err.to_string())?;
+            on_launch();
+        }
+    }
+}
@@ -0,0 +1,550 @@
+//! Checking a vault for damage, and setting aside the entries that can't be read so that the rest
+//! of the vault stays usable.
+//!
+//! Quarantined entries are moved to their own table as they were, with their UUID and revision.
+//! One whose password and other encrypted details decrypt with the master password, e.g. because
+//! only an unencrypted column was damaged, can be restored to the vault as the same entry with
+//! what can be read of the rest. The others can only be discarded.
+//! Changing the master password re-encrypts the ones that decrypt, the others stay encrypted with
+//! the key they had.
+//!
+//! Quarantining is local to the vault and leaves no tombstone: the entry is damaged here, not on
+//! the other devices, and when the vault is synced their copies bring it back. Discarding a
+//! quarantined entry doesn't delete it elsewhere either, only deleting it from the vault does.
+
+use sqlx::{prelude::*, sqlite::SqliteRow, SqliteConnection};
+use zeroize::Zeroizing;
+
+use crate::{
+    models::{self, unix_now, AuthState, PasswordEntrySafe},
+    services::{
+        audit::{self, AuditAction, AuditEvent},
+        database::{DatabaseService, SchemaStatus},
+        error::ServiceError,
+        password_entry::{self, EntryError, EntryProblem},
+    },
+};
+
+/// The quarantine's columns under the names of the vault's, so they read like an entry.
+const QUARANTINED_COLUMNS: &str = "entry_id as id, uuid, revision, site, username, password_hash, urls, encrypted_notes, tags, created_at, updated_at, password_changed_at, encrypted_extras";
+
+/// What the check found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntegrityReport {
+    /// SQLite's findings about the file, empty when it's fine.
+    pub database_problems: Vec<String>,
+    pub schema: SchemaStatus,
+    pub entries_checked: usize,
+    /// Entries that can't be read or decrypted with the current master password.
+    pub entry_problems: Vec<EntryProblem>,
+    /// The entries earlier checks set aside.
+    pub quarantined: Vec<QuarantinedEntry>,
+}
+
+/// An entry [`quarantine_entries`] set aside.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuarantinedEntry {
+    /// Its id in the quarantine, for [`restore_quarantined_entry`] and
+    /// [`discard_quarantined_entry`].
+    pub id: i32,
+    /// Its id when it was in the vault.
+    pub entry_id: i32,
+    pub uuid: String,
+    pub site: String,
+    pub username: String,
+    /// The code of the [`EntryError`] it was set aside for.
+    pub reason: String,
+    pub quarantined_at: i64,
+    /// Whether it can be read and decrypted with the master password, so that it can be restored.
+    pub restorable: bool,
+    /// Whether the vault has an entry with the same UUID again, e.g. brought back by syncing.
+    pub in_vault: bool,
+}
+
+impl IntegrityReport {
+    pub fn is_ok(&self) -> bool {
+        self.database_problems.is_empty() && self.schema.is_ok() && self.entry_problems.is_empty()
+    }
+}
+
+/// Check the database file, its schema and that every entry decrypts with the current key.
+pub async fn check_vault(
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<IntegrityReport, EntryError> {
+    if !auth_state.signed_in {
+        return Err(EntryError::NotSignedIn);
+    }
+
+    let database_problems = db_service.integrity_problems().await?;
+    let schema = db_service.schema_status().await?;
+    let (entries_checked, entry_problems) =
+        password_entry::find_damaged_entries(auth_state, db_service).await?;
+    let quarantined = list_quarantined_entries(auth_state, db_service).await?;
+
+    Ok(IntegrityReport {
+        database_problems,
+        schema,
+        entries_checked,
+        entry_problems,
+        quarantined,
+    })
+}
+
+/// Move entries the check found out of the vault, in a single transaction. Returns how many were
+/// moved, entries that were deleted in the meantime are skipped. No tombstones are left, see the
+/// module's documentation.
+pub async fn quarantine_entries(
+    problems: &[EntryProblem],
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<usize, EntryError> {
+    if !auth_state.signed_in {
+        return Err(EntryError::NotSignedIn);
+    }
+
+    let now = unix_now();
+    let mut moved = 0;
+    let mut tx = db_service.pool.begin().await?;
+
+    for problem in problems {
+        sqlx::query(
+            "insert into quarantined_entries (entry_id, uuid, revision, site, username, password_hash, urls, notes, encrypted_notes, tags, created_at, updated_at, password_changed_at, encrypted_extras, reason, quarantined_at) select id, uuid, revision, site, username, password_hash, urls, notes, encrypted_notes, tags, created_at, updated_at, password_changed_at, encrypted_extras, ?, ? from password_entries where id = ?;",
+        )
+        .bind(problem.error.code())
+        .bind(now)
+        .bind(problem.id)
+        .execute(&mut *tx)
+        .await?;
+
+        let result = sqlx::query("delete from password_entries where id = ?;")
+            .bind(problem.id)
+            .execute(&mut *tx)
+            .await?;
+        moved += result.rows_affected() as usize;
+    }
+
+    tx.commit().await?;
+    Ok(moved)
+}
+
+/// The entries earlier checks set aside, oldest first.
+pub async fn list_quarantined_entries(
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<Vec<QuarantinedEntry>, EntryError> {
+    if !auth_state.signed_in {
+        return Err(EntryError::NotSignedIn);
+    }
+
+    let rows = sqlx::query(&format!(
+        "select quarantined_entries.id as quarantine_id, reason, quarantined_at, exists (select 1 from password_entries where password_entries.uuid = quarantined_entries.uuid and quarantined_entries.uuid != '') as in_vault, {QUARANTINED_COLUMNS} from quarantined_entries order by quarantined_entries.id;"
+    ))
+    .fetch_all(&db_service.pool)
+    .await?;
+
+    let cipher = auth_state.cipher();
+    rows.iter()
+        .map(|row| {
+            let entry = salvage_entry(row);
+            let restorable = entry.to_raw_with(&cipher).is_ok();
+
+            Ok(QuarantinedEntry {
+                id: row.try_get("quarantine_id")?,
+                entry_id: entry.id,
+                uuid: entry.uuid,
+                site: entry.site,
+                username: entry.username,
+                reason: row.try_get("reason")?,
+                quarantined_at: row.try_get("quarantined_at")?,
+                restorable,
+                in_vault: row.try_get("in_vault")?,
+            })
+        })
+        .collect::<Result<_, sqlx::Error>>()
+        .map_err(EntryError::from)
+}
+
+/// Put a quarantined entry back in the vault, returning its new id. Its encrypted details must
+/// decrypt with the master password, see [`salvage_entry`] for the rest. It keeps its UUID, with a new revision so that syncing shares it again,
+/// unless the vault has an entry with that UUID already, when it's restored as a separate entry.
+pub async fn restore_quarantined_entry(
+    id: i32,
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<i32, EntryError> {
+    if !auth_state.signed_in {
+        return Err(EntryError::NotSignedIn);
+    }
+
+    let mut tx = db_service.pool.begin_with("begin immediate").await?;
+    let row = sqlx::query(&format!(
+        "select {QUARANTINED_COLUMNS} from quarantined_entries where id = ?;"
+    ))
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(EntryError::NotFound(id))?;
+    let entry = salvage_entry(&row);
+    entry
+        .to_raw(auth_state)
+        .map_err(EntryError::decrypting(entry.id))?;
+
+    let uuid_taken: bool =
+        sqlx::query_scalar("select exists (select 1 from password_entries where uuid = ?);")
+            .bind(&entry.uuid)
+            .fetch_one(&mut *tx)
+            .await?;
+    let uuid = if uuid_taken {
+        String::new()
+    } else {
+        entry.uuid.clone()
+    };
+    if !uuid.is_empty() {
+        sqlx::query("delete from tombstones where uuid = ?;")
+            .bind(&uuid)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    let restored = PasswordEntrySafe {
+        uuid,
+        revision: entry.revision + 1,
+        updated_at: unix_now(),
+        ..entry
+    };
+    let new_id = password_entry::insert_password_entry(&restored, &mut *tx).await?;
+    sqlx::query("delete from quarantined_entries where id = ?;")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    let event = AuditEvent::for_entry(AuditAction::Create, new_id, &restored.site)
+        .with_detail("Restored from quarantine");
+    let _ = audit::record(event, auth_state, db_service).await;
+    Ok(new_id)
+}
+
+/// Delete a quarantined entry for good. Copies of it on other devices are left alone.
+pub async fn discard_quarantined_entry(
+    id: i32,
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<(), EntryError> {
+    if !auth_state.signed_in {
+        return Err(EntryError::NotSignedIn);
+    }
+
+    let row = sqlx::query("delete from quarantined_entries where id = ? returning entry_id, site;")
+        .bind(id)
+        .fetch_optional(&db_service.pool)
+        .await?
+        .ok_or(EntryError::NotFound(id))?;
+
+    let event = AuditEvent::for_entry(
+        AuditAction::Delete,
+        row.try_get("entry_id").unwrap_or_default(),
+        &row.try_get::<String, _>("site").unwrap_or_default(),
+    )
+    .with_detail("Discarded from quarantine");
+    let _ = audit::record(event, auth_state, db_service).await;
+    Ok(())
+}
+
+/// Encrypt the quarantined entries that decrypt with the old key with the key of a new master
+/// password, as part of changing it. The others are left encrypted with the key they had.
+pub async fn change_key_in(
+    auth_state: &AuthState,
+    new_auth_state: &AuthState,
+    conn: &mut SqliteConnection,
+) -> Result<(), EntryError> {
+    let old_cipher = auth_state.cipher();
+    let new_cipher = new_auth_state.cipher();
+
+    let rows = sqlx::query(
+        "select id, password_hash, encrypted_notes, encrypted_extras from quarantined_entries;",
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    for row in rows {
+        let reencrypt = |column| -> Option<String> {
+            let encrypted = row.try_get::<Option<String>, _>(column).ok()?;
+            match encrypted.unwrap_or_default() {
+                encrypted if encrypted.is_empty() => Some(encrypted),
+                encrypted => {
+                    let plaintext = Zeroizing::new(models::decrypt(&old_cipher, &encrypted).ok()?);
+                    models::encrypt(&new_cipher, &plaintext).ok()
+                }
+            }
+        };
+        let (Some(password_hash), Some(notes), Some(extras)) = (
+            reencrypt("password_hash"),
+            reencrypt("encrypted_notes"),
+            reencrypt("encrypted_extras"),
+        ) else {
+            continue;
+        };
+
+        sqlx::query(
+            "update quarantined_entries set password_hash = ?, encrypted_notes = ?, encrypted_extras = ? where id = ?;",
+        )
+        .bind(password_hash)
+        .bind(notes)
+        .bind(extras)
+        .bind(row.try_get::<i32, _>("id")?)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// What can be read of a quarantined entry. Columns that don't have the schema's type are left
+/// empty, or set to now for the timestamps, as the entry can't be saved with them.
+fn salvage_entry(row: &SqliteRow) -> PasswordEntrySafe {
+    let text = |column| row.try_get::<String, _>(column).unwrap_or_default();
+    let time = |column| row.try_get::<i64, _>(column).unwrap_or_else(|_| unix_now());
+
+    PasswordEntrySafe {
+        id: row.try_get("id").unwrap_or_default(),
+        uuid: text("uuid"),
+        revision: row.try_get("revision").unwrap_or_default(),
+        site: text("site"),
+        username: text("username"),
+        password_hash: text("password_hash"),
+        urls: password_entry::parse_urls(&text("urls")),
+        encrypted_notes: text("encrypted_notes"),
+        tags: password_entry::parse_tags(&text("tags")),
+        created_at: time("created_at"),
+        updated_at: time("updated_at"),
+        password_changed_at: time("password_changed_at"),
+        encrypted_extras: text("encrypted_extras"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{EntryExtras, PasswordEntryRaw},
+        services::authentication::{self, AuthError},
+    };
+
+    fn new_entry(site: &str) -> PasswordEntryRaw {
+        PasswordEntryRaw {
+            id: 0,
+            uuid: String::new(),
+            revision: 0,
+            site: site.into(),
+            username: "me".into(),
+            raw_password: Zeroizing::new("hunter2".into()),
+            urls: vec!["https://example.com".into()],
+            notes: "note".into(),
+            tags: Vec::new(),
+            created_at: 100,
+            updated_at: 100,
+            password_changed_at: 100,
+            extras: EntryExtras::default(),
+        }
+    }
+
+    fn key(byte: u8) -> AuthState {
+        AuthState::from_key_material(Zeroizing::new([byte; 32]))
+    }
+
+    /// A vault with a damaged but decryptable entry, one from another key and a good one,
+    /// returning the damaged one's UUID.
+    async fn damaged_vault(auth_state: &AuthState, db_service: &DatabaseService) -> String {
+        let damaged =
+            password_entry::create_password_entry(new_entry("damaged"), auth_state, db_service)
+                .await
+                .unwrap();
+        password_entry::create_password_entry(new_entry("foreign"), &key(8), db_service)
+            .await
+            .unwrap();
+        password_entry::create_password_entry(new_entry("good"), auth_state, db_service)
+            .await
+            .unwrap();
+
+        sqlx::query("update password_entries set created_at = 'soon' where id = ?;")
+            .bind(damaged)
+            .execute(&db_service.pool)
+            .await
+            .unwrap();
+        sqlx::query_scalar("select uuid from password_entries where id = ?;")
+            .bind(damaged)
+            .fetch_one(&db_service.pool)
+            .await
+            .unwrap()
+    }
+
+    async fn quarantine(auth_state: &AuthState, db_service: &DatabaseService) {
+        let report = check_vault(auth_state, db_service).await.unwrap();
+        assert_eq!(report.entries_checked, 3);
+        let codes: Vec<_> = report
+            .entry_problems
+            .iter()
+            .map(|problem| problem.error.code())
+            .collect();
+        assert_eq!(codes, ["entry.corrupt", "entry.wrong_key"]);
+
+        assert_eq!(
+            quarantine_entries(&report.entry_problems, auth_state, db_service)
+                .await
+                .unwrap(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn quarantined_entries_can_be_restored_or_discarded() {
+        let (_dir, db_service) = DatabaseService::temporary().await;
+        let auth_state = key(7);
+        let uuid = damaged_vault(&auth_state, &db_service).await;
+        quarantine(&auth_state, &db_service).await;
+
+        // The other devices keep their copies.
+        let tombstones: i64 = sqlx::query_scalar("select count(*) from tombstones;")
+            .fetch_one(&db_service.pool)
+            .await
+            .unwrap();
+        assert_eq!(tombstones, 0);
+
+        let report = check_vault(&auth_state, &db_service).await.unwrap();
+        assert!(report.is_ok());
+        let [damaged, foreign] = &report.quarantined[..] else {
+            panic!("{:?}", report.quarantined);
+        };
+        assert_eq!(damaged.uuid, uuid);
+        assert_eq!(damaged.reason, "entry.corrupt");
+        assert!(damaged.restorable && !damaged.in_vault);
+        assert!(!foreign.restorable);
+
+        assert_eq!(
+            restore_quarantined_entry(foreign.id, &auth_state, &db_service)
+                .await
+                .unwrap_err()
+                .code(),
+            "entry.wrong_key"
+        );
+
+        let id = restore_quarantined_entry(damaged.id, &auth_state, &db_service)
+            .await
+            .unwrap();
+        let restored = password_entry::get_password_entry_by_id(id, &auth_state, &db_service)
+            .await
+            .unwrap();
+        assert_eq!(restored.uuid, uuid);
+        assert_eq!(restored.revision, 2);
+        assert_eq!(restored.site, "damaged");
+        assert_eq!(restored.notes, "note");
+        assert_eq!(restored.urls, ["https://example.com"]);
+
+        discard_quarantined_entry(foreign.id, &auth_state, &db_service)
+            .await
+            .unwrap();
+        assert_eq!(
+            discard_quarantined_entry(foreign.id, &auth_state, &db_service).await,
+            Err(EntryError::NotFound(foreign.id))
+        );
+        assert!(list_quarantined_entries(&auth_state, &db_service)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn an_entry_synced_back_is_restored_as_a_copy() {
+        let (_dir, db_service) = DatabaseService::temporary().await;
+        let auth_state = key(7);
+        let uuid = damaged_vault(&auth_state, &db_service).await;
+        quarantine(&auth_state, &db_service).await;
+
+        let synced = PasswordEntryRaw {
+            uuid: uuid.clone(),
+            ..new_entry("damaged")
+        };
+        password_entry::insert_password_entry(
+            &synced.to_safe(&auth_state).unwrap(),
+            &db_service.pool,
+        )
+        .await
+        .unwrap();
+
+        let quarantined = list_quarantined_entries(&auth_state, &db_service)
+            .await
+            .unwrap();
+        assert!(quarantined[0].in_vault);
+
+        let id = restore_quarantined_entry(quarantined[0].id, &auth_state, &db_service)
+            .await
+            .unwrap();
+        let restored = password_entry::get_password_entry_by_id(id, &auth_state, &db_service)
+            .await
+            .unwrap();
+        assert_ne!(restored.uuid, uuid);
+        assert!(!restored.uuid.is_empty());
+    }
+
+    #[tokio::test]
+    async fn changing_the_key_reencrypts_what_decrypts() {
+        let (_dir, db_service) = DatabaseService::temporary().await;
+        let auth_state = key(7);
+        damaged_vault(&auth_state, &db_service).await;
+        quarantine(&auth_state, &db_service).await;
+
+        let new_auth_state = key(9);
+        let mut conn = db_service.pool.acquire().await.unwrap();
+        change_key_in(&auth_state, &new_auth_state, &mut conn)
+            .await
+            .unwrap();
+        drop(conn);
+
+        let quarantined = list_quarantined_entries(&new_auth_state, &db_service)
+            .await
+            .unwrap();
+        let restorable: Vec<_> = quarantined
+            .iter()
+            .map(|entry| (entry.site.as_str(), entry.restorable))
+            .collect();
+        assert_eq!(restorable, [("damaged", true), ("foreign", false)]);
+
+        // The one from another key is left as it was.
+        let still_foreign = list_quarantined_entries(&key(8), &db_service)
+            .await
+            .unwrap();
+        assert!(still_foreign[1].restorable);
+    }
+
+    #[tokio::test]
+    async fn the_master_password_isnt_changed_over_damaged_entries() {
+        let (_dir, db_service) = DatabaseService::temporary().await;
+        let auth_state = key(7);
+        damaged_vault(&auth_state, &db_service).await;
+
+        let err = authentication::set_master_password(
+            Zeroizing::new("Strong-Test-Phrase-123".into()),
+            &auth_state,
+            &db_service,
+        )
+        .await
+        .unwrap_err();
+        let AuthError::DamagedEntries(problems) = &err else {
+            panic!("{err}");
+        };
+        let sites: Vec<_> = problems
+            .iter()
+            .map(|problem| problem.site.as_str())
+            .collect();
+        assert_eq!(sites, ["damaged", "foreign"]);
+        assert!(err.to_string().contains("pw check --quarantine"));
+
+        // Nothing was re-encrypted.
+        let good = password_entry::load_all_password_entries_in(&auth_state, &db_service.pool)
+            .await
+            .unwrap();
+        assert_eq!(good.entries.len(), 1);
+        assert_eq!(good.problems.len(), 2);
+    }
+}
@@ -0,0 +1,1614 @@
+//! Reading and writing KeePass KDBX 4 databases, the format KeePass 2 and KeePassXC save in.
+//!
+//! A database is read into [`PasswordEntryRaw`]s by [`read_database`] and written by
+//! [`write_database`]. The parts of a KeePass entry this app has no fields for, such as its group,
+//! custom fields, attachments and history, are kept in [`EntryExtras`] so that moving a vault
+//! between the two loses nothing. Databases locked with a key file aren't supported.
+
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    path::Path,
+};
+
+use aes::{
+    cipher::{
+        block_padding::Pkcs7, BlockDecryptMut, BlockEncrypt, BlockEncryptMut, KeyInit, KeyIvInit,
+        StreamCipher,
+    },
+    Aes256,
+};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::prelude::*;
+use chacha20::ChaCha20;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use hmac::{Hmac, Mac};
+use quick_xml::{
+    escape::{escape, resolve_predefined_entity},
+    events::{BytesStart, Event},
+    Reader,
+};
+use salsa20::Salsa20;
+use sha2::{Digest, Sha256, Sha512};
+use zeroize::Zeroizing;
+
+use crate::{
+    models::{
+        unix_now, Attachment, AuthState, CustomField, EntryExtras, EntryRevision, PasswordEntryRaw,
+    },
+    services::{
+        audit::{self, AuditAction, AuditEvent},
+        database::DatabaseService,
+        import, password_entry, query,
+    },
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SIGNATURE: [u8; 8] = [0x03, 0xd9, 0xa2, 0x9a, 0x67, 0xfb, 0x4b, 0xb5];
+/// KDBX 4.0, which KeePass 2.35 and KeePassXC 2.3 and later can open.
+const VERSION: u32 = 0x0004_0000;
+const MAJOR_VERSION_MASK: u32 = 0xffff_0000;
+
+const CIPHER_AES256: [u8; 16] = [
+    0x31, 0xc1, 0xf2, 0xe6, 0xbf, 0x71, 0x43, 0x50, 0xbe, 0x58, 0x05, 0x21, 0x6a, 0xfc, 0x5a, 0xff,
+];
+const CIPHER_CHACHA20: [u8; 16] = [
+    0xd6, 0x03, 0x8a, 0x2b, 0x8b, 0x6f, 0x4c, 0xb5, 0xa5, 0x24, 0x33, 0x9a, 0x31, 0xdb, 0xb5, 0x9a,
+];
+const CIPHER_TWOFISH: [u8; 16] = [
+    0xad, 0x68, 0xf2, 0x9f, 0x57, 0x6f, 0x4b, 0xb9, 0xa3, 0x6a, 0xd4, 0x7a, 0xf9, 0x65, 0x34, 0x6c,
+];
+const KDF_AES: [u8; 16] = [
+    0xc9, 0xd9, 0xf3, 0x9a, 0x62, 0x8a, 0x44, 0x60, 0xbf, 0x74, 0x0d, 0x08, 0xc1, 0x8a, 0x4f, 0xea,
+];
+const KDF_ARGON2D: [u8; 16] = [
+    0xef, 0x63, 0x6d, 0xdf, 0x8c, 0x29, 0x44, 0x4b, 0x91, 0xf7, 0xa9, 0xa4, 0x03, 0xe3, 0x0a, 0x0c,
+];
+const KDF_ARGON2ID: [u8; 16] = [
+    0x9e, 0x29, 0x8b, 0x19, 0x56, 0xdb, 0x47, 0x73, 0xb2, 0x3d, 0xfc, 0x3e, 0xc6, 0xf0, 0xa1, 0xe6,
+];
+
+/// Argon2 settings for exported databases, similar to KeePassXC's defaults.
+const ARGON2_MEMORY_BYTES: u64 = 64 * 1024 * 1024;
+const ARGON2_ITERATIONS: u64 = 10;
+const ARGON2_PARALLELISM: u32 = 2;
+
+const HEADER_END: u8 = 0;
+const HEADER_CIPHER_ID: u8 = 2;
+const HEADER_COMPRESSION: u8 = 3;
+const HEADER_MASTER_SEED: u8 = 4;
+const HEADER_ENCRYPTION_IV: u8 = 7;
+const HEADER_KDF_PARAMETERS: u8 = 11;
+
+const INNER_HEADER_END: u8 = 0;
+const INNER_HEADER_STREAM_ID: u8 = 1;
+const INNER_HEADER_STREAM_KEY: u8 = 2;
+const INNER_HEADER_BINARY: u8 = 3;
+
+const STREAM_SALSA20: u32 = 2;
+const STREAM_CHACHA20: u32 = 3;
+const SALSA20_NONCE: [u8; 8] = [0xe8, 0x30, 0x09, 0x4b, 0x97, 0x20, 0x5d, 0x2a];
+
+/// The payload is split into blocks of this size, each with its own HMAC.
+const BLOCK_SIZE: usize = 1024 * 1024;
+/// Seconds from 0001-01-01, which KeePass counts time from, to the unix epoch.
+const KEEPASS_EPOCH_OFFSET: i64 = 62_135_596_800;
+/// The string field KeePassXC keeps an entry's additional URLs in, followed by `_1`, `_2`...
+const EXTRA_URL_FIELD: &str = "KP2A_URL";
+const GROUP_ICON: u32 = 48;
+
+const WRONG_PASSWORD: &str =
+    "The password is wrong, or the database also needs a key file, which isn't supported";
+const DAMAGED: &str = "The database is damaged";
+
+/// The cipher a database's contents are encrypted with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KdbxCipher {
+    Aes256,
+    ChaCha20,
+}
+
+impl KdbxCipher {
+    pub const ALL: [KdbxCipher; 2] = [KdbxCipher::Aes256, KdbxCipher::ChaCha20];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            KdbxCipher::Aes256 => "AES-256",
+            KdbxCipher::ChaCha20 => "ChaCha20",
+        }
+    }
+
+    /// A stable name for the cipher, e.g. for form values.
+    pub fn key(&self) -> &'static str {
+        match self {
+            KdbxCipher::Aes256 => "aes256",
+            KdbxCipher::ChaCha20 => "chacha20",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|cipher| cipher.key() == key)
+    }
+
+    fn from_uuid(uuid: &[u8]) -> Result<Self, String> {
+        if uuid == CIPHER_AES256 {
+            Ok(KdbxCipher::Aes256)
+        } else if uuid == CIPHER_CHACHA20 {
+            Ok(KdbxCipher::ChaCha20)
+        } else if uuid == CIPHER_TWOFISH {
+            Err("Databases encrypted with Twofish aren't supported, change the encryption to AES-256 or ChaCha20 in KeePass first".into())
+        } else {
+            Err("The database is encrypted with an unknown cipher".into())
+        }
+    }
+
+    fn uuid(&self) -> [u8; 16] {
+        match self {
+            KdbxCipher::Aes256 => CIPHER_AES256,
+            KdbxCipher::ChaCha20 => CIPHER_CHACHA20,
+        }
+    }
+
+    fn iv_len(&self) -> usize {
+        match self {
+            KdbxCipher::Aes256 => 16,
+            KdbxCipher::ChaCha20 => 12,
+        }
+    }
+
+    fn encrypt(&self, key: &[u8], iv: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, String> {
+        match self {
+            KdbxCipher::Aes256 => Ok(cbc::Encryptor::<Aes256>::new_from_slices(key, iv)
+                .map_err(|err| err.to_string())?
+                .encrypt_padded_vec_mut::<Pkcs7>(plaintext)),
+            KdbxCipher::ChaCha20 => {
+                let mut data = plaintext.to_vec();
+                ChaCha20::new_from_slices(key, iv)
+                    .map_err(|err| err.to_string())?
+                    .apply_keystream(&mut data);
+                Ok(data)
+            }
+        }
+    }
+
+    fn decrypt(&self, key: &[u8], iv: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, String> {
+        match self {
+            KdbxCipher::Aes256 => cbc::Decryptor::<Aes256>::new_from_slices(key, iv)
+                .map_err(|_| DAMAGED.to_string())?
+                .decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+                .map_err(|_| DAMAGED.to_string()),
+            KdbxCipher::ChaCha20 => {
+                let mut data = ciphertext.to_vec();
+                ChaCha20::new_from_slices(key, iv)
+                    .map_err(|_| DAMAGED.to_string())?
+                    .apply_keystream(&mut data);
+                Ok(data)
+            }
+        }
+    }
+}
+
+/// The entries read from a database.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KdbxDatabase {
+    pub name: String,
+    pub entries: Vec<PasswordEntryRaw>,
+    /// Entries in the recycle bin, which aren't read.
+    pub skipped: usize,
+}
+
+/// Whether `data` looks like a KeePass database.
+pub fn is_kdbx(data: &[u8]) -> bool {
+    data.starts_with(&SIGNATURE)
+}
+
+/// Decrypt and read a KDBX 4 database.
+pub fn read_database(data: &[u8], password: &str) -> Result<KdbxDatabase, String> {
+    let mut reader = ByteReader::new(data);
+    if !is_kdbx(reader.take(SIGNATURE.len())?) {
+        return Err("The file is not a KeePass database".into());
+    }
+
+    let version = reader.u32()?;
+    if version & MAJOR_VERSION_MASK < VERSION {
+        return Err("This is an older KDBX 3 database, save it as KDBX 4 in KeePass or KeePassXC first to import it".into());
+    } else if version & MAJOR_VERSION_MASK > VERSION {
+        return Err(
+            "The database was saved by a newer version of KeePass than is supported".into(),
+        );
+    }
+
+    let header = OuterHeader::read(&mut reader)?;
+    let header_bytes = &data[..reader.position];
+
+    if sha256(&[header_bytes]).as_slice() != reader.take(32)? {
+        return Err(DAMAGED.into());
+    }
+
+    let keys = Keys::derive(
+        &header.master_seed,
+        header.kdf.transform(password)?.as_slice(),
+    );
+    let expected_hmac = reader.take(32)?;
+    keys.hmac(u64::MAX, &[header_bytes])
+        .verify_slice(expected_hmac)
+        .map_err(|_| WRONG_PASSWORD.to_string())?;
+
+    let ciphertext = read_blocks(&mut reader, &keys)?;
+    let plaintext = Zeroizing::new(header.cipher.decrypt(
+        keys.cipher_key.as_slice(),
+        &header.encryption_iv,
+        &ciphertext,
+    )?);
+
+    let payload = if header.compressed {
+        let mut decompressed = Zeroizing::new(Vec::new());
+        GzDecoder::new(plaintext.as_slice())
+            .read_to_end(&mut decompressed)
+            .map_err(|_| DAMAGED.to_string())?;
+        decompressed
+    } else {
+        plaintext
+    };
+
+    let mut reader = ByteReader::new(&payload);
+    let inner_header = InnerHeader::read(&mut reader)?;
+    let xml = std::str::from_utf8(reader.rest()).map_err(|_| DAMAGED.to_string())?;
+    let mut stream = ProtectedStream::new(inner_header.stream_id, &inner_header.stream_key)?;
+    let document = parse_xml(xml, &mut stream)?;
+
+    database_from_xml(&document, &inner_header.binaries)
+}
+
+/// Write `entries` as a KDBX 4 database locked with `password`. Entries' groups, custom fields,
+/// attachments and history are written from their [`EntryExtras`].
+pub fn write_database(
+    name: &str,
+    entries: &[PasswordEntryRaw],
+    password: &str,
+    cipher: KdbxCipher,
+) -> Result<Vec<u8>, String> {
+    let mut kdf_salt = [0u8; 32];
+    OsRng.fill_bytes(&mut kdf_salt);
+
+    let kdf = Kdf::Argon2 {
+        algorithm: Algorithm::Argon2d,
+        version: Version::V0x13,
+        salt: kdf_salt.to_vec(),
+        iterations: ARGON2_ITERATIONS,
+        memory: ARGON2_MEMORY_BYTES,
+        parallelism: ARGON2_PARALLELISM,
+    };
+
+    write_database_with(name, entries, password, cipher, &kdf)
+}
+
+fn write_database_with(
+    name: &str,
+    entries: &[PasswordEntryRaw],
+    password: &str,
+    cipher: KdbxCipher,
+    kdf: &Kdf,
+) -> Result<Vec<u8>, String> {
+    let mut master_seed = [0u8; 32];
+    let mut encryption_iv = vec![0u8; cipher.iv_len()];
+    let mut stream_key = Zeroizing::new([0u8; 64]);
+    OsRng.fill_bytes(&mut master_seed);
+    OsRng.fill_bytes(&mut encryption_iv);
+    OsRng.fill_bytes(stream_key.as_mut_slice());
+
+    let mut header = SIGNATURE.to_vec();
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    write_field(&mut header, HEADER_CIPHER_ID, &cipher.uuid());
+    write_field(&mut header, HEADER_COMPRESSION, &1u32.to_le_bytes());
+    write_field(&mut header, HEADER_MASTER_SEED, &master_seed);
+    write_field(&mut header, HEADER_ENCRYPTION_IV, &encryption_iv);
+    write_field(&mut header, HEADER_KDF_PARAMETERS, &kdf.to_parameters());
+    write_field(&mut header, HEADER_END, b"\r\n\r\n");
+
+    let keys = Keys::derive(&master_seed, kdf.transform(password)?.as_slice());
+
+    let mut stream = ProtectedStream::new(STREAM_CHACHA20, stream_key.as_slice())?;
+    let mut writer = XmlWriter::new(&mut stream);
+    writer.database(name, entries);
+    let XmlWriter { xml, binaries, .. } = writer;
+
+    let mut payload = Zeroizing::new(Vec::new());
+    write_field(
+        &mut payload,
+        INNER_HEADER_STREAM_ID,
+        &STREAM_CHACHA20.to_le_bytes(),
+    );
+    write_field(&mut payload, INNER_HEADER_STREAM_KEY, stream_key.as_slice());
+    for binary in binaries {
+        // The leading byte holds flags, none of which are needed.
+        let mut field = Zeroizing::new(vec![0u8]);
+        field.extend_from_slice(binary);
+        write_field(&mut payload, INNER_HEADER_BINARY, &field);
+    }
+    write_field(&mut payload, INNER_HEADER_END, &[]);
+    payload.extend_from_slice(xml.as_bytes());
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&payload).map_err(|err| err.to_string())?;
+    let compressed = Zeroizing::new(encoder.finish().map_err(|err| err.to_string())?);
+    let ciphertext = cipher.encrypt(keys.cipher_key.as_slice(), &encryption_iv, &compressed)?;
+
+    let mut output = header.clone();
+    output.extend_from_slice(sha256(&[&header]).as_slice());
+    output.extend_from_slice(&keys.hmac(u64::MAX, &[&header]).finalize().into_bytes());
+    write_blocks(&mut output, &ciphertext, &keys);
+
+    Ok(output)
+}
+
+/// Export the whole vault to a KDBX 4 database at `path`, returning how many entries were written.
+pub async fn export_database(
+    path: &Path,
+    password: Zeroizing<String>,
+    cipher: KdbxCipher,
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<usize, String> {
+    if path.exists() {
+        return Err(format!(
+            "There is already a file at {}, choose another name",
+            path.display()
+        ));
+    }
+
+    let entries =
+        password_entry::get_all_password_entries(auth_state, db_service, String::new()).await?;
+    let event = AuditEvent::new(AuditAction::Export).with_detail(format!(
+        "{} entries to the KeePass database {}",
+        entries.len(),
+        path.display()
+    ));
+    let path = path.to_path_buf();
+
+    let written = tokio::task::spawn_blocking(move || {
+        let data = write_database("Password Manager", &entries, &password, cipher)?;
+        std::fs::write(&path, data).map_err(|err| err.to_string())?;
+        Ok::<_, String>(entries.len())
+    })
+    .await
+    .map_err(|err| err.to_string())??;
+
+    let _ = audit::record(event, auth_state, db_service).await;
+    Ok(written)
+}
+
+/// Reads the little endian values KDBX files are made of.
+struct ByteReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or("The database is truncated")?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        let bytes = &self.data[self.position..];
+        self.position = self.data.len();
+        bytes
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    /// A header field's id and data.
+    fn field(&mut self) -> Result<(u8, &'a [u8]), String> {
+        let id = self.u8()?;
+        let len = self.u32()? as usize;
+        Ok((id, self.take(len)?))
+    }
+}
+
+fn write_field(output: &mut Vec<u8>, id: u8, data: &[u8]) {
+    output.push(id);
+    output.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    output.extend_from_slice(data);
+}
+
+/// The unencrypted header at the start of the file.
+struct OuterHeader {
+    cipher: KdbxCipher,
+    compressed: bool,
+    master_seed: Vec<u8>,
+    encryption_iv: Vec<u8>,
+    kdf: Kdf,
+}
+
+impl OuterHeader {
+    fn read(reader: &mut ByteReader) -> Result<Self, String> {
+        let mut cipher = None;
+        let mut compressed = false;
+        let mut master_seed = None;
+        let mut encryption_iv = None;
+        let mut kdf = None;
+
+        loop {
+            let (id, data) = reader.field()?;
+            match id {
+                HEADER_END => break,
+                HEADER_CIPHER_ID => cipher = Some(KdbxCipher::from_uuid(data)?),
+                HEADER_COMPRESSION => compressed = data != [0, 0, 0, 0],
+                HEADER_MASTER_SEED => master_seed = Some(data.to_vec()),
+                HEADER_ENCRYPTION_IV => encryption_iv = Some(data.to_vec()),
+                HEADER_KDF_PARAMETERS => kdf = Some(Kdf::from_parameters(data)?),
+                // Public custom data and anything newer isn't needed to read the database.
+                _ => {}
+            }
+        }
+
+        let missing = || "The database header is incomplete".to_string();
+        let cipher = cipher.ok_or_else(missing)?;
+        let encryption_iv = encryption_iv.ok_or_else(missing)?;
+        if encryption_iv.len() != cipher.iv_len() {
+            return Err(DAMAGED.into());
+        }
+
+        Ok(Self {
+            cipher,
+            compressed,
+            master_seed: master_seed.ok_or_else(missing)?,
+            encryption_iv,
+            kdf: kdf.ok_or_else(missing)?,
+        })
+    }
+}
+
+/// How the password is turned into the key the database is encrypted with.
+enum Kdf {
+    Aes {
+        seed: Vec<u8>,
+        rounds: u64,
+    },
+    Argon2 {
+        algorithm: Algorithm,
+        version: Version,
+        salt: Vec<u8>,
+        iterations: u64,
+        /// In bytes, not the KiB Argon2 usually counts in.
+        memory: u64,
+        parallelism: u32,
+    },
+}
+
+impl Kdf {
+    fn from_parameters(data: &[u8]) -> Result<Self, String> {
+        let parameters = read_variant_dictionary(data)?;
+        let bytes = |name: &str| match parameters.get(name) {
+            Some(Variant::Bytes(bytes)) => Ok(bytes.clone()),
+            _ => Err(format!(
+                "The database's key derivation is missing \"{name}\""
+            )),
+        };
+        let number = |name: &str| match parameters.get(name) {
+            Some(Variant::U32(value)) => Ok(*value as u64),
+            Some(Variant::U64(value)) => Ok(*value),
+            _ => Err(format!(
+                "The database's key derivation is missing \"{name}\""
+            )),
+        };
+
+        let uuid = bytes("$UUID")?;
+        if uuid == KDF_AES {
+            return Ok(Kdf::Aes {
+                seed: bytes("S")?,
+                rounds: number("R")?,
+            });
+        }
+
+        let algorithm = if uuid == KDF_ARGON2D {
+            Algorithm::Argon2d
+        } else if uuid == KDF_ARGON2ID {
+            Algorithm::Argon2id
+        } else {
+            return Err("The database uses an unknown key derivation function".into());
+        };
+        let version = match number("V")? {
+            0x10 => Version::V0x10,
+            0x13 => Version::V0x13,
+            _ => return Err("The database uses an unknown version of Argon2".into()),
+        };
+
+        Ok(Kdf::Argon2 {
+            algorithm,
+            version,
+            salt: bytes("S")?,
+            iterations: number("I")?,
+            memory: number("M")?,
+            parallelism: number("P")? as u32,
+        })
+    }
+
+    fn to_parameters(&self) -> Vec<u8> {
+        let parameters = match self {
+            Kdf::Aes { seed, rounds } => vec![
+                ("$UUID", Variant::Bytes(KDF_AES.to_vec())),
+                ("R", Variant::U64(*rounds)),
+                ("S", Variant::Bytes(seed.clone())),
+            ],
+            Kdf::Argon2 {
+                algorithm,
+                version,
+                salt,
+                iterations,
+                memory,
+                parallelism,
+            } => {
+                let uuid = match algorithm {
+                    Algorithm::Argon2id => KDF_ARGON2ID,
+                    _ => KDF_ARGON2D,
+                };
+                vec![
+                    ("$UUID", Variant::Bytes(uuid.to_vec())),
+                    ("S", Variant::Bytes(salt.clone())),
+                    ("P", Variant::U32(*parallelism)),
+                    ("M", Variant::U64(*memory)),
+                    ("I", Variant::U64(*iterations)),
+                    ("V", Variant::U32(*version as u32)),
+                ]
+            }
+        };
+
+        write_variant_dictionary(&parameters)
+    }
+
+    /// Derive the key from the password. This is deliberately slow.
+    fn transform(&self, password: &str) -> Result<Zeroizing<[u8; 32]>, String> {
+        // Key files would be hashed in alongside the password here.
+        let composite_key = sha256(&[sha256(&[password.as_bytes()]).as_slice()]);
+        let mut key = Zeroizing::new([0u8; 32]);
+
+        match self {
+            Kdf::Aes { seed, rounds } => {
+                let cipher = Aes256::new_from_slice(seed).map_err(|_| DAMAGED.to_string())?;
+                key.copy_from_slice(composite_key.as_slice());
+                for _ in 0..*rounds {
+                    for block in key.chunks_exact_mut(16) {
+                        cipher.encrypt_block(aes::Block::from_mut_slice(block));
+                    }
+                }
+                key = sha256(&[key.as_slice()]);
+            }
+            Kdf::Argon2 {
+                algorithm,
+                version,
+                salt,
+                iterations,
+                memory,
+                parallelism,
+            } => {
+                let unsupported = |_| "The database's Argon2 settings are out of range".to_string();
+                let params = Params::new(
+                    u32::try_from(memory / 1024).map_err(unsupported)?,
+                    u32::try_from(*iterations).map_err(unsupported)?,
+                    *parallelism,
+                    Some(key.len()),
+                )
+                .map_err(|err| err.to_string())?;
+
+                Argon2::new(*algorithm, *version, params)
+                    .hash_password_into(composite_key.as_slice(), salt, key.as_mut_slice())
+                    .map_err(|err| err.to_string())?;
+            }
+        }
+
+        Ok(key)
+    }
+}
+
+/// A value in the `VariantDictionary`s KDBX 4 stores key derivation settings in.
+enum Variant {
+    U32(u32),
+    U64(u64),
+    Bool(bool),
+    I32(i32),
+    I64(i64),
+    String(String),
+    Bytes(Vec<u8>),
+}
+
+impl Variant {
+    fn type_id(&self) -> u8 {
+        match self {
+            Variant::U32(_) => 0x04,
+            Variant::U64(_) => 0x05,
+            Variant::Bool(_) => 0x08,
+            Variant::I32(_) => 0x0c,
+            Variant::I64(_) => 0x0d,
+            Variant::String(_) => 0x18,
+            Variant::Bytes(_) => 0x42,
+        }
+    }
+}
+
+fn read_variant_dictionary(data: &[u8]) -> Result<HashMap<String, Variant>, String> {
+    let mut reader = ByteReader::new(data);
+    if reader.u16()? & 0xff00 != 0x0100 {
+        return Err("The database's key derivation settings are in an unknown format".into());
+    }
+
+    let mut dictionary = HashMap::new();
+    loop {
+        let type_id = reader.u8()?;
+        if type_id == 0 {
+            break;
+        }
+
+        let name_len = reader.u32()? as usize;
+        let name = String::from_utf8_lossy(reader.take(name_len)?).to_string();
+        let value_len = reader.u32()? as usize;
+        let value = reader.take(value_len)?;
+        let mut value_reader = ByteReader::new(value);
+
+        let variant = match type_id {
+            0x04 => Variant::U32(value_reader.u32()?),
+            0x05 => Variant::U64(u64::from_le_bytes(value_reader.array()?)),
+            0x08 => Variant::Bool(value_reader.u8()? != 0),
+            0x0c => Variant::I32(i32::from_le_bytes(value_reader.array()?)),
+            0x0d => Variant::I64(i64::from_le_bytes(value_reader.array()?)),
+            0x18 => Variant::String(String::from_utf8_lossy(value).to_string()),
+            0x42 => Variant::Bytes(value.to_vec()),
+            _ => continue,
+        };
+        dictionary.insert(name, variant);
+    }
+
+    Ok(dictionary)
+}
+
+fn write_variant_dictionary(entries: &[(&str, Variant)]) -> Vec<u8> {
+    let mut output = 0x0100u16.to_le_bytes().to_vec();
+
+    for (name, value) in entries {
+        let value_bytes = match value {
+            Variant::U32(value) => value.to_le_bytes().to_vec(),
+            Variant::U64(value) => value.to_le_bytes().to_vec(),
+            Variant::Bool(value) => vec![*value as u8],
+            Variant::I32(value) => value.to_le_bytes().to_vec(),
+            Variant::I64(value) => value.to_le_bytes().to_vec(),
+            Variant::String(value) => value.as_bytes().to_vec(),
+            Variant::Bytes(value) => value.clone(),
+        };
+
+        output.push(value.type_id());
+        output.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        output.extend_from_slice(name.as_bytes());
+        output.extend_from_slice(&(value_bytes.len() as u32).to_le_bytes());
+        output.extend_from_slice(&value_bytes);
+    }
+
+    output.push(0);
+    output
+}
+
+/// The keys derived from the password and the header's master seed.
+struct Keys {
+    cipher_key: Zeroizing<[u8; 32]>,
+    hmac_key: Zeroizing<[u8; 64]>,
+}
+
+impl Keys {
+    fn derive(master_seed: &[u8], transformed_key: &[u8]) -> Self {
+        Self {
+            cipher_key: sha256(&[master_seed, transformed_key]),
+            hmac_key: sha512(&[master_seed, transformed_key, &[1]]),
+        }
+    }
+
+    /// An HMAC over `parts` keyed for the block at `index`. The header uses `u64::MAX`.
+    fn hmac(&self, index: u64, parts: &[&[u8]]) -> HmacSha256 {
+        let block_key = sha512(&[&index.to_le_bytes(), self.hmac_key.as_slice()]);
+        let mut mac = <HmacSha256 as Mac>::new_from_slice(block_key.as_slice())
+            .expect("HMAC accepts keys of any length");
+        for part in parts {
+            mac.update(part);
+        }
+        mac
+    }
+}
+
+fn sha256(parts: &[&[u8]]) -> Zeroizing<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    let mut hash = Zeroizing::new([0u8; 32]);
+    hash.copy_from_slice(&hasher.finalize());
+    hash
+}
+
+fn sha512(parts: &[&[u8]]) -> Zeroizing<[u8; 64]> {
+    let mut hasher = Sha512::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    let mut hash = Zeroizing::new([0u8; 64]);
+    hash.copy_from_slice(&hasher.finalize());
+    hash
+}
+
+/// Read and check the HMAC protected blocks the encrypted payload is split into.
+fn read_blocks(reader: &mut ByteReader, keys: &Keys) -> Result<Vec<u8>, String> {
+    let mut ciphertext = Vec::new();
+
+    for index in 0u64.. {
+        let expected_hmac = reader.take(32)?;
+        let len_bytes = reader.take(4)?;
+        let len = i32::from_le_bytes(len_bytes.try_into().map_err(|_| DAMAGED.to_string())?);
+        let block = reader.take(usize::try_from(len).map_err(|_| DAMAGED.to_string())?)?;
+
+        keys.hmac(index, &[&index.to_le_bytes(), len_bytes, block])
+            .verify_slice(expected_hmac)
+            .map_err(|_| DAMAGED.to_string())?;
+
+        // An empty block marks the end.
+        if block.is_empty() {
+            break;
+        }
+        ciphertext.extend_from_slice(block);
+    }
+
+    Ok(ciphertext)
+}
+
+fn write_blocks(output: &mut Vec<u8>, ciphertext: &[u8], keys: &Keys) {
+    let mut blocks: Vec<&[u8]> = ciphertext.chunks(BLOCK_SIZE).collect();
+    blocks.push(&[]);
+
+    for (index, block) in blocks.into_iter().enumerate() {
+        let index = index as u64;
+        let len_bytes = (block.len() as i32).to_le_bytes();
+        let hmac = keys
+            .hmac(index, &[&index.to_le_bytes(), &len_bytes, block])
+            .finalize()
+            .into_bytes();
+
+        output.extend_from_slice(&hmac);
+        output.extend_from_slice(&len_bytes);
+        output.extend_from_slice(block);
+    }
+}
+
+/// The header at the start of the decrypted payload.
+struct InnerHeader {
+    stream_id: u32,
+    stream_key: Zeroizing<Vec<u8>>,
+    /// Attachment contents, referred to by their index.
+    binaries: Vec<Zeroizing<Vec<u8>>>,
+}
+
+impl InnerHeader {
+    fn read(reader: &mut ByteReader) -> Result<Self, String> {
+        let mut stream_id = None;
+        let mut stream_key = None;
+        let mut binaries = Vec::new();
+
+        loop {
+            let (id, data) = reader.field()?;
+            match id {
+                INNER_HEADER_END => break,
+                INNER_HEADER_STREAM_ID => {
+                    stream_id = Some(u32::from_le_bytes(
+                        data.try_into().map_err(|_| DAMAGED.to_string())?,
+                    ))
+                }
+                INNER_HEADER_STREAM_KEY => stream_key = Some(Zeroizing::new(data.to_vec())),
+                // Skip the flags byte.
+                INNER_HEADER_BINARY => {
+                    binaries.push(Zeroizing::new(data.get(1..).unwrap_or_default().to_vec()))
+                }
+                _ => {}
+            }
+        }
+
+        let missing = || "The database's inner header is incomplete".to_string();
+        Ok(Self {
+            stream_id: stream_id.ok_or_else(missing)?,
+            stream_key: stream_key.ok_or_else(missing)?,
+            binaries,
+        })
+    }
+}
+
+/// Protected values, passwords for example, are additionally encrypted with this stream cipher
+/// in the order they appear in the XML.
+enum ProtectedStream {
+    Salsa20(Salsa20),
+    ChaCha20(ChaCha20),
+}
+
+impl ProtectedStream {
+    fn new(id: u32, key: &[u8]) -> Result<Self, String> {
+        match id {
+            STREAM_SALSA20 => {
+                let key = sha256(&[key]);
+                Salsa20::new_from_slices(key.as_slice(), &SALSA20_NONCE)
+                    .map(ProtectedStream::Salsa20)
+                    .map_err(|err| err.to_string())
+            }
+            STREAM_CHACHA20 => {
+                let hash = sha512(&[key]);
+                ChaCha20::new_from_slices(&hash[..32], &hash[32..44])
+                    .map(ProtectedStream::ChaCha20)
+                    .map_err(|err| err.to_string())
+            }
+            _ => Err("The database protects values with an unsupported cipher".into()),
+        }
+    }
+
+    fn apply(&mut self, data: &mut [u8]) {
+        match self {
+            ProtectedStream::Salsa20(cipher) => cipher.apply_keystream(data),
+            ProtectedStream::ChaCha20(cipher) => cipher.apply_keystream(data),
+        }
+    }
+}
+
+/// An element of the database's XML.
+#[derive(Default)]
+struct Node {
+    name: String,
+    attributes: Vec<(String, String)>,
+    text: Zeroizing<String>,
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn from_start(start: &BytesStart) -> Result<Self, String> {
+        let mut attributes = Vec::new();
+        for attribute in start.attributes() {
+            let attribute = attribute.map_err(|err| err.to_string())?;
+            attributes.push((
+                String::from_utf8_lossy(attribute.key.as_ref()).to_string(),
+                attribute
+                    .unescape_value()
+                    .map_err(|err| err.to_string())?
+                    .to_string(),
+            ));
+        }
+
+        Ok(Self {
+            name: String::from_utf8_lossy(start.name().as_ref()).to_string(),
+            attributes,
+            ..Default::default()
+        })
+    }
+
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn child(&self, name: &str) -> Option<&Node> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Node> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    fn child_text(&self, name: &str) -> &str {
+        self.child(name).map_or("", |child| child.text.as_str())
+    }
+
+    /// Decrypt the text of a protected value. Must be called in document order.
+    fn unprotect(&mut self, stream: &mut ProtectedStream) -> Result<(), String> {
+        if self.name != "Value" || self.attribute("Protected") != Some("True") {
+            return Ok(());
+        }
+
+        let mut value = Zeroizing::new(
+            BASE64_STANDARD
+                .decode(self.text.trim())
+                .map_err(|_| DAMAGED.to_string())?,
+        );
+        stream.apply(&mut value);
+        self.text = Zeroizing::new(
+            std::str::from_utf8(&value)
+                .map_err(|_| DAMAGED.to_string())?
+                .to_string(),
+        );
+        Ok(())
+    }
+}
+
+fn parse_xml(xml: &str, stream: &mut ProtectedStream) -> Result<Node, String> {
+    let xml_error = |err: quick_xml::Error| format!("The database's contents are invalid: {err}");
+    let mut reader = Reader::from_str(xml);
+    let mut stack = vec![Node::default()];
+
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            Event::Start(start) => stack.push(Node::from_start(&start)?),
+            Event::Empty(start) => {
+                let mut node = Node::from_start(&start)?;
+                node.unprotect(stream)?;
+                stack.last_mut().ok_or(DAMAGED)?.children.push(node);
+            }
+            Event::End(_) => {
+                let mut node = stack.pop().ok_or(DAMAGED)?;
+                node.unprotect(stream)?;
+                stack.last_mut().ok_or(DAMAGED)?.children.push(node);
+            }
+            Event::Text(text) => {
+                let text = text.xml10_content().map_err(|err| xml_error(err.into()))?;
+                stack.last_mut().ok_or(DAMAGED)?.text.push_str(&text);
+            }
+            Event::CData(data) => {
+                let text = data.decode().map_err(|err| xml_error(err.into()))?;
+                stack.last_mut().ok_or(DAMAGED)?.text.push_str(&text);
+            }
+            Event::GeneralRef(reference) => {
+                let resolved = match reference.resolve_char_ref().map_err(xml_error)? {
+                    Some(character) => character.to_string(),
+                    None => {
+                        let name = reference.decode().map_err(|err| xml_error(err.into()))?;
+                        resolve_predefined_entity(&name)
+                            .ok_or_else(|| {
+                                format!(
+                                    "The database's contents are invalid: unknown entity &{name};"
+                                )
+                            })?
+                            .to_string()
+                    }
+                };
+                stack.last_mut().ok_or(DAMAGED)?.text.push_str(&resolved);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    match (stack.pop(), stack.is_empty()) {
+        (Some(document), true) => Ok(document),
+        _ => Err(DAMAGED.into()),
+    }
+}
+
+fn database_from_xml(
+    document: &Node,
+    binaries: &[Zeroizing<Vec<u8>>],
+) -> Result<KdbxDatabase, String> {
+    let file = document
+        .child("KeePassFile")
+        .ok_or("The database's contents are invalid: KeePassFile is missing")?;
+    let meta = file.child("Meta");
+    let name = meta.map_or("", |meta| meta.child_text("DatabaseName"));
+    let recycle_bin = meta
+        .map(|meta| meta.child_text("RecycleBinUUID").trim())
+        .filter(|uuid| !uuid.is_empty());
+
+    let mut database = KdbxDatabase {
+        name: name.to_string(),
+        entries: Vec::new(),
+        skipped: 0,
+    };
+
+    // The top level group stands for the database itself so it isn't part of entries' groups.
+    if let Some(root_group) = file.child("Root").and_then(|root| root.child("Group")) {
+        read_group(
+            root_group,
+            &mut Vec::new(),
+            recycle_bin,
+            binaries,
+            &mut database,
+        )?;
+    }
+
+    Ok(database)
+}
+
+fn read_group(
+    group: &Node,
+    path: &mut Vec<String>,
+    recycle_bin: Option<&str>,
+    binaries: &[Zeroizing<Vec<u8>>],
+    database: &mut KdbxDatabase,
+) -> Result<(), String> {
+    if recycle_bin.is_some_and(|uuid| group.child_text("UUID").trim() == uuid) {
+        database.skipped += count_entries(group);
+        return Ok(());
+    }
+
+    for entry in group.children("Entry") {
+        database
+            .entries
+            .push(read_entry(entry, path.clone(), binaries)?);
+    }
+
+    for subgroup in group.children("Group") {
+        path.push(subgroup.child_text("Name").to_string());
+        read_group(subgroup, path, recycle_bin, binaries, database)?;
+        path.pop();
+    }
+
+    Ok(())
+}
+
+fn count_entries(group: &Node) -> usize {
+    group.children("Entry").count() + group.children("Group").map(count_entries).sum::<usize>()
+}
+
+fn read_entry(
+    entry: &Node,
+    group: Vec<String>,
+    binaries: &[Zeroizing<Vec<u8>>],
+) -> Result<PasswordEntryRaw, String> {
+    let now = unix_now();
+    let current = read_revision(entry, binaries, now)?;
+    let times = entry.child("Times");
+    let time = |name: &str| times.and_then(|times| parse_time(times.child_text(name)));
+
+    let mut history = Vec::new();
+    if let Some(history_node) = entry.child("History") {
+        for revision in history_node.children("Entry") {
+            history.push(read_revision(revision, binaries, now)?);
+        }
+    }
+    history.sort_by_key(|revision| revision.updated_at);
+
+    // KeePass doesn't record when the password changed, but the history shows when the current
+    // one first appeared.
+    let password_changed_at = history
+        .iter()
+        .rev()
+        .take_while(|revision| revision.raw_password == current.raw_password)
+        .last()
+        .map_or(current.updated_at, |revision| revision.updated_at);
+
+    let expires = times.is_some_and(|times| times.child_text("Expires").trim() == "True");
+    let keepass_uuid = BASE64_STANDARD
+        .decode(entry.child_text("UUID").trim())
+        .ok()
+        .and_then(|uuid| uuid.try_into().ok());
+
+    let site = match current.site.trim() {
+        "" => current
+            .urls
+            .first()
+            .map(|url| query::url_host(url).to_string())
+            .unwrap_or_default(),
+        site => site.to_string(),
+    };
+
+    Ok(PasswordEntryRaw {
+        id: 0,
+        uuid: String::new(),
+        revision: 0,
+        site,
+        username: current.username,
+        raw_password: current.raw_password,
+        urls: current.urls,
+        notes: current.notes,
+        tags: current.tags,
+        created_at: time("CreationTime").unwrap_or(current.updated_at),
+        updated_at: current.updated_at,
+        password_changed_at,
+        extras: EntryExtras {
+            group,
+            custom_fields: current.custom_fields,
+            attachments: current.attachments,
+            history,
+            expires_at: if expires { time("ExpiryTime") } else { None },
+            keepass_uuid,
+            ssh_key: None,
+        },
+    })
+}
+
+/// Read the fields an entry and each version in its history have.
+fn read_revision(
+    entry: &Node,
+    binaries: &[Zeroizing<Vec<u8>>],
+    now: i64,
+) -> Result<EntryRevision, String> {
+    let mut revision = EntryRevision {
+        tags: entry
+            .child_text("Tags")
+            .split([';', ','])
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(String::from)
+            .collect(),
+        updated_at: entry
+            .child("Times")
+            .and_then(|times| parse_time(times.child_text("LastModificationTime")))
+            .unwrap_or(now),
+        ..Default::default()
+    };
+    let mut extra_urls = Vec::new();
+
+    for string in entry.children("String") {
+        let key = string.child_text("Key");
+        let value_node = string.child("Value");
+        let value = value_node.map_or("", |value| value.text.as_str());
+
+        match key {
+            "Title" => revision.site = value.to_string(),
+            "UserName" => revision.username = value.to_string(),
+            "Password" => revision.raw_password = Zeroizing::new(value.to_string()),
+            "URL" => {
+                if !value.trim().is_empty() {
+                    revision.urls.insert(0, value.trim().to_string());
+                }
+            }
+            "Notes" => revision.notes = value.to_string(),
+            _ if is_extra_url_field(key) => {
+                if !value.trim().is_empty() {
+                    extra_urls.push(value.trim().to_string());
+                }
+            }
+            _ => revision.custom_fields.push(CustomField {
+                name: key.to_string(),
+                value: Zeroizing::new(value.to_string()),
+                protected: value_node.and_then(|value| value.attribute("Protected"))
+                    == Some("True"),
+            }),
+        }
+    }
+    revision.urls.extend(extra_urls);
+
+    for binary in entry.children("Binary") {
+        let reference = binary
+            .child("Value")
+            .and_then(|value| value.attribute("Ref"))
+            .and_then(|reference| reference.parse::<usize>().ok());
+        let data = reference
+            .and_then(|reference| binaries.get(reference))
+            .ok_or("The database refers to an attachment it doesn't contain")?;
+
+        revision.attachments.push(Attachment {
+            name: binary.child_text("Key").to_string(),
+            data: data.to_vec(),
+        });
+    }
+
+    Ok(revision)
+}
+
+fn is_extra_url_field(key: &str) -> bool {
+    key == EXTRA_URL_FIELD
+        || key
+            .strip_prefix(EXTRA_URL_FIELD)
+            .and_then(|suffix| suffix.strip_prefix('_'))
+            .is_some_and(|number| number.parse::<u32>().is_ok())
+}
+
+/// KDBX 4 stores times as base64 encoded seconds since 0001-01-01, older files as ISO 8601.
+fn parse_time(text: &str) -> Option<i64> {
+    let text = text.trim();
+    match BASE64_STANDARD.decode(text) {
+        Ok(bytes) if bytes.len() == 8 => {
+            let seconds = i64::from_le_bytes(bytes.try_into().ok()?);
+            Some(seconds - KEEPASS_EPOCH_OFFSET)
+        }
+        _ => import::parse_iso8601(text),
+    }
+}
+
+fn format_time(unix_time: i64) -> String {
+    BASE64_STANDARD.encode((unix_time + KEEPASS_EPOCH_OFFSET).to_le_bytes())
+}
+
+fn random_uuid() -> [u8; 16] {
+    let mut uuid = [0u8; 16];
+    OsRng.fill_bytes(&mut uuid);
+    uuid
+}
+
+/// Entries arranged into the groups they'll be written in.
+#[derive(Default)]
+struct GroupTree<'a> {
+    entries: Vec<&'a PasswordEntryRaw>,
+    groups: Vec<(&'a str, GroupTree<'a>)>,
+}
+
+impl<'a> GroupTree<'a> {
+    fn insert(&mut self, path: &'a [String], entry: &'a PasswordEntryRaw) {
+        let Some((name, rest)) = path.split_first() else {
+            self.entries.push(entry);
+            return;
+        };
+
+        let index = match self
+            .groups
+            .iter()
+            .position(|(group, _)| *group == name.as_str())
+        {
+            Some(index) => index,
+            None => {
+                self.groups.push((name, GroupTree::default()));
+                self.groups.len() - 1
+            }
+        };
+        self.groups[index].1.insert(rest, entry);
+    }
+}
+
+/// Writes the database's XML, encrypting protected values and collecting attachments as it goes.
+struct XmlWriter<'a> {
+    xml: Zeroizing<String>,
+    stream: &'a mut ProtectedStream,
+    /// Attachment contents in the order they're referred to, each stored once.
+    binaries: Vec<&'a [u8]>,
+    now: i64,
+}
+
+impl<'a> XmlWriter<'a> {
+    fn new(stream: &'a mut ProtectedStream) -> Self {
+        Self {
+            xml: Zeroizing::new(String::new()),
+            stream,
+            binaries: Vec::new(),
+            now: unix_now(),
+        }
+    }
+
+    fn open(&mut self, name: &str) {
+        self.xml.push('<');
+        self.xml.push_str(name);
+        self.xml.push('>');
+    }
+
+    fn close(&mut self, name: &str) {
+        self.xml.push_str("</");
+        self.xml.push_str(name);
+        self.xml.push_str(">\n");
+    }
+
+    fn element(&mut self, name: &str, text: &str) {
+        // Control characters aren't allowed in XML 1.0 even when escaped.
+        let text: String = text
+            .chars()
+            .filter(|character| !character.is_control() || matches!(character, '\t' | '\n' | '\r'))
+            .collect();
+        self.open(name);
+        self.xml.push_str(&escape(text.as_str()));
+        self.close(name);
+    }
+
+    fn database(&mut self, name: &str, entries: &'a [PasswordEntryRaw]) {
+        let mut tree = GroupTree::default();
+        for entry in entries {
+            tree.insert(&entry.extras.group, entry);
+        }
+
+        self.xml
+            .push_str("<?xml version=\"1.0\" encoding=\"utf-8\" standalone=\"yes\"?>\n");
+        self.open("KeePassFile");
+        self.open("Meta");
+        self.element("Generator", "Password Manager");
+        self.element("DatabaseName", name);
+        self.element("DatabaseNameChanged", &format_time(self.now));
+        self.open("MemoryProtection");
+        self.element("ProtectTitle", "False");
+        self.element("ProtectUserName", "False");
+        self.element("ProtectPassword", "True");
+        self.element("ProtectURL", "False");
+        self.element("ProtectNotes", "False");
+        self.close("MemoryProtection");
+        self.element("RecycleBinEnabled", "False");
+        self.close("Meta");
+        self.open("Root");
+        self.group(name, &tree);
+        self.xml.push_str("<DeletedObjects/>\n");
+        self.close("Root");
+        self.close("KeePassFile");
+    }
+
+    fn group(&mut self, name: &str, tree: &GroupTree<'a>) {
+        self.open("Group");
+        self.element("UUID", &BASE64_STANDARD.encode(random_uuid()));
+        self.element("Name", name);
+        self.element("IconID", &GROUP_ICON.to_string());
+        self.times(self.now, self.now, None);
+        self.element("IsExpanded", "True");
+
+        for entry in &tree.entries {
+            self.entry(entry);
+        }
+        for (name, subtree) in &tree.groups {
+            self.group(name, subtree);
+        }
+
+        self.close("Group");
+    }
+
+    fn entry(&mut self, entry: &'a PasswordEntryRaw) {
+        let uuid = BASE64_STANDARD.encode(entry.extras.keepass_uuid.unwrap_or_else(random_uuid));
+
+        self.open("Entry");
+        self.revision_fields(
+            &uuid,
+            &EntryFields {
+                site: &entry.site,
+                username: &entry.username,
+                raw_password: &entry.raw_password,
+                urls: &entry.urls,
+                notes: &entry.notes,
+                tags: &entry.tags,
+                custom_fields: &entry.extras.custom_fields,
+                attachments: &entry.extras.attachments,
+            },
+            entry.created_at,
+            entry.updated_at,
+            entry.extras.expires_at,
+        );
+
+        self.open("History");
+        for revision in &entry.extras.history {
+            self.open("Entry");
+            self.revision_fields(
+                &uuid,
+                &EntryFields {
+                    site: &revision.site,
+                    username: &revision.username,
+                    raw_password: &revision.raw_password,
+                    urls: &revision.urls,
+                    notes: &revision.notes,
+                    tags: &revision.tags,
+                    custom_fields: &revision.custom_fields,
+                    attachments: &revision.attachments,
+                },
+                entry.created_at,
+                revision.updated_at,
+                None,
+            );
+            self.close("Entry");
+        }
+        self.close("History");
+
+        self.close("Entry");
+    }
+
+    fn revision_fields(
+        &mut self,
+        uuid: &str,
+        fields: &EntryFields<'a>,
+        created_at: i64,
+        updated_at: i64,
+        expires_at: Option<i64>,
+    ) {
+        self.element("UUID", uuid);
+        self.element("IconID", "0");
+        self.element("Tags", &fields.tags.join(";"));
+        self.times(created_at, updated_at, expires_at);
+
+        self.string("Title", fields.site, false);
+        self.string("UserName", fields.username, false);
+        self.string("Password", fields.raw_password, true);
+        self.string("URL", fields.urls.first().map_or("", String::as_str), false);
+        self.string("Notes", fields.notes, false);
+        for (index, url) in fields.urls.iter().skip(1).enumerate() {
+            let key = match index {
+                0 => EXTRA_URL_FIELD.to_string(),
+                _ => format!("{EXTRA_URL_FIELD}_{index}"),
+            };
+            self.string(&key, url, false);
+        }
+        for field in fields.custom_fields {
+            self.string(&field.name, &field.value, field.protected);
+        }
+
+        for attachment in fields.attachments {
+            let reference = self.binary_ref(&attachment.data);
+            self.open("Binary");
+            self.element("Key", &attachment.name);
+            self.xml
+                .push_str(&format!("<Value Ref=\"{reference}\"/>\n"));
+            self.close("Binary");
+        }
+
+        self.open("AutoType");
+        self.element("Enabled", "True");
+        self.element("DataTransferObfuscation", "0");
+        self.close("AutoType");
+    }
+
+    fn times(&mut self, created_at: i64, updated_at: i64, expires_at: Option<i64>) {
+        self.open("Times");
+        self.element("CreationTime", &format_time(created_at));
+        self.element("LastModificationTime", &format_time(updated_at));
+        self.element("LastAccessTime", &format_time(updated_at));
+        self.element("ExpiryTime", &format_time(expires_at.unwrap_or(updated_at)));
+        self.element(
+            "Expires",
+            if expires_at.is_some() {
+                "True"
+            } else {
+                "False"
+            },
+        );
+        self.element("UsageCount", "0");
+        self.element("LocationChanged", &format_time(updated_at));
+        self.close("Times");
+    }
+
+    fn string(&mut self, key: &str, value: &str, protected: bool) {
+        self.open("String");
+        self.element("Key", key);
+        if protected {
+            let mut encrypted = Zeroizing::new(value.as_bytes().to_vec());
+            self.stream.apply(&mut encrypted);
+            self.xml.push_str("<Value Protected=\"True\">");
+            self.xml.push_str(&BASE64_STANDARD.encode(&encrypted));
+            self.close("Value");
+        } else {
+            self.element("Value", value);
+        }
+        self.close("String");
+    }
+
+    fn binary_ref(&mut self, data: &'a [u8]) -> usize {
+        match self.binaries.iter().position(|binary| *binary == data) {
+            Some(index) => index,
+            None => {
+                self.binaries.push(data);
+                self.binaries.len() - 1
+            }
+        }
+    }
+}
+
+/// The fields written for both an entry and the versions in its history.
+struct EntryFields<'a> {
+    site: &'a str,
+    username: &'a str,
+    raw_password: &'a str,
+    urls: &'a [String],
+    notes: &'a str,
+    tags: &'a [String],
+    custom_fields: &'a [CustomField],
+    attachments: &'a [Attachment],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWORD: &str = "correct horse battery staple";
+
+    /// Cheap enough to run in a debug build, the format is the same as with the real settings.
+    fn test_kdf() -> Kdf {
+        Kdf::Argon2 {
+            algorithm: Algorithm::Argon2d,
+            version: Version::V0x13,
+            salt: vec![7; 32],
+            iterations: 1,
+            memory: 64 * 1024,
+            parallelism: 1,
+        }
+    }
+
+    fn entry() -> PasswordEntryRaw {
+        PasswordEntryRaw {
+            id: 0,
+            uuid: String::new(),
+            revision: 0,
+            site: "GitHub".into(),
+            username: "octocat".into(),
+            raw_password: Zeroizing::new("s3cret & <xml>".into()),
+            urls: vec![
+                "https://github.com/login".into(),
+                "https://gist.github.com".into(),
+            ],
+            notes: "First line\nSecond line".into(),
+            tags: vec!["dev".into(), "work".into()],
+            created_at: 1_600_000_000,
+            updated_at: 1_700_000_000,
+            password_changed_at: 1_700_000_000,
+            extras: EntryExtras {
+                group: vec!["Work".into(), "Code".into()],
+                custom_fields: vec![
+                    CustomField {
+                        name: "Team".into(),
+                        value: Zeroizing::new("Platform".into()),
+                        protected: false,
+                    },
+                    CustomField {
+                        name: "Recovery code".into(),
+                        value: Zeroizing::new("1234-5678".into()),
+                        protected: true,
+                    },
+                ],
+                attachments: vec![Attachment {
+                    name: "key.txt".into(),
+                    data: b"attached".to_vec(),
+                }],
+                history: vec![EntryRevision {
+                    site: "GitHub".into(),
+                    username: "octocat".into(),
+                    raw_password: Zeroizing::new("old password".into()),
+                    updated_at: 1_650_000_000,
+                    ..EntryRevision::default()
+                }],
+                expires_at: Some(1_800_000_000),
+                keepass_uuid: Some([9; 16]),
+                ssh_key: None,
+            },
+        }
+    }
+
+    #[test]
+    fn entries_survive_a_round_trip() {
+        for cipher in KdbxCipher::ALL {
+            let written =
+                write_database_with("Vault", &[entry()], PASSWORD, cipher, &test_kdf()).unwrap();
+            assert!(is_kdbx(&written));
+
+            let database = read_database(&written, PASSWORD).unwrap();
+            assert_eq!(database.name, "Vault");
+            assert_eq!(database.skipped, 0);
+            assert_eq!(database.entries, vec![entry()], "with {}", cipher.label());
+        }
+    }
+
+    #[test]
+    fn a_wrong_password_is_rejected() {
+        let written = write_database_with(
+            "Vault",
+            &[entry()],
+            PASSWORD,
+            KdbxCipher::Aes256,
+            &test_kdf(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            read_database(&written, "wrong").unwrap_err(),
+            WRONG_PASSWORD
+        );
+    }
+
+    #[test]
+    fn tampering_is_detected() {
+        let mut written = write_database_with(
+            "Vault",
+            &[entry()],
+            PASSWORD,
+            KdbxCipher::ChaCha20,
+            &test_kdf(),
+        )
+        .unwrap();
+        let last = written.len() - 1;
+        written[last] ^= 1;
+
+        assert!(read_database(&written, PASSWORD).is_err());
+    }
+}
@@ -1,4 +1,29 @@
+pub mod agent;
+pub mod audit;
 pub mod authentication;
+pub mod backup;
+pub mod breach_check;
 pub mod clipboard;
+pub mod data_dir;
 pub mod database;
+pub mod error;
+pub mod git_credential;
+pub mod http;
+pub mod import;
+pub mod instance;
+pub mod integrity;
+pub mod keepass;
 pub mod password_entry;
+pub mod password_generator;
+pub mod password_strength;
+pub mod query;
+pub mod search;
+pub mod security_report;
+pub mod settings;
+pub mod ssh_agent;
+pub mod sync;
+pub mod sync_client;
+pub mod sync_server;
+pub mod vault_file;
+pub mod vault_lock;
+pub mod vaults;
@@ -1,38 +1,222 @@
-use sqlx::{prelude::*, QueryBuilder, Sqlite, SqliteExecutor};
+use std::fmt;
+
+use aes_gcm::Aes256Gcm;
+use sqlx::{prelude::*, sqlite::SqliteRow, QueryBuilder, Sqlite, SqliteConnection, SqliteExecutor};
+use zeroize::Zeroizing;
 
 use crate::{
-    models::{AuthState, PasswordEntryRaw, PasswordEntrySafe},
-    services::database::DatabaseService,
+    models::{self, unix_now, AuthState, CryptoError, PasswordEntryRaw, PasswordEntrySafe},
+    services::{
+        audit::{self, AuditAction, AuditEvent},
+        database::{DatabaseService, QueryError},
+        error::ServiceError,
+    },
 };
 
+const ENTRY_COLUMNS: &str = "id, uuid, revision, site, username, password_hash, urls, encrypted_notes, tags, created_at, updated_at, password_changed_at, encrypted_extras";
+
+/// Why an entry couldn't be read or saved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryError {
+    NotSignedIn,
+    /// There is no entry with the id, e.g. because it was deleted in the meantime.
+    NotFound(i32),
+    /// The entry doesn't decrypt with the master password, it was encrypted with another one or
+    /// has been changed.
+    WrongKey {
+        id: i32,
+    },
+    /// The entry's stored data is damaged.
+    Corrupt {
+        id: i32,
+        message: String,
+    },
+    Encrypt(String),
+    Database(QueryError),
+}
+
+impl EntryError {
+    /// For mapping the errors of decrypting the entry with the id.
+    pub fn decrypting(id: i32) -> impl FnOnce(CryptoError) -> Self {
+        move |err| match err {
+            CryptoError::WrongKey => EntryError::WrongKey { id },
+            CryptoError::Malformed(message) | CryptoError::Encrypt(message) => {
+                EntryError::Corrupt { id, message }
+            }
+        }
+    }
+}
+
+impl fmt::Display for EntryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EntryError::NotSignedIn => {
+                write!(f, "You must be signed in to access these resources")
+            }
+            EntryError::NotFound(id) => {
+                write!(f, "There is no entry {id}, it may have been deleted")
+            }
+            EntryError::WrongKey { id } => write!(
+                f,
+                "Entry {id} couldn't be decrypted with the master password, it was encrypted with another one or has been changed"
+            ),
+            EntryError::Corrupt { id, message } => write!(f, "Entry {id} is damaged: {message}"),
+            EntryError::Encrypt(message) => write!(f, "Could not encrypt the entry: {message}"),
+            EntryError::Database(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for EntryError {}
+
+impl ServiceError for EntryError {
+    fn code(&self) -> &'static str {
+        match self {
+            EntryError::NotSignedIn => "entry.not_signed_in",
+            EntryError::NotFound(_) => "entry.not_found",
+            EntryError::WrongKey { .. } => "entry.wrong_key",
+            EntryError::Corrupt { .. } => "entry.corrupt",
+            EntryError::Encrypt(_) => "entry.encrypt",
+            EntryError::Database(err) => err.code(),
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            EntryError::NotSignedIn => "Not signed in",
+            EntryError::NotFound(_) => "Entry not found",
+            EntryError::WrongKey { .. } => "Wrong key",
+            EntryError::Corrupt { .. } => "Damaged entry",
+            EntryError::Encrypt(_) => "Encryption failed",
+            EntryError::Database(err) => err.title(),
+        }
+    }
+}
+
+/// Encrypting an entry can only fail in the cipher, decrypting goes through
+/// [`EntryError::decrypting`] to keep the entry's id.
+impl From<CryptoError> for EntryError {
+    fn from(err: CryptoError) -> Self {
+        EntryError::Encrypt(err.to_string())
+    }
+}
+
+impl From<QueryError> for EntryError {
+    fn from(err: QueryError) -> Self {
+        EntryError::Database(err)
+    }
+}
+
+impl From<sqlx::Error> for EntryError {
+    fn from(err: sqlx::Error) -> Self {
+        EntryError::Database(err.into())
+    }
+}
+
+impl From<EntryError> for String {
+    fn from(err: EntryError) -> Self {
+        err.to_string()
+    }
+}
+
+/// Save a new entry, returning its id.
 pub async fn create_password_entry(
     new_entry: PasswordEntryRaw,
     auth_state: &AuthState,
     db_service: &DatabaseService,
-) -> Result<(), String> {
-    let safe = new_entry.to_safe(auth_state)?;
-    sqlx::query("insert into password_entries (site, username, password_hash) values (?, ?, ?);")
-        .bind(safe.site.clone())
-        .bind(safe.username.clone())
-        .bind(safe.password_hash.clone())
-        .execute(&db_service.pool)
-        .await
-        .map(|_| ())
-        .map_err(|err| err.to_string())
+) -> Result<i32, EntryError> {
+    let id = insert_password_entry(&new_entry.to_safe(auth_state)?, &db_service.pool).await?;
+
+    let event = AuditEvent::for_entry(AuditAction::Create, id, &new_entry.site);
+    let _ = audit::record(event, auth_state, db_service).await;
+    Ok(id)
 }
 
+/// Create several entries at once. Either all of them are saved or, if any fails, none are.
+pub async fn create_password_entries(
+    new_entries: &[PasswordEntryRaw],
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<usize, EntryError> {
+    let cipher = auth_state.cipher();
+    let mut tx = db_service.pool.begin().await?;
+
+    for new_entry in new_entries {
+        insert_password_entry(&new_entry.to_safe_with(&cipher)?, &mut *tx).await?;
+    }
+
+    tx.commit().await?;
+    Ok(new_entries.len())
+}
+
+/// Insert an entry that's already been encrypted, e.g. as one of many in a transaction. Returns
+/// the new entry's id. The entry keeps its UUID if it has one, e.g. when it comes from another
+/// device, and is given a new one otherwise.
+pub async fn insert_password_entry<'a, T: SqliteExecutor<'a>>(
+    safe: &PasswordEntrySafe,
+    executor: T,
+) -> Result<i32, EntryError> {
+    let uuid = match safe.uuid.as_str() {
+        "" => models::new_uuid(),
+        uuid => uuid.to_string(),
+    };
+
+    let result = sqlx::query(
+        "insert into password_entries (uuid, revision, site, username, password_hash, urls, encrypted_notes, tags, created_at, updated_at, password_changed_at, encrypted_extras) values (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
+    )
+    .bind(uuid)
+    .bind(safe.revision.max(1))
+    .bind(safe.site.clone())
+    .bind(safe.username.clone())
+    .bind(safe.password_hash.clone())
+    .bind(join_urls(&safe.urls))
+    .bind(safe.encrypted_notes.clone())
+    .bind(join_tags(&safe.tags))
+    .bind(safe.created_at)
+    .bind(safe.updated_at)
+    .bind(safe.password_changed_at)
+    .bind(safe.encrypted_extras.clone())
+    .execute(executor)
+    .await?;
+
+    Ok(result.last_insert_rowid() as i32)
+}
+
+/// Get and decrypt every entry, failing on the first one that can't be read. Use
+/// [`load_password_entries`] to keep going past damaged entries.
 pub async fn get_all_password_entries(
     auth_state: &AuthState,
     db_service: &DatabaseService,
     search_string: String,
-) -> Result<Vec<PasswordEntryRaw>, String> {
+) -> Result<Vec<PasswordEntryRaw>, EntryError> {
+    let loaded = load_password_entries(auth_state, db_service, search_string).await?;
+
+    match loaded.problems.into_iter().next() {
+        Some(problem) => Err(problem.error),
+        None => Ok(loaded.entries),
+    }
+}
+
+/// The entries that could be decrypted and the ones that couldn't.
+#[derive(Debug, Default)]
+pub struct LoadedEntries {
+    pub entries: Vec<PasswordEntryRaw>,
+    pub problems: Vec<EntryProblem>,
+}
+
+/// Get and decrypt every entry on its own, so that a damaged entry doesn't keep the others from
+/// loading.
+pub async fn load_password_entries(
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+    search_string: String,
+) -> Result<LoadedEntries, EntryError> {
     if !auth_state.signed_in {
-        return Err("You must be signed in to access these resources".into());
+        return Err(EntryError::NotSignedIn);
     }
 
-    let mut builder = QueryBuilder::<Sqlite>::new(
-        "select id, site, username, password_hash from password_entries",
-    );
+    let mut builder =
+        QueryBuilder::<Sqlite>::new(format!("select {ENTRY_COLUMNS} from password_entries"));
 
     if !search_string.is_empty() {
         builder
@@ -40,87 +224,640 @@ pub async fn get_all_password_entries(
             .push_bind(format!("%{search_string}%"));
     }
 
-    let rows = builder
-        .build()
-        .fetch_all(&db_service.pool)
-        .await
-        .map_err(|err| err.to_string())?;
+    let rows = builder.build().fetch_all(&db_service.pool).await?;
 
-    let mut password_entries: Vec<PasswordEntryRaw> = Vec::with_capacity(rows.len());
+    let cipher = auth_state.cipher();
+    let mut loaded = LoadedEntries {
+        entries: Vec::with_capacity(rows.len()),
+        problems: Vec::new(),
+    };
 
-    for row in rows {
-        let id: i32 = row.get("id");
-        let site: String = row.get("site");
-        let username: String = row.get("username");
-        let password_hash: String = row.get("password_hash");
+    for row in &rows {
+        match read_entry(row, &cipher) {
+            Ok((_, entry)) => loaded.entries.push(entry),
+            Err(problem) => loaded.problems.push(problem),
+        }
+    }
 
-        password_entries.push(
-            PasswordEntrySafe {
-                id,
-                site,
-                username,
-                password_hash,
-            }
-            .to_raw(auth_state)?,
-        );
+    Ok(loaded)
+}
+
+/// Like [`load_password_entries`] but for every entry and with `executor`, e.g. in a transaction
+/// that's about to change them.
+pub async fn load_all_password_entries_in<'a, T: SqliteExecutor<'a>>(
+    auth_state: &AuthState,
+    executor: T,
+) -> Result<LoadedEntries, EntryError> {
+    if !auth_state.signed_in {
+        return Err(EntryError::NotSignedIn);
+    }
+
+    let rows = sqlx::query(&format!(
+        "select {ENTRY_COLUMNS} from password_entries order by id;"
+    ))
+    .fetch_all(executor)
+    .await?;
+
+    let cipher = auth_state.cipher();
+    let mut loaded = LoadedEntries {
+        entries: Vec::with_capacity(rows.len()),
+        problems: Vec::new(),
+    };
+
+    for row in &rows {
+        match read_entry(row, &cipher) {
+            Ok((_, entry)) => loaded.entries.push(entry),
+            Err(problem) => loaded.problems.push(problem),
+        }
+    }
+
+    Ok(loaded)
+}
+
+/// An entry that can't be read or decrypted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryProblem {
+    pub id: i32,
+    /// Empty when it can't be read.
+    pub site: String,
+    /// Empty when it can't be read.
+    pub username: String,
+    pub error: EntryError,
+}
+
+/// Read and decrypt every entry on its own, for checking the vault. Returns how many entries
+/// there are and the ones that failed, which don't keep the others from being checked.
+pub async fn find_damaged_entries(
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<(usize, Vec<EntryProblem>), EntryError> {
+    if !auth_state.signed_in {
+        return Err(EntryError::NotSignedIn);
     }
 
-    Ok(password_entries)
+    let rows = sqlx::query(&format!(
+        "select {ENTRY_COLUMNS} from password_entries order by id;"
+    ))
+    .fetch_all(&db_service.pool)
+    .await?;
+
+    let cipher = auth_state.cipher();
+    let problems = rows
+        .iter()
+        .filter_map(|row| read_entry(row, &cipher).err())
+        .collect();
+
+    Ok((rows.len(), problems))
+}
+
+/// Get every entry without decrypting the passwords.
+pub async fn get_all_password_entry_summaries(
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<Vec<PasswordEntrySafe>, EntryError> {
+    get_all_password_entry_summaries_in(auth_state, &db_service.pool).await
+}
+
+/// Get every entry without decrypting the passwords with `executor`, e.g. in a transaction.
+pub async fn get_all_password_entry_summaries_in<'a, T: SqliteExecutor<'a>>(
+    auth_state: &AuthState,
+    executor: T,
+) -> Result<Vec<PasswordEntrySafe>, EntryError> {
+    if !auth_state.signed_in {
+        return Err(EntryError::NotSignedIn);
+    }
+
+    let rows = sqlx::query(&format!(
+        "select {ENTRY_COLUMNS} from password_entries order by id;"
+    ))
+    .fetch_all(executor)
+    .await?;
+
+    rows.iter().map(safe_entry_from_row).collect()
+}
+
+/// Get a page of entries without decrypting them, for listing the vault. Entries are ordered by id
+/// and the page starts after `after_id`, or at the beginning when it's `None`. Rows that can't be
+/// read are returned as problems in their place. Passwords are decrypted one at a time with
+/// [`PasswordEntrySafe::decrypt_password`] when they're needed, so an entry that doesn't decrypt
+/// is only noticed then, or by [`crate::services::integrity::check_vault`].
+pub async fn get_password_entry_summaries_page(
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+    after_id: Option<i32>,
+    page_size: u32,
+) -> Result<Vec<Result<PasswordEntrySafe, EntryProblem>>, EntryError> {
+    if !auth_state.signed_in {
+        return Err(EntryError::NotSignedIn);
+    }
+
+    let rows = sqlx::query(&format!(
+        "select {ENTRY_COLUMNS} from password_entries where id > ? order by id limit ?;"
+    ))
+    .bind(after_id.unwrap_or(i32::MIN))
+    .bind(page_size)
+    .fetch_all(&db_service.pool)
+    .await?;
+
+    Ok(rows.iter().map(summary_from_row).collect())
 }
 
 pub async fn get_password_entry_by_id(
     id: i32,
     auth_state: &AuthState,
     db_service: &DatabaseService,
-) -> Result<PasswordEntryRaw, String> {
+) -> Result<PasswordEntryRaw, EntryError> {
     if !auth_state.signed_in {
-        return Err("You must be signed in to access these resources".into());
+        return Err(EntryError::NotSignedIn);
     }
 
-    let row =
-        sqlx::query("select id, site, username, password_hash from password_entries where id = ?;")
-            .bind(id)
-            .fetch_one(&db_service.pool)
-            .await
-            .map_err(|err| err.to_string())?;
+    let row = sqlx::query(&format!(
+        "select {ENTRY_COLUMNS} from password_entries where id = ?;"
+    ))
+    .bind(id)
+    .fetch_optional(&db_service.pool)
+    .await?
+    .ok_or(EntryError::NotFound(id))?;
 
-    let safe_entry = PasswordEntrySafe {
-        id: row.get("id"),
-        site: row.get("site"),
-        username: row.get("username"),
-        password_hash: row.get("password_hash"),
-    };
+    safe_entry_from_row(&row)?
+        .to_raw(auth_state)
+        .map_err(EntryError::decrypting(id))
+}
+
+/// The fields of an entry that are stored as they are, so they can still be read and edited
+/// when the password can't be decrypted. The notes are encrypted with the password, so they
+/// aren't among them. The URLs and tags are left unencrypted so that the vault can be searched
+/// without decrypting every entry.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlainFields {
+    pub site: String,
+    pub username: String,
+    pub urls: Vec<String>,
+    pub tags: Vec<String>,
+}
 
-    safe_entry.to_raw(auth_state)
+/// How one of an entry's columns is stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredColumn {
+    pub name: String,
+    /// SQLite's type for the value, e.g. `text` or `null`.
+    pub kind: String,
+    /// The length of the value as text, in characters.
+    pub length: i64,
 }
 
-pub async fn save_updated_password<'a, T: SqliteExecutor<'a>>(
+/// An entry that can't be read or decrypted, for inspecting and repairing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DamagedEntry {
+    pub id: i32,
+    /// `None` when the entry can be read after all, e.g. once its fields were repaired.
+    pub error: Option<EntryError>,
+    /// The fields that could be read, the others are empty.
+    pub fields: PlainFields,
+    pub columns: Vec<StoredColumn>,
+}
+
+/// Get what can still be read of an entry, with why the rest of it can't be.
+pub async fn get_damaged_entry(
+    id: i32,
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<DamagedEntry, EntryError> {
+    if !auth_state.signed_in {
+        return Err(EntryError::NotSignedIn);
+    }
+
+    let row = sqlx::query(&format!(
+        "select {ENTRY_COLUMNS} from password_entries where id = ?;"
+    ))
+    .bind(id)
+    .fetch_optional(&db_service.pool)
+    .await?
+    .ok_or(EntryError::NotFound(id))?;
+
+    let names: Vec<&str> = ENTRY_COLUMNS.split(", ").collect();
+    let described: Vec<String> = names
+        .iter()
+        .map(|name| format!("typeof({name}), coalesce(length({name}), 0)"))
+        .collect();
+    let types = sqlx::query(&format!(
+        "select {} from password_entries where id = ?;",
+        described.join(", ")
+    ))
+    .bind(id)
+    .fetch_one(&db_service.pool)
+    .await?;
+
+    let columns = names
+        .iter()
+        .enumerate()
+        .map(|(index, name)| StoredColumn {
+            name: name.to_string(),
+            kind: types.try_get(index * 2).unwrap_or_default(),
+            length: types.try_get(index * 2 + 1).unwrap_or_default(),
+        })
+        .collect();
+
+    Ok(DamagedEntry {
+        id,
+        error: read_entry(&row, &auth_state.cipher())
+            .err()
+            .map(|problem| problem.error),
+        fields: PlainFields {
+            site: row.try_get("site").unwrap_or_default(),
+            username: row.try_get("username").unwrap_or_default(),
+            urls: parse_urls(row.try_get("urls").unwrap_or_default()),
+            tags: parse_tags(row.try_get("tags").unwrap_or_default()),
+        },
+        columns,
+    })
+}
+
+/// Save the plain fields of an entry without touching its encrypted ones, for repairing an entry
+/// that can't be decrypted. Timestamps that aren't numbers are reset to now.
+pub async fn update_plain_fields(
+    id: i32,
+    fields: &PlainFields,
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<(), EntryError> {
+    if !auth_state.signed_in {
+        return Err(EntryError::NotSignedIn);
+    }
+
+    let now = unix_now();
+    let result = sqlx::query(
+        "update password_entries set site = ?, username = ?, urls = ?, tags = ?, updated_at = ?, created_at = case when typeof(created_at) = 'integer' then created_at else ? end, password_changed_at = case when typeof(password_changed_at) = 'integer' then password_changed_at else ? end, revision = revision + 1 where id = ?",
+    )
+    .bind(fields.site.clone())
+    .bind(fields.username.clone())
+    .bind(join_urls(&fields.urls))
+    .bind(join_tags(&fields.tags))
+    .bind(now)
+    .bind(now)
+    .bind(now)
+    .bind(id)
+    .execute(&db_service.pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(EntryError::NotFound(id));
+    }
+
+    let event = AuditEvent::for_entry(AuditAction::Edit, id, &fields.site);
+    let _ = audit::record(event, auth_state, db_service).await;
+    Ok(())
+}
+
+/// Save the user's changes to an entry.
+pub async fn save_updated_password(
     id: i32,
     password_entry: PasswordEntryRaw,
     auth_state: &AuthState,
-    executor: T,
-) -> Result<(), String> {
-    let safe = password_entry.to_safe(auth_state)?;
+    db_service: &DatabaseService,
+) -> Result<(), EntryError> {
+    update_password_entry(id, &password_entry.to_safe(auth_state)?, &db_service.pool).await?;
+
+    let event = AuditEvent::for_entry(AuditAction::Edit, id, &password_entry.site);
+    let _ = audit::record(event, auth_state, db_service).await;
+    Ok(())
+}
 
-    sqlx::query(
-        "update password_entries set site = ?, username = ?, password_hash = ? where id = ?",
+/// Update an entry with one that's already been encrypted, e.g. as one of many in a transaction.
+/// The entry keeps its UUID and its revision goes up by one.
+pub async fn update_password_entry<'a, T: SqliteExecutor<'a>>(
+    id: i32,
+    safe: &PasswordEntrySafe,
+    executor: T,
+) -> Result<(), EntryError> {
+    let result = sqlx::query(
+        "update password_entries set site = ?, username = ?, password_hash = ?, urls = ?, encrypted_notes = ?, tags = ?, updated_at = ?, password_changed_at = ?, encrypted_extras = ?, revision = revision + 1 where id = ?",
     )
     .bind(safe.site.clone())
     .bind(safe.username.clone())
     .bind(safe.password_hash.clone())
+    .bind(join_urls(&safe.urls))
+    .bind(safe.encrypted_notes.clone())
+    .bind(join_tags(&safe.tags))
+    .bind(safe.updated_at)
+    .bind(safe.password_changed_at)
+    .bind(safe.encrypted_extras.clone())
     .bind(id)
     .execute(executor)
-    .await
-    .map_err(|err| err.to_string())?;
+    .await?;
 
+    if result.rows_affected() == 0 {
+        return Err(EntryError::NotFound(id));
+    }
     Ok(())
 }
 
-pub async fn delete_password(id: i32, db_service: &DatabaseService) -> Result<(), String> {
-    sqlx::query("delete from password_entries where id = ?")
+/// Update the entry with `safe`'s UUID to a version of it merged with another device's, taking
+/// its revision as well. Fails with [`EntryError::NotFound`] if the entry isn't at
+/// `expected_revision`, i.e. it was changed since it was merged.
+pub async fn update_synced_entry<'a, T: SqliteExecutor<'a>>(
+    safe: &PasswordEntrySafe,
+    expected_revision: i64,
+    executor: T,
+) -> Result<(), EntryError> {
+    let result = sqlx::query(
+        "update password_entries set site = ?, username = ?, password_hash = ?, urls = ?, encrypted_notes = ?, tags = ?, created_at = ?, updated_at = ?, password_changed_at = ?, encrypted_extras = ?, revision = ? where uuid = ? and revision = ?",
+    )
+    .bind(safe.site.clone())
+    .bind(safe.username.clone())
+    .bind(safe.password_hash.clone())
+    .bind(join_urls(&safe.urls))
+    .bind(safe.encrypted_notes.clone())
+    .bind(join_tags(&safe.tags))
+    .bind(safe.created_at)
+    .bind(safe.updated_at)
+    .bind(safe.password_changed_at)
+    .bind(safe.encrypted_extras.clone())
+    .bind(safe.revision)
+    .bind(safe.uuid.clone())
+    .bind(expected_revision)
+    .execute(executor)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(EntryError::NotFound(safe.id));
+    }
+    Ok(())
+}
+
+/// Delete the entry with the UUID because another device deleted it, unless it has been changed
+/// since it was merged like with [`update_synced_entry`]. The caller leaves the tombstone.
+pub async fn delete_synced_entry<'a, T: SqliteExecutor<'a>>(
+    entry: &PasswordEntryRaw,
+    executor: T,
+) -> Result<(), EntryError> {
+    let result = sqlx::query("delete from password_entries where uuid = ? and revision = ?")
+        .bind(entry.uuid.clone())
+        .bind(entry.revision)
+        .execute(executor)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(EntryError::NotFound(entry.id));
+    }
+    Ok(())
+}
+
+/// Store an entry encrypted again with another key, without counting it as a change.
+pub async fn reencrypt_password_entry<'a, T: SqliteExecutor<'a>>(
+    safe: &PasswordEntrySafe,
+    executor: T,
+) -> Result<(), EntryError> {
+    let result = sqlx::query(
+        "update password_entries set password_hash = ?, encrypted_notes = ?, encrypted_extras = ? where id = ?",
+    )
+    .bind(safe.password_hash.clone())
+    .bind(safe.encrypted_notes.clone())
+    .bind(safe.encrypted_extras.clone())
+    .bind(safe.id)
+    .execute(executor)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(EntryError::NotFound(safe.id));
+    }
+    Ok(())
+}
+
+/// Encrypt the notes still stored unencrypted by earlier versions of the app, including those of
+/// quarantined entries, and clear the old column. Returns how many entries were changed.
+pub async fn encrypt_plain_notes(
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<u64, EntryError> {
+    if !auth_state.signed_in {
+        return Err(EntryError::NotSignedIn);
+    }
+
+    let cipher = auth_state.cipher();
+    let mut changed = 0;
+    let mut tx = db_service.pool.begin().await?;
+
+    for table in ["password_entries", "quarantined_entries"] {
+        let rows = sqlx::query(&format!(
+            "select id, notes from {table} where typeof(notes) = 'text' and notes != '';"
+        ))
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for row in rows {
+            let id: i32 = row.try_get("id")?;
+            let notes: Zeroizing<String> = Zeroizing::new(row.try_get("notes")?);
+            sqlx::query(&format!(
+                "update {table} set encrypted_notes = ?, notes = '' where id = ?;"
+            ))
+            .bind(models::encrypt(&cipher, notes.as_bytes())?)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+            changed += 1;
+        }
+    }
+
+    tx.commit().await?;
+    Ok(changed)
+}
+
+/// Delete every entry, returning how many there were. Each leaves a tombstone behind.
+pub async fn delete_all_password_entries(conn: &mut SqliteConnection) -> Result<u64, EntryError> {
+    sqlx::query("insert or replace into tombstones (uuid, deleted_at) select uuid, ? from password_entries;")
+        .bind(unix_now())
+        .execute(&mut *conn)
+        .await?;
+    let result = sqlx::query("delete from password_entries")
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Delete the entry with the id and leave a tombstone behind for syncing.
+pub async fn delete_password(
+    id: i32,
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<(), EntryError> {
+    if !auth_state.signed_in {
+        return Err(EntryError::NotSignedIn);
+    }
+
+    let mut tx = db_service.pool.begin().await?;
+    let row = sqlx::query("delete from password_entries where id = ? returning uuid, site")
         .bind(id)
-        .execute(&db_service.pool)
-        .await
-        .map(|_| ())
-        .map_err(|err| err.to_string())
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(EntryError::NotFound(id))?;
+
+    let uuid: String = row.try_get("uuid").unwrap_or_default();
+    if !uuid.is_empty() {
+        sqlx::query("insert or replace into tombstones (uuid, deleted_at) values (?, ?);")
+            .bind(uuid)
+            .bind(unix_now())
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    let site: String = row.try_get("site").unwrap_or_default();
+    let _ = audit::record(
+        AuditEvent::for_entry(AuditAction::Delete, id, &site),
+        auth_state,
+        db_service,
+    )
+    .await;
+    Ok(())
+}
+
+/// Split user input into a list of tags. Tags are separated by commas and surrounding whitespace
+/// is ignored.
+pub fn parse_tags(input: &str) -> Vec<String> {
+    input
+        .split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Split user input into a list of URLs, one per line.
+pub fn parse_urls(input: &str) -> Vec<String> {
+    input
+        .lines()
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+pub fn join_tags(tags: &[String]) -> String {
+    tags.join(", ")
+}
+
+pub fn join_urls(urls: &[String]) -> String {
+    urls.join("\n")
+}
+
+/// Read an entry's columns, failing with [`EntryError::Corrupt`] when a value doesn't have the
+/// schema's type, e.g. because the vault was changed outside the app.
+fn safe_entry_from_row(row: &SqliteRow) -> Result<PasswordEntrySafe, EntryError> {
+    let read = || -> Result<PasswordEntrySafe, sqlx::Error> {
+        Ok(PasswordEntrySafe {
+            id: row.try_get("id")?,
+            uuid: row.try_get("uuid")?,
+            revision: row.try_get("revision")?,
+            site: row.try_get("site")?,
+            username: row.try_get("username")?,
+            password_hash: row.try_get("password_hash")?,
+            urls: parse_urls(row.try_get("urls")?),
+            encrypted_notes: row.try_get("encrypted_notes")?,
+            tags: parse_tags(row.try_get("tags")?),
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+            password_changed_at: row.try_get("password_changed_at")?,
+            encrypted_extras: row.try_get("encrypted_extras")?,
+        })
+    };
+
+    read().map_err(|err| EntryError::Corrupt {
+        id: row.try_get("id").unwrap_or_default(),
+        message: err.to_string(),
+    })
+}
+
+/// Read an entry without decrypting it, or describe why it can't be read.
+fn summary_from_row(row: &SqliteRow) -> Result<PasswordEntrySafe, EntryProblem> {
+    safe_entry_from_row(row).map_err(|error| problem_for(row, error))
+}
+
+/// Read and decrypt an entry, or describe why it can't be with what can still be read of it.
+fn read_entry(
+    row: &SqliteRow,
+    cipher: &Aes256Gcm,
+) -> Result<(PasswordEntrySafe, PasswordEntryRaw), EntryProblem> {
+    let safe = summary_from_row(row)?;
+    let raw = safe
+        .to_raw_with(cipher)
+        .map_err(|err| problem_for(row, EntryError::decrypting(safe.id)(err)))?;
+    Ok((safe, raw))
+}
+
+/// An [`EntryProblem`] with what can still be read of the row.
+fn problem_for(row: &SqliteRow, error: EntryError) -> EntryProblem {
+    EntryProblem {
+        id: row.try_get("id").unwrap_or_default(),
+        site: row.try_get("site").unwrap_or_default(),
+        username: row.try_get("username").unwrap_or_default(),
+        error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::EntryExtras;
+
+    fn new_entry(site: &str) -> PasswordEntryRaw {
+        PasswordEntryRaw {
+            id: 0,
+            uuid: String::new(),
+            revision: 0,
+            site: site.into(),
+            username: "me".into(),
+            raw_password: Zeroizing::new("hunter2".into()),
+            urls: Vec::new(),
+            notes: String::new(),
+            tags: Vec::new(),
+            created_at: 0,
+            updated_at: 0,
+            password_changed_at: 0,
+            extras: EntryExtras::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn pages_list_entries_without_decrypting_them() {
+        let (_dir, db_service) = DatabaseService::temporary().await;
+        let auth_state = AuthState::from_key_material(Zeroizing::new([7; 32]));
+        let other_key = AuthState::from_key_material(Zeroizing::new([8; 32]));
+
+        let first = create_password_entry(new_entry("a"), &auth_state, &db_service)
+            .await
+            .unwrap();
+        let foreign = create_password_entry(new_entry("b"), &other_key, &db_service)
+            .await
+            .unwrap();
+        create_password_entry(new_entry("c"), &auth_state, &db_service)
+            .await
+            .unwrap();
+
+        let page = get_password_entry_summaries_page(&auth_state, &db_service, None, 2)
+            .await
+            .unwrap();
+        let sites: Vec<_> = page
+            .iter()
+            .map(|entry| entry.as_ref().unwrap().site.as_str())
+            .collect();
+        assert_eq!(sites, ["a", "b"]);
+
+        // The entry encrypted with another key is only noticed once its password is needed.
+        let entry = page[1].as_ref().unwrap();
+        assert!(entry.decrypt_password(&auth_state).is_err());
+        assert_eq!(
+            get_password_entry_by_id(foreign, &auth_state, &db_service)
+                .await
+                .unwrap_err(),
+            EntryError::WrongKey { id: foreign }
+        );
+
+        let rest = get_password_entry_summaries_page(&auth_state, &db_service, Some(foreign), 2)
+            .await
+            .unwrap();
+        assert_eq!(rest.len(), 1);
+        assert!(rest[0].as_ref().unwrap().id > first);
+    }
 }
@@ -0,0 +1,93 @@
+//! Random password generation.
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use zeroize::Zeroizing;
+
+pub const DEFAULT_LENGTH: usize = 20;
+/// Longer passwords than this are refused, they're almost certainly a typo.
+pub const MAX_LENGTH: usize = 1024;
+
+const LOWERCASE: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+const UPPERCASE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &[u8] = b"0123456789";
+const SYMBOLS: &[u8] = b"!@#$%^&*()-_=+[]{};:,.<>/?~";
+
+/// Which characters a generated password is made of and how many.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GeneratorOptions {
+    pub length: usize,
+    pub lowercase: bool,
+    pub uppercase: bool,
+    pub digits: bool,
+    pub symbols: bool,
+}
+
+impl Default for GeneratorOptions {
+    fn default() -> Self {
+        Self {
+            length: DEFAULT_LENGTH,
+            lowercase: true,
+            uppercase: true,
+            digits: true,
+            symbols: true,
+        }
+    }
+}
+
+/// Generate a password with at least one character of every allowed kind, the rest picked
+/// uniformly from all of them.
+pub fn generate_password(options: &GeneratorOptions) -> Result<Zeroizing<String>, String> {
+    let sets: Vec<&[u8]> = [
+        (options.lowercase, LOWERCASE),
+        (options.uppercase, UPPERCASE),
+        (options.digits, DIGITS),
+        (options.symbols, SYMBOLS),
+    ]
+    .into_iter()
+    .filter(|(allowed, _)| *allowed)
+    .map(|(_, set)| set)
+    .collect();
+
+    if sets.is_empty() {
+        return Err("At least one kind of character must be allowed".into());
+    }
+    if options.length < sets.len() {
+        return Err(format!(
+            "Passwords need to be at least {} characters long to include every kind of character",
+            sets.len()
+        ));
+    }
+    if options.length > MAX_LENGTH {
+        return Err(format!(
+            "Passwords can be at most {MAX_LENGTH} characters long"
+        ));
+    }
+
+    let alphabet = sets.concat();
+    let mut chars: Zeroizing<Vec<u8>> = Zeroizing::new(Vec::with_capacity(options.length));
+    chars.extend(sets.iter().map(|set| set[random_below(set.len())]));
+    while chars.len() < options.length {
+        chars.push(alphabet[random_below(alphabet.len())]);
+    }
+
+    // Fisher-Yates, so the guaranteed characters aren't always at the start.
+    for i in (1..chars.len()).rev() {
+        chars.swap(i, random_below(i + 1));
+    }
+
+    Ok(Zeroizing::new(
+        chars.iter().map(|byte| char::from(*byte)).collect(),
+    ))
+}
+
+/// A uniformly random number in `0..bound`, rejecting the values that would bias it.
+fn random_below(bound: usize) -> usize {
+    let bound = bound as u32;
+    let zone = u32::MAX - u32::MAX % bound;
+    loop {
+        let value = OsRng.next_u32();
+        if value < zone {
+            return (value % bound) as usize;
+        }
+    }
+}
@@ -0,0 +1,1188 @@
+//! Offline password strength estimation modelled after zxcvbn.
+//!
+//! The password is broken down into the patterns an attacker would try first (common passwords
+//! and words, keyboard walks, repeats, sequences and dates). The cheapest combination of those
+//! patterns gives an estimated number of guesses, which is turned into a 0-4 score, crack time
+//! estimates and feedback for the user. Nothing here touches the network or the database.
+
+use std::{
+    collections::HashMap,
+    sync::OnceLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use zeroize::Zeroizing;
+
+const COMMON_PASSWORDS: &str = include_str!("../../assets/wordlists/common_passwords.txt");
+const ENGLISH_WORDS: &str = include_str!("../../assets/wordlists/english_words.txt");
+
+/// Only the first `MAX_ANALYZED_LENGTH` characters are pattern matched. Anything longer than
+/// this is already far beyond the strongest score.
+const MAX_ANALYZED_LENGTH: usize = 100;
+
+const BRUTEFORCE_CARDINALITY: f64 = 10.0;
+const MIN_GUESSES_BEFORE_GROWING_SEQUENCE: f64 = 10_000.0;
+const MIN_SUBMATCH_GUESSES_SINGLE_CHAR: f64 = 10.0;
+const MIN_SUBMATCH_GUESSES_MULTI_CHAR: f64 = 50.0;
+const MIN_YEAR_SPACE: i32 = 20;
+
+const KEYBOARD_ROWS: [&str; 4] = [
+    "`1234567890-=",
+    "qwertyuiop[]\\",
+    "asdfghjkl;'",
+    "zxcvbnm,./",
+];
+const SHIFTED_KEYBOARD_ROWS: [&str; 4] = [
+    "~!@#$%^&*()_+",
+    "QWERTYUIOP{}|",
+    "ASDFGHJKL:\"",
+    "ZXCVBNM<>?",
+];
+
+const L33T_TABLE: [(char, char); 11] = [
+    ('4', 'a'),
+    ('@', 'a'),
+    ('8', 'b'),
+    ('(', 'c'),
+    ('3', 'e'),
+    ('1', 'i'),
+    ('!', 'i'),
+    ('0', 'o'),
+    ('$', 's'),
+    ('5', 's'),
+    ('7', 't'),
+];
+
+/// The scores a password can receive, from 0 (trivially guessable) to 4 (very unguessable).
+pub const MAX_SCORE: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dictionary {
+    CommonPasswords,
+    EnglishWords,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Dictionary {
+        dictionary: Dictionary,
+        rank: usize,
+        reversed: bool,
+        l33t: bool,
+        uppercase: Uppercase,
+    },
+    Spatial {
+        turns: usize,
+    },
+    Repeat {
+        base_length: usize,
+        repeat_count: usize,
+    },
+    Sequence {
+        ascending: bool,
+    },
+    Date {
+        year: i32,
+        year_only: bool,
+    },
+    BruteForce,
+}
+
+/// How the letters of a dictionary match were capitalised.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Uppercase {
+    None,
+    FirstOrLast,
+    All,
+    Mixed,
+}
+
+/// A pattern found in the password, spanning characters `i..=j`.
+///
+/// Matches deliberately don't keep a copy of the matched text so no plaintext outlives the call
+/// to [`estimate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    pub i: usize,
+    pub j: usize,
+    pub pattern: Pattern,
+    pub guesses: f64,
+}
+
+impl Match {
+    fn len(&self) -> usize {
+        self.j - self.i + 1
+    }
+}
+
+/// Estimated time in seconds to crack a password under different attack scenarios.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CrackTimes {
+    /// An online attack against a service that rate limits (100 guesses per hour).
+    pub online_throttled: f64,
+    /// An online attack against a service without rate limiting (10 guesses per second).
+    pub online_unthrottled: f64,
+    /// An offline attack against a slow hash such as Argon2 (10k guesses per second).
+    pub offline_slow_hashing: f64,
+    /// An offline attack against a fast hash (10B guesses per second).
+    pub offline_fast_hashing: f64,
+}
+
+impl CrackTimes {
+    fn from_guesses(guesses: f64) -> Self {
+        Self {
+            online_throttled: guesses / (100.0 / 3600.0),
+            online_unthrottled: guesses / 10.0,
+            offline_slow_hashing: guesses / 1e4,
+            offline_fast_hashing: guesses / 1e10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Feedback {
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrengthEstimate {
+    pub guesses: f64,
+    pub guesses_log10: f64,
+    pub score: u8,
+    pub crack_times: CrackTimes,
+    pub feedback: Feedback,
+    pub sequence: Vec<Match>,
+}
+
+/// Estimate the strength of `password`.
+pub fn estimate(password: &str) -> StrengthEstimate {
+    let chars: Zeroizing<Vec<char>> =
+        Zeroizing::new(password.chars().take(MAX_ANALYZED_LENGTH).collect());
+
+    let matches = omnimatch(&chars);
+    let (guesses, sequence) = most_guessable_match_sequence(&chars, matches);
+    let score = guesses_to_score(guesses);
+
+    StrengthEstimate {
+        guesses,
+        guesses_log10: guesses.log10(),
+        score,
+        crack_times: CrackTimes::from_guesses(guesses),
+        feedback: feedback(score, &sequence),
+        sequence,
+    }
+}
+
+/// A short human readable label for a score.
+pub fn score_label(score: u8) -> &'static str {
+    match score {
+        0 => "Very weak",
+        1 => "Weak",
+        2 => "Fair",
+        3 => "Strong",
+        _ => "Very strong",
+    }
+}
+
+/// Turn a number of seconds into a rough human readable duration, e.g. "3 hours" or "centuries".
+pub fn display_time(seconds: f64) -> String {
+    const MINUTE: f64 = 60.0;
+    const HOUR: f64 = MINUTE * 60.0;
+    const DAY: f64 = HOUR * 24.0;
+    const MONTH: f64 = DAY * 31.0;
+    const YEAR: f64 = MONTH * 12.0;
+    const CENTURY: f64 = YEAR * 100.0;
+
+    let (amount, unit) = if seconds < 1.0 {
+        return "less than a second".into();
+    } else if seconds < MINUTE {
+        (seconds, "second")
+    } else if seconds < HOUR {
+        (seconds / MINUTE, "minute")
+    } else if seconds < DAY {
+        (seconds / HOUR, "hour")
+    } else if seconds < MONTH {
+        (seconds / DAY, "day")
+    } else if seconds < YEAR {
+        (seconds / MONTH, "month")
+    } else if seconds < CENTURY {
+        (seconds / YEAR, "year")
+    } else {
+        return "centuries".into();
+    };
+
+    let amount = amount.round() as u64;
+    if amount == 1 {
+        format!("1 {unit}")
+    } else {
+        format!("{amount} {unit}s")
+    }
+}
+
+fn guesses_to_score(guesses: f64) -> u8 {
+    // A small margin keeps passwords right on a boundary from being rounded up.
+    const DELTA: f64 = 5.0;
+
+    if guesses < 1e3 + DELTA {
+        0
+    } else if guesses < 1e6 + DELTA {
+        1
+    } else if guesses < 1e8 + DELTA {
+        2
+    } else if guesses < 1e10 + DELTA {
+        3
+    } else {
+        4
+    }
+}
+
+// ---------------------------------------------------------------------------------------------
+// Matching
+// ---------------------------------------------------------------------------------------------
+
+fn omnimatch(chars: &[char]) -> Vec<Match> {
+    let mut matches = Vec::new();
+    matches.extend(dictionary_matches(chars));
+    matches.extend(reversed_dictionary_matches(chars));
+    matches.extend(l33t_matches(chars));
+    matches.extend(spatial_matches(chars));
+    matches.extend(repeat_matches(chars));
+    matches.extend(sequence_matches(chars));
+    matches.extend(date_matches(chars));
+    matches
+}
+
+fn ranked_dictionaries() -> &'static [(Dictionary, HashMap<&'static str, usize>)] {
+    static DICTIONARIES: OnceLock<Vec<(Dictionary, HashMap<&'static str, usize>)>> =
+        OnceLock::new();
+
+    DICTIONARIES.get_or_init(|| {
+        let rank = |list: &'static str| {
+            let mut ranked = HashMap::new();
+            for (index, word) in list
+                .lines()
+                .map(str::trim)
+                .filter(|w| !w.is_empty())
+                .enumerate()
+            {
+                ranked.entry(word).or_insert(index + 1);
+            }
+            ranked
+        };
+
+        vec![
+            (Dictionary::CommonPasswords, rank(COMMON_PASSWORDS)),
+            (Dictionary::EnglishWords, rank(ENGLISH_WORDS)),
+        ]
+    })
+}
+
+fn uppercase_pattern(chars: &[char]) -> Uppercase {
+    let letters: Vec<&char> = chars.iter().filter(|c| c.is_alphabetic()).collect();
+    let upper = letters.iter().filter(|c| c.is_uppercase()).count();
+
+    if upper == 0 {
+        Uppercase::None
+    } else if upper == letters.len() {
+        Uppercase::All
+    } else if upper == 1
+        && (letters.first().is_some_and(|c| c.is_uppercase())
+            || letters.last().is_some_and(|c| c.is_uppercase()))
+    {
+        Uppercase::FirstOrLast
+    } else {
+        Uppercase::Mixed
+    }
+}
+
+/// Look up every substring of `chars` in the ranked dictionaries, after passing it through `map`.
+/// `on_match` receives the start and end of each match, its dictionary and its rank.
+fn lookup_dictionaries(
+    chars: &[char],
+    map: impl Fn(char) -> char,
+    mut on_match: impl FnMut(usize, usize, Dictionary, usize),
+) {
+    let mut buffer = Zeroizing::new(String::new());
+
+    for i in 0..chars.len() {
+        for j in i..chars.len() {
+            buffer.clear();
+            buffer.extend(chars[i..=j].iter().map(|c| map(*c)));
+
+            for (dictionary, ranked) in ranked_dictionaries() {
+                if let Some(rank) = ranked.get(buffer.as_str()) {
+                    on_match(i, j, *dictionary, *rank);
+                }
+            }
+        }
+    }
+}
+
+fn dictionary_guesses(
+    chars: &[char],
+    rank: usize,
+    reversed: bool,
+    l33t_subs: usize,
+) -> (Uppercase, f64) {
+    let uppercase = uppercase_pattern(chars);
+    let uppercase_variations = match uppercase {
+        Uppercase::None => 1.0,
+        Uppercase::FirstOrLast | Uppercase::All => 2.0,
+        Uppercase::Mixed => {
+            let upper = chars.iter().filter(|c| c.is_uppercase()).count();
+            let lower = chars.iter().filter(|c| c.is_lowercase()).count();
+            (1..=upper.min(lower))
+                .map(|k| n_choose_k(upper + lower, k))
+                .sum()
+        }
+    };
+
+    let l33t_variations = if l33t_subs == 0 {
+        1.0
+    } else {
+        let unsubbed = chars.len() - l33t_subs;
+        (1..=l33t_subs.min(unsubbed).max(1))
+            .map(|k| n_choose_k(chars.len(), k))
+            .sum::<f64>()
+            .max(2.0)
+    };
+
+    let reversed_variations = if reversed { 2.0 } else { 1.0 };
+
+    (
+        uppercase,
+        rank as f64 * uppercase_variations * l33t_variations * reversed_variations,
+    )
+}
+
+fn dictionary_matches(chars: &[char]) -> Vec<Match> {
+    let mut matches = Vec::new();
+
+    lookup_dictionaries(
+        chars,
+        |c| c.to_lowercase().next().unwrap_or(c),
+        |i, j, dictionary, rank| {
+            let (uppercase, guesses) = dictionary_guesses(&chars[i..=j], rank, false, 0);
+            matches.push(Match {
+                i,
+                j,
+                pattern: Pattern::Dictionary {
+                    dictionary,
+                    rank,
+                    reversed: false,
+                    l33t: false,
+                    uppercase,
+                },
+                guesses,
+            });
+        },
+    );
+
+    matches
+}
+
+fn reversed_dictionary_matches(chars: &[char]) -> Vec<Match> {
+    let reversed: Zeroizing<Vec<char>> = Zeroizing::new(chars.iter().rev().copied().collect());
+    let n = chars.len();
+    let mut matches = Vec::new();
+
+    lookup_dictionaries(
+        &reversed,
+        |c| c.to_lowercase().next().unwrap_or(c),
+        |i, j, dictionary, rank| {
+            // Palindromes are already found by the forward pass.
+            if reversed[i..=j].iter().eq(reversed[i..=j].iter().rev()) {
+                return;
+            }
+
+            let (uppercase, guesses) = dictionary_guesses(&reversed[i..=j], rank, true, 0);
+            matches.push(Match {
+                i: n - 1 - j,
+                j: n - 1 - i,
+                pattern: Pattern::Dictionary {
+                    dictionary,
+                    rank,
+                    reversed: true,
+                    l33t: false,
+                    uppercase,
+                },
+                guesses,
+            });
+        },
+    );
+
+    matches
+}
+
+fn unl33t(c: char) -> char {
+    L33T_TABLE
+        .iter()
+        .find(|(l33t, _)| *l33t == c)
+        .map(|(_, letter)| *letter)
+        .unwrap_or_else(|| c.to_lowercase().next().unwrap_or(c))
+}
+
+fn l33t_matches(chars: &[char]) -> Vec<Match> {
+    let mut matches = Vec::new();
+
+    lookup_dictionaries(chars, unl33t, |i, j, dictionary, rank| {
+        let token = &chars[i..=j];
+        let subs = token
+            .iter()
+            .filter(|c| L33T_TABLE.iter().any(|(l33t, _)| l33t == *c))
+            .count();
+
+        // Without a substitution this is a plain dictionary match, and a token made only of
+        // substituted characters (e.g. "1") isn't a meaningful word.
+        if subs == 0 || subs == token.len() {
+            return;
+        }
+
+        let (uppercase, guesses) = dictionary_guesses(token, rank, false, subs);
+        matches.push(Match {
+            i,
+            j,
+            pattern: Pattern::Dictionary {
+                dictionary,
+                rank,
+                reversed: false,
+                l33t: true,
+                uppercase,
+            },
+            guesses,
+        });
+    });
+
+    matches
+}
+
+/// The (row, column) of a key on a US qwerty keyboard, and whether shift is needed to type it.
+fn key_position(c: char) -> Option<(i32, i32, bool)> {
+    for (row, (keys, shifted_keys)) in KEYBOARD_ROWS.iter().zip(SHIFTED_KEYBOARD_ROWS).enumerate() {
+        if let Some(col) = keys.chars().position(|k| k == c) {
+            return Some((row as i32, col as i32, false));
+        }
+        if let Some(col) = shifted_keys.chars().position(|k| k == c) {
+            return Some((row as i32, col as i32, true));
+        }
+    }
+    None
+}
+
+/// The direction from key `a` to an adjacent key `b`, or `None` when they aren't neighbours.
+/// Rows are staggered, so the key above-left of a key shares its column and the key below-right
+/// shares its column too.
+fn adjacency_direction(a: char, b: char) -> Option<(i32, i32)> {
+    let (row_a, col_a, _) = key_position(a)?;
+    let (row_b, col_b, _) = key_position(b)?;
+    let direction = (row_b - row_a, col_b - col_a);
+
+    match direction {
+        (0, -1) | (0, 1) | (-1, 0) | (-1, 1) | (1, 0) | (1, -1) => Some(direction),
+        _ => None,
+    }
+}
+
+fn spatial_matches(chars: &[char]) -> Vec<Match> {
+    let mut matches = Vec::new();
+    let mut i = 0;
+
+    while i + 2 < chars.len() {
+        let mut j = i;
+        let mut turns = 0;
+        let mut shifted = usize::from(key_position(chars[i]).is_some_and(|(_, _, s)| s));
+        let mut last_direction = None;
+
+        while j + 1 < chars.len() {
+            match adjacency_direction(chars[j], chars[j + 1]) {
+                Some(direction) => {
+                    if last_direction != Some(direction) {
+                        turns += 1;
+                        last_direction = Some(direction);
+                    }
+                    if key_position(chars[j + 1]).is_some_and(|(_, _, s)| s) {
+                        shifted += 1;
+                    }
+                    j += 1;
+                }
+                None => break,
+            }
+        }
+
+        if j - i + 1 >= 3 {
+            matches.push(Match {
+                i,
+                j,
+                pattern: Pattern::Spatial { turns },
+                guesses: spatial_guesses(j - i + 1, turns, shifted),
+            });
+            i = j + 1;
+        } else {
+            i += 1;
+        }
+    }
+
+    matches
+}
+
+fn spatial_guesses(length: usize, turns: usize, shifted: usize) -> f64 {
+    let starting_positions: f64 = KEYBOARD_ROWS.iter().map(|row| row.len() as f64).sum();
+    // Interior keys have six neighbours, edge keys fewer.
+    let average_degree: f64 = 4.6;
+
+    let mut guesses = 0.0;
+    for i in 2..=length {
+        for j in 1..=turns.min(i - 1) {
+            guesses +=
+                n_choose_k(i - 1, j - 1) * starting_positions * average_degree.powi(j as i32);
+        }
+    }
+
+    if shifted > 0 {
+        let unshifted = length - shifted;
+        if unshifted == 0 {
+            guesses *= 2.0;
+        } else {
+            guesses *= (1..=shifted.min(unshifted))
+                .map(|k| n_choose_k(length, k))
+                .sum::<f64>();
+        }
+    }
+
+    guesses
+}
+
+fn repeat_matches(chars: &[char]) -> Vec<Match> {
+    let mut matches = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        // Find the base that covers the most characters when repeated from `i`.
+        let mut best: Option<(usize, usize)> = None;
+        for base_length in 1..=(chars.len() - i) / 2 {
+            let base = &chars[i..i + base_length];
+            let mut count = 1;
+            while chars[i + count * base_length..]
+                .get(..base_length)
+                .is_some_and(|next| next == base)
+            {
+                count += 1;
+            }
+
+            let covered = base_length * count;
+            let long_enough = if base_length == 1 {
+                count >= 3
+            } else {
+                count >= 2
+            };
+            if long_enough && best.is_none_or(|(l, c)| covered > l * c) {
+                best = Some((base_length, count));
+            }
+        }
+
+        match best {
+            Some((base_length, repeat_count)) => {
+                let (base_guesses, _) = most_guessable_match_sequence(
+                    &chars[i..i + base_length],
+                    omnimatch(&chars[i..i + base_length]),
+                );
+                let j = i + base_length * repeat_count - 1;
+                matches.push(Match {
+                    i,
+                    j,
+                    pattern: Pattern::Repeat {
+                        base_length,
+                        repeat_count,
+                    },
+                    guesses: base_guesses * repeat_count as f64,
+                });
+                i = j + 1;
+            }
+            None => i += 1,
+        }
+    }
+
+    matches
+}
+
+fn sequence_matches(chars: &[char]) -> Vec<Match> {
+    let mut matches = Vec::new();
+    let same_class = |a: char, b: char| {
+        (a.is_ascii_digit() && b.is_ascii_digit())
+            || (a.is_ascii_lowercase() && b.is_ascii_lowercase())
+            || (a.is_ascii_uppercase() && b.is_ascii_uppercase())
+    };
+
+    let mut i = 0;
+    while i + 2 < chars.len() {
+        let delta = chars[i + 1] as i32 - chars[i] as i32;
+        if !(delta == 1 || delta == -1) || !same_class(chars[i], chars[i + 1]) {
+            i += 1;
+            continue;
+        }
+
+        let mut j = i + 1;
+        while j + 1 < chars.len()
+            && chars[j + 1] as i32 - chars[j] as i32 == delta
+            && same_class(chars[j], chars[j + 1])
+        {
+            j += 1;
+        }
+
+        if j - i + 1 >= 3 {
+            let first = chars[i];
+            let base_guesses = if matches!(first, 'a' | 'A' | 'z' | 'Z' | '0' | '1' | '9') {
+                4.0
+            } else if first.is_ascii_digit() {
+                10.0
+            } else {
+                26.0
+            };
+            let ascending = delta > 0;
+            let direction = if ascending { 1.0 } else { 2.0 };
+
+            matches.push(Match {
+                i,
+                j,
+                pattern: Pattern::Sequence { ascending },
+                guesses: base_guesses * (j - i + 1) as f64 * direction,
+            });
+            i = j + 1;
+        } else {
+            i += 1;
+        }
+    }
+
+    matches
+}
+
+fn reference_year() -> i32 {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    1970 + (seconds / 31_556_952) as i32
+}
+
+fn date_matches(chars: &[char]) -> Vec<Match> {
+    let reference = reference_year();
+    let mut matches = Vec::new();
+    let mut token = Zeroizing::new(String::new());
+
+    for i in 0..chars.len() {
+        for j in (i + 3)..chars.len().min(i + 10) {
+            token.clear();
+            token.extend(&chars[i..=j]);
+
+            if let Some((year, year_only, separated)) = parse_date(&token, reference) {
+                let year_space = (year - reference).abs().max(MIN_YEAR_SPACE) as f64;
+                let guesses = if year_only {
+                    year_space
+                } else if separated {
+                    year_space * 365.0 * 4.0
+                } else {
+                    year_space * 365.0
+                };
+
+                matches.push(Match {
+                    i,
+                    j,
+                    pattern: Pattern::Date { year, year_only },
+                    guesses,
+                });
+            }
+        }
+    }
+
+    matches
+}
+
+/// Try to read `token` as a year or a day/month/year date in any common order. Returns the year,
+/// whether the token was only a year, and whether the parts were separated by punctuation.
+fn parse_date(token: &str, reference: i32) -> Option<(i32, bool, bool)> {
+    let is_plausible_year = |year: i32| (1900..=2050).contains(&year);
+    let expand_year = |year: i32, digits: usize| match digits {
+        2 if year > (reference % 100) + 10 => Some(1900 + year),
+        2 => Some(2000 + year),
+        4 if is_plausible_year(year) => Some(year),
+        _ => None,
+    };
+    let is_day_month = |a: i32, b: i32| {
+        ((1..=31).contains(&a) && (1..=12).contains(&b))
+            || ((1..=12).contains(&a) && (1..=31).contains(&b))
+    };
+
+    if token.len() == 4 && token.chars().all(|c| c.is_ascii_digit()) {
+        let year: i32 = token.parse().ok()?;
+        return is_plausible_year(year).then_some((year, true, false));
+    }
+
+    let separator = token.chars().find(|c| "/-._ ".contains(*c));
+    let parts: Vec<&str> = match separator {
+        Some(separator) => token.split(separator).collect(),
+        None if token.chars().all(|c| c.is_ascii_digit()) => {
+            // Without separators only the common layouts are considered: ddmmyy, ddmmyyyy
+            // and yyyymmdd (and their month-first variants).
+            match token.len() {
+                6 => vec![&token[0..2], &token[2..4], &token[4..6]],
+                8 => {
+                    let year_first = &token[0..4];
+                    if year_first.parse::<i32>().is_ok_and(is_plausible_year) {
+                        vec![&token[0..4], &token[4..6], &token[6..8]]
+                    } else {
+                        vec![&token[0..2], &token[2..4], &token[4..8]]
+                    }
+                }
+                _ => return None,
+            }
+        }
+        None => return None,
+    };
+
+    if parts.len() != 3
+        || parts
+            .iter()
+            .any(|p| p.is_empty() || !p.chars().all(|c| c.is_ascii_digit()))
+    {
+        return None;
+    }
+
+    let numbers: Vec<i32> = parts.iter().map(|p| p.parse().unwrap_or(-1)).collect();
+
+    // Year last (dd/mm/yyyy or mm/dd/yyyy), then year first (yyyy/mm/dd).
+    if let Some(year) = expand_year(numbers[2], parts[2].len()) {
+        if is_day_month(numbers[0], numbers[1]) {
+            return Some((year, false, separator.is_some()));
+        }
+    }
+    if let Some(year) = expand_year(numbers[0], parts[0].len()) {
+        if is_day_month(numbers[1], numbers[2]) {
+            return Some((year, false, separator.is_some()));
+        }
+    }
+
+    None
+}
+
+// ---------------------------------------------------------------------------------------------
+// Scoring
+// ---------------------------------------------------------------------------------------------
+
+fn n_choose_k(n: usize, k: usize) -> f64 {
+    if k > n {
+        return 0.0;
+    }
+    (1..=k).fold(1.0, |acc, i| acc * (n - k + i) as f64 / i as f64)
+}
+
+fn factorial(n: usize) -> f64 {
+    (1..=n).fold(1.0, |acc, i| acc * i as f64)
+}
+
+fn bruteforce_match(i: usize, j: usize) -> Match {
+    Match {
+        i,
+        j,
+        pattern: Pattern::BruteForce,
+        guesses: BRUTEFORCE_CARDINALITY.powi((j - i + 1) as i32),
+    }
+}
+
+/// The best way to cover the whole password with non-overlapping matches, using brute force for
+/// any gaps. This is the dynamic programming search zxcvbn describes: for every prefix and every
+/// number of matches the cheapest sequence is kept, and the overall guess count for a sequence of
+/// `l` matches is `l! * product(guesses) + MIN_GUESSES_BEFORE_GROWING_SEQUENCE^(l - 1)`.
+fn most_guessable_match_sequence(chars: &[char], matches: Vec<Match>) -> (f64, Vec<Match>) {
+    let n = chars.len();
+    if n == 0 {
+        return (1.0, Vec::new());
+    }
+
+    // Single character or partial matches are never allowed to look cheaper than brute forcing a
+    // character or two.
+    let matches: Vec<Match> = matches
+        .into_iter()
+        .map(|mut m| {
+            if m.len() < n {
+                let minimum = if m.len() == 1 {
+                    MIN_SUBMATCH_GUESSES_SINGLE_CHAR
+                } else {
+                    MIN_SUBMATCH_GUESSES_MULTI_CHAR
+                };
+                m.guesses = m.guesses.max(minimum);
+            }
+            m.guesses = m.guesses.max(1.0);
+            m
+        })
+        .collect();
+
+    #[derive(Clone)]
+    struct Step {
+        product: f64,
+        total: f64,
+        m: Match,
+    }
+
+    // optimal[k][l] is the cheapest sequence of `l` matches covering chars[0..=k].
+    let mut optimal: Vec<HashMap<usize, Step>> = vec![HashMap::new(); n];
+
+    let update = |optimal: &mut Vec<HashMap<usize, Step>>, m: Match, length: usize| {
+        let k = m.j;
+        let product = if length > 1 {
+            optimal[m.i - 1][&(length - 1)].product * m.guesses
+        } else {
+            m.guesses
+        };
+        let total = factorial(length) * product
+            + MIN_GUESSES_BEFORE_GROWING_SEQUENCE.powi(length as i32 - 1);
+
+        // A sequence is only worth keeping if no shorter sequence is at least as cheap.
+        let dominated = optimal[k]
+            .iter()
+            .any(|(other_length, step)| *other_length <= length && step.total <= total);
+        if !dominated {
+            optimal[k].insert(length, Step { product, total, m });
+        }
+    };
+
+    for k in 0..n {
+        for m in matches.iter().filter(|m| m.j == k) {
+            if m.i > 0 {
+                let lengths: Vec<usize> = optimal[m.i - 1].keys().copied().collect();
+                for length in lengths {
+                    update(&mut optimal, m.clone(), length + 1);
+                }
+            } else {
+                update(&mut optimal, m.clone(), 1);
+            }
+        }
+
+        // Brute force from the start, or after any sequence that didn't itself end in brute force.
+        update(&mut optimal, bruteforce_match(0, k), 1);
+        for i in 1..=k {
+            let candidates: Vec<usize> = optimal[i - 1]
+                .iter()
+                .filter(|(_, step)| step.m.pattern != Pattern::BruteForce)
+                .map(|(length, _)| *length)
+                .collect();
+            for length in candidates {
+                update(&mut optimal, bruteforce_match(i, k), length + 1);
+            }
+        }
+    }
+
+    let (mut length, best) = optimal[n - 1]
+        .iter()
+        .min_by(|(_, a), (_, b)| a.total.total_cmp(&b.total))
+        .map(|(length, step)| (*length, step.total))
+        .expect("at least the brute force sequence covers the password");
+
+    let mut sequence = Vec::with_capacity(length);
+    let mut k = n;
+    while k > 0 {
+        let step = &optimal[k - 1][&length];
+        sequence.push(step.m.clone());
+        k = step.m.i;
+        length -= 1;
+    }
+    sequence.reverse();
+
+    (best, sequence)
+}
+
+// ---------------------------------------------------------------------------------------------
+// Feedback
+// ---------------------------------------------------------------------------------------------
+
+fn feedback(score: u8, sequence: &[Match]) -> Feedback {
+    if sequence.is_empty() {
+        return Feedback {
+            warning: None,
+            suggestions: vec![
+                "Use a few words, avoid common phrases".into(),
+                "No need for symbols, digits, or uppercase letters".into(),
+            ],
+        };
+    }
+
+    if score > 2 {
+        return Feedback::default();
+    }
+
+    let longest = sequence
+        .iter()
+        .max_by_key(|m| m.len())
+        .expect("sequence is not empty");
+
+    let mut feedback = match_feedback(longest, sequence.len() == 1);
+    feedback.suggestions.insert(
+        0,
+        "Add another word or two. Uncommon words are better.".into(),
+    );
+    feedback
+}
+
+fn match_feedback(m: &Match, is_sole_match: bool) -> Feedback {
+    match &m.pattern {
+        Pattern::Dictionary {
+            dictionary,
+            rank,
+            reversed,
+            l33t,
+            uppercase,
+        } => {
+            let warning = match dictionary {
+                Dictionary::CommonPasswords if is_sole_match && !l33t && !reversed => {
+                    if *rank <= 10 {
+                        Some("This is a top-10 common password")
+                    } else if *rank <= 100 {
+                        Some("This is a top-100 common password")
+                    } else {
+                        Some("This is a very common password")
+                    }
+                }
+                Dictionary::CommonPasswords => Some("This is similar to a commonly used password"),
+                Dictionary::EnglishWords if is_sole_match => {
+                    Some("A word by itself is easy to guess")
+                }
+                Dictionary::EnglishWords => None,
+            };
+
+            let mut suggestions = Vec::new();
+            match uppercase {
+                Uppercase::FirstOrLast => {
+                    suggestions.push("Capitalization doesn't help very much".to_string())
+                }
+                Uppercase::All => suggestions
+                    .push("All-uppercase is almost as easy to guess as all-lowercase".to_string()),
+                Uppercase::None | Uppercase::Mixed => {}
+            }
+            if *reversed {
+                suggestions.push("Reversed words aren't much harder to guess".into());
+            }
+            if *l33t {
+                suggestions.push(
+                    "Predictable substitutions like '@' instead of 'a' don't help very much".into(),
+                );
+            }
+
+            Feedback {
+                warning: warning.map(String::from),
+                suggestions,
+            }
+        }
+        Pattern::Spatial { turns } => Feedback {
+            warning: Some(if *turns == 1 {
+                "Straight rows of keys are easy to guess".into()
+            } else {
+                "Short keyboard patterns are easy to guess".into()
+            }),
+            suggestions: vec!["Use a longer keyboard pattern with more turns".into()],
+        },
+        Pattern::Repeat { base_length, .. } => Feedback {
+            warning: Some(if *base_length == 1 {
+                "Repeats like \"aaa\" are easy to guess".into()
+            } else {
+                "Repeats like \"abcabcabc\" are only slightly harder to guess than \"abc\"".into()
+            }),
+            suggestions: vec!["Avoid repeated words and characters".into()],
+        },
+        Pattern::Sequence { .. } => Feedback {
+            warning: Some("Sequences like abc or 6543 are easy to guess".into()),
+            suggestions: vec!["Avoid sequences".into()],
+        },
+        Pattern::Date { year_only, .. } => Feedback {
+            warning: Some(if *year_only {
+                "Recent years are easy to guess".into()
+            } else {
+                "Dates are often easy to guess".into()
+            }),
+            suggestions: vec!["Avoid dates and years that are associated with you".into()],
+        },
+        Pattern::BruteForce => Feedback::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patterns(estimate: &StrengthEstimate) -> Vec<&Pattern> {
+        estimate.sequence.iter().map(|m| &m.pattern).collect()
+    }
+
+    fn warning(estimate: &StrengthEstimate) -> Option<&str> {
+        estimate.feedback.warning.as_deref()
+    }
+
+    fn suggests(estimate: &StrengthEstimate, suggestion: &str) -> bool {
+        estimate
+            .feedback
+            .suggestions
+            .iter()
+            .any(|s| s == suggestion)
+    }
+
+    #[test]
+    fn common_passwords_are_very_weak() {
+        let found = estimate("password");
+
+        assert_eq!(found.score, 0);
+        assert!(matches!(
+            patterns(&found)[..],
+            [Pattern::Dictionary {
+                dictionary: Dictionary::CommonPasswords,
+                l33t: false,
+                ..
+            }]
+        ));
+        assert_eq!(warning(&found), Some("This is a top-10 common password"));
+        assert!(suggests(
+            &found,
+            "Add another word or two. Uncommon words are better."
+        ));
+    }
+
+    #[test]
+    fn l33t_substitutions_dont_help() {
+        let found = estimate("P4ssw0rd");
+
+        assert_eq!(found.score, 0);
+        assert!(matches!(
+            patterns(&found)[..],
+            [Pattern::Dictionary {
+                dictionary: Dictionary::CommonPasswords,
+                l33t: true,
+                uppercase: Uppercase::FirstOrLast,
+                ..
+            }]
+        ));
+        assert_eq!(
+            warning(&found),
+            Some("This is similar to a commonly used password")
+        );
+        assert!(suggests(
+            &found,
+            "Predictable substitutions like '@' instead of 'a' don't help very much"
+        ));
+        assert!(suggests(&found, "Capitalization doesn't help very much"));
+    }
+
+    #[test]
+    fn keyboard_walks_are_weak() {
+        let straight = estimate("poiuytre");
+        assert!(straight.score <= 1);
+        assert_eq!(patterns(&straight), [&Pattern::Spatial { turns: 1 }]);
+        assert_eq!(
+            warning(&straight),
+            Some("Straight rows of keys are easy to guess")
+        );
+
+        let turning = estimate("zxcvfdsa");
+        assert!(turning.score <= 1);
+        assert_eq!(patterns(&turning), [&Pattern::Spatial { turns: 3 }]);
+        assert_eq!(
+            warning(&turning),
+            Some("Short keyboard patterns are easy to guess")
+        );
+        assert!(turning.guesses > straight.guesses);
+    }
+
+    #[test]
+    fn dates_are_weak() {
+        for date in ["14/07/1989", "19890714"] {
+            let found = estimate(date);
+            assert!(found.score <= 1, "{date}");
+            assert_eq!(
+                patterns(&found),
+                [&Pattern::Date {
+                    year: 1989,
+                    year_only: false
+                }],
+                "{date}"
+            );
+            assert_eq!(warning(&found), Some("Dates are often easy to guess"));
+        }
+
+        let year = estimate("2019");
+        assert_eq!(year.score, 0);
+        assert_eq!(warning(&year), Some("Recent years are easy to guess"));
+        assert!(suggests(
+            &year,
+            "Avoid dates and years that are associated with you"
+        ));
+    }
+
+    #[test]
+    fn repeats_are_weak() {
+        let found = estimate("aaaaaaaa");
+        assert_eq!(found.score, 0);
+        assert_eq!(
+            patterns(&found),
+            [&Pattern::Repeat {
+                base_length: 1,
+                repeat_count: 8
+            }]
+        );
+        assert_eq!(
+            warning(&found),
+            Some("Repeats like \"aaa\" are easy to guess")
+        );
+
+        let found = estimate("abcabcabc");
+        assert_eq!(found.score, 0);
+        assert_eq!(
+            patterns(&found),
+            [&Pattern::Repeat {
+                base_length: 3,
+                repeat_count: 3
+            }]
+        );
+        assert!(suggests(&found, "Avoid repeated words and characters"));
+    }
+
+    #[test]
+    fn sequences_are_weak() {
+        for (sequence, ascending) in [("abcdefgh", true), ("98765432", false)] {
+            let found = estimate(sequence);
+            assert_eq!(found.score, 0, "{sequence}");
+            assert_eq!(
+                patterns(&found),
+                [&Pattern::Sequence { ascending }],
+                "{sequence}"
+            );
+            assert_eq!(
+                warning(&found),
+                Some("Sequences like abc or 6543 are easy to guess")
+            );
+            assert!(suggests(&found, "Avoid sequences"));
+        }
+    }
+
+    #[test]
+    fn long_random_passphrases_are_strong() {
+        let found = estimate("crimson-otter-lantern-quartz-93");
+
+        assert_eq!(found.score, MAX_SCORE);
+        assert!(found.guesses_log10 > 12.0);
+        assert_eq!(found.feedback, Feedback::default());
+        assert!(found.crack_times.offline_slow_hashing > 100.0 * 365.0 * 24.0 * 3600.0);
+    }
+
+    #[test]
+    fn the_empty_password_gets_general_advice() {
+        let found = estimate("");
+
+        assert_eq!(found.score, 0);
+        assert!(found.sequence.is_empty());
+        assert_eq!(warning(&found), None);
+        assert_eq!(
+            found.feedback.suggestions,
+            [
+                "Use a few words, avoid common phrases",
+                "No need for symbols, digits, or uppercase letters",
+            ]
+        );
+    }
+}
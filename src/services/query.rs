@@ -0,0 +1,943 @@
+//! The vault search query language.
+//!
+//! A query is a list of terms separated by whitespace, all of which must match an entry:
+//!
+//! - `github` fuzzy matches any searchable field, see [`crate::services::search`]
+//! - `"work laptop"` matches fields containing the exact phrase
+//! - `site:`, `user:`, `url:`, `tag:` and `notes:` restrict a term to one field, e.g. `tag:work`.
+//!   Values may be quoted and may use `*` and `?` wildcards. For `url:` a wildcard pattern is
+//!   matched against the host, so `url:*.corp.example` matches `https://git.corp.example/login`.
+//!   Notes are encrypted, other terms only search their first line and `notes:` all of them
+//! - `created:`, `modified:` and `changed:` (when the password was last changed) compare dates
+//!   written as `YYYY`, `YYYY-MM` or `YYYY-MM-DD`, optionally prefixed by `<`, `<=`, `>` or `>=`,
+//!   e.g. `modified:<2024-01-01`. Without an operator the date must fall in that year, month or day
+//! - `is:weak`, `is:reused` and `is:empty` select entries with those problems, `weak`, `reused`
+//!   and `empty` on their own are shorthands
+//! - any term can be negated with a leading `-`, e.g. `-tag:personal` or `-weak`
+
+use std::{
+    cell::{OnceCell, Ref, RefCell},
+    collections::{HashMap, HashSet},
+    fmt::{self, Display},
+};
+
+use aes_gcm::{
+    aead::{rand_core::RngCore, OsRng},
+    Aes256Gcm,
+};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use crate::{
+    models::{days_from_civil, AuthState, PasswordEntrySafe},
+    services::{
+        password_strength,
+        search::{self, SearchField, SearchResult},
+        security_report::WEAK_SCORE_THRESHOLD,
+    },
+};
+
+const SECONDS_PER_DAY: i64 = 60 * 60 * 24;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError {
+    pub message: String,
+    /// Character offset in the query where the problem was found.
+    pub position: usize,
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (at character {})", self.message, self.position + 1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateField {
+    Created,
+    Modified,
+    PasswordChanged,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Before,
+    OnOrBefore,
+    After,
+    OnOrAfter,
+    Within,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flag {
+    Weak,
+    Reused,
+    Empty,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    Fuzzy(String),
+    Phrase(String),
+    Field {
+        field: SearchField,
+        pattern: String,
+    },
+    Date {
+        field: DateField,
+        comparison: Comparison,
+        /// The unix timestamps (seconds) the written date covers, `start..end`.
+        start: i64,
+        end: i64,
+    },
+    Flag(Flag),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clause {
+    pub negated: bool,
+    pub term: Term,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    pub clauses: Vec<Clause>,
+}
+
+impl Query {
+    pub fn is_empty(&self) -> bool {
+        self.clauses.is_empty()
+    }
+}
+
+/// Facts about entries that need the whole vault or decrypting. They are worked out the first
+/// time a query asks for them and reused, entries added to the end of the list later (e.g. the
+/// next page) are checked as they come in. Entries that are changed or removed need a new context.
+#[derive(Default)]
+pub struct QueryContext {
+    flags: RefCell<PasswordFlags>,
+    /// The title of each entry's notes, by entry id.
+    notes_titles: RefCell<HashMap<i32, Zeroizing<String>>>,
+}
+
+// The notes titles and password hashes are secret, so they're left out.
+impl fmt::Debug for QueryContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QueryContext")
+            .field("checked", &self.flags.borrow().checked)
+            .finish_non_exhaustive()
+    }
+}
+
+struct PasswordFlags {
+    /// How many of the entries have been checked.
+    checked: usize,
+    weak: HashSet<i32>,
+    reused: HashSet<i32>,
+    empty: HashSet<i32>,
+    /// A random key for hashing passwords, so reuse is found without keeping the passwords.
+    key: Zeroizing<[u8; 32]>,
+    /// The first entry seen with each password, by the password's keyed hash.
+    seen: HashMap<[u8; 32], i32>,
+}
+
+impl Default for PasswordFlags {
+    fn default() -> Self {
+        let mut key = Zeroizing::new([0u8; 32]);
+        OsRng.fill_bytes(key.as_mut());
+
+        Self {
+            checked: 0,
+            weak: HashSet::new(),
+            reused: HashSet::new(),
+            empty: HashSet::new(),
+            key,
+            seen: HashMap::new(),
+        }
+    }
+}
+
+impl PasswordFlags {
+    /// Check the entries after the ones already checked. Passwords are decrypted one at a time
+    /// and zeroized as soon as they have been checked. Entries that can't be decrypted get no
+    /// flags.
+    fn check(&mut self, entries: &[PasswordEntrySafe], auth_state: &AuthState) {
+        let Some(unchecked) = entries.get(self.checked..).filter(|rest| !rest.is_empty()) else {
+            return;
+        };
+        let cipher = auth_state.cipher();
+
+        for entry in unchecked {
+            let Ok(password) = entry.decrypt_password_with(&cipher) else {
+                continue;
+            };
+
+            if password.is_empty() {
+                self.empty.insert(entry.id);
+                continue;
+            }
+
+            if password_strength::estimate(&password).score < WEAK_SCORE_THRESHOLD {
+                self.weak.insert(entry.id);
+            }
+
+            let mut mac = HmacSha256::new_from_slice(self.key.as_ref()).expect("any key length");
+            mac.update(password.as_bytes());
+            let hash: [u8; 32] = mac.finalize().into_bytes().into();
+            match self.seen.get(&hash) {
+                Some(first) => {
+                    self.reused.insert(*first);
+                    self.reused.insert(entry.id);
+                }
+                None => {
+                    self.seen.insert(hash, entry.id);
+                }
+            }
+        }
+
+        self.checked = entries.len();
+    }
+}
+
+impl QueryContext {
+    fn flags(
+        &self,
+        entries: &[PasswordEntrySafe],
+        auth_state: &AuthState,
+    ) -> Ref<'_, PasswordFlags> {
+        self.flags.borrow_mut().check(entries, auth_state);
+        self.flags.borrow()
+    }
+
+    /// The first line of the entry's notes, decrypted once and kept for the next query. Entries
+    /// whose notes can't be decrypted have no title to match.
+    fn notes_title(&self, entry: &PasswordEntrySafe, cipher: &Aes256Gcm) -> Zeroizing<String> {
+        self.notes_titles
+            .borrow_mut()
+            .entry(entry.id)
+            .or_insert_with(|| {
+                let notes = entry.decrypt_notes_with(cipher).unwrap_or_default();
+                Zeroizing::new(search::notes_title(&notes).to_string())
+            })
+            .clone()
+    }
+}
+
+/// Parse a query string.
+pub fn parse(input: &str) -> Result<Query, QueryError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut clauses = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i].is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        let negated = chars[i] == '-';
+        if negated {
+            i += 1;
+            if i >= chars.len() || chars[i].is_whitespace() {
+                return Err(error("Expected a term after '-'", start));
+            }
+        }
+
+        let term = if chars[i] == '"' {
+            let (phrase, next) = read_quoted(&chars, i)?;
+            i = next;
+            Term::Phrase(phrase)
+        } else {
+            // Read up to a ':' that introduces a value, or the end of the word.
+            let mut word = String::new();
+            while i < chars.len() && !chars[i].is_whitespace() && chars[i] != ':' {
+                word.push(chars[i]);
+                i += 1;
+            }
+
+            if i < chars.len() && chars[i] == ':' && is_qualifier(&word) {
+                i += 1;
+                let value_start = i;
+                let value = if i < chars.len() && chars[i] == '"' {
+                    let (value, next) = read_quoted(&chars, i)?;
+                    i = next;
+                    value
+                } else {
+                    let mut value = String::new();
+                    while i < chars.len() && !chars[i].is_whitespace() {
+                        value.push(chars[i]);
+                        i += 1;
+                    }
+                    value
+                };
+
+                if value.is_empty() {
+                    return Err(error(
+                        format!("Expected a value after '{word}:'"),
+                        value_start,
+                    ));
+                }
+
+                qualified_term(&word, value, value_start)?
+            } else if i < chars.len() && chars[i] == ':' {
+                // Not a field, e.g. the scheme of a URL or a time, so the whole word is searched.
+                while i < chars.len() && !chars[i].is_whitespace() {
+                    word.push(chars[i]);
+                    i += 1;
+                }
+                Term::Fuzzy(word)
+            } else {
+                match word.to_lowercase().as_str() {
+                    "weak" => Term::Flag(Flag::Weak),
+                    "reused" => Term::Flag(Flag::Reused),
+                    "empty" => Term::Flag(Flag::Empty),
+                    _ => Term::Fuzzy(word),
+                }
+            }
+        };
+
+        clauses.push(Clause { negated, term });
+    }
+
+    Ok(Query { clauses })
+}
+
+/// Run `query` over `entries`. Entries are ranked by how well they match the fuzzy terms,
+/// otherwise they keep their original order. Passwords are only decrypted if the query uses a
+/// flag such as `is:weak`, notes for a `notes:` term and notes titles for a word or phrase, one
+/// entry at a time.
+pub fn execute(
+    query: &Query,
+    entries: &[PasswordEntrySafe],
+    context: &QueryContext,
+    auth_state: &AuthState,
+) -> Vec<SearchResult> {
+    let cipher = OnceCell::new();
+    let mut results: Vec<SearchResult> = entries
+        .iter()
+        .enumerate()
+        .filter_map(|(index, entry)| {
+            evaluate(
+                query,
+                index,
+                entry,
+                |entry_id, flag| {
+                    let flags = context.flags(entries, auth_state);
+                    match flag {
+                        Flag::Weak => flags.weak.contains(&entry_id),
+                        Flag::Reused => flags.reused.contains(&entry_id),
+                        Flag::Empty => flags.empty.contains(&entry_id),
+                    }
+                },
+                |entry| {
+                    // Entries whose notes can't be decrypted have none to match.
+                    entry
+                        .decrypt_notes_with(cipher.get_or_init(|| auth_state.cipher()))
+                        .unwrap_or_default()
+                },
+                |entry| context.notes_title(entry, cipher.get_or_init(|| auth_state.cipher())),
+            )
+        })
+        .collect();
+
+    results.sort_by(|a, b| b.score.cmp(&a.score).then(a.index.cmp(&b.index)));
+    results
+}
+
+fn evaluate(
+    query: &Query,
+    index: usize,
+    entry: &PasswordEntrySafe,
+    has_flag: impl Fn(i32, Flag) -> bool,
+    notes: impl Fn(&PasswordEntrySafe) -> Zeroizing<String>,
+    notes_title: impl Fn(&PasswordEntrySafe) -> Zeroizing<String>,
+) -> Option<SearchResult> {
+    let mut result = SearchResult {
+        index,
+        score: 0,
+        matches: Vec::new(),
+    };
+    let title = OnceCell::new();
+    let title = || title.get_or_init(|| notes_title(entry)).as_str();
+
+    for clause in &query.clauses {
+        let matched = match &clause.term {
+            // A negated fuzzy term would exclude almost everything, so it excludes entries
+            // containing the word instead.
+            Term::Fuzzy(word) if clause.negated => contains_phrase(entry, title(), word),
+            Term::Fuzzy(word) => match search::search_entry(index, entry, title(), word) {
+                Some(found) => {
+                    result.merge(found);
+                    true
+                }
+                None => false,
+            },
+            Term::Phrase(phrase) => contains_phrase(entry, title(), phrase),
+            Term::Field {
+                field: SearchField::Notes,
+                pattern,
+            } => text_matches(SearchField::Notes, &notes(entry), pattern),
+            Term::Field { field, pattern } => field_matches(entry, *field, pattern),
+            Term::Date {
+                field,
+                comparison,
+                start,
+                end,
+            } => {
+                let timestamp = match field {
+                    DateField::Created => entry.created_at,
+                    DateField::Modified => entry.updated_at,
+                    DateField::PasswordChanged => entry.password_changed_at,
+                };
+                match comparison {
+                    Comparison::Before => timestamp < *start,
+                    Comparison::OnOrBefore => timestamp < *end,
+                    Comparison::After => timestamp >= *end,
+                    Comparison::OnOrAfter => timestamp >= *start,
+                    Comparison::Within => (*start..*end).contains(&timestamp),
+                }
+            }
+            Term::Flag(flag) => has_flag(entry.id, *flag),
+        };
+
+        if matched == clause.negated {
+            return None;
+        }
+    }
+
+    Some(result)
+}
+
+fn error(message: impl Into<String>, position: usize) -> QueryError {
+    QueryError {
+        message: message.into(),
+        position,
+    }
+}
+
+/// Read a double quoted string starting at `start`, returning it and the index after the closing
+/// quote. `\"` and `\\` escape a quote or backslash.
+fn read_quoted(chars: &[char], start: usize) -> Result<(String, usize), QueryError> {
+    let mut value = String::new();
+    let mut i = start + 1;
+
+    while i < chars.len() {
+        match chars[i] {
+            '"' => return Ok((value, i + 1)),
+            '\\' if i + 1 < chars.len() => {
+                value.push(chars[i + 1]);
+                i += 2;
+            }
+            c => {
+                value.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    Err(error("Missing closing quote", start))
+}
+
+/// Whether `word` followed by `:` restricts a term to a field or is a flag.
+fn is_qualifier(word: &str) -> bool {
+    matches!(
+        word.to_lowercase().as_str(),
+        "site"
+            | "user"
+            | "username"
+            | "url"
+            | "tag"
+            | "notes"
+            | "created"
+            | "modified"
+            | "changed"
+            | "is"
+    )
+}
+
+fn qualified_term(qualifier: &str, value: String, value_start: usize) -> Result<Term, QueryError> {
+    let field = match qualifier.to_lowercase().as_str() {
+        "site" => SearchField::Site,
+        "user" | "username" => SearchField::Username,
+        "url" => SearchField::Url,
+        "tag" => SearchField::Tag,
+        "notes" => SearchField::Notes,
+        "created" => return date_term(DateField::Created, &value, value_start),
+        "modified" => return date_term(DateField::Modified, &value, value_start),
+        "changed" => return date_term(DateField::PasswordChanged, &value, value_start),
+        "is" => {
+            return match value.to_lowercase().as_str() {
+                "weak" => Ok(Term::Flag(Flag::Weak)),
+                "reused" => Ok(Term::Flag(Flag::Reused)),
+                "empty" => Ok(Term::Flag(Flag::Empty)),
+                _ => Err(error(
+                    format!("Unknown flag '{value}', expected weak, reused or empty"),
+                    value_start,
+                )),
+            }
+        }
+        _ => return Ok(Term::Fuzzy(format!("{qualifier}:{value}"))),
+    };
+
+    Ok(Term::Field {
+        field,
+        pattern: value,
+    })
+}
+
+fn date_term(field: DateField, value: &str, position: usize) -> Result<Term, QueryError> {
+    let (comparison, date) = if let Some(date) = value.strip_prefix("<=") {
+        (Comparison::OnOrBefore, date)
+    } else if let Some(date) = value.strip_prefix(">=") {
+        (Comparison::OnOrAfter, date)
+    } else if let Some(date) = value.strip_prefix('<') {
+        (Comparison::Before, date)
+    } else if let Some(date) = value.strip_prefix('>') {
+        (Comparison::After, date)
+    } else {
+        (Comparison::Within, value.strip_prefix('=').unwrap_or(value))
+    };
+
+    let (start, end) = parse_date_range(date).ok_or_else(|| {
+        error(
+            format!("Invalid date '{date}', expected YYYY, YYYY-MM or YYYY-MM-DD"),
+            position,
+        )
+    })?;
+
+    Ok(Term::Date {
+        field,
+        comparison,
+        start,
+        end,
+    })
+}
+
+/// The unix timestamps covered by a `YYYY`, `YYYY-MM` or `YYYY-MM-DD` date, in UTC.
+fn parse_date_range(date: &str) -> Option<(i64, i64)> {
+    let parts: Vec<&str> = date.split('-').collect();
+    let numbers: Vec<i64> = parts
+        .iter()
+        .map(|part| {
+            (!part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+                .then(|| part.parse().ok())
+                .flatten()
+        })
+        .collect::<Option<_>>()?;
+
+    let start_of =
+        |year: i64, month: i64, day: i64| days_from_civil(year, month, day) * SECONDS_PER_DAY;
+
+    match numbers.as_slice() {
+        [year] if parts[0].len() == 4 => Some((start_of(*year, 1, 1), start_of(year + 1, 1, 1))),
+        [year, month] if parts[0].len() == 4 && (1..=12).contains(month) => {
+            let (next_year, next_month) = if *month == 12 {
+                (year + 1, 1)
+            } else {
+                (*year, month + 1)
+            };
+            Some((
+                start_of(*year, *month, 1),
+                start_of(next_year, next_month, 1),
+            ))
+        }
+        [year, month, day]
+            if parts[0].len() == 4
+                && (1..=12).contains(month)
+                && (1..=days_in_month(*year, *month)).contains(day) =>
+        {
+            let start = start_of(*year, *month, *day);
+            Some((start, start + SECONDS_PER_DAY))
+        }
+        _ => None,
+    }
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn contains_phrase(entry: &PasswordEntrySafe, notes_title: &str, phrase: &str) -> bool {
+    let phrase = phrase.to_lowercase();
+    search::entry_fields(entry, notes_title)
+        .iter()
+        .any(|(_, text)| Zeroizing::new(text.to_lowercase()).contains(&phrase))
+}
+
+/// Match one of the unencrypted fields, the notes are matched with [`text_matches`] once they've
+/// been decrypted.
+fn field_matches(entry: &PasswordEntrySafe, field: SearchField, pattern: &str) -> bool {
+    let texts: Vec<&str> = match field {
+        SearchField::Site => vec![entry.site.as_str()],
+        SearchField::Username => vec![entry.username.as_str()],
+        SearchField::Url => entry.urls.iter().map(String::as_str).collect(),
+        SearchField::Tag => entry.tags.iter().map(String::as_str).collect(),
+        SearchField::Notes => Vec::new(),
+    };
+
+    texts.iter().any(|text| text_matches(field, text, pattern))
+}
+
+fn text_matches(field: SearchField, text: &str, pattern: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let has_wildcard = pattern.contains(['*', '?']);
+    let text = Zeroizing::new(text.to_lowercase());
+
+    match field {
+        SearchField::Url if has_wildcard => {
+            glob_match(&pattern, url_host(&text)) || glob_match(&pattern, &text)
+        }
+        // Tags are matched whole, so `tag:work` doesn't match `homework`.
+        SearchField::Tag if !has_wildcard => *text == pattern,
+        _ if has_wildcard => glob_match(&pattern, &text),
+        _ => text.contains(&pattern),
+    }
+}
+
+/// The host part of a URL, e.g. `git.corp.example` for `https://me@git.corp.example:443/login`.
+/// An IPv6 address is returned without its brackets, e.g. `::1` for `http://[::1]:8080/`.
+pub fn url_host(url: &str) -> &str {
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = without_scheme
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or_default();
+    let host_and_port = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    match host_and_port.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next().unwrap_or_default(),
+        None => host_and_port.split(':').next().unwrap_or_default(),
+    }
+}
+
+/// Match `text` against a pattern where `*` matches any run of characters and `?` any single one.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{EntryExtras, PasswordEntryRaw};
+
+    fn auth_state() -> AuthState {
+        AuthState::from_key_material(Zeroizing::new([7; 32]))
+    }
+
+    fn entry(id: i32, site: &str, urls: &[&str], tags: &[&str], notes: &str) -> PasswordEntrySafe {
+        PasswordEntryRaw {
+            raw_password: Zeroizing::new(format!("Unique-Password-{id}-Long-Enough")),
+            ..raw_entry(id, site, urls, tags, notes)
+        }
+        .to_safe(&auth_state())
+        .unwrap()
+    }
+
+    fn entry_with_password(id: i32, password: &str) -> PasswordEntrySafe {
+        PasswordEntryRaw {
+            raw_password: Zeroizing::new(password.into()),
+            ..raw_entry(id, "Site", &[], &[], "")
+        }
+        .to_safe(&auth_state())
+        .unwrap()
+    }
+
+    fn raw_entry(
+        id: i32,
+        site: &str,
+        urls: &[&str],
+        tags: &[&str],
+        notes: &str,
+    ) -> PasswordEntryRaw {
+        PasswordEntryRaw {
+            id,
+            uuid: format!("uuid-{id}"),
+            revision: 1,
+            site: site.into(),
+            username: format!("user{id}"),
+            raw_password: Zeroizing::new(String::new()),
+            urls: urls.iter().map(|url| url.to_string()).collect(),
+            notes: notes.into(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            created_at: 0,
+            updated_at: 0,
+            password_changed_at: 0,
+            extras: EntryExtras::default(),
+        }
+    }
+
+    fn matching_ids(input: &str, entries: &[PasswordEntrySafe]) -> Vec<i32> {
+        matching_ids_in(input, entries, &QueryContext::default())
+    }
+
+    fn matching_ids_in(
+        input: &str,
+        entries: &[PasswordEntrySafe],
+        context: &QueryContext,
+    ) -> Vec<i32> {
+        let query = parse(input).unwrap();
+        let mut ids: Vec<i32> = execute(&query, entries, context, &auth_state())
+            .iter()
+            .map(|result| entries[result.index].id)
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    fn terms(input: &str) -> Vec<Term> {
+        parse(input)
+            .unwrap()
+            .clauses
+            .into_iter()
+            .map(|clause| clause.term)
+            .collect()
+    }
+
+    #[test]
+    fn parses_words_phrases_and_fields() {
+        let query = parse(r#"git "work laptop" -tag:personal user:"a b""#).unwrap();
+
+        assert_eq!(
+            query.clauses,
+            vec![
+                Clause {
+                    negated: false,
+                    term: Term::Fuzzy("git".into()),
+                },
+                Clause {
+                    negated: false,
+                    term: Term::Phrase("work laptop".into()),
+                },
+                Clause {
+                    negated: true,
+                    term: Term::Field {
+                        field: SearchField::Tag,
+                        pattern: "personal".into(),
+                    },
+                },
+                Clause {
+                    negated: false,
+                    term: Term::Field {
+                        field: SearchField::Username,
+                        pattern: "a b".into(),
+                    },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_flags_with_and_without_is() {
+        assert_eq!(
+            terms("weak is:reused IS:Empty"),
+            vec![
+                Term::Flag(Flag::Weak),
+                Term::Flag(Flag::Reused),
+                Term::Flag(Flag::Empty),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_date_ranges() {
+        let day = |year, month, day| days_from_civil(year, month, day) * SECONDS_PER_DAY;
+
+        assert_eq!(
+            terms("modified:<2024-01-01 created:2023-02 changed:>=2020"),
+            vec![
+                Term::Date {
+                    field: DateField::Modified,
+                    comparison: Comparison::Before,
+                    start: day(2024, 1, 1),
+                    end: day(2024, 1, 2),
+                },
+                Term::Date {
+                    field: DateField::Created,
+                    comparison: Comparison::Within,
+                    start: day(2023, 2, 1),
+                    end: day(2023, 3, 1),
+                },
+                Term::Date {
+                    field: DateField::PasswordChanged,
+                    comparison: Comparison::OnOrAfter,
+                    start: day(2020, 1, 1),
+                    end: day(2021, 1, 1),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_qualifiers_are_searched_as_words() {
+        assert_eq!(
+            terms("https://github.com/login 10:30 -foo:bar"),
+            vec![
+                Term::Fuzzy("https://github.com/login".into()),
+                Term::Fuzzy("10:30".into()),
+                Term::Fuzzy("foo:bar".into()),
+            ]
+        );
+        assert!(parse("-foo:bar").unwrap().clauses[0].negated);
+    }
+
+    #[test]
+    fn reports_errors_with_their_position() {
+        let message_at = |input| {
+            let err = parse(input).unwrap_err();
+            (err.message, err.position)
+        };
+
+        assert_eq!(
+            message_at("site:"),
+            ("Expected a value after 'site:'".into(), 5)
+        );
+        assert_eq!(message_at("a \"open"), ("Missing closing quote".into(), 2));
+        assert_eq!(message_at("x - y"), ("Expected a term after '-'".into(), 2));
+        assert_eq!(
+            message_at("is:old"),
+            (
+                "Unknown flag 'old', expected weak, reused or empty".into(),
+                3
+            )
+        );
+        assert_eq!(
+            message_at("created:2023-02-30"),
+            (
+                "Invalid date '2023-02-30', expected YYYY, YYYY-MM or YYYY-MM-DD".into(),
+                8
+            )
+        );
+    }
+
+    #[test]
+    fn matches_fields_and_wildcards() {
+        let entries = [
+            entry(1, "GitHub", &["https://github.com/login"], &["work"], ""),
+            entry(
+                2,
+                "Corp Git",
+                &["https://git.corp.example:443/"],
+                &["homework"],
+                "",
+            ),
+            entry(
+                3,
+                "Router",
+                &["http://[::1]:8080/admin"],
+                &[],
+                "admin pin 0000",
+            ),
+        ];
+
+        assert_eq!(matching_ids("tag:work", &entries), vec![1]);
+        assert_eq!(matching_ids("tag:*work", &entries), vec![1, 2]);
+        assert_eq!(matching_ids("url:*.corp.example", &entries), vec![2]);
+        assert_eq!(matching_ids("url:::1", &entries), vec![3]);
+        assert_eq!(matching_ids("-tag:work git", &entries), vec![2]);
+        assert_eq!(matching_ids("https://github.com", &entries), vec![1]);
+        assert_eq!(matching_ids(r#""corp git""#, &entries), vec![2]);
+    }
+
+    #[test]
+    fn words_search_the_notes_title_and_notes_terms_all_the_notes() {
+        let entries = [
+            entry(1, "Router", &[], &[], "admin pin 0000\nreset code 4242"),
+            entry(2, "Mail", &[], &[], ""),
+        ];
+
+        assert_eq!(matching_ids("pin", &entries), vec![1]);
+        assert_eq!(matching_ids(r#""admin pin""#, &entries), vec![1]);
+        assert!(matching_ids("4242", &entries).is_empty());
+        assert_eq!(matching_ids("notes:pin", &entries), vec![1]);
+        assert_eq!(matching_ids("notes:4242", &entries), vec![1]);
+        assert_eq!(matching_ids("notes:admin*", &entries), vec![1]);
+        assert_eq!(matching_ids("-notes:pin", &entries), vec![2]);
+    }
+
+    #[test]
+    fn notes_titles_rank_below_sites() {
+        let entries = [
+            entry(1, "Mail", &[], &[], "pin code"),
+            entry(2, "Pinboard", &[], &[], ""),
+        ];
+
+        let query = parse("pin").unwrap();
+        let results = execute(&query, &entries, &QueryContext::default(), &auth_state());
+        let ids: Vec<i32> = results
+            .iter()
+            .map(|result| entries[result.index].id)
+            .collect();
+        assert_eq!(ids, vec![2, 1]);
+        assert_eq!(results[1].positions(SearchField::Notes), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn flags_cover_entries_loaded_later() {
+        let entries = [
+            entry_with_password(1, "Correct-Horse-Battery-Staple-42"),
+            entry_with_password(2, "hunter2"),
+            entry_with_password(3, ""),
+            entry_with_password(4, "Correct-Horse-Battery-Staple-42"),
+        ];
+        let context = QueryContext::default();
+
+        assert_eq!(matching_ids_in("weak", &entries[..2], &context), vec![2]);
+        assert!(matching_ids_in("reused", &entries[..2], &context).is_empty());
+
+        assert_eq!(matching_ids_in("weak", &entries, &context), vec![2]);
+        assert_eq!(matching_ids_in("reused", &entries, &context), vec![1, 4]);
+        assert_eq!(matching_ids_in("empty", &entries, &context), vec![3]);
+        assert_eq!(context.flags.borrow().checked, entries.len());
+    }
+
+    #[test]
+    fn finds_the_host_of_a_url() {
+        assert_eq!(
+            url_host("https://me@git.corp.example:443/login"),
+            "git.corp.example"
+        );
+        assert_eq!(url_host("github.com/login?next=/"), "github.com");
+        assert_eq!(url_host("http://[::1]:8080/"), "::1");
+        assert_eq!(url_host("ssh://git@[2001:db8::2]/repo"), "2001:db8::2");
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_match("*.example", "git.example"));
+        assert!(glob_match("g?t*", "github"));
+        assert!(!glob_match("*.example", "example"));
+        assert!(glob_match("*", ""));
+    }
+}
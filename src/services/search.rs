@@ -0,0 +1,272 @@
+//! In-memory fuzzy search over password entries.
+//!
+//! A query matches a piece of text when all of its characters appear in order, case-insensitively,
+//! like "gh" matching "github.com". Matches are scored so that consecutive characters, characters
+//! at the start of words and matches near the start of the text rank higher, and the positions of
+//! the matched characters are kept so the UI can highlight them.
+
+use crate::models::PasswordEntrySafe;
+
+const SCORE_MATCH: i64 = 16;
+const BONUS_CONSECUTIVE: i64 = 24;
+const BONUS_WORD_START: i64 = 20;
+const BONUS_FIRST_CHAR: i64 = 12;
+const PENALTY_GAP: i64 = 2;
+const PENALTY_LEADING_GAP: i64 = 1;
+
+/// The part of an entry a query matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchField {
+    Site,
+    Username,
+    Url,
+    Notes,
+    Tag,
+}
+
+impl SearchField {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SearchField::Site => "site",
+            SearchField::Username => "username",
+            SearchField::Url => "url",
+            SearchField::Notes => "notes",
+            SearchField::Tag => "tag",
+        }
+    }
+
+    /// How much a match in this field counts towards the entry's rank, in percent.
+    fn weight(&self) -> i64 {
+        match self {
+            SearchField::Site => 100,
+            SearchField::Tag => 90,
+            SearchField::Url => 80,
+            SearchField::Username => 70,
+            SearchField::Notes => 50,
+        }
+    }
+}
+
+/// A successful fuzzy match of a query against a piece of text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzyMatch {
+    pub score: i64,
+    /// Character (not byte) positions of the matched characters in the text.
+    pub positions: Vec<usize>,
+}
+
+/// A match of a query against one field of an entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldMatch {
+    pub field: SearchField,
+    pub text: String,
+    pub positions: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    /// Index of the entry in the slice that was searched.
+    pub index: usize,
+    pub score: i64,
+    /// The best match per field, best first.
+    pub matches: Vec<FieldMatch>,
+}
+
+impl SearchResult {
+    /// Positions to highlight in `field`, empty if the field didn't match.
+    pub fn positions(&self, field: SearchField) -> Vec<usize> {
+        self.matches
+            .iter()
+            .find(|m| m.field == field)
+            .map(|m| m.positions.clone())
+            .unwrap_or_default()
+    }
+
+    /// Combine the matches of another search term for the same entry.
+    pub fn merge(&mut self, other: SearchResult) {
+        self.score += other.score;
+        for found in other.matches {
+            match self
+                .matches
+                .iter_mut()
+                .find(|m| m.field == found.field && m.text == found.text)
+            {
+                Some(existing) => {
+                    existing.positions.extend(found.positions);
+                    existing.positions.sort_unstable();
+                    existing.positions.dedup();
+                }
+                None => self.matches.push(found),
+            }
+        }
+    }
+}
+
+/// Fuzzy match `query` against `text`. Returns `None` when not every query character could be
+/// found in order. Whitespace in the query is ignored.
+pub fn fuzzy_match(query: &str, text: &str) -> Option<FuzzyMatch> {
+    let query: Vec<char> = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect();
+    if query.is_empty() {
+        return Some(FuzzyMatch {
+            score: 0,
+            positions: Vec::new(),
+        });
+    }
+
+    let text: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = text
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+
+    // best[i][j]: best score matching query[..=i] with query[i] at text position j, together
+    // with the position query[i - 1] was matched at.
+    let mut best: Vec<Vec<Option<(i64, usize)>>> = vec![vec![None; text.len()]; query.len()];
+
+    for (i, query_char) in query.iter().enumerate() {
+        for j in 0..text.len() {
+            if lower[j] != *query_char {
+                continue;
+            }
+
+            let bonus = SCORE_MATCH + word_start_bonus(&text, j);
+
+            if i == 0 {
+                let first_char = if j == 0 { BONUS_FIRST_CHAR } else { 0 };
+                best[i][j] = Some((bonus + first_char - PENALTY_LEADING_GAP * j as i64, j));
+                continue;
+            }
+
+            let mut candidate: Option<(i64, usize)> = None;
+            for (previous, cell) in best[i - 1][..j].iter().enumerate() {
+                if let Some((score, _)) = *cell {
+                    let transition = if previous + 1 == j {
+                        BONUS_CONSECUTIVE
+                    } else {
+                        -PENALTY_GAP * (j - previous - 1) as i64
+                    };
+                    let total = score + bonus + transition;
+                    if candidate.is_none_or(|(best_score, _)| total > best_score) {
+                        candidate = Some((total, previous));
+                    }
+                }
+            }
+            best[i][j] = candidate;
+        }
+    }
+
+    let last = query.len() - 1;
+    let (end, score) = best[last]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, cell)| cell.map(|(score, _)| (j, score)))
+        .max_by_key(|(_, score)| *score)?;
+
+    let mut positions = vec![end];
+    let mut j = end;
+    for i in (1..query.len()).rev() {
+        let (_, previous) = best[i][j].expect("reachable cells have a predecessor");
+        positions.push(previous);
+        j = previous;
+    }
+    positions.reverse();
+
+    Some(FuzzyMatch { score, positions })
+}
+
+fn word_start_bonus(text: &[char], j: usize) -> i64 {
+    if j == 0 {
+        return BONUS_WORD_START;
+    }
+    let previous = text[j - 1];
+    let current = text[j];
+    if !previous.is_alphanumeric() || (previous.is_lowercase() && current.is_uppercase()) {
+        BONUS_WORD_START
+    } else {
+        0
+    }
+}
+
+/// Fuzzy match `query` against every searchable field of `entry`, whose decrypted notes title is
+/// `notes_title`.
+pub fn search_entry(
+    index: usize,
+    entry: &PasswordEntrySafe,
+    notes_title: &str,
+    query: &str,
+) -> Option<SearchResult> {
+    let mut matches: Vec<(i64, FieldMatch)> = Vec::new();
+    for (field, text) in entry_fields(entry, notes_title) {
+        let Some(found) = fuzzy_match(query, text) else {
+            continue;
+        };
+        let weighted = found.score * field.weight() / 100;
+
+        // Only the best match per field kind is kept, e.g. the best of several URLs.
+        match matches.iter_mut().find(|(_, m)| m.field == field) {
+            Some(existing) if existing.0 >= weighted => {}
+            Some(existing) => {
+                *existing = (weighted, field_match(field, text, found.positions));
+            }
+            None => matches.push((weighted, field_match(field, text, found.positions))),
+        }
+    }
+
+    if matches.is_empty() {
+        return None;
+    }
+
+    matches.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    Some(SearchResult {
+        index,
+        score: matches[0].0,
+        matches: matches.into_iter().map(|(_, m)| m).collect(),
+    })
+}
+
+/// The first non-empty line of an entry's notes, which is what search matches against.
+pub fn notes_title(notes: &str) -> &str {
+    notes
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default()
+}
+
+/// Every searchable piece of text in `entry`, labelled with the field it came from. The notes are
+/// encrypted, so the caller decrypts them and passes in their title.
+pub fn entry_fields<'a>(
+    entry: &'a PasswordEntrySafe,
+    notes_title: &'a str,
+) -> Vec<(SearchField, &'a str)> {
+    let mut fields: Vec<(SearchField, &str)> = vec![
+        (SearchField::Site, &entry.site),
+        (SearchField::Username, &entry.username),
+        (SearchField::Notes, notes_title),
+    ];
+    fields.extend(
+        entry
+            .urls
+            .iter()
+            .map(|url| (SearchField::Url, url.as_str())),
+    );
+    fields.extend(
+        entry
+            .tags
+            .iter()
+            .map(|tag| (SearchField::Tag, tag.as_str())),
+    );
+    fields
+}
+
+fn field_match(field: SearchField, text: &str, positions: Vec<usize>) -> FieldMatch {
+    FieldMatch {
+        field,
+        text: text.to_string(),
+        positions,
+    }
+}
@@ -0,0 +1,168 @@
+//! The vault health report. Every entry is decrypted in memory, checked for common problems and
+//! only the id, site and username of problem entries are kept, so the finished report never
+//! holds a plaintext password.
+
+use std::path::Path;
+
+use crate::{
+    models::{unix_now, AuthState, PasswordEntryRaw},
+    services::{
+        breach_check::{self, BreachIndex, BreachedPassword},
+        database::DatabaseService,
+        password_entry, password_strength, settings,
+    },
+};
+
+/// Passwords scoring below this are reported as weak.
+pub const WEAK_SCORE_THRESHOLD: u8 = 3;
+
+const SECONDS_PER_MONTH: i64 = 60 * 60 * 24 * 30;
+
+/// The identifying details of an entry that appears in the report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportEntry {
+    pub id: i32,
+    pub site: String,
+    pub username: String,
+}
+
+impl From<&PasswordEntryRaw> for ReportEntry {
+    fn from(entry: &PasswordEntryRaw) -> Self {
+        Self {
+            id: entry.id,
+            site: entry.site.clone(),
+            username: entry.username.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeakPassword {
+    pub entry: ReportEntry,
+    pub score: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OldPassword {
+    pub entry: ReportEntry,
+    pub months_since_changed: i64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SecurityReport {
+    pub total_entries: usize,
+    /// Groups of two or more entries that share the same password.
+    pub reused: Vec<Vec<ReportEntry>>,
+    pub weak: Vec<WeakPassword>,
+    pub old: Vec<OldPassword>,
+    pub empty: Vec<ReportEntry>,
+    /// Entries whose password appears in the local breach dataset, `None` when no breach index
+    /// has been configured.
+    pub breached: Option<Vec<BreachedPassword>>,
+    pub max_password_age_months: u32,
+    /// Entries that couldn't be decrypted, and so weren't checked.
+    pub unreadable: Vec<ReportEntry>,
+}
+
+impl SecurityReport {
+    pub fn issue_count(&self) -> usize {
+        self.reused.iter().map(Vec::len).sum::<usize>()
+            + self.weak.len()
+            + self.old.len()
+            + self.empty.len()
+            + self.breached.as_ref().map_or(0, Vec::len)
+            + self.unreadable.len()
+    }
+}
+
+pub async fn generate_report(
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<SecurityReport, String> {
+    let settings = settings::get_settings(db_service).await?;
+    let loaded =
+        password_entry::load_password_entries(auth_state, db_service, String::new()).await?;
+    let entries = loaded.entries;
+
+    let mut report = analyze_entries(&entries, settings.max_password_age_months, unix_now());
+    report.unreadable = loaded
+        .problems
+        .into_iter()
+        .map(|problem| ReportEntry {
+            id: problem.id,
+            site: problem.site,
+            username: problem.username,
+        })
+        .collect();
+
+    // Looking every password up in the index reads from a file that may be several gigabytes.
+    if !settings.breach_index_path.is_empty() {
+        let index_path = settings.breach_index_path;
+        let breached = tokio::task::spawn_blocking(move || {
+            let mut index = BreachIndex::open(Path::new(&index_path))?;
+            breach_check::check_entries(&mut index, &entries)
+        })
+        .await
+        .map_err(|err| err.to_string())??;
+        report.breached = Some(breached);
+    }
+
+    Ok(report)
+}
+
+/// Build the report for already decrypted `entries`. `now` is a unix timestamp in seconds.
+pub fn analyze_entries(
+    entries: &[PasswordEntryRaw],
+    max_password_age_months: u32,
+    now: i64,
+) -> SecurityReport {
+    let mut report = SecurityReport {
+        total_entries: entries.len(),
+        max_password_age_months,
+        ..Default::default()
+    };
+
+    // Sorting references by password groups identical passwords together without making any
+    // further copies of the plaintext.
+    let mut by_password: Vec<&PasswordEntryRaw> = entries
+        .iter()
+        .filter(|entry| !entry.raw_password.is_empty())
+        .collect();
+    by_password.sort_by(|a, b| a.raw_password.cmp(&b.raw_password));
+
+    for group in by_password.chunk_by(|a, b| a.raw_password == b.raw_password) {
+        if group.len() > 1 {
+            report.reused.push(
+                group
+                    .iter()
+                    .map(|entry| ReportEntry::from(*entry))
+                    .collect(),
+            );
+        }
+    }
+
+    for entry in entries {
+        if entry.raw_password.is_empty() {
+            report.empty.push(entry.into());
+            continue;
+        }
+
+        let score = password_strength::estimate(&entry.raw_password).score;
+        if score < WEAK_SCORE_THRESHOLD {
+            report.weak.push(WeakPassword {
+                entry: entry.into(),
+                score,
+            });
+        }
+
+        let months_since_changed = (now - entry.password_changed_at) / SECONDS_PER_MONTH;
+        if months_since_changed >= max_password_age_months as i64 {
+            report.old.push(OldPassword {
+                entry: entry.into(),
+                months_since_changed,
+            });
+        }
+    }
+
+    report
+}
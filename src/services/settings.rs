@@ -0,0 +1,40 @@
+use sqlx::prelude::*;
+
+use crate::{
+    models::Settings,
+    services::database::{DatabaseService, QueryError},
+};
+
+pub async fn get_settings(db_service: &DatabaseService) -> Result<Settings, QueryError> {
+    let row = sqlx::query(
+        "select min_master_password_score, max_password_age_months, breach_index_path, backup_retention, sync_folder from settings where id = 1;",
+    )
+    .fetch_one(&db_service.pool)
+    .await?;
+
+    Ok(Settings {
+        min_master_password_score: row.get("min_master_password_score"),
+        max_password_age_months: row.get("max_password_age_months"),
+        breach_index_path: row.get("breach_index_path"),
+        backup_retention: row.get("backup_retention"),
+        sync_folder: row.get("sync_folder"),
+    })
+}
+
+pub async fn save_settings(
+    settings: &Settings,
+    db_service: &DatabaseService,
+) -> Result<(), QueryError> {
+    sqlx::query(
+        "update settings set min_master_password_score = ?, max_password_age_months = ?, breach_index_path = ?, backup_retention = ?, sync_folder = ? where id = 1;",
+    )
+    .bind(settings.min_master_password_score)
+    .bind(settings.max_password_age_months)
+    .bind(settings.breach_index_path.clone())
+    .bind(settings.backup_retention)
+    .bind(settings.sync_folder.clone())
+    .execute(&db_service.pool)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,430 @@
+//! An SSH agent serving the SSH keys kept in vault entries, speaking the OpenSSH agent protocol
+//! (draft-miller-ssh-agent) on a Unix domain socket next to the vault. Point SSH at it with
+//! `SSH_AUTH_SOCK` or `ssh -o IdentityAgent=...`.
+//!
+//! The agent only lists and signs, keys can't be added to it or removed from it other than by
+//! editing the entries. It has no keys while the app is locked, and keys of entries flagged that
+//! way are only used after the use has been allowed in the app.
+
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use ssh_key::{Algorithm, EcdsaCurve, HashAlg, LineEnding, PrivateKey};
+use tokio::sync::oneshot;
+use zeroize::Zeroizing;
+
+use crate::{
+    models::AuthState,
+    services::{database::DatabaseService, password_entry},
+};
+
+/// Named after the vault's file like the unlock agent's socket.
+pub const SOCKET_EXTENSION: &str = "ssh-agent.sock";
+
+/// The app's sign-in, shared with the agent. Keys are only served while it's signed in.
+pub type SharedAuthState = Arc<Mutex<AuthState>>;
+
+/// A key of an entry that's flagged to ask before each use, waiting to be allowed or denied.
+#[derive(Debug)]
+pub struct ConfirmRequest {
+    pub site: String,
+    pub fingerprint: String,
+    pub respond: oneshot::Sender<bool>,
+}
+
+/// An entry's SSH key, ready to sign with.
+pub struct SshIdentity {
+    pub entry_id: i32,
+    pub site: String,
+    pub key: PrivateKey,
+    pub confirm_use: bool,
+}
+
+impl SshIdentity {
+    /// What `ssh-add -l` shows for the key: its own comment, or else the entry's site.
+    pub fn comment(&self) -> &str {
+        match self.key.comment() {
+            "" => &self.site,
+            comment => comment,
+        }
+    }
+}
+
+pub fn socket_path(db_service: &DatabaseService) -> PathBuf {
+    db_service.path.with_extension(SOCKET_EXTENSION)
+}
+
+/// Read a private key in OpenSSH format, e.g. the contents of `~/.ssh/id_ed25519`, removing its
+/// passphrase if it has one. The vault encrypts the key it returns.
+pub fn import_private_key(text: &str, passphrase: &str) -> Result<Zeroizing<String>, String> {
+    let key = PrivateKey::from_openssh(text.trim())
+        .map_err(|err| format!("This isn't a private key in OpenSSH format: {err}"))?;
+
+    let key = if key.is_encrypted() {
+        if passphrase.is_empty() {
+            return Err(
+                "The key is protected with a passphrase, enter it to import the key".into(),
+            );
+        }
+        key.decrypt(passphrase)
+            .map_err(|_| "The passphrase for the key is incorrect".to_string())?
+    } else {
+        key
+    };
+
+    if !is_supported(key.algorithm()) {
+        return Err(format!(
+            "{} keys aren't supported, use an Ed25519, RSA or ECDSA P-256 key",
+            key.algorithm()
+        ));
+    }
+
+    key.to_openssh(LineEnding::LF)
+        .map_err(|err| format!("Could not encode the key: {err}"))
+}
+
+/// The key's type and SHA-256 fingerprint, as `ssh-keygen -l` shows them.
+pub fn describe_key(private_key: &str) -> Result<String, String> {
+    let key = PrivateKey::from_openssh(private_key).map_err(|err| err.to_string())?;
+    Ok(format!(
+        "{} {}",
+        key.algorithm(),
+        key.fingerprint(HashAlg::Sha256)
+    ))
+}
+
+/// Every entry's SSH key. Keys that can't be read are left out, as are entries that can't be
+/// decrypted.
+pub async fn load_identities(
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<Vec<SshIdentity>, String> {
+    let loaded =
+        password_entry::load_password_entries(auth_state, db_service, String::new()).await?;
+
+    Ok(loaded
+        .entries
+        .into_iter()
+        .filter_map(|entry| {
+            let ssh_key = entry.extras.ssh_key?;
+            let key = PrivateKey::from_openssh(ssh_key.private_key.as_bytes()).ok()?;
+            Some(SshIdentity {
+                entry_id: entry.id,
+                site: entry.site,
+                key,
+                confirm_use: ssh_key.confirm_use,
+            })
+        })
+        .collect())
+}
+
+fn is_supported(algorithm: Algorithm) -> bool {
+    matches!(
+        algorithm,
+        Algorithm::Ed25519
+            | Algorithm::Rsa { .. }
+            | Algorithm::Ecdsa {
+                curve: EcdsaCurve::NistP256
+            }
+    )
+}
+
+#[cfg(unix)]
+pub use unix::run_ssh_agent;
+
+#[cfg(not(unix))]
+pub async fn run_ssh_agent(
+    _socket_path: &std::path::Path,
+    _auth_state: SharedAuthState,
+    _db_service: Arc<DatabaseService>,
+    _confirm: tokio::sync::mpsc::UnboundedSender<ConfirmRequest>,
+) -> Result<(), String> {
+    Err("The SSH agent is only available on Unix".into())
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::{
+        os::unix::fs::{MetadataExt, PermissionsExt},
+        path::{Path, PathBuf},
+        sync::Arc,
+        time::Duration,
+    };
+
+    use sha2::{Sha256, Sha512};
+    use signature::{SignatureEncoding, Signer};
+    use ssh_key::{
+        private::{KeypairData, RsaKeypair},
+        Algorithm, HashAlg, Mpint, PrivateKey, Signature,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{UnixListener, UnixStream},
+        sync::{mpsc, oneshot, Mutex},
+    };
+    use zeroize::Zeroizing;
+
+    use super::{load_identities, ConfirmRequest, SharedAuthState, SshIdentity};
+    use crate::services::database::DatabaseService;
+
+    const SSH_AGENT_FAILURE: u8 = 5;
+    const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+    const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+    const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+    const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+    const SSH_AGENT_RSA_SHA2_256: u32 = 2;
+    const SSH_AGENT_RSA_SHA2_512: u32 = 4;
+
+    /// OpenSSH's own limit for agent messages.
+    const MAX_MESSAGE_LEN: usize = 256 * 1024;
+    /// How long a use of a key waits to be allowed before it's denied.
+    const CONFIRM_TIMEOUT: Duration = Duration::from_secs(60);
+
+    /// Only one use of a key is asked about at a time.
+    type Confirmations = Arc<Mutex<mpsc::UnboundedSender<ConfirmRequest>>>;
+
+    /// Removes the socket once the agent stops, however it stops.
+    struct SocketGuard(PathBuf);
+
+    impl Drop for SocketGuard {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    /// Serve SSH clients until the returned future is dropped.
+    pub async fn run_ssh_agent(
+        socket_path: &Path,
+        auth_state: SharedAuthState,
+        db_service: Arc<DatabaseService>,
+        confirm: mpsc::UnboundedSender<ConfirmRequest>,
+    ) -> Result<(), String> {
+        if socket_path.exists() {
+            if UnixStream::connect(socket_path).await.is_ok() {
+                return Err(format!(
+                    "An SSH agent is already running on {}",
+                    socket_path.display()
+                ));
+            }
+            // Left behind by an app that didn't exit cleanly.
+            std::fs::remove_file(socket_path).map_err(|err| err.to_string())?;
+        }
+
+        let listener = UnixListener::bind(socket_path).map_err(|err| err.to_string())?;
+        let _guard = SocketGuard(socket_path.to_path_buf());
+        std::fs::set_permissions(socket_path, std::fs::Permissions::from_mode(0o600))
+            .map_err(|err| err.to_string())?;
+        let owner = std::fs::metadata(socket_path)
+            .map_err(|err| err.to_string())?
+            .uid();
+
+        let confirm: Confirmations = Arc::new(Mutex::new(confirm));
+
+        loop {
+            let (stream, _) = listener.accept().await.map_err(|err| err.to_string())?;
+
+            // The socket's permissions should already keep other users out.
+            let same_user = stream
+                .peer_cred()
+                .is_ok_and(|credentials| credentials.uid() == owner);
+            if same_user {
+                tokio::spawn(serve(
+                    stream,
+                    auth_state.clone(),
+                    db_service.clone(),
+                    confirm.clone(),
+                ));
+            }
+        }
+    }
+
+    async fn serve(
+        mut stream: UnixStream,
+        auth_state: SharedAuthState,
+        db_service: Arc<DatabaseService>,
+        confirm: Confirmations,
+    ) {
+        loop {
+            let mut len = [0; 4];
+            if stream.read_exact(&mut len).await.is_err() {
+                return;
+            }
+            let len = u32::from_be_bytes(len) as usize;
+            if len == 0 || len > MAX_MESSAGE_LEN {
+                return;
+            }
+
+            let mut message = Zeroizing::new(vec![0; len]);
+            if stream.read_exact(&mut message).await.is_err() {
+                return;
+            }
+
+            let response = respond(&message, &auth_state, &db_service, &confirm)
+                .await
+                .unwrap_or_else(|| vec![SSH_AGENT_FAILURE]);
+
+            let mut framed = (response.len() as u32).to_be_bytes().to_vec();
+            framed.extend_from_slice(&response);
+            if stream.write_all(&framed).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// The response to a message, `None` for a failure. Anything but listing keys and signing
+    /// with one fails.
+    async fn respond(
+        message: &[u8],
+        auth_state: &SharedAuthState,
+        db_service: &DatabaseService,
+        confirm: &Confirmations,
+    ) -> Option<Vec<u8>> {
+        let mut reader = Reader(message);
+
+        match reader.byte()? {
+            SSH_AGENTC_REQUEST_IDENTITIES => {
+                let identities = identities(auth_state, db_service).await;
+
+                let mut response = vec![SSH_AGENT_IDENTITIES_ANSWER];
+                response.extend_from_slice(&(identities.len() as u32).to_be_bytes());
+                for identity in &identities {
+                    put_string(&mut response, &identity.key.public_key().to_bytes().ok()?);
+                    put_string(&mut response, identity.comment().as_bytes());
+                }
+                Some(response)
+            }
+            SSH_AGENTC_SIGN_REQUEST => {
+                let key_blob = reader.string()?;
+                let data = reader.string()?;
+                let flags = reader.u32()?;
+
+                let identities = identities(auth_state, db_service).await;
+                let identity = identities.iter().find(|identity| {
+                    identity
+                        .key
+                        .public_key()
+                        .to_bytes()
+                        .is_ok_and(|blob| blob == key_blob)
+                })?;
+
+                if identity.confirm_use && !allowed(identity, confirm).await {
+                    return None;
+                }
+
+                let mut response = vec![SSH_AGENT_SIGN_RESPONSE];
+                put_string(&mut response, &sign(&identity.key, data, flags)?);
+                Some(response)
+            }
+            _ => None,
+        }
+    }
+
+    /// The keys to serve, none while the app is locked.
+    async fn identities(
+        auth_state: &SharedAuthState,
+        db_service: &DatabaseService,
+    ) -> Vec<SshIdentity> {
+        let auth_state = auth_state.lock().unwrap().clone();
+        if !auth_state.signed_in {
+            return Vec::new();
+        }
+
+        load_identities(&auth_state, db_service)
+            .await
+            .unwrap_or_default()
+    }
+
+    /// Ask the app whether the key may be used, denying it when there's no answer in time.
+    async fn allowed(identity: &SshIdentity, confirm: &Confirmations) -> bool {
+        let confirm = confirm.lock().await;
+        let (respond, answer) = oneshot::channel();
+
+        let request = ConfirmRequest {
+            site: identity.site.clone(),
+            fingerprint: identity.key.fingerprint(HashAlg::Sha256).to_string(),
+            respond,
+        };
+        if confirm.send(request).is_err() {
+            return false;
+        }
+
+        matches!(
+            tokio::time::timeout(CONFIRM_TIMEOUT, answer).await,
+            Ok(Ok(true))
+        )
+    }
+
+    /// The signature blob for `data`. RSA keys sign with SHA-2 as the client asks, never with the
+    /// SHA-1 of the original `ssh-rsa` algorithm.
+    fn sign(key: &PrivateKey, data: &[u8], flags: u32) -> Option<Vec<u8>> {
+        let signature = match key.key_data() {
+            KeypairData::Rsa(keypair) => {
+                let private_key = rsa_private_key(keypair)?;
+                let (hash, bytes) = if flags & SSH_AGENT_RSA_SHA2_512 != 0 {
+                    let signing_key = rsa::pkcs1v15::SigningKey::<Sha512>::new(private_key);
+                    (HashAlg::Sha512, signing_key.try_sign(data).ok()?.to_vec())
+                } else if flags & SSH_AGENT_RSA_SHA2_256 != 0 {
+                    let signing_key = rsa::pkcs1v15::SigningKey::<Sha256>::new(private_key);
+                    (HashAlg::Sha256, signing_key.try_sign(data).ok()?.to_vec())
+                } else {
+                    return None;
+                };
+                Signature::new(Algorithm::Rsa { hash: Some(hash) }, bytes).ok()?
+            }
+            _ => key.try_sign(data).ok()?,
+        };
+
+        let mut blob = Vec::new();
+        put_string(&mut blob, signature.algorithm().as_str().as_bytes());
+        put_string(&mut blob, signature.as_bytes());
+        Some(blob)
+    }
+
+    /// ssh-key's own conversion builds the key from `p` twice rather than from `p` and `q`, so no
+    /// signature made with it is valid.
+    fn rsa_private_key(keypair: &RsaKeypair) -> Option<rsa::RsaPrivateKey> {
+        let uint = |mpint: &Mpint| mpint.as_positive_bytes().map(rsa::BigUint::from_bytes_be);
+
+        rsa::RsaPrivateKey::from_components(
+            uint(&keypair.public.n)?,
+            uint(&keypair.public.e)?,
+            uint(&keypair.private.d)?,
+            vec![uint(&keypair.private.p)?, uint(&keypair.private.q)?],
+        )
+        .ok()
+    }
+
+    fn put_string(buffer: &mut Vec<u8>, value: &[u8]) {
+        buffer.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        buffer.extend_from_slice(value);
+    }
+
+    /// Reads the fields of a message, `None` once it runs out.
+    struct Reader<'a>(&'a [u8]);
+
+    impl<'a> Reader<'a> {
+        fn byte(&mut self) -> Option<u8> {
+            let (&byte, rest) = self.0.split_first()?;
+            self.0 = rest;
+            Some(byte)
+        }
+
+        fn u32(&mut self) -> Option<u32> {
+            let (bytes, rest) = self.0.split_first_chunk::<4>()?;
+            self.0 = rest;
+            Some(u32::from_be_bytes(*bytes))
+        }
+
+        fn string(&mut self) -> Option<&'a [u8]> {
+            let len = self.u32()? as usize;
+            if len > self.0.len() {
+                return None;
+            }
+            let (value, rest) = self.0.split_at(len);
+            self.0 = rest;
+            Some(value)
+        }
+    }
+}
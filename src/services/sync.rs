@@ -0,0 +1,1330 @@
+//! Syncing a vault with copies of it on other devices through a shared folder, e.g. one kept in
+//! step by Syncthing or a USB stick, without a server.
+//!
+//! Each device writes its entries and tombstones to a sync file of its own in the folder, in the
+//! vault file format and encrypted with the master password, and only ever reads the others'.
+//! Entries are matched by their UUID. For each other device the vault keeps the version of each
+//! entry last seen in that device's file, so that a three-way merge can tell which side changed
+//! an entry since: a change on one side is taken as it is, changes to different fields on both
+//! sides are combined, and a field changed differently on both sides is a [`SyncConflict`] left
+//! for the user to resolve. A deletion wins over an edit made before it and loses to one made
+//! after it.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use aes_gcm::Aes256Gcm;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{prelude::*, SqliteConnection};
+use zeroize::Zeroizing;
+
+use crate::{
+    models::{self, unix_now, AuthState, CryptoError, EntryExtras, PasswordEntryRaw, Tombstone},
+    services::{
+        audit::{self, AuditAction, AuditEvent},
+        backup::{self, BackupReason},
+        database::{DatabaseService, QueryError},
+        error::ServiceError,
+        password_entry::{self, EntryError},
+        vault_file::{self, SyncFile},
+        vault_lock,
+    },
+};
+
+pub const SYNC_FILE_EXTENSION: &str = "pmsync";
+
+/// The parts of an entry that are merged one by one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SyncField {
+    Site,
+    Username,
+    Password,
+    Urls,
+    Notes,
+    Tags,
+    /// Everything in the entry's [`EntryExtras`], merged as a whole.
+    Extras,
+}
+
+impl SyncField {
+    pub const ALL: [SyncField; 7] = [
+        SyncField::Site,
+        SyncField::Username,
+        SyncField::Password,
+        SyncField::Urls,
+        SyncField::Notes,
+        SyncField::Tags,
+        SyncField::Extras,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SyncField::Site => "Site",
+            SyncField::Username => "Username",
+            SyncField::Password => "Password",
+            SyncField::Urls => "URLs",
+            SyncField::Notes => "Notes",
+            SyncField::Tags => "Tags",
+            SyncField::Extras => "Other details",
+        }
+    }
+
+    /// The field's value in `entry` as it's shown when choosing between two, with the password
+    /// hidden.
+    pub fn describe(&self, entry: &PasswordEntryRaw) -> String {
+        match self {
+            SyncField::Site => entry.site.clone(),
+            SyncField::Username => entry.username.clone(),
+            SyncField::Password => format!(
+                "Changed {}",
+                models::format_timestamp(entry.password_changed_at)
+            ),
+            SyncField::Urls => password_entry::join_urls(&entry.urls),
+            SyncField::Notes => entry.notes.clone(),
+            SyncField::Tags => password_entry::join_tags(&entry.tags),
+            SyncField::Extras => describe_extras(&entry.extras),
+        }
+    }
+
+    fn differs(&self, a: &PasswordEntryRaw, b: &PasswordEntryRaw) -> bool {
+        match self {
+            SyncField::Site => a.site != b.site,
+            SyncField::Username => a.username != b.username,
+            SyncField::Password => a.raw_password != b.raw_password,
+            SyncField::Urls => a.urls != b.urls,
+            SyncField::Notes => a.notes != b.notes,
+            SyncField::Tags => a.tags != b.tags,
+            SyncField::Extras => a.extras != b.extras,
+        }
+    }
+
+    /// Set the field of `entry` to its value in `from`.
+    fn copy(&self, entry: &mut PasswordEntryRaw, from: &PasswordEntryRaw) {
+        match self {
+            SyncField::Site => entry.site = from.site.clone(),
+            SyncField::Username => entry.username = from.username.clone(),
+            SyncField::Password => {
+                entry.raw_password = from.raw_password.clone();
+                entry.password_changed_at = from.password_changed_at;
+            }
+            SyncField::Urls => entry.urls = from.urls.clone(),
+            SyncField::Notes => entry.notes = from.notes.clone(),
+            SyncField::Tags => entry.tags = from.tags.clone(),
+            SyncField::Extras => entry.extras = from.extras.clone(),
+        }
+    }
+}
+
+fn describe_extras(extras: &EntryExtras) -> String {
+    let mut parts = Vec::new();
+    if !extras.custom_fields.is_empty() {
+        parts.push(format!("{} custom fields", extras.custom_fields.len()));
+    }
+    if !extras.attachments.is_empty() {
+        parts.push(format!("{} attachments", extras.attachments.len()));
+    }
+    if !extras.history.is_empty() {
+        parts.push(format!("{} earlier versions", extras.history.len()));
+    }
+    if extras.ssh_key.is_some() {
+        parts.push("an SSH key".to_string());
+    }
+    if let Some(expires_at) = extras.expires_at {
+        parts.push(format!("expires {}", models::format_timestamp(expires_at)));
+    }
+
+    if parts.is_empty() {
+        "None".to_string()
+    } else {
+        parts.join(", ")
+    }
+}
+
+/// An entry that was changed differently on both sides.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncConflict {
+    /// The device whose sync file has the other version.
+    pub device: String,
+    pub local: PasswordEntryRaw,
+    pub remote: PasswordEntryRaw,
+    /// The entry with the changes that could be merged, and this device's values of `fields`.
+    pub merged: PasswordEntryRaw,
+    /// The fields that were changed on both sides.
+    pub fields: Vec<SyncField>,
+}
+
+/// What syncing changed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyncSummary {
+    /// How many other devices' sync files were read.
+    pub devices: usize,
+    pub added: usize,
+    pub updated: usize,
+    pub deleted: usize,
+    /// Files in the folder that couldn't be read, with why.
+    pub skipped: Vec<String>,
+    pub conflicts: Vec<SyncConflict>,
+}
+
+impl fmt::Display for SyncSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} updated, {} deleted from {} other devices",
+            self.added, self.updated, self.deleted, self.devices
+        )?;
+        if !self.conflicts.is_empty() {
+            write!(f, ", {} conflicts to resolve", self.conflicts.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// This device's sync details.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncState {
+    /// The name of this device's file in the sync folder.
+    pub device: String,
+    /// Unix timestamp (seconds) of the last sync, 0 if there hasn't been one.
+    pub synced_at: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncError {
+    NotSignedIn,
+    /// The sync files are encrypted with the master password, which isn't known when the vault
+    /// was unlocked with the agent's key.
+    NeedsMasterPassword,
+    NoFolder,
+    /// The vault isn't connected to a sync server.
+    NotConnected,
+    /// The sync server couldn't be reached, or turned a request down.
+    Server(String),
+    /// The team's passphrase doesn't match the one the server's other devices use.
+    TeamPassphrase,
+    /// The vault couldn't be backed up before the other devices' changes were saved.
+    Backup(String),
+    /// The folder or a file in it couldn't be read or written.
+    Folder(String),
+    /// An entry was changed while syncing.
+    Interrupted,
+    /// What's kept of an earlier sync is damaged.
+    Corrupt(String),
+    Entry(EntryError),
+    Database(QueryError),
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncError::NotSignedIn => write!(f, "You must be signed in to sync the vault"),
+            SyncError::NeedsMasterPassword => write!(
+                f,
+                "Sign in with the master password to sync, the sync files are encrypted with it"
+            ),
+            SyncError::NoFolder => write!(f, "Choose a folder to sync the vault through"),
+            SyncError::NotConnected => write!(f, "The vault isn't connected to a sync server"),
+            SyncError::Server(message) => write!(f, "{message}"),
+            SyncError::TeamPassphrase => write!(
+                f,
+                "The team's passphrase is wrong, it must be the one the other devices connected with"
+            ),
+            SyncError::Backup(message) => {
+                write!(f, "Could not back up the vault before syncing: {message}")
+            }
+            SyncError::Folder(message) => write!(f, "{message}"),
+            SyncError::Interrupted => write!(
+                f,
+                "An entry was changed while the vault was syncing, sync again"
+            ),
+            SyncError::Corrupt(message) => {
+                write!(f, "What's kept of the last sync is damaged: {message}")
+            }
+            SyncError::Entry(err) => err.fmt(f),
+            SyncError::Database(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for SyncError {}
+
+impl ServiceError for SyncError {
+    fn code(&self) -> &'static str {
+        match self {
+            SyncError::NotSignedIn => "sync.not_signed_in",
+            SyncError::NeedsMasterPassword => "sync.needs_master_password",
+            SyncError::NoFolder => "sync.no_folder",
+            SyncError::NotConnected => "sync.not_connected",
+            SyncError::Server(_) => "sync.server",
+            SyncError::TeamPassphrase => "sync.team_passphrase",
+            SyncError::Backup(_) => "sync.backup",
+            SyncError::Folder(_) => "sync.folder",
+            SyncError::Interrupted => "sync.interrupted",
+            SyncError::Corrupt(_) => "sync.corrupt",
+            SyncError::Entry(err) => err.code(),
+            SyncError::Database(err) => err.code(),
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            SyncError::NotSignedIn => "Not signed in",
+            SyncError::NeedsMasterPassword => "Master password needed",
+            SyncError::NoFolder => "No sync folder",
+            SyncError::NotConnected => "No sync server",
+            SyncError::Server(_) => "Sync server error",
+            SyncError::TeamPassphrase => "Wrong team passphrase",
+            SyncError::Backup(_) => "Backup failed",
+            SyncError::Folder(_) => "Sync folder error",
+            SyncError::Interrupted => "Sync interrupted",
+            SyncError::Corrupt(_) => "Damaged sync state",
+            SyncError::Entry(err) => err.title(),
+            SyncError::Database(err) => err.title(),
+        }
+    }
+}
+
+impl From<CryptoError> for SyncError {
+    fn from(err: CryptoError) -> Self {
+        SyncError::Corrupt(err.to_string())
+    }
+}
+
+impl From<EntryError> for SyncError {
+    fn from(err: EntryError) -> Self {
+        match err {
+            EntryError::NotFound(_) => SyncError::Interrupted,
+            err => SyncError::Entry(err),
+        }
+    }
+}
+
+impl From<QueryError> for SyncError {
+    fn from(err: QueryError) -> Self {
+        SyncError::Database(err)
+    }
+}
+
+impl From<sqlx::Error> for SyncError {
+    fn from(err: sqlx::Error) -> Self {
+        SyncError::Database(err.into())
+    }
+}
+
+impl From<SyncError> for String {
+    fn from(err: SyncError) -> Self {
+        err.to_string()
+    }
+}
+
+/// The merged fields of an entry as last seen in another device's file.
+#[derive(Serialize, Deserialize)]
+struct BaseEntry {
+    site: String,
+    username: String,
+    password: Zeroizing<String>,
+    urls: Vec<String>,
+    notes: String,
+    tags: Vec<String>,
+    extras: EntryExtras,
+}
+
+impl BaseEntry {
+    fn from_entry(entry: &PasswordEntryRaw) -> Self {
+        Self {
+            site: entry.site.clone(),
+            username: entry.username.clone(),
+            password: entry.raw_password.clone(),
+            urls: entry.urls.clone(),
+            notes: entry.notes.clone(),
+            tags: entry.tags.clone(),
+            extras: entry.extras.clone(),
+        }
+    }
+
+    fn into_entry(self) -> PasswordEntryRaw {
+        PasswordEntryRaw {
+            id: 0,
+            uuid: String::new(),
+            revision: 0,
+            site: self.site,
+            username: self.username,
+            raw_password: self.password,
+            urls: self.urls,
+            notes: self.notes,
+            tags: self.tags,
+            created_at: 0,
+            updated_at: 0,
+            password_changed_at: 0,
+            extras: self.extras,
+        }
+    }
+
+    fn encrypt(entry: &PasswordEntryRaw, cipher: &Aes256Gcm) -> Result<String, SyncError> {
+        let json = Zeroizing::new(
+            serde_json::to_vec(&Self::from_entry(entry))
+                .map_err(|err| SyncError::Corrupt(err.to_string()))?,
+        );
+        Ok(models::encrypt(cipher, &json)?)
+    }
+
+    fn decrypt(encrypted: &str, cipher: &Aes256Gcm) -> Result<PasswordEntryRaw, SyncError> {
+        let json = Zeroizing::new(models::decrypt(cipher, encrypted)?);
+        serde_json::from_slice::<Self>(&json)
+            .map(Self::into_entry)
+            .map_err(|err| SyncError::Corrupt(err.to_string()))
+    }
+}
+
+/// Whether two versions of an entry have the same value for every [`SyncField`].
+pub fn same_fields(a: &PasswordEntryRaw, b: &PasswordEntryRaw) -> bool {
+    !SyncField::ALL.iter().any(|field| field.differs(a, b))
+}
+
+/// How an entry both sides have is merged.
+enum Merge {
+    /// Both sides have the same fields.
+    Same,
+    /// Only this side changed it.
+    KeepLocal,
+    /// Only the other side changed it.
+    TakeRemote,
+    /// Both sides changed different fields.
+    Merged(PasswordEntryRaw),
+    Conflict {
+        merged: PasswordEntryRaw,
+        fields: Vec<SyncField>,
+    },
+}
+
+fn merge_entry(
+    base: Option<&PasswordEntryRaw>,
+    local: &PasswordEntryRaw,
+    remote: &PasswordEntryRaw,
+) -> Merge {
+    let differ = |a, b| !same_fields(a, b);
+    if !differ(local, remote) {
+        return Merge::Same;
+    }
+    if let Some(base) = base {
+        if !differ(base, remote) {
+            return Merge::KeepLocal;
+        }
+        if !differ(base, local) {
+            return Merge::TakeRemote;
+        }
+    }
+
+    // Without a base, e.g. when the vault's sync state was lost, every difference is a conflict.
+    let mut merged = local.clone();
+    let mut fields = Vec::new();
+    for field in SyncField::ALL {
+        if !field.differs(local, remote) {
+            continue;
+        }
+        match base {
+            Some(base) if !field.differs(base, remote) => {}
+            Some(base) if !field.differs(base, local) => field.copy(&mut merged, remote),
+            _ => fields.push(field),
+        }
+    }
+    merged.created_at = local.created_at.min(remote.created_at);
+    merged.updated_at = unix_now();
+    merged.revision = local.revision.max(remote.revision) + 1;
+
+    if !fields.is_empty() {
+        Merge::Conflict { merged, fields }
+    } else if !differ(&merged, remote) {
+        // The other side already has both sides' changes.
+        Merge::TakeRemote
+    } else {
+        Merge::Merged(merged)
+    }
+}
+
+/// Get this device's sync details, giving it a name if it doesn't have one yet.
+pub async fn get_state(db_service: &DatabaseService) -> Result<SyncState, SyncError> {
+    let mut conn = db_service.pool.acquire().await?;
+    let (state, _) = state_in(&mut conn).await?;
+    Ok(state)
+}
+
+async fn state_in(conn: &mut SqliteConnection) -> Result<(SyncState, String), SyncError> {
+    sqlx::query("insert or ignore into sync_state (id, device) values (1, ?);")
+        .bind(new_device_name())
+        .execute(&mut *conn)
+        .await?;
+
+    let row = sqlx::query("select device, last_written, synced_at from sync_state where id = 1;")
+        .fetch_one(&mut *conn)
+        .await?;
+    let state = SyncState {
+        device: row.try_get("device")?,
+        synced_at: row.try_get("synced_at")?,
+    };
+    Ok((state, row.try_get("last_written")?))
+}
+
+fn new_device_name() -> String {
+    models::new_uuid()[..8].to_string()
+}
+
+/// A sync file in the folder and its contents, or why it couldn't be read.
+struct FolderFile {
+    name: String,
+    data: Vec<u8>,
+    contents: Result<SyncFile, String>,
+}
+
+/// Read and decrypt every sync file in `folder`.
+fn read_folder(folder: &Path, passphrase: &str) -> Result<Vec<FolderFile>, SyncError> {
+    let read_dir = std::fs::read_dir(folder)
+        .map_err(|err| SyncError::Folder(format!("{}: {err}", folder.display())))?;
+
+    let mut paths: Vec<PathBuf> = read_dir
+        .filter_map(|dir_entry| dir_entry.ok().map(|dir_entry| dir_entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path.extension().and_then(|ext| ext.to_str()) == Some(SYNC_FILE_EXTENSION)
+        })
+        .collect();
+    paths.sort();
+
+    Ok(paths
+        .into_iter()
+        .map(|path| {
+            let name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let data = std::fs::read(&path).unwrap_or_default();
+            let contents = if data.is_empty() {
+                Err("The file is empty or can't be read".to_string())
+            } else {
+                vault_file::read_sync_file(&data, passphrase)
+            };
+            FolderFile {
+                name,
+                data,
+                contents,
+            }
+        })
+        .collect())
+}
+
+fn hash(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// The vault as it's being merged with the other devices' files, before it's saved.
+struct Merged {
+    /// Every entry that could be read, by UUID, with the merged changes.
+    entries: HashMap<String, PasswordEntryRaw>,
+    /// Entries that can't be decrypted. They're left alone, the vault check deals with them.
+    unreadable: HashSet<String>,
+    /// The revisions changed entries had when they were read, by UUID.
+    read_revisions: HashMap<String, i64>,
+    added: HashSet<String>,
+    updated: HashSet<String>,
+    /// Deleted entries as they were read, by UUID.
+    deleted: HashMap<String, PasswordEntryRaw>,
+    tombstones: HashMap<String, i64>,
+    read_tombstones: HashMap<String, i64>,
+    /// The encrypted bases kept from the last sync, by device and UUID.
+    read_bases: HashMap<(String, String), String>,
+    /// The encrypted bases to keep for each device merged, by UUID.
+    bases: HashMap<String, Vec<(String, String)>>,
+    conflicts: Vec<SyncConflict>,
+}
+
+impl Merged {
+    async fn read(auth_state: &AuthState, db_service: &DatabaseService) -> Result<Self, SyncError> {
+        let loaded =
+            password_entry::load_password_entries(auth_state, db_service, String::new()).await?;
+        let entries: HashMap<String, PasswordEntryRaw> = loaded
+            .entries
+            .into_iter()
+            .map(|entry| (entry.uuid.clone(), entry))
+            .collect();
+
+        let unreadable = sqlx::query_scalar::<_, String>("select uuid from password_entries;")
+            .fetch_all(&db_service.pool)
+            .await?
+            .into_iter()
+            .filter(|uuid| !entries.contains_key(uuid))
+            .collect();
+
+        let tombstones: HashMap<String, i64> =
+            sqlx::query("select uuid, deleted_at from tombstones;")
+                .fetch_all(&db_service.pool)
+                .await?
+                .iter()
+                .map(|row| Ok((row.try_get("uuid")?, row.try_get("deleted_at")?)))
+                .collect::<Result<_, sqlx::Error>>()?;
+
+        let read_bases = sqlx::query("select device, uuid, entry from sync_bases;")
+            .fetch_all(&db_service.pool)
+            .await?
+            .iter()
+            .map(|row| {
+                Ok((
+                    (row.try_get("device")?, row.try_get("uuid")?),
+                    row.try_get("entry")?,
+                ))
+            })
+            .collect::<Result<_, sqlx::Error>>()?;
+
+        Ok(Self {
+            entries,
+            unreadable,
+            read_revisions: HashMap::new(),
+            added: HashSet::new(),
+            updated: HashSet::new(),
+            deleted: HashMap::new(),
+            read_tombstones: tombstones.clone(),
+            tombstones,
+            read_bases,
+            bases: HashMap::new(),
+            conflicts: Vec::new(),
+        })
+    }
+
+    fn has_changes(&self) -> bool {
+        !self.added.is_empty()
+            || !self.updated.is_empty()
+            || !self.deleted.is_empty()
+            || self.tombstones != self.read_tombstones
+    }
+
+    fn is_conflicted(&self, uuid: &str) -> bool {
+        self.conflicts
+            .iter()
+            .any(|conflict| conflict.local.uuid == uuid)
+    }
+
+    /// Replace an entry with a changed version of it.
+    fn change(&mut self, entry: PasswordEntryRaw) {
+        let uuid = entry.uuid.clone();
+        if let Some(previous) = self.entries.insert(uuid.clone(), entry) {
+            self.read_revisions
+                .entry(uuid.clone())
+                .or_insert(previous.revision);
+        }
+        if !self.added.contains(&uuid) {
+            self.updated.insert(uuid);
+        }
+    }
+
+    fn add(&mut self, entry: PasswordEntryRaw) {
+        let uuid = entry.uuid.clone();
+        self.tombstones.remove(&uuid);
+
+        // Deleted because of an earlier device's file, so it's still in the vault.
+        if let Some(deleted) = self.deleted.remove(&uuid) {
+            self.read_revisions
+                .entry(uuid.clone())
+                .or_insert(deleted.revision);
+            self.updated.insert(uuid.clone());
+            self.entries.insert(
+                uuid,
+                PasswordEntryRaw {
+                    id: deleted.id,
+                    ..entry
+                },
+            );
+        } else {
+            self.added.insert(uuid.clone());
+            self.entries
+                .insert(uuid, PasswordEntryRaw { id: 0, ..entry });
+        }
+    }
+
+    fn delete(&mut self, uuid: &str, deleted_at: i64) {
+        if let Some(entry) = self.entries.remove(uuid) {
+            self.updated.remove(uuid);
+            if !self.added.remove(uuid) {
+                let revision = self.read_revisions.remove(uuid).unwrap_or(entry.revision);
+                self.deleted
+                    .insert(uuid.to_string(), PasswordEntryRaw { revision, ..entry });
+            }
+        }
+        self.tombstones.insert(uuid.to_string(), deleted_at);
+    }
+
+    /// Merge one other device's sync file.
+    fn merge_device(
+        &mut self,
+        device: &str,
+        contents: &SyncFile,
+        cipher: &Aes256Gcm,
+    ) -> Result<(), SyncError> {
+        // Entries whose base stays as it was, so that they're merged again next time.
+        let mut unmerged: HashSet<&str> = HashSet::new();
+
+        for remote in contents.entries.iter() {
+            let uuid = remote.uuid.as_str();
+            if uuid.is_empty() || self.unreadable.contains(uuid) || self.is_conflicted(uuid) {
+                unmerged.insert(uuid);
+                continue;
+            }
+
+            let Some(local) = self.entries.get(uuid).cloned() else {
+                match self.tombstones.get(uuid) {
+                    Some(&deleted_at) if remote.updated_at <= deleted_at => {}
+                    _ => self.add(remote.clone()),
+                }
+                continue;
+            };
+
+            let base = self
+                .read_bases
+                .get(&(device.to_string(), uuid.to_string()))
+                .map(|encrypted| BaseEntry::decrypt(encrypted, cipher))
+                .transpose()?;
+
+            match merge_entry(base.as_ref(), &local, remote) {
+                Merge::Same | Merge::KeepLocal => {}
+                Merge::TakeRemote => self.change(PasswordEntryRaw {
+                    id: local.id,
+                    ..remote.clone()
+                }),
+                Merge::Merged(entry) => self.change(entry),
+                Merge::Conflict { merged, fields } => {
+                    self.conflicts.push(SyncConflict {
+                        device: device.to_string(),
+                        local,
+                        remote: remote.clone(),
+                        merged,
+                        fields,
+                    });
+                    unmerged.insert(uuid);
+                }
+            }
+        }
+
+        for tombstone in contents.tombstones.iter() {
+            let uuid = tombstone.uuid.as_str();
+            if self.unreadable.contains(uuid) || self.is_conflicted(uuid) {
+                continue;
+            }
+            match self.entries.get(uuid) {
+                Some(local) if local.updated_at > tombstone.deleted_at => {}
+                Some(_) => self.delete(uuid, tombstone.deleted_at),
+                None => {
+                    let known = self.tombstones.get(uuid).copied().unwrap_or_default();
+                    self.tombstones
+                        .insert(uuid.to_string(), known.max(tombstone.deleted_at));
+                }
+            }
+        }
+
+        let mut bases = Vec::new();
+        for remote in contents.entries.iter() {
+            let key = (device.to_string(), remote.uuid.clone());
+            if !unmerged.contains(remote.uuid.as_str()) {
+                bases.push((remote.uuid.clone(), BaseEntry::encrypt(remote, cipher)?));
+            } else if let Some(encrypted) = self.read_bases.get(&key) {
+                bases.push((remote.uuid.clone(), encrypted.clone()));
+            }
+        }
+        self.bases.insert(device.to_string(), bases);
+
+        Ok(())
+    }
+
+    /// Save the merged entries, tombstones and bases in a single transaction. An entry that was
+    /// changed here since it was read fails it with [`SyncError::Interrupted`].
+    async fn save(
+        &self,
+        state: &SyncState,
+        cipher: &Aes256Gcm,
+        db_service: &DatabaseService,
+    ) -> Result<(), SyncError> {
+        let mut tx = db_service.pool.begin_with("begin immediate").await?;
+
+        for uuid in self.added.iter() {
+            let safe = self.entries[uuid].to_safe_with(cipher)?;
+            password_entry::insert_password_entry(&safe, &mut *tx).await?;
+        }
+        for uuid in self.updated.iter() {
+            let safe = self.entries[uuid].to_safe_with(cipher)?;
+            password_entry::update_synced_entry(&safe, self.read_revisions[uuid], &mut *tx).await?;
+        }
+        for entry in self.deleted.values() {
+            password_entry::delete_synced_entry(entry, &mut *tx).await?;
+        }
+
+        for (uuid, deleted_at) in self.tombstones.iter() {
+            if self.read_tombstones.get(uuid) != Some(deleted_at) {
+                sqlx::query("insert or replace into tombstones (uuid, deleted_at) values (?, ?);")
+                    .bind(uuid)
+                    .bind(deleted_at)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+        for uuid in self.read_tombstones.keys() {
+            if !self.tombstones.contains_key(uuid) {
+                sqlx::query("delete from tombstones where uuid = ?;")
+                    .bind(uuid)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+
+        for (device, bases) in self.bases.iter() {
+            sqlx::query("delete from sync_bases where device = ?;")
+                .bind(device)
+                .execute(&mut *tx)
+                .await?;
+            for (uuid, encrypted) in bases {
+                sqlx::query("insert into sync_bases (device, uuid, entry) values (?, ?, ?);")
+                    .bind(device)
+                    .bind(uuid)
+                    .bind(encrypted)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+
+        sqlx::query("update sync_state set device = ?, synced_at = ? where id = 1;")
+            .bind(&state.device)
+            .bind(unix_now())
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// This device's sync file with the merged entries.
+    fn into_sync_file(self, device: &str) -> SyncFile {
+        let mut entries: Vec<PasswordEntryRaw> = self.entries.into_values().collect();
+        entries.sort_by(|a, b| a.uuid.cmp(&b.uuid));
+        let mut tombstones: Vec<Tombstone> = self
+            .tombstones
+            .into_iter()
+            .map(|(uuid, deleted_at)| Tombstone { uuid, deleted_at })
+            .collect();
+        tombstones.sort_by(|a, b| a.uuid.cmp(&b.uuid));
+
+        SyncFile {
+            device: device.to_string(),
+            written_at: unix_now(),
+            entries,
+            tombstones,
+        }
+    }
+}
+
+/// Merge the other devices' sync files in `folder` into the vault and write this device's file
+/// there. Fields changed differently on both sides are left as they are here and returned as
+/// conflicts to resolve with [`resolve_conflict`].
+pub async fn sync_folder(
+    folder: &Path,
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<SyncSummary, SyncError> {
+    if !auth_state.signed_in {
+        return Err(SyncError::NotSignedIn);
+    }
+    if folder.as_os_str().is_empty() {
+        return Err(SyncError::NoFolder);
+    }
+    if auth_state.raw_master_password.is_empty() {
+        return Err(SyncError::NeedsMasterPassword);
+    }
+
+    let _lock = vault_lock::lock_vault(db_service).await?;
+    let (mut state, last_written) = {
+        let mut conn = db_service.pool.acquire().await?;
+        state_in(&mut conn).await?
+    };
+
+    let passphrase = auth_state.raw_master_password.clone();
+    let files = {
+        let folder = folder.to_path_buf();
+        tokio::task::spawn_blocking(move || read_folder(&folder, &passphrase))
+            .await
+            .map_err(|err| SyncError::Folder(err.to_string()))??
+    };
+
+    // A file under this device's name that it didn't write comes from a copy of the vault on
+    // another device. Its changes are merged like any other's and this device takes a new name.
+    let previous_device = state.device.clone();
+    if files.iter().any(|file| {
+        file.name == state.device && !last_written.is_empty() && hash(&file.data) != last_written
+    }) {
+        state.device = new_device_name();
+    }
+
+    let mut summary = SyncSummary::default();
+    let mut remotes = Vec::new();
+    for file in files {
+        if file.name == state.device {
+            continue;
+        }
+        match file.contents {
+            Ok(mut contents) => {
+                if contents.device.is_empty() {
+                    contents.device = file.name;
+                }
+                remotes.push(contents);
+            }
+            Err(err) => summary
+                .skipped
+                .push(format!("{}.{SYNC_FILE_EXTENSION}: {err}", file.name)),
+        }
+    }
+    summary.devices = remotes.len();
+
+    let own = merge_remotes(&state, &remotes, &mut summary, auth_state, db_service).await?;
+
+    let written = write_own_file(folder, &own, auth_state).await?;
+    sqlx::query("update sync_state set last_written = ? where id = 1;")
+        .bind(written)
+        .execute(&db_service.pool)
+        .await?;
+
+    let mut detail = format!("Through {}: {summary}", folder.display());
+    if state.device != previous_device {
+        detail.push_str(&format!(
+            ", another copy of the vault wrote as {previous_device} so this device is now {}",
+            state.device
+        ));
+    }
+    let _ = audit::record(
+        AuditEvent::new(AuditAction::Sync).with_detail(detail),
+        auth_state,
+        db_service,
+    )
+    .await;
+
+    Ok(summary)
+}
+
+/// Merge other devices' versions of the vault into it and save the result, adding what changed
+/// to `summary`. Returns this device's merged version of the vault to share with them, where an
+/// entry in `summary.conflicts` is still this device's version. The vault must be locked with
+/// [`vault_lock::lock_vault`] while it runs.
+pub async fn merge_remotes(
+    state: &SyncState,
+    remotes: &[SyncFile],
+    summary: &mut SyncSummary,
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<SyncFile, SyncError> {
+    let cipher = auth_state.cipher();
+    let mut merged = Merged::read(auth_state, db_service).await?;
+    for remote in remotes {
+        merged.merge_device(&remote.device, remote, &cipher)?;
+    }
+
+    summary.added += merged.added.len();
+    summary.updated += merged.updated.len();
+    summary.deleted += merged.deleted.len();
+    summary.conflicts = merged.conflicts.clone();
+
+    if merged.has_changes() {
+        backup::create_backup(BackupReason::Sync, db_service)
+            .await
+            .map_err(SyncError::Backup)?;
+    }
+    merged.save(state, &cipher, db_service).await?;
+
+    Ok(merged.into_sync_file(&state.device))
+}
+
+/// Write this device's sync file, through a temporary file so that the other devices never see
+/// half of it. Returns the file's hash.
+async fn write_own_file(
+    folder: &Path,
+    file: &SyncFile,
+    auth_state: &AuthState,
+) -> Result<String, SyncError> {
+    let path = folder.join(format!("{}.{SYNC_FILE_EXTENSION}", file.device));
+    let partial = folder.join(format!("{}.{SYNC_FILE_EXTENSION}.partial", file.device));
+    let passphrase = auth_state.raw_master_password.clone();
+    let file = file.clone();
+
+    tokio::task::spawn_blocking(move || {
+        let data = vault_file::write_sync_file(&file, &passphrase).map_err(SyncError::Folder)?;
+        std::fs::write(&partial, &data)
+            .and_then(|_| std::fs::rename(&partial, &path))
+            .map_err(|err| SyncError::Folder(format!("{}: {err}", path.display())))?;
+        Ok(hash(&data))
+    })
+    .await
+    .map_err(|err| SyncError::Folder(err.to_string()))?
+}
+
+/// Save a conflict's entry with the fields in `take_remote` set to the other device's values and
+/// the rest to this device's. The result reaches the other devices with the next sync.
+pub async fn resolve_conflict(
+    conflict: &SyncConflict,
+    take_remote: &[SyncField],
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<(), SyncError> {
+    if !auth_state.signed_in {
+        return Err(SyncError::NotSignedIn);
+    }
+
+    let mut resolved = conflict.merged.clone();
+    for field in take_remote {
+        field.copy(&mut resolved, &conflict.remote);
+    }
+    resolved.updated_at = unix_now();
+
+    let cipher = auth_state.cipher();
+    let mut tx = db_service.pool.begin_with("begin immediate").await?;
+    password_entry::update_synced_entry(
+        &resolved.to_safe_with(&cipher)?,
+        conflict.local.revision,
+        &mut *tx,
+    )
+    .await?;
+    // The other device's version is now merged, so it's the base for the next sync.
+    keep_base(&conflict.device, &conflict.remote, &cipher, &mut tx).await?;
+    tx.commit().await?;
+
+    let event = AuditEvent::for_entry(AuditAction::Edit, resolved.id, &resolved.site)
+        .with_detail(format!("Resolved a sync conflict with {}", conflict.device));
+    let _ = audit::record(event, auth_state, db_service).await;
+    Ok(())
+}
+
+/// Keep `entry` as the version of it `device` has, which the next sync with it merges from.
+pub async fn keep_base(
+    device: &str,
+    entry: &PasswordEntryRaw,
+    cipher: &Aes256Gcm,
+    conn: &mut SqliteConnection,
+) -> Result<(), SyncError> {
+    sqlx::query("insert or replace into sync_bases (device, uuid, entry) values (?, ?, ?);")
+        .bind(device)
+        .bind(&entry.uuid)
+        .bind(BaseEntry::encrypt(entry, cipher)?)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Encrypt the kept bases with the key of a new master password, as part of changing it.
+pub async fn change_key_in(
+    auth_state: &AuthState,
+    new_auth_state: &AuthState,
+    conn: &mut SqliteConnection,
+) -> Result<(), SyncError> {
+    let old_cipher = auth_state.cipher();
+    let new_cipher = new_auth_state.cipher();
+
+    let rows = sqlx::query("select device, uuid, entry from sync_bases;")
+        .fetch_all(&mut *conn)
+        .await?;
+    for row in rows {
+        let encrypted: String = row.try_get("entry")?;
+        let plaintext = Zeroizing::new(models::decrypt(&old_cipher, &encrypted)?);
+        sqlx::query("update sync_bases set entry = ? where device = ? and uuid = ?;")
+            .bind(models::encrypt(&new_cipher, &plaintext)?)
+            .bind(row.try_get::<String, _>("device")?)
+            .bind(row.try_get::<String, _>("uuid")?)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(uuid: &str) -> PasswordEntryRaw {
+        PasswordEntryRaw {
+            id: 0,
+            uuid: uuid.into(),
+            revision: 1,
+            site: "example.com".into(),
+            username: "me".into(),
+            raw_password: Zeroizing::new("first".into()),
+            urls: Vec::new(),
+            notes: String::new(),
+            tags: Vec::new(),
+            created_at: 100,
+            updated_at: 100,
+            password_changed_at: 100,
+            extras: EntryExtras::default(),
+        }
+    }
+
+    fn with_password(entry: &PasswordEntryRaw, password: &str) -> PasswordEntryRaw {
+        PasswordEntryRaw {
+            raw_password: Zeroizing::new(password.into()),
+            ..entry.clone()
+        }
+    }
+
+    fn phone(entries: Vec<PasswordEntryRaw>, tombstones: Vec<Tombstone>) -> SyncFile {
+        SyncFile {
+            device: "phone".into(),
+            written_at: 0,
+            entries,
+            tombstones,
+        }
+    }
+
+    async fn merge(
+        remote: SyncFile,
+        auth_state: &AuthState,
+        db_service: &DatabaseService,
+    ) -> (SyncSummary, SyncFile) {
+        let state = get_state(db_service).await.unwrap();
+        let mut summary = SyncSummary::default();
+        let own = merge_remotes(&state, &[remote], &mut summary, auth_state, db_service)
+            .await
+            .unwrap();
+        (summary, own)
+    }
+
+    async fn local(
+        uuid: &str,
+        auth_state: &AuthState,
+        db_service: &DatabaseService,
+    ) -> Option<PasswordEntryRaw> {
+        password_entry::get_all_password_entries(auth_state, db_service, String::new())
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|entry| entry.uuid == uuid)
+    }
+
+    #[test]
+    fn merges_against_the_base() {
+        let base = entry("a");
+        let renamed = PasswordEntryRaw {
+            username: "you".into(),
+            ..base.clone()
+        };
+        let new_password = with_password(&base, "second");
+
+        assert!(matches!(
+            merge_entry(Some(&base), &base, &base),
+            Merge::Same
+        ));
+        assert!(matches!(
+            merge_entry(Some(&base), &renamed, &base),
+            Merge::KeepLocal
+        ));
+        assert!(matches!(
+            merge_entry(Some(&base), &base, &renamed),
+            Merge::TakeRemote
+        ));
+
+        let Merge::Merged(merged) = merge_entry(Some(&base), &renamed, &new_password) else {
+            panic!("changes to different fields weren't combined");
+        };
+        assert_eq!(merged.username, "you");
+        assert_eq!(*merged.raw_password, "second");
+        assert_eq!(merged.revision, 2);
+
+        let other_password = with_password(&base, "third");
+        let Merge::Conflict { merged, fields } =
+            merge_entry(Some(&base), &other_password, &new_password)
+        else {
+            panic!("the same field changed on both sides isn't a conflict");
+        };
+        assert_eq!(fields, [SyncField::Password]);
+        assert_eq!(*merged.raw_password, "third");
+
+        // Without a base there's no telling which side changed a field.
+        let Merge::Conflict { fields, .. } = merge_entry(None, &renamed, &new_password) else {
+            panic!("differences without a base aren't conflicts");
+        };
+        assert_eq!(fields, [SyncField::Username, SyncField::Password]);
+    }
+
+    #[tokio::test]
+    async fn merges_another_devices_changes_into_the_vault() {
+        let (_dir, db_service) = DatabaseService::temporary().await;
+        let auth_state = AuthState::from_key_material(Zeroizing::new([7; 32]));
+        let remote = entry("a");
+
+        let (summary, own) = merge(
+            phone(vec![remote.clone()], Vec::new()),
+            &auth_state,
+            &db_service,
+        )
+        .await;
+        assert_eq!((summary.added, summary.updated, summary.deleted), (1, 0, 0));
+        assert_eq!(own.entries.len(), 1);
+        let added = local("a", &auth_state, &db_service).await.unwrap();
+        assert!(same_fields(&added, &remote));
+
+        // The phone changed the password and this device the username, since the last sync.
+        let mut edited = added.clone();
+        edited.username = "you".into();
+        password_entry::save_updated_password(added.id, edited, &auth_state, &db_service)
+            .await
+            .unwrap();
+        let remote = PasswordEntryRaw {
+            revision: 2,
+            ..with_password(&remote, "second")
+        };
+
+        let (summary, own) = merge(phone(vec![remote], Vec::new()), &auth_state, &db_service).await;
+        assert_eq!((summary.added, summary.updated), (0, 1));
+        assert!(summary.conflicts.is_empty());
+        let merged = local("a", &auth_state, &db_service).await.unwrap();
+        assert_eq!(merged.username, "you");
+        assert_eq!(*merged.raw_password, "second");
+        assert!(same_fields(&own.entries[0], &merged));
+    }
+
+    #[tokio::test]
+    async fn leaves_a_field_changed_on_both_sides_to_resolve() {
+        let (_dir, db_service) = DatabaseService::temporary().await;
+        let auth_state = AuthState::from_key_material(Zeroizing::new([7; 32]));
+        let remote = entry("a");
+        merge(
+            phone(vec![remote.clone()], Vec::new()),
+            &auth_state,
+            &db_service,
+        )
+        .await;
+
+        let added = local("a", &auth_state, &db_service).await.unwrap();
+        password_entry::save_updated_password(
+            added.id,
+            with_password(&added, "mine"),
+            &auth_state,
+            &db_service,
+        )
+        .await
+        .unwrap();
+
+        let theirs = with_password(&remote, "theirs");
+        let (summary, _) = merge(phone(vec![theirs], Vec::new()), &auth_state, &db_service).await;
+        let [conflict] = &summary.conflicts[..] else {
+            panic!("{:?}", summary.conflicts);
+        };
+        assert_eq!(conflict.device, "phone");
+        assert_eq!(conflict.fields, [SyncField::Password]);
+        assert_eq!(
+            *local("a", &auth_state, &db_service)
+                .await
+                .unwrap()
+                .raw_password,
+            "mine"
+        );
+
+        resolve_conflict(conflict, &[SyncField::Password], &auth_state, &db_service)
+            .await
+            .unwrap();
+        assert_eq!(
+            *local("a", &auth_state, &db_service)
+                .await
+                .unwrap()
+                .raw_password,
+            "theirs"
+        );
+        // The phone's version is the base now, so the same file merges cleanly.
+        let theirs = with_password(&remote, "theirs");
+        let (summary, _) = merge(phone(vec![theirs], Vec::new()), &auth_state, &db_service).await;
+        assert!(summary.conflicts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn tombstones_win_over_older_edits_only() {
+        let (_dir, db_service) = DatabaseService::temporary().await;
+        let auth_state = AuthState::from_key_material(Zeroizing::new([7; 32]));
+        let now = unix_now();
+        let kept = PasswordEntryRaw {
+            updated_at: now + 100,
+            ..entry("kept")
+        };
+        merge(
+            phone(vec![entry("deleted"), kept.clone()], Vec::new()),
+            &auth_state,
+            &db_service,
+        )
+        .await;
+
+        // The phone deleted both, but "kept" was edited here after that.
+        let tombstones = vec![
+            Tombstone {
+                uuid: "deleted".into(),
+                deleted_at: now,
+            },
+            Tombstone {
+                uuid: "kept".into(),
+                deleted_at: now,
+            },
+        ];
+        let (summary, own) = merge(phone(Vec::new(), tombstones), &auth_state, &db_service).await;
+        assert_eq!(summary.deleted, 1);
+        assert!(local("deleted", &auth_state, &db_service).await.is_none());
+        assert!(local("kept", &auth_state, &db_service).await.is_some());
+        assert!(own
+            .tombstones
+            .iter()
+            .any(|tombstone| tombstone.uuid == "deleted"));
+
+        // An older copy from another device doesn't bring it back, an edit made since does.
+        let (summary, _) = merge(
+            phone(vec![entry("deleted")], Vec::new()),
+            &auth_state,
+            &db_service,
+        )
+        .await;
+        assert_eq!(summary.added, 0);
+
+        let edited = PasswordEntryRaw {
+            updated_at: now + 100,
+            ..with_password(&entry("deleted"), "second")
+        };
+        let (summary, own) = merge(phone(vec![edited], Vec::new()), &auth_state, &db_service).await;
+        assert_eq!(summary.added, 1);
+        assert!(local("deleted", &auth_state, &db_service).await.is_some());
+        assert!(own
+            .tombstones
+            .iter()
+            .all(|tombstone| tombstone.uuid != "deleted"));
+    }
+
+    #[tokio::test]
+    async fn deleting_here_reaches_the_other_devices() {
+        let (_dir, db_service) = DatabaseService::temporary().await;
+        let auth_state = AuthState::from_key_material(Zeroizing::new([7; 32]));
+        merge(
+            phone(vec![entry("a")], Vec::new()),
+            &auth_state,
+            &db_service,
+        )
+        .await;
+
+        let added = local("a", &auth_state, &db_service).await.unwrap();
+        password_entry::delete_password(added.id, &auth_state, &db_service)
+            .await
+            .unwrap();
+
+        // The phone still has the entry as it was, it isn't added back.
+        let (summary, own) = merge(
+            phone(vec![entry("a")], Vec::new()),
+            &auth_state,
+            &db_service,
+        )
+        .await;
+        assert_eq!(summary.added, 0);
+        assert!(own.entries.is_empty());
+        assert_eq!(own.tombstones.len(), 1);
+        assert_eq!(own.tombstones[0].uuid, "a");
+    }
+}
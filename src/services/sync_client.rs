@@ -0,0 +1,513 @@
+//! Syncing a vault with a team's sync server, see [`sync_server`](super::sync_server) for the
+//! server's side.
+//!
+//! Connecting derives the team's key from its passphrase and the salt the server hands out, and
+//! keeps it in the vault encrypted with the vault's key, like the device's token. Syncing pulls
+//! the records that changed on the server since the last time and keeps them as they came,
+//! encrypted with the team's key. The server's records are then merged into the vault as if they
+//! were another device's sync file, with the same three-way merge as
+//! [`sync::sync_folder`], and every entry that ends up different from the server's version is
+//! pushed with a version that counts this device's change. When the server turns a push down
+//! because another device changed the entry first, its record is merged and pushed again.
+
+use std::collections::HashMap;
+
+use aes_gcm::{Aes256Gcm, Key, KeyInit};
+use argon2::{Algorithm, Argon2, Params, Version as Argon2Version};
+use base64::prelude::*;
+use serde::de::DeserializeOwned;
+use sqlx::{prelude::*, SqliteConnection};
+use zeroize::Zeroizing;
+
+use crate::{
+    models::{self, unix_now, AuthState, PasswordEntryRaw, Tombstone},
+    services::{
+        audit::{self, AuditAction, AuditEvent},
+        database::DatabaseService,
+        http::{self, Response, ServerUrl},
+        sync::{self, SyncError, SyncSummary},
+        sync_server::{Changes, ErrorBody, KeyCheck, PushRecord, ServerRecord, TeamInfo, Version},
+        vault_file::{self, SyncFile, SyncRecord},
+        vault_lock,
+    },
+};
+
+/// What the server's versions are called in conflicts, and the device their bases are kept
+/// under.
+pub const SERVER_DEVICE: &str = "the sync server";
+
+/// How many times to pull and merge again when the server turns pushes down.
+const MAX_ATTEMPTS: usize = 3;
+/// What the first device encrypts with the team's key for the others to check theirs with.
+const KEY_CHECK: &[u8] = b"pw sync server team key";
+
+const ARGON2_MEMORY_KIB: u32 = 64 * 1024;
+const ARGON2_ITERATIONS: u32 = 3;
+const ARGON2_PARALLELISM: u32 = 4;
+
+/// The sync server a vault is connected to.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServerConnection {
+    pub url: String,
+    /// The name the server knows this device by.
+    pub device: String,
+    /// Unix timestamp (seconds) of the last sync, 0 if there hasn't been one.
+    pub synced_at: i64,
+}
+
+/// What's needed to talk to the server, decrypted.
+struct Connected {
+    server: ServerUrl,
+    url: String,
+    device: String,
+    token: Zeroizing<String>,
+    team_cipher: Aes256Gcm,
+    cursor: i64,
+}
+
+/// The server's records as they were last pulled, by UUID, with the record each decrypts to or
+/// `None` when it can't be.
+type Cache = HashMap<String, (Version, Option<SyncRecord>)>;
+
+/// The sync server the vault is connected to, if any.
+pub async fn get_connection(
+    db_service: &DatabaseService,
+) -> Result<Option<ServerConnection>, SyncError> {
+    let row = sqlx::query("select url, device, synced_at from sync_server where id = 1;")
+        .fetch_optional(&db_service.pool)
+        .await?;
+    row.map(|row| {
+        Ok(ServerConnection {
+            url: row.try_get("url")?,
+            device: row.try_get("device")?,
+            synced_at: row.try_get("synced_at")?,
+        })
+    })
+    .transpose()
+}
+
+/// Connect the vault to a sync server with this device's token and the team's passphrase,
+/// replacing any server it was connected to. The first device to connect sets the passphrase
+/// the others are checked against.
+pub async fn connect(
+    url: &str,
+    token: &str,
+    passphrase: &str,
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<ServerConnection, SyncError> {
+    if !auth_state.signed_in {
+        return Err(SyncError::NotSignedIn);
+    }
+    let server = ServerUrl::parse(url).map_err(SyncError::Server)?;
+    let token = token.trim();
+    if token.is_empty() || passphrase.is_empty() {
+        return Err(SyncError::Server(
+            "Enter the device's token and the team's passphrase".into(),
+        ));
+    }
+
+    let team: TeamInfo = parse(call(&server, "GET", "/v1/team", token, None).await?)?;
+    let salt = BASE64_STANDARD
+        .decode(&team.salt)
+        .map_err(|_| SyncError::Server("The server's salt isn't base64".into()))?;
+    let team_key = {
+        let passphrase = Zeroizing::new(passphrase.to_string());
+        tokio::task::spawn_blocking(move || derive_team_key(&passphrase, &salt))
+            .await
+            .map_err(|err| SyncError::Server(err.to_string()))??
+    };
+    let team_cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(team_key.as_slice()));
+
+    let mut key_check = team.key_check;
+    if key_check.is_empty() {
+        let ours = models::encrypt(&team_cipher, KEY_CHECK)?;
+        let body = serde_json::to_vec(&KeyCheck {
+            key_check: ours.clone(),
+        })
+        .map_err(|err| SyncError::Server(err.to_string()))?;
+        let response = call(&server, "PUT", "/v1/team/key-check", token, Some(&body)).await?;
+        key_check = match response.status {
+            // Another device set it in the meantime.
+            409 => {
+                parse::<TeamInfo>(call(&server, "GET", "/v1/team", token, None).await?)?.key_check
+            }
+            _ => parse::<KeyCheck>(response)?.key_check,
+        };
+    }
+    match models::decrypt(&team_cipher, &key_check) {
+        Ok(plaintext) if plaintext == KEY_CHECK => {}
+        _ => return Err(SyncError::TeamPassphrase),
+    }
+
+    let url = url.trim().trim_end_matches('/').to_string();
+    let cipher = auth_state.cipher();
+    let mut tx = db_service.pool.begin_with("begin immediate").await?;
+    // What was kept of another server is of no use with this one.
+    sqlx::query("delete from sync_server_records;")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("delete from sync_bases where device = ?;")
+        .bind(SERVER_DEVICE)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        "insert or replace into sync_server (id, url, device, token, team_key, cursor, synced_at)
+        values (1, ?, ?, ?, ?, 0, 0);",
+    )
+    .bind(&url)
+    .bind(&team.device)
+    .bind(models::encrypt(&cipher, token.as_bytes())?)
+    .bind(models::encrypt(&cipher, team_key.as_slice())?)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    let event = AuditEvent::new(AuditAction::Sync)
+        .with_detail(format!("Connected to {url} as {}", team.device));
+    let _ = audit::record(event, auth_state, db_service).await;
+
+    Ok(ServerConnection {
+        url,
+        device: team.device,
+        synced_at: 0,
+    })
+}
+
+/// Stop syncing with the server. The entries stay as they are, on both sides.
+pub async fn disconnect(
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<(), SyncError> {
+    if !auth_state.signed_in {
+        return Err(SyncError::NotSignedIn);
+    }
+
+    let mut tx = db_service.pool.begin().await?;
+    sqlx::query("delete from sync_server;")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("delete from sync_server_records;")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("delete from sync_bases where device = ?;")
+        .bind(SERVER_DEVICE)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Pull the changes made on the server, merge them into the vault and push this device's.
+/// Fields changed differently on both sides are left as they are here and returned as
+/// conflicts to resolve with [`sync::resolve_conflict`], they're pushed once they are.
+pub async fn sync_server(
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<SyncSummary, SyncError> {
+    if !auth_state.signed_in {
+        return Err(SyncError::NotSignedIn);
+    }
+
+    let _lock = vault_lock::lock_vault(db_service).await?;
+    let mut connected = load(auth_state, db_service).await?;
+    let state = sync::get_state(db_service).await?;
+    let mut summary = SyncSummary {
+        devices: 1,
+        ..SyncSummary::default()
+    };
+
+    for _ in 0..MAX_ATTEMPTS {
+        pull(&mut connected, db_service).await?;
+        let cache = read_cache(&connected, db_service).await?;
+
+        let (remote, skipped) = remote_file(&cache);
+        summary.skipped = skipped;
+        let own =
+            sync::merge_remotes(&state, &[remote], &mut summary, auth_state, db_service).await?;
+
+        if push(&connected, &own, &summary, &cache, auth_state, db_service).await? {
+            continue;
+        }
+
+        sqlx::query("update sync_server set synced_at = ? where id = 1;")
+            .bind(unix_now())
+            .execute(&db_service.pool)
+            .await?;
+        let event = AuditEvent::new(AuditAction::Sync)
+            .with_detail(format!("With {}: {summary}", connected.url));
+        let _ = audit::record(event, auth_state, db_service).await;
+        return Ok(summary);
+    }
+
+    Err(SyncError::Interrupted)
+}
+
+/// Encrypt the device's token and the team's key with the key of a new master password, as
+/// part of changing it.
+pub async fn change_key_in(
+    auth_state: &AuthState,
+    new_auth_state: &AuthState,
+    conn: &mut SqliteConnection,
+) -> Result<(), SyncError> {
+    let Some(row) = sqlx::query("select token, team_key from sync_server where id = 1;")
+        .fetch_optional(&mut *conn)
+        .await?
+    else {
+        return Ok(());
+    };
+
+    let old_cipher = auth_state.cipher();
+    let new_cipher = new_auth_state.cipher();
+    let token = Zeroizing::new(models::decrypt(&old_cipher, row.try_get("token")?)?);
+    let team_key = Zeroizing::new(models::decrypt(&old_cipher, row.try_get("team_key")?)?);
+    sqlx::query("update sync_server set token = ?, team_key = ? where id = 1;")
+        .bind(models::encrypt(&new_cipher, &token)?)
+        .bind(models::encrypt(&new_cipher, &team_key)?)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+async fn load(
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<Connected, SyncError> {
+    let row =
+        sqlx::query("select url, device, token, team_key, cursor from sync_server where id = 1;")
+            .fetch_optional(&db_service.pool)
+            .await?
+            .ok_or(SyncError::NotConnected)?;
+
+    let cipher = auth_state.cipher();
+    let url: String = row.try_get("url")?;
+    let token = Zeroizing::new(models::decrypt(&cipher, row.try_get("token")?)?);
+    let team_key = Zeroizing::new(models::decrypt(&cipher, row.try_get("team_key")?)?);
+    if team_key.len() != 32 {
+        return Err(SyncError::Corrupt(
+            "the team's key is the wrong length".into(),
+        ));
+    }
+
+    Ok(Connected {
+        server: ServerUrl::parse(&url).map_err(SyncError::Server)?,
+        url,
+        device: row.try_get("device")?,
+        token: Zeroizing::new(
+            String::from_utf8(token.to_vec())
+                .map_err(|_| SyncError::Corrupt("the device's token isn't text".into()))?,
+        ),
+        team_cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&team_key)),
+        cursor: row.try_get("cursor")?,
+    })
+}
+
+/// Keep the records that changed on the server since the last pull.
+async fn pull(connected: &mut Connected, db_service: &DatabaseService) -> Result<(), SyncError> {
+    let path = format!("/v1/records?since={}", connected.cursor);
+    let changes: Changes =
+        parse(call(&connected.server, "GET", &path, &connected.token, None).await?)?;
+
+    let mut tx = db_service.pool.begin().await?;
+    for record in changes.records.iter() {
+        keep_record(record, &mut tx).await?;
+    }
+    sqlx::query("update sync_server set cursor = ? where id = 1;")
+        .bind(changes.latest)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    connected.cursor = changes.latest;
+    Ok(())
+}
+
+async fn keep_record(record: &ServerRecord, conn: &mut SqliteConnection) -> Result<(), SyncError> {
+    sqlx::query(
+        "insert or replace into sync_server_records (uuid, version, blob) values (?, ?, ?);",
+    )
+    .bind(&record.uuid)
+    .bind(serde_json::to_string(&record.version).map_err(|err| SyncError::Server(err.to_string()))?)
+    .bind(&record.blob)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+async fn read_cache(
+    connected: &Connected,
+    db_service: &DatabaseService,
+) -> Result<Cache, SyncError> {
+    let rows = sqlx::query("select uuid, version, blob from sync_server_records;")
+        .fetch_all(&db_service.pool)
+        .await?;
+
+    let mut cache = Cache::new();
+    for row in rows {
+        let uuid: String = row.try_get("uuid")?;
+        let version: Version = serde_json::from_str(row.try_get("version")?)
+            .map_err(|err| SyncError::Corrupt(err.to_string()))?;
+        let blob: String = row.try_get("blob")?;
+        let record =
+            vault_file::open_sync_record(&blob, &uuid, &version, &connected.team_cipher).ok();
+        cache.insert(uuid, (version, record));
+    }
+    Ok(cache)
+}
+
+/// The server's records as a sync file, and the records that couldn't be read.
+fn remote_file(cache: &Cache) -> (SyncFile, Vec<String>) {
+    let mut entries = Vec::new();
+    let mut tombstones = Vec::new();
+    let mut skipped = Vec::new();
+    for (uuid, (_, record)) in cache.iter() {
+        match record {
+            Some(SyncRecord::Entry(entry)) => entries.push(entry.as_ref().clone()),
+            Some(SyncRecord::Deleted(tombstone)) => tombstones.push(tombstone.clone()),
+            None => skipped.push(format!(
+                "The server's record of {uuid}: it isn't encrypted with the team's key or has been changed"
+            )),
+        }
+    }
+    entries.sort_by(|a: &PasswordEntryRaw, b| a.uuid.cmp(&b.uuid));
+    tombstones.sort_by(|a: &Tombstone, b| a.uuid.cmp(&b.uuid));
+    skipped.sort();
+
+    let file = SyncFile {
+        device: SERVER_DEVICE.to_string(),
+        written_at: unix_now(),
+        entries,
+        tombstones,
+    };
+    (file, skipped)
+}
+
+/// Push every entry and deletion that differs from the server's version, except those still in
+/// conflict. Returns whether the server turned any down because it has changes to them that
+/// weren't merged yet, which it sends back and are kept for the next attempt.
+async fn push(
+    connected: &Connected,
+    own: &SyncFile,
+    summary: &SyncSummary,
+    cache: &Cache,
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<bool, SyncError> {
+    let mut records = Vec::new();
+    for entry in own.entries.iter() {
+        if summary
+            .conflicts
+            .iter()
+            .any(|conflict| conflict.local.uuid == entry.uuid)
+        {
+            continue;
+        }
+        match cache.get(&entry.uuid) {
+            Some((_, Some(SyncRecord::Entry(remote)))) if sync::same_fields(entry, remote) => {}
+            // Left alone rather than overwritten with something the other devices might not
+            // have merged.
+            Some((_, None)) => {}
+            _ => records.push(SyncRecord::Entry(Box::new(entry.clone()))),
+        }
+    }
+    for tombstone in own.tombstones.iter() {
+        if let Some((_, Some(SyncRecord::Entry(_)))) = cache.get(&tombstone.uuid) {
+            records.push(SyncRecord::Deleted(tombstone.clone()));
+        }
+    }
+
+    let cipher = auth_state.cipher();
+    let mut rejected = false;
+    for record in records {
+        let mut version = cache
+            .get(record.uuid())
+            .map(|(version, _)| version.clone())
+            .unwrap_or_default();
+        *version.entry(connected.device.clone()).or_default() += 1;
+
+        let pushed = ServerRecord {
+            uuid: record.uuid().to_string(),
+            blob: vault_file::seal_sync_record(&record, &version, &connected.team_cipher)
+                .map_err(SyncError::Server)?,
+            version,
+            seq: 0,
+        };
+        let body = serde_json::to_vec(&PushRecord {
+            version: pushed.version.clone(),
+            blob: pushed.blob.clone(),
+        })
+        .map_err(|err| SyncError::Server(err.to_string()))?;
+
+        let path = format!("/v1/records/{}", pushed.uuid);
+        let response = call(
+            &connected.server,
+            "PUT",
+            &path,
+            &connected.token,
+            Some(&body),
+        )
+        .await?;
+        let mut conn = db_service.pool.acquire().await?;
+        match response.status {
+            409 => {
+                rejected = true;
+                if let Some(current) = parse_error(&response).record {
+                    keep_record(&current, &mut conn).await?;
+                }
+            }
+            _ => {
+                parse::<serde_json::Value>(response)?;
+                keep_record(&pushed, &mut conn).await?;
+                // The server has this device's version now, the next merge starts from it.
+                if let SyncRecord::Entry(entry) = &record {
+                    sync::keep_base(SERVER_DEVICE, entry, &cipher, &mut conn).await?;
+                }
+            }
+        }
+    }
+
+    Ok(rejected)
+}
+
+async fn call(
+    server: &ServerUrl,
+    method: &str,
+    path: &str,
+    token: &str,
+    body: Option<&[u8]>,
+) -> Result<Response, SyncError> {
+    http::send(server, method, path, token, body)
+        .await
+        .map_err(SyncError::Server)
+}
+
+/// The body of a successful response, or the server's error.
+fn parse<T: DeserializeOwned>(response: Response) -> Result<T, SyncError> {
+    if !response.is_success() {
+        return Err(SyncError::Server(parse_error(&response).error));
+    }
+    serde_json::from_slice(&response.body)
+        .map_err(|err| SyncError::Server(format!("The server's answer can't be read: {err}")))
+}
+
+fn parse_error(response: &Response) -> ErrorBody {
+    serde_json::from_slice(&response.body).unwrap_or_else(|_| ErrorBody {
+        error: format!("The server answered with status {}", response.status),
+        record: None,
+    })
+}
+
+fn derive_team_key(passphrase: &str, salt: &[u8]) -> Result<Zeroizing<[u8; 32]>, SyncError> {
+    let params = Params::new(
+        ARGON2_MEMORY_KIB,
+        ARGON2_ITERATIONS,
+        ARGON2_PARALLELISM,
+        Some(32),
+    )
+    .map_err(|err| SyncError::Server(err.to_string()))?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::new(Algorithm::Argon2id, Argon2Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut_slice())
+        .map_err(|err| SyncError::Server(err.to_string()))?;
+    Ok(key)
+}
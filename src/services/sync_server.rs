@@ -0,0 +1,440 @@
+//! The optional sync server a team's devices push their changes to and pull each other's from,
+//! run with the `pw-sync-server` binary.
+//!
+//! The server never has a key. The apps encrypt each entry or deletion with a key derived from
+//! the team's passphrase before sending it, and the server keeps the latest blob for each entry
+//! UUID with its version vector, which counts the changes each device made to it. A device may
+//! only replace a blob with a version that includes every change the server has, so that a
+//! device that missed a change merges it first instead of overwriting it. Devices sign in with
+//! a token of their own, of which the server only keeps a hash.
+
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use base64::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{
+    migrate::Migrator,
+    prelude::*,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous},
+    SqlitePool,
+};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::{
+    models::unix_now,
+    services::http::{self, Request, Response},
+};
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations-sync-server");
+
+/// The server's database file when none is given.
+pub const DEFAULT_DATABASE_FILE_NAME: &str = "pw-sync-server.sqlite";
+pub const DEFAULT_LISTEN_ADDRESS: &str = "127.0.0.1:8787";
+
+/// How many changes each device made to an entry, by device name.
+pub type Version = BTreeMap<String, u64>;
+
+/// Whether `version` includes every change counted in `other`.
+pub fn includes(version: &Version, other: &Version) -> bool {
+    other
+        .iter()
+        .all(|(device, count)| version.get(device).is_some_and(|own| own >= count))
+}
+
+/// What a device needs to know about the team, from `GET /v1/team`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TeamInfo {
+    /// The name the device's token was added under, which its changes are counted under.
+    pub device: String,
+    /// The base64 encoded salt the team's key is derived from.
+    pub salt: String,
+    /// A value encrypted with the team's key by the first device, empty until then.
+    pub key_check: String,
+}
+
+/// The body of `PUT /v1/team/key-check`, only accepted while the team has none.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyCheck {
+    pub key_check: String,
+}
+
+/// The latest version of an entry or deletion.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerRecord {
+    pub uuid: String,
+    pub version: Version,
+    /// The entry or deletion encrypted with the team's key.
+    pub blob: String,
+    pub seq: i64,
+}
+
+/// The response to `GET /v1/records?since=<seq>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Changes {
+    /// The records changed after `since`, oldest change first.
+    pub records: Vec<ServerRecord>,
+    /// The sequence number of the latest change, to ask from next time.
+    pub latest: i64,
+}
+
+/// The body of `PUT /v1/records/<uuid>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PushRecord {
+    pub version: Version,
+    pub blob: String,
+}
+
+/// The response to a push that was stored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pushed {
+    pub seq: i64,
+}
+
+/// The body of every response that isn't a success. A push that's missing changes the server
+/// has is answered with `409` and the server's record.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorBody {
+    pub error: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub record: Option<ServerRecord>,
+}
+
+/// A device that's allowed to sync.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Device {
+    pub name: String,
+    pub created_at: i64,
+    /// Unix timestamp (seconds) of the device's last request, 0 if it hasn't made one.
+    pub last_seen_at: i64,
+}
+
+pub struct SyncServer {
+    pool: SqlitePool,
+    pub path: PathBuf,
+}
+
+impl SyncServer {
+    /// Open the server's database, creating it if it doesn't exist yet.
+    pub async fn open(path: &Path) -> Result<Self, String> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal);
+        let pool = SqlitePoolOptions::new()
+            .connect_with(options)
+            .await
+            .map_err(|err| format!("Could not open {}: {err}", path.display()))?;
+        MIGRATOR
+            .run(&pool)
+            .await
+            .map_err(|err| format!("Could not set up {}: {err}", path.display()))?;
+
+        sqlx::query("insert or ignore into team (id, salt) values (1, ?);")
+            .bind(BASE64_STANDARD.encode(random_bytes::<16>()))
+            .execute(&pool)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        Ok(Self {
+            pool,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Allow a device to sync, returning the token it signs in with. The token isn't kept and
+    /// can't be shown again.
+    pub async fn add_device(&self, name: &str) -> Result<String, String> {
+        let name = name.trim();
+        if name.is_empty() || name.len() > 64 {
+            return Err("A device's name must be between 1 and 64 characters".into());
+        }
+
+        let token = BASE64_URL_SAFE_NO_PAD.encode(random_bytes::<32>());
+        let result = sqlx::query(
+            "insert or ignore into devices (name, token_hash, created_at) values (?, ?, ?);",
+        )
+        .bind(name)
+        .bind(hash_token(&token))
+        .bind(unix_now())
+        .execute(&self.pool)
+        .await
+        .map_err(|err| err.to_string())?;
+
+        match result.rows_affected() {
+            0 => Err(format!("There's already a device named {name}")),
+            _ => Ok(token),
+        }
+    }
+
+    /// Stop a device from syncing. Its changes stay on the server.
+    pub async fn revoke_device(&self, name: &str) -> Result<(), String> {
+        let result = sqlx::query("delete from devices where name = ?;")
+            .bind(name.trim())
+            .execute(&self.pool)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        match result.rows_affected() {
+            0 => Err(format!("There's no device named {}", name.trim())),
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn list_devices(&self) -> Result<Vec<Device>, String> {
+        sqlx::query("select name, created_at, last_seen_at from devices order by name;")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|err| err.to_string())?
+            .iter()
+            .map(|row| {
+                Ok(Device {
+                    name: row.try_get("name")?,
+                    created_at: row.try_get("created_at")?,
+                    last_seen_at: row.try_get("last_seen_at")?,
+                })
+            })
+            .collect::<Result<_, sqlx::Error>>()
+            .map_err(|err| err.to_string())
+    }
+
+    /// Answer one request.
+    pub async fn handle(&self, request: &Request) -> Response {
+        match self.respond(request).await {
+            Ok(response) => response,
+            Err(err) => {
+                eprintln!("pw-sync-server: {} {}: {err}", request.method, request.path);
+                error(500, "The server couldn't handle the request")
+            }
+        }
+    }
+
+    async fn respond(&self, request: &Request) -> Result<Response, sqlx::Error> {
+        let Some(device) = self.authenticate(request).await? else {
+            return Ok(error(
+                401,
+                "The device's token is missing, wrong or revoked",
+            ));
+        };
+
+        let path = request.path.trim_end_matches('/');
+        let response = match (request.method.as_str(), path) {
+            ("GET", "/v1/team") => self.team(&device).await?,
+            ("PUT", "/v1/team/key-check") => self.set_key_check(request).await?,
+            ("GET", "/v1/records") => self.changes(request).await?,
+            ("PUT", path) if path.starts_with("/v1/records/") => {
+                self.push(&device, &path["/v1/records/".len()..], request)
+                    .await?
+            }
+            (_, "/v1/team" | "/v1/team/key-check" | "/v1/records") => {
+                error(405, "The method isn't allowed here")
+            }
+            _ => error(404, "There's nothing here"),
+        };
+        Ok(response)
+    }
+
+    /// The name of the device the request's token belongs to, if any.
+    async fn authenticate(&self, request: &Request) -> Result<Option<String>, sqlx::Error> {
+        let Some(token) = request.bearer_token() else {
+            return Ok(None);
+        };
+        sqlx::query_scalar(
+            "update devices set last_seen_at = ? where token_hash = ? returning name;",
+        )
+        .bind(unix_now())
+        .bind(hash_token(token))
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    async fn team(&self, device: &str) -> Result<Response, sqlx::Error> {
+        let row = sqlx::query("select salt, key_check from team where id = 1;")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(Response::json(
+            200,
+            &TeamInfo {
+                device: device.to_string(),
+                salt: row.try_get("salt")?,
+                key_check: row.try_get("key_check")?,
+            },
+        ))
+    }
+
+    async fn set_key_check(&self, request: &Request) -> Result<Response, sqlx::Error> {
+        let Ok(body) = serde_json::from_slice::<KeyCheck>(&request.body) else {
+            return Ok(error(400, "The body isn't a key check"));
+        };
+        if body.key_check.is_empty() {
+            return Ok(error(400, "The key check is empty"));
+        }
+
+        let result = sqlx::query("update team set key_check = ? where id = 1 and key_check = '';")
+            .bind(&body.key_check)
+            .execute(&self.pool)
+            .await?;
+        Ok(match result.rows_affected() {
+            0 => error(409, "The team already has a key check"),
+            _ => Response::json(200, &body),
+        })
+    }
+
+    async fn changes(&self, request: &Request) -> Result<Response, sqlx::Error> {
+        let since = match request.query_param("since").map(str::parse::<i64>) {
+            None => 0,
+            Some(Ok(since)) => since,
+            Some(Err(_)) => return Ok(error(400, "`since` must be a number")),
+        };
+
+        let records =
+            sqlx::query("select uuid, version, blob, seq from records where seq > ? order by seq;")
+                .bind(since)
+                .fetch_all(&self.pool)
+                .await?
+                .iter()
+                .map(record_from_row)
+                .collect::<Result<Vec<_>, _>>()?;
+        let latest = records.last().map_or(since, |record| record.seq);
+
+        Ok(Response::json(200, &Changes { records, latest }))
+    }
+
+    async fn push(
+        &self,
+        device: &str,
+        uuid: &str,
+        request: &Request,
+    ) -> Result<Response, sqlx::Error> {
+        let valid_uuid = !uuid.is_empty()
+            && uuid.len() <= 64
+            && uuid
+                .chars()
+                .all(|char| char.is_ascii_alphanumeric() || char == '-');
+        if !valid_uuid {
+            return Ok(error(400, "The entry's UUID is invalid"));
+        }
+        let Ok(pushed) = serde_json::from_slice::<PushRecord>(&request.body) else {
+            return Ok(error(400, "The body isn't a record"));
+        };
+        if pushed.blob.is_empty() {
+            return Ok(error(400, "The record is empty"));
+        }
+
+        let mut tx = self.pool.begin_with("begin immediate").await?;
+        let current = sqlx::query("select uuid, version, blob, seq from records where uuid = ?;")
+            .bind(uuid)
+            .fetch_optional(&mut *tx)
+            .await?
+            .as_ref()
+            .map(record_from_row)
+            .transpose()?;
+
+        let known = current
+            .as_ref()
+            .map(|record| record.version.clone())
+            .unwrap_or_default();
+        if !includes(&pushed.version, &known) {
+            return Ok(Response::json(
+                409,
+                &ErrorBody {
+                    error: "The server has changes to the entry that the device doesn't".into(),
+                    record: current,
+                },
+            ));
+        }
+        if pushed.version.get(device) <= known.get(device) {
+            return Ok(error(
+                400,
+                "The version must count the device's change to the entry",
+            ));
+        }
+
+        let seq: i64 = sqlx::query_scalar("select coalesce(max(seq), 0) + 1 from records;")
+            .fetch_one(&mut *tx)
+            .await?;
+        sqlx::query(
+            "insert or replace into records (uuid, version, blob, seq, device, updated_at)
+            values (?, ?, ?, ?, ?, ?);",
+        )
+        .bind(uuid)
+        .bind(serde_json::to_string(&pushed.version).unwrap_or_default())
+        .bind(&pushed.blob)
+        .bind(seq)
+        .bind(device)
+        .bind(unix_now())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(Response::json(200, &Pushed { seq }))
+    }
+}
+
+/// Serve requests on `listener` until interrupted.
+pub async fn serve(server: Arc<SyncServer>, listener: TcpListener) -> Result<(), String> {
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                match accepted {
+                    Ok((stream, peer)) => {
+                        tokio::spawn(serve_connection(server.clone(), stream, peer));
+                    }
+                    Err(err) => eprintln!("pw-sync-server: {err}"),
+                }
+            }
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+    }
+}
+
+async fn serve_connection(server: Arc<SyncServer>, mut stream: TcpStream, peer: SocketAddr) {
+    let response = match http::read_request(&mut stream).await {
+        Ok(request) => server.handle(&request).await,
+        Err(err) => {
+            eprintln!("pw-sync-server: {peer}: {err}");
+            error(400, &err)
+        }
+    };
+    let _ = http::write_response(&mut stream, &response).await;
+}
+
+fn record_from_row(row: &sqlx::sqlite::SqliteRow) -> Result<ServerRecord, sqlx::Error> {
+    let version: String = row.try_get("version")?;
+    Ok(ServerRecord {
+        uuid: row.try_get("uuid")?,
+        version: serde_json::from_str(&version).map_err(|err| sqlx::Error::Decode(err.into()))?,
+        blob: row.try_get("blob")?,
+        seq: row.try_get("seq")?,
+    })
+}
+
+fn error(status: u16, message: &str) -> Response {
+    Response::json(
+        status,
+        &ErrorBody {
+            error: message.to_string(),
+            record: None,
+        },
+    )
+}
+
+fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
@@ -0,0 +1,760 @@
+//! The app's own export format: every entry in a single file, encrypted with a passphrase.
+//!
+//! A vault file starts with a header holding the format version, the Argon2id settings and salt
+//! the key is derived with, and the nonce. The entries follow as gzipped JSON encrypted with
+//! AES-256-GCM, with the header as associated data so that a change anywhere in the file is
+//! caught when it's opened. Files are read and checked in full before the vault is changed.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Display},
+    io::{Read, Write},
+    path::Path,
+};
+
+use aes_gcm::{
+    aead::{rand_core::RngCore, Aead, OsRng, Payload},
+    Aes256Gcm, Key, KeyInit, Nonce,
+};
+use argon2::{Algorithm, Argon2, Params, Version};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::{
+    models::{
+        self, unix_now, AuthState, EntryExtras, PasswordEntryRaw, PasswordEntrySafe, Tombstone,
+    },
+    services::{
+        audit::{self, AuditAction, AuditEvent},
+        backup::{self, BackupReason},
+        database::DatabaseService,
+        import, password_entry,
+        sync_server::Version as RecordVersion,
+        vault_lock,
+    },
+};
+
+pub const FILE_EXTENSION: &str = "pmvault";
+
+const MAGIC: &[u8; 8] = b"PWMVAULT";
+const FORMAT_VERSION: u16 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+/// Magic, version, the three Argon2 settings, salt and nonce.
+const HEADER_LEN: usize = MAGIC.len() + 2 + 3 * 4 + SALT_LEN + NONCE_LEN;
+
+const ARGON2_MEMORY_KIB: u32 = 64 * 1024;
+const ARGON2_ITERATIONS: u32 = 3;
+const ARGON2_PARALLELISM: u32 = 4;
+/// Files asking for more than these are refused, rather than risk running out of memory or
+/// deriving the key for hours.
+const MAX_ARGON2_MEMORY_KIB: u32 = 4 * 1024 * 1024;
+const MAX_ARGON2_ITERATIONS: u32 = 64;
+const MAX_ARGON2_PARALLELISM: u32 = 64;
+
+/// What happens to the entries already in the vault when a vault file is imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Keep them, adding new entries and updating ones the file has a newer version of.
+    Merge,
+    /// Delete them and keep only the entries in the file.
+    Replace,
+}
+
+impl ImportMode {
+    pub const ALL: [ImportMode; 2] = [ImportMode::Merge, ImportMode::Replace];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ImportMode::Merge => "Merge into the vault",
+            ImportMode::Replace => "Replace the vault",
+        }
+    }
+
+    /// A stable name for the mode, e.g. for form values.
+    pub fn key(&self) -> &'static str {
+        match self {
+            ImportMode::Merge => "merge",
+            ImportMode::Replace => "replace",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.key() == key)
+    }
+}
+
+/// What importing a vault file changed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub added: usize,
+    /// Entries updated because the file had a newer version of them.
+    pub updated: usize,
+    /// Entries in the file that were already in the vault, at least as up to date.
+    pub unchanged: usize,
+    /// Entries deleted when replacing the vault.
+    pub removed: usize,
+}
+
+impl Display for ImportSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} added, {} updated", self.added, self.updated)?;
+        if self.unchanged > 0 {
+            write!(f, ", {} already up to date", self.unchanged)?;
+        }
+        if self.removed > 0 {
+            write!(f, ", {} removed", self.removed)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct VaultFileContents {
+    exported_at: i64,
+    entries: Vec<VaultFileEntry>,
+    /// The name of the device that wrote a sync file, empty for an export.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    device: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tombstones: Vec<Tombstone>,
+}
+
+/// The entries and deletions one device shares with the others it syncs with, in the same
+/// format as an export.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncFile {
+    pub device: String,
+    pub written_at: i64,
+    pub entries: Vec<PasswordEntryRaw>,
+    pub tombstones: Vec<Tombstone>,
+}
+
+/// An entry as it's stored in the file. Kept separate from [`PasswordEntryRaw`] so that the file
+/// format only changes on purpose.
+#[derive(Serialize, Deserialize)]
+struct VaultFileEntry {
+    #[serde(default)]
+    uuid: String,
+    #[serde(default)]
+    revision: i64,
+    site: String,
+    username: String,
+    password: Zeroizing<String>,
+    urls: Vec<String>,
+    notes: String,
+    tags: Vec<String>,
+    created_at: i64,
+    updated_at: i64,
+    password_changed_at: i64,
+    #[serde(default)]
+    extras: EntryExtras,
+}
+
+impl From<&PasswordEntryRaw> for VaultFileEntry {
+    fn from(entry: &PasswordEntryRaw) -> Self {
+        Self {
+            uuid: entry.uuid.clone(),
+            revision: entry.revision,
+            site: entry.site.clone(),
+            username: entry.username.clone(),
+            password: entry.raw_password.clone(),
+            urls: entry.urls.clone(),
+            notes: entry.notes.clone(),
+            tags: entry.tags.clone(),
+            created_at: entry.created_at,
+            updated_at: entry.updated_at,
+            password_changed_at: entry.password_changed_at,
+            extras: entry.extras.clone(),
+        }
+    }
+}
+
+impl From<VaultFileEntry> for PasswordEntryRaw {
+    fn from(entry: VaultFileEntry) -> Self {
+        Self {
+            id: 0,
+            uuid: entry.uuid,
+            revision: entry.revision,
+            site: entry.site,
+            username: entry.username,
+            raw_password: entry.password,
+            urls: entry.urls,
+            notes: entry.notes,
+            tags: entry.tags,
+            created_at: entry.created_at,
+            updated_at: entry.updated_at,
+            password_changed_at: entry.password_changed_at,
+            extras: entry.extras,
+        }
+    }
+}
+
+/// Encrypt `entries` into a vault file protected by `passphrase`.
+pub fn write_vault_file(entries: &[PasswordEntryRaw], passphrase: &str) -> Result<Vec<u8>, String> {
+    seal(
+        &VaultFileContents {
+            exported_at: unix_now(),
+            entries: entries.iter().map(VaultFileEntry::from).collect(),
+            device: String::new(),
+            tombstones: Vec::new(),
+        },
+        passphrase,
+    )
+}
+
+/// Decrypt a vault file and check it's complete, without touching the vault.
+pub fn read_vault_file(data: &[u8], passphrase: &str) -> Result<Vec<PasswordEntryRaw>, String> {
+    Ok(open(data, passphrase)?
+        .entries
+        .into_iter()
+        .map(PasswordEntryRaw::from)
+        .collect())
+}
+
+/// Encrypt a device's sync file with `passphrase`.
+pub fn write_sync_file(file: &SyncFile, passphrase: &str) -> Result<Vec<u8>, String> {
+    seal(
+        &VaultFileContents {
+            exported_at: file.written_at,
+            entries: file.entries.iter().map(VaultFileEntry::from).collect(),
+            device: file.device.clone(),
+            tombstones: file.tombstones.clone(),
+        },
+        passphrase,
+    )
+}
+
+/// Decrypt another device's sync file.
+pub fn read_sync_file(data: &[u8], passphrase: &str) -> Result<SyncFile, String> {
+    let contents = open(data, passphrase)?;
+    Ok(SyncFile {
+        device: contents.device,
+        written_at: contents.exported_at,
+        entries: contents
+            .entries
+            .into_iter()
+            .map(PasswordEntryRaw::from)
+            .collect(),
+        tombstones: contents.tombstones,
+    })
+}
+
+/// An entry or a deletion as a device shares it through a sync server.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncRecord {
+    Entry(Box<PasswordEntryRaw>),
+    Deleted(Tombstone),
+}
+
+impl SyncRecord {
+    pub fn uuid(&self) -> &str {
+        match self {
+            SyncRecord::Entry(entry) => &entry.uuid,
+            SyncRecord::Deleted(tombstone) => &tombstone.uuid,
+        }
+    }
+}
+
+/// A [`SyncRecord`] with the version it's stored under on the server, encrypted together so
+/// that the server can't pass one record or version off as another.
+#[derive(Serialize, Deserialize)]
+struct SyncRecordContents {
+    uuid: String,
+    version: RecordVersion,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    entry: Option<VaultFileEntry>,
+    #[serde(default)]
+    deleted_at: i64,
+}
+
+/// Encrypt a record for the sync server with the team's key.
+pub fn seal_sync_record(
+    record: &SyncRecord,
+    version: &RecordVersion,
+    cipher: &Aes256Gcm,
+) -> Result<String, String> {
+    let contents = SyncRecordContents {
+        uuid: record.uuid().to_string(),
+        version: version.clone(),
+        entry: match record {
+            SyncRecord::Entry(entry) => Some(VaultFileEntry::from(entry.as_ref())),
+            SyncRecord::Deleted(_) => None,
+        },
+        deleted_at: match record {
+            SyncRecord::Entry(_) => 0,
+            SyncRecord::Deleted(tombstone) => tombstone.deleted_at,
+        },
+    };
+    let json = Zeroizing::new(serde_json::to_vec(&contents).map_err(|err| err.to_string())?);
+    models::encrypt(cipher, &json).map_err(|err| err.to_string())
+}
+
+/// Decrypt a record from the sync server, checking it's the one stored under `uuid` and
+/// `version`.
+pub fn open_sync_record(
+    blob: &str,
+    uuid: &str,
+    version: &RecordVersion,
+    cipher: &Aes256Gcm,
+) -> Result<SyncRecord, String> {
+    let json = Zeroizing::new(
+        models::decrypt(cipher, blob)
+            .map_err(|_| "It isn't encrypted with the team's key or has been changed")?,
+    );
+    let contents: SyncRecordContents =
+        serde_json::from_slice(&json).map_err(|err| format!("It's damaged: {err}"))?;
+    if contents.uuid != uuid || &contents.version != version {
+        return Err("It was stored under another entry or version".into());
+    }
+
+    Ok(match contents.entry {
+        Some(entry) => SyncRecord::Entry(Box::new(PasswordEntryRaw {
+            uuid: contents.uuid,
+            ..entry.into()
+        })),
+        None => SyncRecord::Deleted(Tombstone {
+            uuid: contents.uuid,
+            deleted_at: contents.deleted_at,
+        }),
+    })
+}
+
+fn seal(contents: &VaultFileContents, passphrase: &str) -> Result<Vec<u8>, String> {
+    seal_with(
+        contents,
+        passphrase,
+        ARGON2_MEMORY_KIB,
+        ARGON2_ITERATIONS,
+        ARGON2_PARALLELISM,
+    )
+}
+
+fn seal_with(
+    contents: &VaultFileContents,
+    passphrase: &str,
+    memory: u32,
+    iterations: u32,
+    parallelism: u32,
+) -> Result<Vec<u8>, String> {
+    let json = Zeroizing::new(serde_json::to_vec(&contents).map_err(|err| err.to_string())?);
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&json).map_err(|err| err.to_string())?;
+    let compressed = Zeroizing::new(encoder.finish().map_err(|err| err.to_string())?);
+
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut header = MAGIC.to_vec();
+    header.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    header.extend_from_slice(&memory.to_le_bytes());
+    header.extend_from_slice(&iterations.to_le_bytes());
+    header.extend_from_slice(&parallelism.to_le_bytes());
+    header.extend_from_slice(&salt);
+    header.extend_from_slice(&nonce);
+
+    let cipher = derive_cipher(passphrase, &salt, memory, iterations, parallelism)?;
+    let ciphertext = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &compressed,
+                aad: &header,
+            },
+        )
+        .map_err(|err| err.to_string())?;
+
+    let mut output = header;
+    output.extend_from_slice(&ciphertext);
+    Ok(output)
+}
+
+fn open(data: &[u8], passphrase: &str) -> Result<VaultFileContents, String> {
+    if data.len() < HEADER_LEN || !data.starts_with(MAGIC) {
+        return Err("The file is not a vault export".into());
+    }
+
+    let (header, ciphertext) = data.split_at(HEADER_LEN);
+    let version = u16::from_le_bytes([header[MAGIC.len()], header[MAGIC.len() + 1]]);
+    if version != FORMAT_VERSION {
+        return Err(
+            "The vault export was made by a newer version of the app, update the app to import it"
+                .into(),
+        );
+    }
+
+    let settings_start = MAGIC.len() + 2;
+    let setting = |index: usize| {
+        let start = settings_start + index * 4;
+        u32::from_le_bytes(
+            header[start..start + 4]
+                .try_into()
+                .expect("settings are 4 bytes"),
+        )
+    };
+    let (memory, iterations, parallelism) = (setting(0), setting(1), setting(2));
+    if memory > MAX_ARGON2_MEMORY_KIB {
+        return Err("The vault export asks for more memory than is allowed to unlock it".into());
+    }
+    if iterations > MAX_ARGON2_ITERATIONS || parallelism > MAX_ARGON2_PARALLELISM {
+        return Err(
+            "The vault export asks for more work than is allowed to unlock it, it may have been changed"
+                .into(),
+        );
+    }
+    let salt_start = settings_start + 3 * 4;
+    let salt = &header[salt_start..salt_start + SALT_LEN];
+    let nonce = &header[salt_start + SALT_LEN..];
+
+    let cipher = derive_cipher(passphrase, salt, memory, iterations, parallelism)?;
+    let compressed = Zeroizing::new(
+        cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: header,
+                },
+            )
+            .map_err(|_| "The passphrase is wrong or the file has been damaged or changed")?,
+    );
+
+    let mut json = Zeroizing::new(Vec::new());
+    GzDecoder::new(compressed.as_slice())
+        .read_to_end(&mut json)
+        .map_err(|err| format!("The vault export is damaged: {err}"))?;
+    serde_json::from_slice(&json).map_err(|err| format!("The vault export is damaged: {err}"))
+}
+
+/// Export the whole vault to a vault file at `path`, returning how many entries were written.
+pub async fn export_vault(
+    path: &Path,
+    passphrase: Zeroizing<String>,
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<usize, String> {
+    if path.exists() {
+        return Err(format!(
+            "There is already a file at {}, choose another name",
+            path.display()
+        ));
+    }
+
+    let entries =
+        password_entry::get_all_password_entries(auth_state, db_service, String::new()).await?;
+    let event = AuditEvent::new(AuditAction::Export).with_detail(format!(
+        "{} entries to the vault file {}",
+        entries.len(),
+        path.display()
+    ));
+    let path = path.to_path_buf();
+
+    let written = tokio::task::spawn_blocking(move || {
+        let data = write_vault_file(&entries, &passphrase)?;
+        std::fs::write(&path, data).map_err(|err| err.to_string())?;
+        Ok::<_, String>(entries.len())
+    })
+    .await
+    .map_err(|err| err.to_string())??;
+
+    let _ = audit::record(event, auth_state, db_service).await;
+    Ok(written)
+}
+
+/// Import the vault file at `path`. The file is decrypted and checked first and then the vault is
+/// changed in a single transaction, so a bad file or a failure part way leaves it as it was.
+pub async fn import_vault(
+    path: &Path,
+    passphrase: Zeroizing<String>,
+    mode: ImportMode,
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<ImportSummary, String> {
+    if !auth_state.signed_in {
+        return Err("You must be signed in to access these resources".into());
+    }
+
+    let path = path.to_path_buf();
+    let entries = tokio::task::spawn_blocking(move || {
+        let data = std::fs::read(&path).map_err(|err| err.to_string())?;
+        read_vault_file(&data, &passphrase)
+    })
+    .await
+    .map_err(|err| err.to_string())??;
+
+    let _lock = vault_lock::lock_vault(db_service).await?;
+    backup::create_backup(BackupReason::Import, db_service)
+        .await
+        .map_err(|err| format!("Could not back up the vault before importing: {err}"))?;
+
+    let summary = restore_entries(&entries, mode, auth_state, db_service).await?;
+
+    let event =
+        AuditEvent::new(AuditAction::Import).with_detail(format!("From a vault file: {summary}"));
+    let _ = audit::record(event, auth_state, db_service).await;
+    Ok(summary)
+}
+
+/// Merge `entries` into the vault or replace it with them, in a single transaction. Callers
+/// that back up the vault first should hold the [`vault_lock`] for both.
+pub async fn restore_entries(
+    entries: &[PasswordEntryRaw],
+    mode: ImportMode,
+    auth_state: &AuthState,
+    db_service: &DatabaseService,
+) -> Result<ImportSummary, String> {
+    if !auth_state.signed_in {
+        return Err("You must be signed in to access these resources".into());
+    }
+
+    // Immediate so that the entries matched against can't change before they're updated.
+    let mut tx = db_service
+        .pool
+        .begin_with("begin immediate")
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let existing = match mode {
+        ImportMode::Merge => {
+            password_entry::get_all_password_entry_summaries_in(auth_state, &mut *tx).await?
+        }
+        ImportMode::Replace => Vec::new(),
+    };
+
+    // Each existing entry can be matched by one entry from the file, in order.
+    let mut matches: HashMap<(String, String), Vec<(i32, i64)>> = HashMap::new();
+    for entry in existing.iter().rev() {
+        matches
+            .entry(import::identity(&entry.site, &entry.username, &entry.urls))
+            .or_default()
+            .push((entry.id, entry.updated_at));
+    }
+
+    let cipher = auth_state.cipher();
+    let mut summary = ImportSummary::default();
+    let mut kept_uuids = HashSet::new();
+
+    if mode == ImportMode::Replace {
+        summary.removed = password_entry::delete_all_password_entries(&mut tx).await? as usize;
+    }
+
+    for entry in entries {
+        let matched = matches
+            .get_mut(&import::identity(&entry.site, &entry.username, &entry.urls))
+            .and_then(Vec::pop);
+
+        match matched {
+            Some((id, updated_at)) if entry.updated_at > updated_at => {
+                password_entry::update_password_entry(id, &entry.to_safe_with(&cipher)?, &mut *tx)
+                    .await?;
+                summary.updated += 1;
+            }
+            Some(_) => summary.unchanged += 1,
+            None => {
+                // Replacing the vault keeps the entries' UUIDs, so that syncing sees the same
+                // entries come back. An entry merged in is a copy and gets its own, so it can't
+                // clash with the entry it was exported from.
+                let keep_uuid = mode == ImportMode::Replace
+                    && !entry.uuid.is_empty()
+                    && kept_uuids.insert(entry.uuid.clone());
+                let safe = if keep_uuid {
+                    entry.to_safe_with(&cipher)?
+                } else {
+                    PasswordEntrySafe {
+                        uuid: String::new(),
+                        revision: 0,
+                        ..entry.to_safe_with(&cipher)?
+                    }
+                };
+                if keep_uuid {
+                    sqlx::query("delete from tombstones where uuid = ?;")
+                        .bind(&entry.uuid)
+                        .execute(&mut *tx)
+                        .await
+                        .map_err(|err| err.to_string())?;
+                }
+                password_entry::insert_password_entry(&safe, &mut *tx).await?;
+                summary.added += 1;
+            }
+        }
+    }
+
+    tx.commit()
+        .await
+        .map(|_| summary)
+        .map_err(|err| err.to_string())
+}
+
+fn derive_cipher(
+    passphrase: &str,
+    salt: &[u8],
+    memory: u32,
+    iterations: u32,
+    parallelism: u32,
+) -> Result<Aes256Gcm, String> {
+    let params = Params::new(memory, iterations, parallelism, Some(32))
+        .map_err(|err| format!("The vault export's key settings are invalid: {err}"))?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut_slice())
+        .map_err(|err| err.to_string())?;
+
+    Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key.as_slice())))
+}
+
+#[cfg(test)]
+mod tests {
+    use aes_gcm::KeyInit;
+
+    use super::*;
+    use crate::models::CustomField;
+
+    const PASSPHRASE: &str = "correct horse battery staple";
+
+    fn entry(uuid: &str) -> PasswordEntryRaw {
+        PasswordEntryRaw {
+            id: 0,
+            uuid: uuid.into(),
+            revision: 3,
+            site: "GitHub".into(),
+            username: "octocat".into(),
+            raw_password: Zeroizing::new("s3cret".into()),
+            urls: vec!["https://github.com/login".into()],
+            notes: "Recovery codes in the safe".into(),
+            tags: vec!["work".into()],
+            created_at: 1_600_000_000,
+            updated_at: 1_700_000_000,
+            password_changed_at: 1_650_000_000,
+            extras: EntryExtras {
+                custom_fields: vec![CustomField {
+                    name: "PIN".into(),
+                    value: Zeroizing::new("0000".into()),
+                    protected: true,
+                }],
+                ..EntryExtras::default()
+            },
+        }
+    }
+
+    /// A file like [`write_vault_file`] writes, with settings cheap enough for a debug build.
+    fn sealed(entries: &[PasswordEntryRaw], tombstones: Vec<Tombstone>) -> Vec<u8> {
+        let contents = VaultFileContents {
+            exported_at: 1_700_000_000,
+            entries: entries.iter().map(VaultFileEntry::from).collect(),
+            device: "laptop".into(),
+            tombstones,
+        };
+        seal_with(&contents, PASSPHRASE, 1024, 1, 1).unwrap()
+    }
+
+    fn with_setting(mut data: Vec<u8>, index: usize, value: u32) -> Vec<u8> {
+        let start = MAGIC.len() + 2 + index * 4;
+        data[start..start + 4].copy_from_slice(&value.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn entries_survive_a_round_trip() {
+        let entries = vec![entry("a"), entry("b")];
+
+        assert_eq!(
+            read_vault_file(&sealed(&entries, Vec::new()), PASSPHRASE).unwrap(),
+            entries
+        );
+    }
+
+    #[test]
+    fn sync_files_keep_the_device_and_tombstones() {
+        let tombstones = vec![Tombstone {
+            uuid: "gone".into(),
+            deleted_at: 1_700_000_100,
+        }];
+
+        let file = read_sync_file(&sealed(&[entry("a")], tombstones.clone()), PASSPHRASE).unwrap();
+
+        assert_eq!(
+            file,
+            SyncFile {
+                device: "laptop".into(),
+                written_at: 1_700_000_000,
+                entries: vec![entry("a")],
+                tombstones,
+            }
+        );
+    }
+
+    #[test]
+    fn a_wrong_passphrase_or_a_changed_byte_is_rejected() {
+        let data = sealed(&[entry("a")], Vec::new());
+        assert!(read_vault_file(&data, "wrong").is_err());
+
+        let mut changed = data.clone();
+        let last = changed.len() - 1;
+        changed[last] ^= 1;
+        assert!(read_vault_file(&changed, PASSPHRASE).is_err());
+
+        // The header is authenticated too.
+        let changed = with_setting(data, 1, 2);
+        assert!(read_vault_file(&changed, PASSPHRASE).is_err());
+    }
+
+    #[test]
+    fn costly_key_settings_are_refused_before_deriving_the_key() {
+        let data = sealed(&[entry("a")], Vec::new());
+
+        for (index, value) in [
+            (0, MAX_ARGON2_MEMORY_KIB + 1),
+            (1, u32::MAX),
+            (2, MAX_ARGON2_PARALLELISM + 1),
+        ] {
+            let err =
+                read_vault_file(&with_setting(data.clone(), index, value), PASSPHRASE).unwrap_err();
+            assert!(err.contains("than is allowed"), "{err}");
+        }
+    }
+
+    #[test]
+    fn other_files_are_refused() {
+        assert_eq!(
+            read_vault_file(b"not a vault", PASSPHRASE).unwrap_err(),
+            "The file is not a vault export"
+        );
+
+        let mut newer = sealed(&[], Vec::new());
+        newer[MAGIC.len()] = 2;
+        assert!(read_vault_file(&newer, PASSPHRASE)
+            .unwrap_err()
+            .contains("newer version"));
+    }
+
+    #[test]
+    fn sync_records_only_open_under_their_uuid_and_version() {
+        let cipher = Aes256Gcm::new(&[5u8; 32].into());
+        let version = RecordVersion::from([("laptop".to_string(), 2)]);
+        let record = SyncRecord::Entry(Box::new(entry("a")));
+
+        let blob = seal_sync_record(&record, &version, &cipher).unwrap();
+        assert_eq!(
+            open_sync_record(&blob, "a", &version, &cipher).unwrap(),
+            record
+        );
+
+        let newer = RecordVersion::from([("laptop".to_string(), 3)]);
+        assert!(open_sync_record(&blob, "b", &version, &cipher).is_err());
+        assert!(open_sync_record(&blob, "a", &newer, &cipher).is_err());
+
+        let deleted = SyncRecord::Deleted(Tombstone {
+            uuid: "a".into(),
+            deleted_at: 1_700_000_100,
+        });
+        let blob = seal_sync_record(&deleted, &version, &cipher).unwrap();
+        assert_eq!(
+            open_sync_record(&blob, "a", &version, &cipher).unwrap(),
+            deleted
+        );
+    }
+}
@@ -0,0 +1,64 @@
+//! Keeps processes from changing a vault in bulk at the same time.
+//!
+//! SQLite makes each transaction atomic, but changing the master password, importing and
+//! restoring a backup take more than one step: the vault is backed up and then changed. They hold
+//! an advisory lock on a `.lock` file next to the vault while they run, so that the app, the CLI
+//! and the agent take turns. Single edits are one transaction and don't take it.
+
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::services::database::{DatabaseService, QueryError};
+
+const LOCK_SUFFIX: &str = ".lock";
+/// How long to wait for another process to finish changing the vault.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Held while the vault is being changed, the lock is released when it's dropped.
+#[derive(Debug)]
+pub struct VaultLock {
+    _file: Option<File>,
+}
+
+/// Wait for other processes to finish changing the vault and lock it for this one.
+pub async fn lock_vault(db_service: &DatabaseService) -> Result<VaultLock, QueryError> {
+    let path = lock_path(&db_service.path);
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .map_err(|err| QueryError::AccessDenied(format!("{}: {err}", path.display())))?;
+
+    let started = Instant::now();
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(VaultLock { _file: Some(file) }),
+            Err(TryLockError::WouldBlock) if started.elapsed() < LOCK_TIMEOUT => {
+                tokio::time::sleep(RETRY_INTERVAL).await;
+            }
+            Err(TryLockError::WouldBlock) => return Err(QueryError::Locked),
+            // Some network file systems can't lock files, SQLite still keeps each step safe.
+            Err(TryLockError::Error(err)) if err.kind() == ErrorKind::Unsupported => {
+                return Ok(VaultLock { _file: None });
+            }
+            Err(TryLockError::Error(err)) => {
+                return Err(QueryError::Other(format!(
+                    "Could not lock {}: {err}",
+                    path.display()
+                )));
+            }
+        }
+    }
+}
+
+fn lock_path(db_path: &Path) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push(LOCK_SUFFIX);
+    PathBuf::from(path)
+}
@@ -0,0 +1,230 @@
+//! The vaults the app knows about. Each vault is a database file of its own, anywhere on disk,
+//! with its own master password. The list is kept in the data directory next to the default
+//! vault, which is always on it.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::services::database::{self, DatabaseError, DatabaseService};
+
+pub const REGISTRY_FILE_NAME: &str = "vaults.json";
+pub const DEFAULT_VAULT_NAME: &str = "Default";
+const VAULT_EXTENSION: &str = "sqlite";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VaultInfo {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VaultRegistry {
+    pub vaults: Vec<VaultInfo>,
+    /// The name of the vault the app opened last, which it opens again on start.
+    pub last_used: Option<String>,
+}
+
+impl VaultRegistry {
+    /// A list with only the default vault.
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            vaults: vec![default_vault(data_dir)],
+            last_used: None,
+        }
+    }
+
+    /// Read the list from the data directory, starting a new one when there isn't one yet.
+    pub fn load(data_dir: &Path) -> Result<Self, String> {
+        let path = data_dir.join(REGISTRY_FILE_NAME);
+        let mut registry: Self = match std::fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|err| format!("{} is not a valid vault list: {err}", path.display()))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self::new(data_dir))
+            }
+            Err(err) => return Err(err.to_string()),
+        };
+        for vault in &mut registry.vaults {
+            vault.path = data_dir.join(&vault.path);
+        }
+
+        // The default vault always comes first.
+        let default_vault = match registry
+            .vaults
+            .iter()
+            .position(|vault| vault.path == data_dir.join(database::DATABASE_FILE_NAME))
+        {
+            Some(index) => registry.vaults.remove(index),
+            None => default_vault(data_dir),
+        };
+        registry.vaults.insert(0, default_vault);
+
+        Ok(registry)
+    }
+
+    /// Write the list, replacing the file only once it's been written in full. Vaults inside the
+    /// data directory are saved relative to it, so that they're still found when it's moved, e.g.
+    /// a portable copy on a drive mounted somewhere else.
+    pub fn save(&self, data_dir: &Path) -> Result<(), String> {
+        let mut registry = self.clone();
+        for vault in &mut registry.vaults {
+            if let Ok(relative) = vault.path.strip_prefix(data_dir) {
+                vault.path = relative.to_path_buf();
+            }
+        }
+
+        let data = serde_json::to_vec_pretty(&registry).map_err(|err| err.to_string())?;
+        let path = data_dir.join(REGISTRY_FILE_NAME);
+        let temp_path = path.with_extension("json.tmp");
+
+        std::fs::create_dir_all(data_dir).map_err(|err| err.to_string())?;
+        std::fs::write(&temp_path, data).map_err(|err| err.to_string())?;
+        std::fs::rename(&temp_path, &path).map_err(|err| err.to_string())
+    }
+
+    /// A vault by name, ignoring case.
+    pub fn find(&self, name: &str) -> Option<&VaultInfo> {
+        self.vaults
+            .iter()
+            .find(|vault| vault.name.eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// The vault opened last, or the default one.
+    pub fn current(&self) -> &VaultInfo {
+        self.last_used
+            .as_deref()
+            .and_then(|name| self.find(name))
+            .unwrap_or(&self.vaults[0])
+    }
+
+    /// Add a vault to the list. The file doesn't need to exist yet, a path without an extension
+    /// gets `.sqlite`.
+    pub fn add(&mut self, name: &str, path: &Path) -> Result<VaultInfo, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("The vault needs a name".into());
+        }
+        if self.find(name).is_some() {
+            return Err(format!("There already is a vault called \"{name}\""));
+        }
+        if path.as_os_str().is_empty() || path.is_dir() {
+            return Err("Choose a file for the vault, not a folder".into());
+        }
+
+        let path = if path.extension().is_none() {
+            path.with_extension(VAULT_EXTENSION)
+        } else {
+            path.to_path_buf()
+        };
+        if let Some(existing) = self.vaults.iter().find(|vault| vault.path == path) {
+            return Err(format!(
+                "{} is already the vault \"{}\"",
+                path.display(),
+                existing.name
+            ));
+        }
+
+        let vault = VaultInfo {
+            name: name.to_string(),
+            path,
+        };
+        self.vaults.push(vault.clone());
+        Ok(vault)
+    }
+
+    /// The vault with the file at `path`, which is added to the list under the file's name when
+    /// it isn't on it yet.
+    pub fn find_or_add_file(&mut self, path: &Path) -> Result<VaultInfo, String> {
+        if let Some(vault) = self.vaults.iter().find(|vault| vault.path == path) {
+            return Ok(vault.clone());
+        }
+
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "Vault".into());
+        let mut name = stem.clone();
+        let mut number = 2;
+        while self.find(&name).is_some() {
+            name = format!("{stem} ({number})");
+            number += 1;
+        }
+
+        self.add(&name, path)
+    }
+}
+
+fn default_vault(data_dir: &Path) -> VaultInfo {
+    VaultInfo {
+        name: DEFAULT_VAULT_NAME.into(),
+        path: data_dir.join(database::DATABASE_FILE_NAME),
+    }
+}
+
+/// Where a new vault called `name` goes unless another path is chosen.
+pub fn suggested_path(data_dir: &Path, name: &str) -> PathBuf {
+    let file_name: String = name
+        .trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect();
+    let file_name = file_name.trim_matches('-');
+
+    data_dir
+        .join(if file_name.is_empty() {
+            "vault"
+        } else {
+            file_name
+        })
+        .with_extension(VAULT_EXTENSION)
+}
+
+/// Open a vault whose file already exists.
+pub async fn open_vault(vault: &VaultInfo) -> Result<DatabaseService, DatabaseError> {
+    DatabaseService::open(vault.path.clone()).await
+}
+
+/// Open a vault, creating its file first when it doesn't exist yet.
+pub async fn create_vault(vault: &VaultInfo) -> Result<DatabaseService, DatabaseError> {
+    DatabaseService::create(vault.path.clone()).await
+}
+
+/// Open a vault on the list the way the app does on start. The default vault is created the first
+/// time it's used, the others have to exist.
+pub async fn open_listed_vault(
+    registry: &VaultRegistry,
+    vault: &VaultInfo,
+) -> Result<DatabaseService, DatabaseError> {
+    if vault.path == registry.vaults[0].path {
+        create_vault(vault).await
+    } else {
+        open_vault(vault).await
+    }
+}
+
+/// Open a vault by name for the command-line tools, the default vault when there's no name.
+pub async fn open_named_vault(
+    data_dir: &Path,
+    name: Option<&str>,
+) -> Result<DatabaseService, String> {
+    let registry = VaultRegistry::load(data_dir)?;
+    let vault = match name {
+        Some(name) => registry.find(name).ok_or_else(|| {
+            let names: Vec<&str> = registry
+                .vaults
+                .iter()
+                .map(|vault| vault.name.as_str())
+                .collect();
+            format!(
+                "There is no vault called \"{name}\", the vaults are: {}",
+                names.join(", ")
+            )
+        })?,
+        None => &registry.vaults[0],
+    };
+
+    Ok(open_listed_vault(&registry, vault).await?)
+}
@@ -0,0 +1,176 @@
+use std::sync::Arc;
+
+use dioxus::prelude::*;
+use dioxus_primitives::{scroll_area::ScrollDirection, toast::use_toast};
+
+use crate::{
+    components::{error_options, Card, Input, ScrollArea},
+    models::{format_timestamp, AuthState},
+    routes::Route,
+    services::{
+        audit::{self, AuditAction, AuditFilter, AuditLog, AuditRecord},
+        database::DatabaseService,
+        error::ServiceError,
+    },
+};
+
+/// The page that shows the activity log, rendered when the current route is `[Route::Activity]`.
+/// The log's chain is checked each time it's loaded.
+#[component]
+pub fn Activity() -> Element {
+    let auth_state = use_context::<Signal<AuthState>>();
+    let db_service = use_context::<Arc<DatabaseService>>();
+    let db_service = use_signal(|| db_service.clone());
+    let navigator = use_navigator();
+    let toast_api = use_toast();
+
+    if !auth_state().signed_in {
+        navigator.replace(Route::home());
+    }
+
+    let mut log = use_signal(|| None::<AuditLog>);
+    let mut filter = use_signal(AuditFilter::default);
+
+    use_future(move || async move {
+        match audit::load_log(&auth_state(), db_service().as_ref()).await {
+            Ok(loaded) => log.set(Some(loaded)),
+            Err(err) => toast_api.error(err.title().into(), error_options(&err)),
+        }
+    });
+
+    // Newest first.
+    let shown = use_memo(move || {
+        log.read()
+            .as_ref()
+            .map(|log| {
+                log.records
+                    .iter()
+                    .rev()
+                    .filter(|record| filter.read().matches(record))
+                    .cloned()
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default()
+    });
+
+    rsx! {
+        div { style: "width: 100%; display: flex; justify-content: center;",
+            Card {
+                title: "Activity",
+                width: "100%",
+                height: "calc(100vh - 110px)",
+
+                match log() {
+                    None => rsx! {
+                        div { style: "color: #aaa; text-align: center;", "Loading activity..." }
+                    },
+                    Some(log) => rsx! {
+                        if log.is_intact() {
+                            div { style: "color: var(--secondary-success-color); font-size: 0.9rem; margin-bottom: 0.5rem;",
+                                "The log is intact, {log.records.len()} events were recorded."
+                            }
+                        } else {
+                            div { style: "
+                                    border: 1px solid var(--primary-error-color);
+                                    border-radius: 12px;
+                                    padding: 0.4rem 1rem;
+                                    margin-bottom: 0.5rem;
+                                    font-size: 0.85rem;
+                                    color: #ccc;
+                                ",
+                                strong { style: "color: var(--primary-error-color);",
+                                    "The log has been tampered with"
+                                }
+                                for problem in log.problems.iter() {
+                                    div { "{problem}" }
+                                }
+                            }
+                        }
+
+                        div { style: "display: flex; align-items: center; gap: 1rem; margin-bottom: 0.5rem;",
+                            select {
+                                name: "action",
+                                onchange: move |evt: FormEvent| {
+                                    filter.write().action = AuditAction::from_key(&evt.value());
+                                },
+                                option { value: "", selected: filter.read().action.is_none(), "All events" }
+                                for action in AuditAction::ALL {
+                                    option {
+                                        value: action.key(),
+                                        selected: filter.read().action == Some(action),
+                                        {action.label()}
+                                    }
+                                }
+                            }
+                            Input {
+                                name: "search",
+                                placeholder: "Filter by site or detail",
+                                value: filter.read().text.clone(),
+                                value_changed: move |evt: FormEvent| filter.write().text = evt.value(),
+                                style: "width: 280px;",
+                            }
+                        }
+
+                        ScrollArea {
+                            height: "calc(100vh - 300px)",
+                            min_height: "200px",
+                            padding: "0 1.2em 1.2em 1.2em",
+                            direction: ScrollDirection::Vertical,
+                            tabindex: "0",
+                            style: "
+                                border: 1px solid #444;
+                                border-radius: 12px;
+                                background-color: #1b1b1b;
+                            ",
+                            div { class: "scroll-content", style: "padding-top: 15px;",
+                                if shown.read().is_empty() {
+                                    div { style: "color: #aaa; text-align: center;", "No events match." }
+                                }
+                                for record in shown.read().iter() {
+                                    ActivityRow { key: "{record.sequence}", record: record.clone() }
+                                }
+                            }
+                        }
+                    },
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn ActivityRow(record: AuditRecord) -> Element {
+    let subject = match (record.event.site.is_empty(), record.event.entry_id) {
+        (false, _) => record.event.site.clone(),
+        (true, Some(id)) => format!("Entry {id}"),
+        (true, None) => String::new(),
+    };
+    let action = record.event.action.label();
+    let recorded_at = format_timestamp(record.recorded_at);
+
+    rsx! {
+        div {
+            style: "
+                background: #1e1e1e;
+                border: 1px solid #2a2a2a;
+                border-radius: 12px;
+                padding: 0.5rem 1.2rem;
+                margin-bottom: 0.4rem;
+                display: flex;
+                justify-content: space-between;
+                align-items: center;
+                gap: 1rem;
+            ",
+            div {
+                strong { style: "font-size: 0.95rem; color: #f0f0f0;", "{action}" }
+                if !subject.is_empty() {
+                    span { style: "font-size: 0.9rem; color: #ccc; margin-left: 0.5rem;", "{subject}" }
+                }
+                if !record.event.detail.is_empty() {
+                    div { style: "font-size: 0.8rem; color: #aaa;", "{record.event.detail}" }
+                }
+            }
+            div { style: "font-size: 0.8rem; color: #aaa; white-space: nowrap;", "{recorded_at}" }
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use std::sync::Arc;
+
+use dioxus::prelude::*;
+use dioxus_primitives::toast::{use_toast, ToastOptions};
+
+use crate::{
+    components::{error_options, Button, ButtonVariant, Card, Field, FieldGroup, Input},
+    models::{AuthState, Settings},
+    routes::Route,
+    services::{
+        breach_check,
+        database::DatabaseService,
+        error::ServiceError,
+        password_strength::{self, MAX_SCORE},
+        settings,
+    },
+    DATA_DIR,
+};
+
+/// The settings page component that will be rendered when the current route is `[Route::AppSettings]`
+#[component]
+pub fn AppSettings() -> Element {
+    let auth_state = use_context::<Signal<AuthState>>();
+    let db_service = use_context::<Arc<DatabaseService>>();
+    let db_service = use_signal(|| db_service.clone());
+    let navigator = use_navigator();
+    let toast_api = use_toast();
+
+    if !auth_state().signed_in {
+        navigator.replace(Route::home());
+    }
+
+    let mut current = use_signal(Settings::default);
+
+    use_future(move || async move {
+        match settings::get_settings(db_service().as_ref()).await {
+            Ok(loaded) => current.set(loaded),
+            Err(err) => toast_api.error(err.title().into(), error_options(&err)),
+        }
+    });
+
+    // The Pwned Passwords dataset to build the breach index from
+    let mut dataset_path = use_signal(|| "".to_string());
+    let mut building_index = use_signal(|| false);
+
+    let build_breach_index = move || {
+        spawn(async move {
+            building_index.set(true);
+            let source = std::path::PathBuf::from(dataset_path());
+            let index_path = DATA_DIR.get().unwrap().join(breach_check::INDEX_FILE_NAME);
+            let target = index_path.clone();
+
+            let result =
+                tokio::task::spawn_blocking(move || breach_check::build_index(&source, &target))
+                    .await
+                    .map_err(|err| err.to_string())
+                    .and_then(|result| result);
+
+            match result {
+                Ok(count) => {
+                    current.write().breach_index_path = index_path.display().to_string();
+                    match settings::save_settings(&current(), db_service().as_ref()).await {
+                        Ok(()) => toast_api.success(
+                            "Breach index built".into(),
+                            ToastOptions::new().description(format!("Indexed {count} hashes.")),
+                        ),
+                        Err(err) => toast_api.error(err.title().into(), error_options(&err)),
+                    }
+                }
+                Err(err) => toast_api.error(
+                    "Error".into(),
+                    ToastOptions::new()
+                        .description(format!("Could not build the breach index: {err}"))
+                        .permanent(true),
+                ),
+            }
+            building_index.set(false);
+        });
+    };
+
+    let save = move || {
+        spawn(async move {
+            match settings::save_settings(&current(), db_service().as_ref()).await {
+                Ok(()) => toast_api.success("Saved".into(), ToastOptions::new()),
+                Err(err) => toast_api.error(err.title().into(), error_options(&err)),
+            }
+        });
+    };
+
+    rsx! {
+        div { style: "display: flex; justify-content: center; padding: 0;",
+            Card { title: "Settings",
+                FieldGroup {
+                    Field { label: "Minimum master password strength",
+                        select {
+                            name: "min_master_password_score",
+                            onchange: move |evt: FormEvent| {
+                                if let Ok(score) = evt.value().parse::<u8>() {
+                                    current.write().min_master_password_score = score;
+                                }
+                            },
+                            for score in 0..=MAX_SCORE {
+                                option {
+                                    value: "{score}",
+                                    selected: current().min_master_password_score == score,
+                                    {password_strength::score_label(score)}
+                                }
+                            }
+                        }
+                    }
+
+                    Field { label: "Flag passwords older than (months)",
+                        Input {
+                            name: "max_password_age_months",
+                            r#type: "number",
+                            min: "1",
+                            value: current().max_password_age_months.to_string(),
+                            value_changed: move |evt: FormEvent| {
+                                if let Ok(months) = evt.value().parse::<u32>() {
+                                    current.write().max_password_age_months = months.max(1);
+                                }
+                            },
+                        }
+                    }
+
+                    Field { label: "Automatic backups to keep",
+                        Input {
+                            name: "backup_retention",
+                            r#type: "number",
+                            min: "0",
+                            value: current().backup_retention.to_string(),
+                            value_changed: move |evt: FormEvent| {
+                                if let Ok(count) = evt.value().parse::<u32>() {
+                                    current.write().backup_retention = count;
+                                }
+                            },
+                        }
+                        small { style: "color: #aaa;",
+                            "The vault is backed up when it's unlocked, before the master password is changed and before imports. Set to 0 to turn backups off."
+                        }
+                    }
+
+                    Field { label: "Pwned Passwords dataset (range file directory or ordered-by-hash file)",
+                        div { style: "display: flex; gap: 0.3rem;",
+                            Input {
+                                name: "breach_dataset_path",
+                                placeholder: "/path/to/pwnedpasswords",
+                                value: dataset_path(),
+                                value_changed: move |evt: FormEvent| dataset_path.set(evt.value()),
+                                style: "flex: 1;",
+                            }
+                            Button {
+                                variant: ButtonVariant::Ghost,
+                                disabled: building_index() || dataset_path().is_empty(),
+                                onclick: move |_| build_breach_index(),
+                                if building_index() {
+                                    "Building..."
+                                } else {
+                                    "Build Index"
+                                }
+                            }
+                        }
+                        small { style: "color: #aaa;",
+                            if current().breach_index_path.is_empty() {
+                                "Breach checking is disabled."
+                            } else {
+                                "Breach checking uses {current().breach_index_path}"
+                            }
+                        }
+                        if !current().breach_index_path.is_empty() {
+                            div {
+                                Button {
+                                    variant: ButtonVariant::Ghost,
+                                    onclick: move |_| current.write().breach_index_path.clear(),
+                                    "Disable Breach Checking"
+                                }
+                            }
+                        }
+                    }
+                }
+
+                div { style: "display: flex; justify-content: flex-end; gap: 0.3rem; margin: 0;",
+                    Button {
+                        variant: ButtonVariant::Ghost,
+                        onclick: move |_| save(),
+                        "Save"
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use std::sync::Arc;
+
+use dioxus::prelude::*;
+use dioxus_primitives::toast::{use_toast, ToastOptions};
+
+use crate::{
+    components::{
+        error_options, AlertDialogAction, AlertDialogActions, AlertDialogCancel,
+        AlertDialogContent, AlertDialogDescription, AlertDialogRoot, AlertDialogTitle, Button,
+        ButtonVariant, Card,
+    },
+    models::{format_timestamp, AuthState},
+    routes::Route,
+    services::{
+        database::{DatabaseService, SchemaStatus},
+        error::ServiceError,
+        integrity::{self, IntegrityReport, QuarantinedEntry},
+        password_entry::EntryProblem,
+    },
+};
+
+/// The page that checks the vault for damage, rendered when the current route is
+/// `[Route::CheckVault]`. Entries that can't be read can be moved out of the vault so that the
+/// rest of it can be used, and restored or discarded later.
+#[component]
+pub fn CheckVault() -> Element {
+    let auth_state = use_context::<Signal<AuthState>>();
+    let db_service = use_context::<Arc<DatabaseService>>();
+    let db_service = use_signal(|| db_service.clone());
+    let navigator = use_navigator();
+    let toast_api = use_toast();
+
+    if !auth_state().signed_in {
+        navigator.replace(Route::home());
+    }
+
+    let mut report = use_signal(|| None::<IntegrityReport>);
+    let mut working = use_signal(|| false);
+    let mut confirmation_open = use_signal(|| false);
+    let mut discarding = use_signal(|| None::<QuarantinedEntry>);
+
+    let mut check = move || {
+        spawn(async move {
+            working.set(true);
+            match integrity::check_vault(&auth_state(), db_service().as_ref()).await {
+                Ok(checked) => report.set(Some(checked)),
+                Err(err) => toast_api.error(err.title().into(), error_options(&err)),
+            }
+            working.set(false);
+        });
+    };
+
+    use_hook(move || check());
+
+    let quarantine = move || {
+        spawn(async move {
+            let Some(problems) = report().map(|report| report.entry_problems) else {
+                return;
+            };
+            working.set(true);
+
+            let result =
+                integrity::quarantine_entries(&problems, &auth_state(), db_service().as_ref())
+                    .await;
+            working.set(false);
+            match result {
+                Ok(moved) => {
+                    toast_api.success(
+                        "Quarantined".into(),
+                        ToastOptions::new()
+                            .description(format!("{moved} entries were moved out of the vault.")),
+                    );
+                    check();
+                }
+                Err(err) => toast_api.error(err.title().into(), error_options(&err)),
+            }
+        });
+    };
+
+    let restore = move |id: i32| {
+        spawn(async move {
+            working.set(true);
+            let result =
+                integrity::restore_quarantined_entry(id, &auth_state(), db_service().as_ref())
+                    .await;
+            working.set(false);
+            match result {
+                Ok(_) => {
+                    toast_api.success(
+                        "Restored".into(),
+                        ToastOptions::new().description("The entry is back in the vault.".into()),
+                    );
+                    check();
+                }
+                Err(err) => toast_api.error(err.title().into(), error_options(&err)),
+            }
+        });
+    };
+
+    let discard = move || {
+        // Read before the dialog closes and clears it.
+        let Some(entry) = discarding() else {
+            return;
+        };
+        spawn(async move {
+            working.set(true);
+            let result = integrity::discard_quarantined_entry(
+                entry.id,
+                &auth_state(),
+                db_service().as_ref(),
+            )
+            .await;
+            working.set(false);
+            match result {
+                Ok(()) => check(),
+                Err(err) => toast_api.error(err.title().into(), error_options(&err)),
+            }
+        });
+    };
+
+    rsx! {
+        div { style: "width: 100%; display: flex; justify-content: center;",
+            Card { title: "Check Vault",
+                div { style: "color: #ccc; font-size: 0.9rem; margin-bottom: 0.5rem;",
+                    "Checks the vault file for damage, that it was upgraded for this version of the app and that every entry can be decrypted with the master password."
+                }
+
+                match report() {
+                    None => rsx! {
+                        div { style: "color: #aaa; text-align: center;", "Checking vault..." }
+                    },
+                    Some(report) => rsx! {
+                        CheckSection {
+                            title: "Vault file",
+                            ok: report.database_problems.is_empty(),
+                            for problem in report.database_problems.iter() {
+                                div { style: "font-size: 0.85rem; color: #ccc;", "{problem}" }
+                            }
+                        }
+
+                        CheckSection { title: "Schema", ok: report.schema.is_ok(),
+                            SchemaDetails { schema: report.schema.clone() }
+                        }
+
+                        CheckSection {
+                            title: format!("Entries ({} checked)", report.entries_checked),
+                            ok: report.entry_problems.is_empty(),
+                            for problem in report.entry_problems.iter() {
+                                EntryProblemRow { problem: problem.clone() }
+                            }
+                        }
+
+                        if !report.quarantined.is_empty() {
+                            CheckSection {
+                                title: format!("Quarantine ({} entries)", report.quarantined.len()),
+                                ok: true,
+                                div { style: "font-size: 0.85rem; color: #aaa;",
+                                    "Moved out of the vault by earlier checks. An entry that decrypts with the master password can be restored, the others stay encrypted with the master password they had when they were moved, even after it's changed."
+                                }
+                                for entry in report.quarantined.iter() {
+                                    QuarantinedRow {
+                                        key: "{entry.id}",
+                                        entry: entry.clone(),
+                                        working: working(),
+                                        on_restore: move |id| restore(id),
+                                        on_discard: move |entry| discarding.set(Some(entry)),
+                                    }
+                                }
+                            }
+                        }
+
+                        div { style: "display: flex; justify-content: flex-end; gap: 0.3rem; margin: 0;",
+                            Button {
+                                variant: ButtonVariant::Ghost,
+                                disabled: working(),
+                                onclick: move |_| check(),
+                                if working() {
+                                    "Checking..."
+                                } else {
+                                    "Check Again"
+                                }
+                            }
+                            if !report.entry_problems.is_empty() {
+                                Button {
+                                    variant: ButtonVariant::Destructive,
+                                    disabled: working(),
+                                    onclick: move |_| confirmation_open.set(true),
+                                    "Quarantine {report.entry_problems.len()} Entries"
+                                }
+                            }
+                        }
+                    },
+                }
+
+                AlertDialogRoot {
+                    open: confirmation_open(),
+                    on_open_change: move |v| confirmation_open.set(v),
+                    AlertDialogContent {
+                        AlertDialogTitle { "Quarantine entries" }
+                        AlertDialogDescription {
+                            "The entries that can't be read are moved out of the vault, so that the rest of it can be used. They're kept in the vault file as they are. If the vault is synced, the next sync brings back the copies the other devices have."
+                        }
+                        AlertDialogActions {
+                            AlertDialogCancel { "Cancel" }
+                            AlertDialogAction {
+                                on_click: move |_| {
+                                    quarantine();
+                                },
+                                "Quarantine"
+                            }
+                        }
+                    }
+                }
+
+                AlertDialogRoot {
+                    open: discarding().is_some(),
+                    on_open_change: move |open: bool| {
+                        if !open {
+                            discarding.set(None);
+                        }
+                    },
+                    AlertDialogContent {
+                        AlertDialogTitle { "Discard quarantined entry" }
+                        AlertDialogDescription {
+                            "The entry is deleted from the vault file for good. Copies of it on other devices are kept."
+                        }
+                        AlertDialogActions {
+                            AlertDialogCancel { "Cancel" }
+                            AlertDialogAction {
+                                on_click: move |_| {
+                                    discard();
+                                },
+                                "Discard"
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn CheckSection(title: String, ok: bool, children: Element) -> Element {
+    let (status, color) = if ok {
+        ("OK", "#7bd88f")
+    } else {
+        ("Problems found", "var(--primary-error-color)")
+    };
+
+    rsx! {
+        div { style: "
+                background: #1e1e1e;
+                border: 1px solid #2a2a2a;
+                border-radius: 12px;
+                padding: 0.6rem 1rem;
+                margin-bottom: 0.5rem;
+            ",
+            div { style: "display: flex; justify-content: space-between;",
+                strong { style: "font-size: 0.95rem; color: #f0f0f0;", "{title}" }
+                span { style: "font-size: 0.85rem; color: {color};", "{status}" }
+            }
+            {children}
+        }
+    }
+}
+
+#[component]
+fn SchemaDetails(schema: SchemaStatus) -> Element {
+    let version = schema
+        .version
+        .map(|version| version.to_string())
+        .unwrap_or_else(|| "none".into());
+    let details: Vec<String> = [
+        ("Not applied", &schema.missing),
+        ("From a newer version of the app", &schema.unknown),
+        ("Failed or changed", &schema.broken),
+    ]
+    .into_iter()
+    .filter(|(_, versions)| !versions.is_empty())
+    .map(|(label, versions)| {
+        let versions: Vec<String> = versions.iter().map(i64::to_string).collect();
+        format!("{label}: {}", versions.join(", "))
+    })
+    .collect();
+
+    rsx! {
+        div { style: "font-size: 0.85rem; color: #ccc;",
+            "Version {version}, this version of the app has {schema.expected}."
+        }
+        for detail in details {
+            div { style: "font-size: 0.85rem; color: #ccc;", "{detail}" }
+        }
+    }
+}
+
+#[component]
+fn EntryProblemRow(problem: EntryProblem) -> Element {
+    let name = match (problem.site.is_empty(), problem.username.is_empty()) {
+        (true, _) => format!("Entry {}", problem.id),
+        (false, true) => problem.site.clone(),
+        (false, false) => format!("{} ({})", problem.site, problem.username),
+    };
+
+    rsx! {
+        div { style: "margin-top: 0.4rem;",
+            div { style: "font-size: 0.9rem; color: #f0f0f0;", "{name}" }
+            div { style: "font-size: 0.85rem; color: #aaa;",
+                "{problem.error} ({problem.error.code()})"
+            }
+        }
+    }
+}
+
+#[component]
+fn QuarantinedRow(
+    entry: QuarantinedEntry,
+    working: bool,
+    on_restore: Callback<i32>,
+    on_discard: Callback<QuarantinedEntry>,
+) -> Element {
+    let name = match (entry.site.is_empty(), entry.username.is_empty()) {
+        (true, _) => format!("Entry {}", entry.entry_id),
+        (false, true) => entry.site.clone(),
+        (false, false) => format!("{} ({})", entry.site, entry.username),
+    };
+    let state = match (entry.restorable, entry.in_vault) {
+        (_, true) => "Back in the vault through sync",
+        (true, false) => "Can be restored",
+        (false, false) => "Encrypted with another master password or damaged",
+    };
+    let quarantined_at = format_timestamp(entry.quarantined_at);
+    let id = entry.id;
+
+    rsx! {
+        div { style: "display: flex; justify-content: space-between; align-items: center; gap: 0.5rem; margin-top: 0.4rem;",
+            div {
+                div { style: "font-size: 0.9rem; color: #f0f0f0;", "{name}" }
+                div { style: "font-size: 0.85rem; color: #aaa;",
+                    "{entry.reason}, quarantined {quarantined_at}. {state}."
+                }
+            }
+            div { style: "display: flex; gap: 0.3rem;",
+                if entry.restorable {
+                    Button {
+                        variant: ButtonVariant::Ghost,
+                        disabled: working,
+                        onclick: move |_| on_restore.call(id),
+                        "Restore"
+                    }
+                }
+                Button {
+                    variant: ButtonVariant::Destructive,
+                    disabled: working,
+                    onclick: move |_| on_discard.call(entry.clone()),
+                    "Discard"
+                }
+            }
+        }
+    }
+}
@@ -1,16 +1,19 @@
 use std::sync::Arc;
 
 use crate::{
-    components::{Button, ButtonVariant, PasswordInput},
-    models::AuthState,
+    components::{error_options, Button, ButtonVariant, PasswordInput, StrengthMeter},
+    models::{AuthState, Settings},
     routes::Route,
     services::{
         authentication::{self},
         database::DatabaseService,
+        error::ServiceError,
+        password_strength, settings,
     },
+    views::VaultSwitcher,
 };
 use dioxus::prelude::*;
-use dioxus_primitives::toast::{use_toast, ToastOptions};
+use dioxus_primitives::toast::use_toast;
 use zeroize::Zeroizing;
 
 /// The CreateMasterPassword page component that will be rendered when the current route is `[Route::CreateMasterPassword]`
@@ -31,6 +34,13 @@ pub fn CreateMasterPassword() -> Element {
     let navigator = use_navigator();
     let toast_api = use_toast();
 
+    let min_score = use_resource(move || async move {
+        settings::get_settings(&db_service())
+            .await
+            .unwrap_or_default()
+            .min_master_password_score
+    });
+
     let set_master_password = move || {
         spawn(async move {
             match authentication::set_master_password(password(), &state(), &db_service()).await {
@@ -40,10 +50,7 @@ pub fn CreateMasterPassword() -> Element {
                     state.set(auth_state);
                     navigator.replace(Route::vault());
                 }
-                Err(e) => toast_api.error(
-                    "Error".into(),
-                    ToastOptions::new().description(e).permanent(true),
-                ),
+                Err(err) => toast_api.error(err.title().into(), error_options(&err)),
             }
         })
     };
@@ -71,8 +78,20 @@ pub fn CreateMasterPassword() -> Element {
                         show_error.set(true);
                         return;
                     }
+                    let min_score = min_score()
+                        .unwrap_or(Settings::default().min_master_password_score);
+                    let score = password_strength::estimate(&password()).score;
+                    if score < min_score {
+                        error_message.set(format!(
+                            "password is too weak, it must be at least \"{}\"",
+                            password_strength::score_label(min_score).to_lowercase()
+                        ));
+                        show_error.set(true);
+                        return;
+                    }
                     set_master_password();
                 },
+                VaultSwitcher {}
                 PasswordInput {
                     style: "width: 200px",
                     name: "master_password",
@@ -83,6 +102,7 @@ pub fn CreateMasterPassword() -> Element {
                         password.set(Zeroizing::new(evt.value()));
                     },
                 }
+                StrengthMeter { password: password() }
                 div {
                     PasswordInput {
                         style: "width: 200px",
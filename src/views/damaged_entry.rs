@@ -0,0 +1,247 @@
+use std::sync::Arc;
+
+use dioxus::prelude::*;
+use dioxus_primitives::toast::{use_toast, ToastOptions};
+
+use crate::{
+    components::{
+        error_options, AlertDialogAction, AlertDialogActions, AlertDialogCancel,
+        AlertDialogContent, AlertDialogDescription, AlertDialogRoot, AlertDialogTitle, Button,
+        ButtonVariant, Card, Field, FieldGroup, Input, TextArea,
+    },
+    models::AuthState,
+    routes::Route,
+    services::{
+        database::DatabaseService,
+        error::ServiceError,
+        password_entry::{self, DamagedEntry, EntryError, PlainFields, StoredColumn},
+    },
+};
+
+/// The page for an entry that can't be read or decrypted, rendered when the current route is
+/// `[Route::DamagedEntryDetails]`. Shows what's stored for the entry and lets its plain fields be
+/// repaired or the entry deleted.
+#[component]
+pub fn DamagedEntryDetails(id: i32) -> Element {
+    let auth_state = use_context::<Signal<AuthState>>();
+    let db_service = use_context::<Arc<DatabaseService>>();
+    let db_service = use_signal(|| db_service.clone());
+    let navigator = use_navigator();
+    let toast_api = use_toast();
+
+    if !auth_state().signed_in {
+        navigator.replace(Route::home());
+    }
+
+    let mut entry = use_signal(|| None::<DamagedEntry>);
+
+    // The values of the fields that can still be edited.
+    let mut new_site = use_signal(|| "".to_string());
+    let mut new_username = use_signal(|| "".to_string());
+    let mut new_urls = use_signal(|| "".to_string());
+    let mut new_tags = use_signal(|| "".to_string());
+
+    // Used for the delete confirmation dialog
+    let mut confirmation_open = use_signal(|| false);
+
+    let mut load_entry = move || {
+        spawn(async move {
+            match password_entry::get_damaged_entry(id, &auth_state(), db_service().as_ref()).await
+            {
+                // Nothing is wrong with it (anymore), show it as usual.
+                Ok(DamagedEntry { error: None, .. }) => {
+                    navigator.replace(Route::password_details(id));
+                }
+                Ok(damaged) => {
+                    new_site.set(damaged.fields.site.clone());
+                    new_username.set(damaged.fields.username.clone());
+                    new_urls.set(password_entry::join_urls(&damaged.fields.urls));
+                    new_tags.set(password_entry::join_tags(&damaged.fields.tags));
+                    entry.set(Some(damaged));
+                }
+                Err(EntryError::NotSignedIn) => {
+                    navigator.replace(Route::home());
+                }
+                Err(err @ EntryError::NotFound(_)) => {
+                    toast_api.error(
+                        err.title().into(),
+                        ToastOptions::new().description(err.to_string()),
+                    );
+                    navigator.replace(Route::vault());
+                }
+                Err(err) => toast_api.error(err.title().into(), error_options(&err)),
+            }
+        });
+    };
+
+    use_hook(move || load_entry());
+
+    let save_fields = move || {
+        spawn(async move {
+            let fields = PlainFields {
+                site: new_site(),
+                username: new_username(),
+                urls: password_entry::parse_urls(&new_urls()),
+                tags: password_entry::parse_tags(&new_tags()),
+            };
+
+            match password_entry::update_plain_fields(
+                id,
+                &fields,
+                &auth_state(),
+                db_service().as_ref(),
+            )
+            .await
+            {
+                Ok(()) => {
+                    toast_api.success(
+                        "Saved".into(),
+                        ToastOptions::new().description("The entry's fields were saved."),
+                    );
+                    load_entry();
+                }
+                Err(err) => toast_api.error(err.title().into(), error_options(&err)),
+            }
+        });
+    };
+
+    let delete_entry = move || {
+        spawn(async move {
+            match password_entry::delete_password(id, &auth_state(), db_service().as_ref()).await {
+                // Deleted in the meantime, e.g. from the CLI.
+                Ok(()) | Err(EntryError::NotFound(_)) => {
+                    navigator.replace(Route::vault());
+                }
+                Err(err) => toast_api.error(err.title().into(), error_options(&err)),
+            }
+        });
+    };
+
+    let Some(damaged) = entry() else {
+        return rsx! {
+            div { style: "display: flex; justify-content: center; padding: 0;",
+                Card { title: "Damaged Entry",
+                    div { style: "color: #aaa; text-align: center;", "Loading entry..." }
+                }
+            }
+        };
+    };
+
+    rsx! {
+        div { style: "display: flex; justify-content: center; padding: 0;",
+
+            Card { title: "Damaged Entry",
+
+                if let Some(error) = damaged.error.as_ref() {
+                    div { style: "
+                            border: 1px solid var(--primary-error-color);
+                            border-radius: 12px;
+                            padding: 0.6rem 1rem;
+                            margin-bottom: 0.5rem;
+                        ",
+                        strong { style: "font-size: 0.95rem; color: #f0f0f0;", "{error.title()}" }
+                        div { style: "font-size: 0.85rem; color: #ccc;", "{error} ({error.code()})" }
+                        div { style: "font-size: 0.85rem; color: #aaa; margin-top: 0.3rem;",
+                            "The password, notes and other encrypted details can't be shown. The fields below are stored unencrypted and can still be edited."
+                        }
+                    }
+                }
+
+                form { style: "display: flex; flex-direction: column",
+                    FieldGroup {
+                        Field { label: "Site",
+                            Input {
+                                name: "site",
+                                placeholder: "Site",
+                                value: new_site(),
+                                value_changed: move |evt: FormEvent| new_site.set(evt.value()),
+                            }
+                        }
+                        Field { label: "Username",
+                            Input {
+                                name: "username",
+                                placeholder: "Username",
+                                value: new_username(),
+                                value_changed: move |evt: FormEvent| new_username.set(evt.value()),
+                            }
+                        }
+                        Field { label: "URLs (one per line)",
+                            TextArea {
+                                name: "urls",
+                                placeholder: "https://example.com/login",
+                                rows: "2",
+                                value: new_urls(),
+                                value_changed: move |evt: FormEvent| new_urls.set(evt.value()),
+                            }
+                        }
+                        Field { label: "Tags (comma separated)",
+                            Input {
+                                name: "tags",
+                                placeholder: "work, email",
+                                value: new_tags(),
+                                value_changed: move |evt: FormEvent| new_tags.set(evt.value()),
+                            }
+                        }
+                    }
+                }
+
+                Field { label: "Stored columns",
+                    for column in damaged.columns.iter() {
+                        StoredColumnRow { column: column.clone() }
+                    }
+                }
+
+                div { style: "display: flex; justify-content: flex-end; gap: 0.3rem; margin: 0;",
+                    Button {
+                        variant: ButtonVariant::Ghost,
+                        onclick: move |_| {
+                            navigator.push(Route::vault());
+                        },
+                        "Back"
+                    }
+                    Button {
+                        variant: ButtonVariant::Ghost,
+                        onclick: move |_| {
+                            save_fields();
+                        },
+                        "Save Fields"
+                    }
+                    Button {
+                        variant: ButtonVariant::Destructive,
+                        onclick: move |_| confirmation_open.set(true),
+                        "Delete"
+                    }
+                    AlertDialogRoot {
+                        open: confirmation_open(),
+                        on_open_change: move |v| confirmation_open.set(v),
+                        AlertDialogContent {
+                            AlertDialogTitle { "Delete item" }
+                            AlertDialogDescription {
+                                "Are you sure you want to delete this item? This action cannot be undone."
+                            }
+                            AlertDialogActions {
+                                AlertDialogCancel { "Cancel" }
+                                AlertDialogAction {
+                                    on_click: move |_| {
+                                        delete_entry();
+                                    },
+                                    "Delete"
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn StoredColumnRow(column: StoredColumn) -> Element {
+    rsx! {
+        div { style: "display: flex; justify-content: space-between; font-size: 0.85rem; color: #ccc;",
+            span { "{column.name}" }
+            span { style: "color: #aaa;", "{column.kind}, {column.length} characters" }
+        }
+    }
+}
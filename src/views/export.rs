@@ -0,0 +1,185 @@
+use std::{path::PathBuf, sync::Arc};
+
+use dioxus::prelude::*;
+use dioxus_primitives::toast::{use_toast, ToastOptions};
+use zeroize::Zeroizing;
+
+use crate::{
+    components::{Button, ButtonVariant, Card, Field, FieldGroup, Input, PasswordInput},
+    models::AuthState,
+    routes::Route,
+    services::{
+        database::DatabaseService,
+        keepass::{self, KdbxCipher},
+        vault_file,
+    },
+};
+
+/// The Export page component that will be rendered when the current route is `[Route::Export]`.
+/// It saves the whole vault as an encrypted vault export, or as a KeePass database that KeePass and
+/// KeePassXC can open.
+#[component]
+pub fn Export() -> Element {
+    let auth_state = use_context::<Signal<AuthState>>();
+    let db_service = use_context::<Arc<DatabaseService>>();
+    let db_service = use_signal(|| db_service.clone());
+    let navigator = use_navigator();
+    let toast_api = use_toast();
+
+    if !auth_state().signed_in {
+        navigator.replace(Route::home());
+    }
+
+    let mut keepass_format = use_signal(|| false);
+    let mut export_path = use_signal(|| "".to_string());
+    let mut file_password = use_signal(|| Zeroizing::new(String::new()));
+    let mut confirm_password = use_signal(|| Zeroizing::new(String::new()));
+    let mut cipher_key = use_signal(|| KdbxCipher::Aes256.key().to_string());
+    let mut working = use_signal(|| false);
+
+    let passwords_match = file_password() == confirm_password();
+    let (path_placeholder, password_label) = if keepass_format() {
+        ("/path/to/vault.kdbx".to_string(), "Database password")
+    } else {
+        (
+            format!("/path/to/vault.{}", vault_file::FILE_EXTENSION),
+            "Passphrase",
+        )
+    };
+
+    let export = move || {
+        spawn(async move {
+            working.set(true);
+            let path = PathBuf::from(export_path().trim());
+            let cipher = KdbxCipher::from_key(&cipher_key()).unwrap_or(KdbxCipher::Aes256);
+
+            let exported = if keepass_format() {
+                keepass::export_database(
+                    &path,
+                    file_password(),
+                    cipher,
+                    &auth_state(),
+                    db_service().as_ref(),
+                )
+                .await
+            } else {
+                vault_file::export_vault(
+                    &path,
+                    file_password(),
+                    &auth_state(),
+                    db_service().as_ref(),
+                )
+                .await
+            };
+
+            match exported {
+                Ok(count) => {
+                    toast_api.success(
+                        "Exported".into(),
+                        ToastOptions::new()
+                            .description(format!("Exported {count} entries to {}", path.display())),
+                    );
+                    navigator.replace(Route::vault());
+                }
+                Err(err) => toast_api.error(
+                    "Error".into(),
+                    ToastOptions::new()
+                        .description(format!("Could not export the vault: {err}"))
+                        .permanent(true),
+                ),
+            }
+            working.set(false);
+        });
+    };
+
+    rsx! {
+        div { style: "width: 100%; display: flex; justify-content: center;",
+            Card { title: "Export",
+                FieldGroup {
+                    Field { label: "Format",
+                        select {
+                            name: "format",
+                            onchange: move |evt: FormEvent| keepass_format.set(evt.value() == "keepass"),
+                            option { value: "vault_file", selected: !keepass_format(), "Encrypted vault export" }
+                            option { value: "keepass", selected: keepass_format(), "KeePass database (KDBX 4)" }
+                        }
+                        small { style: "color: #aaa;",
+                            if keepass_format() {
+                                "Groups, custom fields, attachments and history from imported KeePass databases are written back as they were."
+                            } else {
+                                "A single file with every entry and its details, encrypted with the passphrase. Import it again from the Import page."
+                            }
+                        }
+                    }
+
+                    Field { label: "Save to",
+                        Input {
+                            name: "export_path",
+                            placeholder: path_placeholder,
+                            value: export_path(),
+                            value_changed: move |evt: FormEvent| export_path.set(evt.value()),
+                        }
+                    }
+
+                    Field { label: password_label,
+                        PasswordInput {
+                            name: "file_password",
+                            placeholder: "Password",
+                            value: file_password().to_string(),
+                            value_changed: move |evt: FormEvent| file_password.set(Zeroizing::new(evt.value())),
+                        }
+                    }
+
+                    Field { label: "Confirm {password_label.to_lowercase()}",
+                        PasswordInput {
+                            name: "confirm_password",
+                            placeholder: "Password",
+                            value: confirm_password().to_string(),
+                            value_changed: move |evt: FormEvent| confirm_password.set(Zeroizing::new(evt.value())),
+                        }
+                        if !passwords_match {
+                            small { style: "color: #ff6b6b;", "The passwords don't match" }
+                        }
+                    }
+
+                    if keepass_format() {
+                        Field { label: "Encryption",
+                            select {
+                                name: "cipher",
+                                onchange: move |evt: FormEvent| cipher_key.set(evt.value()),
+                                for cipher in KdbxCipher::ALL {
+                                    option {
+                                        value: cipher.key(),
+                                        selected: cipher_key() == cipher.key(),
+                                        {cipher.label()}
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+
+                div { style: "display: flex; justify-content: flex-end; gap: 0.3rem; margin: 0;",
+                    Button {
+                        variant: ButtonVariant::Ghost,
+                        onclick: move |_| {
+                            navigator.push(Route::vault());
+                        },
+                        "Cancel"
+                    }
+                    Button {
+                        variant: ButtonVariant::Ghost,
+                        disabled: working() || export_path().trim().is_empty()
+                            || file_password().is_empty() || !passwords_match,
+                        onclick: move |_| export(),
+                        if working() {
+                            "Exporting..."
+                        } else {
+                            "Export"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,23 +1,35 @@
 use std::sync::Arc;
 
 use crate::{
+    components::error_options,
+    models::AuthState,
     routes::Route,
     services::{
+        agent,
         authentication::{self},
         database::DatabaseService,
+        error::ServiceError,
     },
 };
 use dioxus::prelude::*;
-use dioxus_primitives::toast::{use_toast, ToastOptions};
+use dioxus_primitives::toast::use_toast;
 
 /// The Home page component that will be rendered when the current route is `[Route::Home]`
 #[component]
 pub fn Home() -> Element {
+    let mut state = use_context::<Signal<AuthState>>();
     let db_service = use_context::<Arc<DatabaseService>>();
     let navigator = use_navigator();
     let toast_api = use_toast();
 
     spawn(async move {
+        // Nothing to ask for when the agent already has the vault unlocked.
+        if let Some(unlocked) = agent::agent_auth_state(&agent::socket_path(&db_service)).await {
+            state.set(unlocked);
+            navigator.replace(Route::vault());
+            return;
+        }
+
         match authentication::is_master_password_set(&db_service).await {
             Ok(is_set) => {
                 if is_set {
@@ -26,11 +38,7 @@ pub fn Home() -> Element {
                     navigator.replace(Route::create_master_password());
                 }
             }
-            Err(err) => toast_api.error(
-                "Critical Error".into(), 
-                ToastOptions::new()
-                    .description(format!("An error occurred while trying to verify your master password was set: {err}"))
-                    .permanent(true)),
+            Err(err) => toast_api.error(err.title().into(), error_options(&err)),
         }
     });
 
@@ -0,0 +1,312 @@
+use std::{path::PathBuf, sync::Arc};
+
+use dioxus::prelude::*;
+use dioxus_primitives::{
+    scroll_area::ScrollDirection,
+    toast::{use_toast, ToastOptions},
+};
+use zeroize::Zeroizing;
+
+use crate::{
+    components::{
+        Button, ButtonVariant, Card, Field, FieldGroup, Input, PasswordInput, ScrollArea,
+    },
+    models::{AuthState, PasswordEntryRaw},
+    routes::Route,
+    services::{
+        database::DatabaseService,
+        import::{self, Duplicate, ImportFormat, ImportPreview},
+    },
+};
+
+/// The Import page component that will be rendered when the current route is `[Route::Import]`.
+/// It walks through choosing an export, previewing what will be imported and saving it.
+#[component]
+pub fn Import() -> Element {
+    let auth_state = use_context::<Signal<AuthState>>();
+    let db_service = use_context::<Arc<DatabaseService>>();
+    let db_service = use_signal(|| db_service.clone());
+    let navigator = use_navigator();
+    let toast_api = use_toast();
+
+    if !auth_state().signed_in {
+        navigator.replace(Route::home());
+    }
+
+    let mut export_path = use_signal(|| "".to_string());
+    // Empty when the format should be detected from the file.
+    let mut format_key = use_signal(|| "".to_string());
+    // Unlocks KeePass databases.
+    let mut file_password = use_signal(|| Zeroizing::new(String::new()));
+    let mut preview = use_signal(|| None::<ImportPreview>);
+    // Whether each previewed entry will be imported, duplicates start out unchecked.
+    let mut included = use_signal(Vec::<bool>::new);
+    let mut working = use_signal(|| false);
+
+    let show_error = move |message: String| {
+        toast_api.error(
+            "Error".into(),
+            ToastOptions::new().description(message).permanent(true),
+        );
+    };
+
+    let load_preview = move || {
+        spawn(async move {
+            working.set(true);
+            let path = PathBuf::from(export_path().trim());
+            let format = ImportFormat::from_key(&format_key());
+
+            match import::preview_import(
+                &path,
+                format,
+                file_password(),
+                &auth_state(),
+                db_service().as_ref(),
+            )
+            .await
+            {
+                Ok(loaded) => {
+                    included.set(
+                        loaded
+                            .items
+                            .iter()
+                            .map(|item| item.duplicate.is_none())
+                            .collect(),
+                    );
+                    preview.set(Some(loaded));
+                }
+                Err(err) => show_error(format!("Could not read the export: {err}")),
+            }
+            working.set(false);
+        });
+    };
+
+    let save = move || {
+        spawn(async move {
+            let Some(loaded) = preview() else {
+                return;
+            };
+            working.set(true);
+
+            let entries: Vec<PasswordEntryRaw> = loaded
+                .items
+                .into_iter()
+                .zip(included())
+                .filter(|(_, include)| *include)
+                .map(|(item, _)| item.entry)
+                .collect();
+
+            match import::import_entries(&entries, &auth_state(), db_service().as_ref()).await {
+                Ok(count) => {
+                    toast_api.success(
+                        "Imported".into(),
+                        ToastOptions::new().description(format!(
+                            "Imported {count} entries. Remember to delete the export file, it isn't encrypted."
+                        )),
+                    );
+                    navigator.replace(Route::vault());
+                }
+                Err(err) => show_error(format!(
+                    "Nothing was imported because an error occurred: {err}"
+                )),
+            }
+            working.set(false);
+        });
+    };
+
+    let included_count = included().iter().filter(|include| **include).count();
+    let is_keepass = format_key() == ImportFormat::KeePassKdbx.key()
+        || export_path().trim().to_lowercase().ends_with(".kdbx");
+
+    rsx! {
+        div { style: "width: 100%; display: flex; justify-content: center;",
+            match preview() {
+                None => rsx! {
+                    Card { title: "Import",
+                        FieldGroup {
+                            Field { label: "Exported from",
+                                select {
+                                    name: "format",
+                                    onchange: move |evt: FormEvent| format_key.set(evt.value()),
+                                    option { value: "", selected: format_key().is_empty(), "Detect automatically" }
+                                    for format in ImportFormat::ALL {
+                                        option {
+                                            value: format.key(),
+                                            selected: format_key() == format.key(),
+                                            {format.label()}
+                                        }
+                                    }
+                                }
+                            }
+
+                            Field { label: "Export file",
+                                Input {
+                                    name: "export_path",
+                                    placeholder: "/path/to/export.csv",
+                                    value: export_path(),
+                                    value_changed: move |evt: FormEvent| export_path.set(evt.value()),
+                                }
+                                small { style: "color: #aaa;",
+                                    "Bitwarden JSON or CSV, Chrome, Edge or Brave CSV, Firefox CSV, 1Password 1PUX or CSV exports, or KeePass databases can be imported. Nothing is saved until you've checked the preview."
+                                }
+                            }
+
+                            if is_keepass {
+                                Field { label: "Database password",
+                                    PasswordInput {
+                                        name: "file_password",
+                                        placeholder: "The KeePass database's password",
+                                        value: file_password().to_string(),
+                                        value_changed: move |evt: FormEvent| file_password.set(Zeroizing::new(evt.value())),
+                                    }
+                                }
+                            }
+                        }
+
+                        div { style: "display: flex; justify-content: flex-end; gap: 0.3rem; margin: 0;",
+                            Button {
+                                variant: ButtonVariant::Ghost,
+                                onclick: move |_| {
+                                    navigator.push(Route::import_vault_file());
+                                },
+                                "Import vault export"
+                            }
+                            Button {
+                                variant: ButtonVariant::Ghost,
+                                onclick: move |_| {
+                                    navigator.push(Route::vault());
+                                },
+                                "Cancel"
+                            }
+                            Button {
+                                variant: ButtonVariant::Ghost,
+                                disabled: working() || export_path().trim().is_empty(),
+                                onclick: move |_| load_preview(),
+                                if working() {
+                                    "Reading..."
+                                } else {
+                                    "Preview"
+                                }
+                            }
+                        }
+                    }
+                },
+                Some(loaded) => rsx! {
+                    Card {
+                        title: "Import Preview",
+                        width: "100%",
+                        height: "calc(100vh - 110px)",
+
+                        div { style: "color: #ccc; font-size: 0.9rem; margin-bottom: 0.5rem;",
+                            "Found {loaded.items.len()} entries in the {loaded.format} export. "
+                            if loaded.skipped > 0 {
+                                "{loaded.skipped} items that aren't logins or are in the recycle bin were skipped. "
+                            }
+                            "Duplicates of existing entries are unchecked."
+                        }
+
+                        ScrollArea {
+                            height: "calc(100vh - 290px)",
+                            min_height: "200px",
+                            padding: "0 1.2em 1.2em 1.2em",
+                            direction: ScrollDirection::Vertical,
+                            tabindex: "0",
+                            style: "
+                                border: 1px solid #444;
+                                border-radius: 12px;
+                                background-color: #1b1b1b;
+                            ",
+                            div { class: "scroll-content", style: "padding-top: 15px;",
+                                for (index, item) in loaded.items.iter().enumerate() {
+                                    ImportRow {
+                                        key: "{index}",
+                                        site: item.entry.site.clone(),
+                                        username: item.entry.username.clone(),
+                                        url: item.entry.urls.first().cloned().unwrap_or_default(),
+                                        duplicate: item.duplicate,
+                                        included: included().get(index).copied().unwrap_or_default(),
+                                        on_toggle: move |include: bool| {
+                                            if let Some(value) = included.write().get_mut(index) {
+                                                *value = include;
+                                            }
+                                        },
+                                    }
+                                }
+                            }
+                        }
+
+                        div { style: "display: flex; justify-content: flex-end; gap: 0.3rem; margin-top: 0.5rem;",
+                            Button {
+                                variant: ButtonVariant::Ghost,
+                                disabled: working(),
+                                onclick: move |_| preview.set(None),
+                                "Back"
+                            }
+                            Button {
+                                variant: ButtonVariant::Ghost,
+                                disabled: working() || included_count == 0,
+                                onclick: move |_| save(),
+                                if working() {
+                                    "Importing..."
+                                } else {
+                                    "Import {included_count} entries"
+                                }
+                            }
+                        }
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[component]
+fn ImportRow(
+    site: String,
+    username: String,
+    url: String,
+    duplicate: Option<Duplicate>,
+    included: bool,
+    on_toggle: EventHandler<bool>,
+) -> Element {
+    let duplicate_label = match duplicate {
+        Some(Duplicate::InVault(_)) => Some("Already in vault"),
+        Some(Duplicate::InImport(_)) => Some("Duplicate in export"),
+        None => None,
+    };
+
+    rsx! {
+        label {
+            style: "
+                display: flex;
+                align-items: center;
+                gap: 0.8rem;
+                background: #1e1e1e;
+                border: 1px solid #2a2a2a;
+                border-radius: 12px;
+                padding: 0.6rem 1rem;
+                margin-bottom: 0.5rem;
+                cursor: pointer;
+            ",
+            input {
+                r#type: "checkbox",
+                checked: included,
+                onchange: move |evt: FormEvent| on_toggle.call(evt.checked()),
+            }
+            div { style: "display: flex; flex-direction: column; flex: 1; overflow: hidden;",
+                strong { style: "font-size: 0.95rem; color: #f0f0f0;", "{site}" }
+                div { style: "font-size: 0.85rem; color: #ccc; overflow: hidden; text-overflow: ellipsis; white-space: nowrap;",
+                    "{username}"
+                    if !url.is_empty() {
+                        span { style: "color: #888;", " · {url}" }
+                    }
+                }
+            }
+            if let Some(duplicate_label) = duplicate_label {
+                small { style: "color: var(--primary-warning-color); white-space: nowrap;",
+                    "{duplicate_label}"
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::{path::PathBuf, sync::Arc};
+
+use dioxus::prelude::*;
+use dioxus_primitives::toast::{use_toast, ToastOptions};
+use zeroize::Zeroizing;
+
+use crate::{
+    components::{
+        AlertDialogAction, AlertDialogActions, AlertDialogCancel, AlertDialogContent,
+        AlertDialogDescription, AlertDialogRoot, AlertDialogTitle, Button, ButtonVariant, Card,
+        Field, FieldGroup, Input, PasswordInput,
+    },
+    models::AuthState,
+    routes::Route,
+    services::{
+        database::DatabaseService,
+        vault_file::{self, ImportMode},
+    },
+};
+
+/// The page for importing an encrypted vault export, rendered when the current route is
+/// `[Route::ImportVaultFile]`. The export is merged into the vault or replaces it.
+#[component]
+pub fn ImportVaultFile() -> Element {
+    let auth_state = use_context::<Signal<AuthState>>();
+    let db_service = use_context::<Arc<DatabaseService>>();
+    let db_service = use_signal(|| db_service.clone());
+    let navigator = use_navigator();
+    let toast_api = use_toast();
+
+    if !auth_state().signed_in {
+        navigator.replace(Route::home());
+    }
+
+    let mut export_path = use_signal(|| "".to_string());
+    let mut passphrase = use_signal(|| Zeroizing::new(String::new()));
+    let mut mode = use_signal(|| ImportMode::Merge);
+    let mut working = use_signal(|| false);
+    // Replacing deletes the current entries so it's confirmed first.
+    let mut confirmation_open = use_signal(|| false);
+
+    let import = move || {
+        spawn(async move {
+            working.set(true);
+            let path = PathBuf::from(export_path().trim());
+
+            match vault_file::import_vault(
+                &path,
+                passphrase(),
+                mode(),
+                &auth_state(),
+                db_service().as_ref(),
+            )
+            .await
+            {
+                Ok(summary) => {
+                    toast_api.success(
+                        "Imported".into(),
+                        ToastOptions::new().description(format!("{summary}.")),
+                    );
+                    navigator.replace(Route::vault());
+                }
+                Err(err) => toast_api.error(
+                    "Error".into(),
+                    ToastOptions::new()
+                        .description(format!(
+                            "The vault wasn't changed because the export couldn't be imported: {err}"
+                        ))
+                        .permanent(true),
+                ),
+            }
+            working.set(false);
+        });
+    };
+
+    let (mode_description, import_variant) = match mode() {
+        ImportMode::Merge => (
+            "New entries are added and entries the export has a newer version of are updated. Nothing is deleted.",
+            ButtonVariant::Ghost,
+        ),
+        ImportMode::Replace => (
+            "Every entry in the vault is deleted and replaced with the entries in the export.",
+            ButtonVariant::Destructive,
+        ),
+    };
+
+    rsx! {
+        div { style: "width: 100%; display: flex; justify-content: center;",
+            Card { title: "Import Vault Export",
+                FieldGroup {
+                    Field { label: "Vault export",
+                        Input {
+                            name: "export_path",
+                            placeholder: format!("/path/to/vault.{}", vault_file::FILE_EXTENSION),
+                            value: export_path(),
+                            value_changed: move |evt: FormEvent| export_path.set(evt.value()),
+                        }
+                    }
+
+                    Field { label: "Passphrase",
+                        PasswordInput {
+                            name: "passphrase",
+                            placeholder: "The passphrase the export was made with",
+                            value: passphrase().to_string(),
+                            value_changed: move |evt: FormEvent| passphrase.set(Zeroizing::new(evt.value())),
+                        }
+                    }
+
+                    Field { label: "Existing entries",
+                        for option_mode in ImportMode::ALL {
+                            label { style: "display: flex; align-items: center; gap: 0.5rem; font-size: 0.9rem;",
+                                input {
+                                    r#type: "radio",
+                                    name: "mode",
+                                    value: option_mode.key(),
+                                    checked: mode() == option_mode,
+                                    onchange: move |_| mode.set(option_mode),
+                                }
+                                {option_mode.label()}
+                            }
+                        }
+                        small { style: "color: #aaa;", "{mode_description}" }
+                    }
+                }
+
+                div { style: "display: flex; justify-content: flex-end; gap: 0.3rem; margin: 0;",
+                    Button {
+                        variant: ButtonVariant::Ghost,
+                        onclick: move |_| {
+                            navigator.push(Route::import());
+                        },
+                        "Cancel"
+                    }
+                    Button {
+                        variant: import_variant,
+                        disabled: working() || export_path().trim().is_empty() || passphrase().is_empty(),
+                        onclick: move |_| {
+                            match mode() {
+                                ImportMode::Merge => import(),
+                                ImportMode::Replace => confirmation_open.set(true),
+                            }
+                        },
+                        if working() {
+                            "Importing..."
+                        } else {
+                            "Import"
+                        }
+                    }
+                    AlertDialogRoot {
+                        open: confirmation_open(),
+                        on_open_change: move |v| confirmation_open.set(v),
+                        AlertDialogContent {
+                            AlertDialogTitle { "Replace vault" }
+                            AlertDialogDescription {
+                                "Every entry in the vault will be deleted and replaced with the entries in the export. This action cannot be undone."
+                            }
+                            AlertDialogActions {
+                                AlertDialogCancel { "Cancel" }
+                                AlertDialogAction {
+                                    on_click: move |_| {
+                                        import();
+                                    },
+                                    "Replace"
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
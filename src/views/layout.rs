@@ -1,8 +1,9 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 use crate::{
     components::{Navbar, NavbarItem},
-    services::authentication,
+    services::{agent, authentication, database::DatabaseService},
+    views::{SshAgentHost, VaultViewState},
     AuthState, Route,
 };
 use dioxus::prelude::*;
@@ -17,9 +18,20 @@ use dioxus_primitives::toast::{use_toast, ToastOptions};
 pub fn Layout() -> Element {
     let mut state = use_context::<Signal<AuthState>>();
     let signed_in = state.map(|s| &s.signed_in);
+    let db_service = use_context::<Arc<DatabaseService>>();
+    let db_service = use_signal(|| db_service);
     let navigator = use_navigator();
     let toast_api = use_toast();
 
+    // The layout is rendered once per open vault, so the vault list's search and position don't
+    // carry over to another vault. Locking the vault forgets them too.
+    let mut vault_view_state = use_context_provider(|| Signal::new(VaultViewState::default()));
+    use_effect(move || {
+        if !state.read().signed_in {
+            vault_view_state.set(VaultViewState::default());
+        }
+    });
+
     use_future(move || async move {
         loop {
             tokio::time::sleep(Duration::from_secs(10)).await;
@@ -54,6 +66,48 @@ pub fn Layout() -> Element {
                     "Set Master Password"
                 }
 
+                NavbarItem {
+                    index: 3usize,
+                    value: "security report".to_string(),
+                    to: Route::security_report(),
+                    "Security Report"
+                }
+
+                NavbarItem {
+                    index: 4usize,
+                    value: "settings".to_string(),
+                    to: Route::app_settings(),
+                    "Settings"
+                }
+
+                NavbarItem {
+                    index: 5usize,
+                    value: "backups".to_string(),
+                    to: Route::restore_backup(),
+                    "Backups"
+                }
+
+                NavbarItem {
+                    index: 6usize,
+                    value: "check vault".to_string(),
+                    to: Route::check_vault(),
+                    "Check Vault"
+                }
+
+                NavbarItem {
+                    index: 7usize,
+                    value: "activity".to_string(),
+                    to: Route::activity(),
+                    "Activity"
+                }
+
+                NavbarItem {
+                    index: 8usize,
+                    value: "sync".to_string(),
+                    to: Route::sync_vault(),
+                    "Sync"
+                }
+
                 NavbarItem {
                     index: 10usize,
                     style: "margin-left: auto",
@@ -61,6 +115,11 @@ pub fn Layout() -> Element {
                     to: Route::home(),
                     onclick: move |_| {
                         state.set(authentication::logout());
+                        // Logging out locks the agent too, otherwise the vault stays open to
+                        // the CLI and the next launch.
+                        spawn(async move {
+                            let _ = agent::lock(&agent::socket_path(&db_service())).await;
+                        });
                     },
                     "Logout"
                 }
@@ -68,5 +127,7 @@ pub fn Layout() -> Element {
         }
 
         div { style: "padding: 0.1rem 0.5rem 0.5rem 0.5rem;", Outlet::<Route> {} }
+
+        SshAgentHost {}
     }
 }
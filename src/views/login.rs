@@ -1,12 +1,16 @@
 use std::sync::Arc;
 
 use crate::{
-    components::{Button, ButtonVariant, PasswordInput},
+    components::{error_options, Button, ButtonVariant, PasswordInput},
     routes::Route,
     services::{
+        agent,
         authentication::{self, LoginError},
+        backup::{self, BackupReason},
         database::DatabaseService,
+        error::ServiceError,
     },
+    views::VaultSwitcher,
     AuthState,
 };
 use dioxus::prelude::*;
@@ -32,25 +36,36 @@ pub fn Login() -> Element {
 
     let do_login = move || {
         spawn(async move {
-            match authentication::login(password(), state(), &db_service()).await {
+            match authentication::sign_in(password(), state(), &db_service()).await {
                 Ok(updated) => {
                     state.set(updated);
                     password.set(Zeroizing::new(String::new()));
                     navigator.replace(Route::vault());
+
+                    // A failed backup shouldn't keep anyone out of their vault, so only warn.
+                    if let Err(err) =
+                        backup::create_backup(BackupReason::Unlock, &db_service()).await
+                    {
+                        toast_api.warning(
+                            "Backup failed".into(),
+                            ToastOptions::new()
+                                .description(format!("The vault couldn't be backed up: {err}")),
+                        );
+                    }
+
+                    // Unlock a running agent as well so the CLI can use the vault.
+                    let socket_path = agent::socket_path(&db_service());
+                    if agent::status(&socket_path).await == Some(false) {
+                        let password = state().raw_master_password.clone();
+                        let _ = agent::unlock(&socket_path, password).await;
+                    }
                 }
                 Err(e) => match e {
                     LoginError::IncorrectPassword => {
                         error_message.set("incorrect password, try again".into());
                         show_error.set(true);
                     }
-                    LoginError::HashingError(err) => toast_api.error(
-                        "Error".into(),
-                        ToastOptions::new()
-                            .description(format!(
-                                "Error occurred that requires developer attention: {err}"
-                            ))
-                            .permanent(true),
-                    ),
+                    err => toast_api.error(err.title().into(), error_options(&err)),
                 },
             }
         })
@@ -76,6 +91,7 @@ pub fn Login() -> Element {
                     show_error.set(false);
                     do_login();
                 },
+                VaultSwitcher {}
                 div {
 
                     PasswordInput {
@@ -28,3 +28,42 @@ pub use password_details::*;
 
 mod new_password_entry;
 pub use new_password_entry::*;
+
+mod app_settings;
+pub use app_settings::*;
+
+mod security_report;
+pub use security_report::*;
+
+mod import;
+pub use import::*;
+
+mod import_vault_file;
+pub use import_vault_file::*;
+
+mod export;
+pub use export::*;
+
+mod restore_backup;
+pub use restore_backup::*;
+
+mod check_vault;
+pub use check_vault::*;
+
+mod damaged_entry;
+pub use damaged_entry::*;
+
+mod activity;
+pub use activity::*;
+
+mod sync_vault;
+pub use sync_vault::*;
+
+mod ssh_agent_host;
+pub use ssh_agent_host::*;
+
+mod vault_switcher;
+pub use vault_switcher::*;
+
+mod recovery;
+pub use recovery::*;
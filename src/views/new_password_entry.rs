@@ -1,14 +1,17 @@
 use std::sync::Arc;
 
 use dioxus::prelude::*;
-use dioxus_primitives::toast::{use_toast, ToastOptions};
+use dioxus_primitives::toast::use_toast;
 use zeroize::Zeroizing;
 
 use crate::{
-    components::{Button, ButtonVariant, Card, Field, FieldGroup, Input, PasswordInput},
-    models::{AuthState, PasswordEntryRaw},
+    components::{
+        error_options, Button, ButtonVariant, Card, Field, FieldGroup, Input, PasswordInput,
+        StrengthMeter, TextArea,
+    },
+    models::{unix_now, AuthState, EntryExtras, PasswordEntryRaw},
     routes::Route,
-    services::{database::DatabaseService, password_entry},
+    services::{database::DatabaseService, error::ServiceError, password_entry},
 };
 
 #[component]
@@ -26,14 +29,27 @@ pub fn NewPasswordEntry() -> Element {
     let mut new_site = use_signal(|| "".to_string());
     let mut new_username = use_signal(|| "".to_string());
     let mut new_raw_password = use_signal(|| Zeroizing::new(String::new()));
+    let mut new_urls = use_signal(|| "".to_string());
+    let mut new_tags = use_signal(|| "".to_string());
+    let mut new_notes = use_signal(|| "".to_string());
 
     let save_pw = move || {
         spawn(async move {
+            let now = unix_now();
             let password = PasswordEntryRaw {
                 id: 0,
+                uuid: String::new(),
+                revision: 0,
                 site: new_site(),
                 username: new_username(),
                 raw_password: new_raw_password(),
+                urls: password_entry::parse_urls(&new_urls()),
+                notes: new_notes(),
+                tags: password_entry::parse_tags(&new_tags()),
+                created_at: now,
+                updated_at: now,
+                password_changed_at: now,
+                extras: EntryExtras::default(),
             };
 
             match password_entry::create_password_entry(
@@ -43,18 +59,11 @@ pub fn NewPasswordEntry() -> Element {
             )
             .await
             {
-                Ok(()) => {
+                Ok(_) => {
                     navigator.replace(Route::vault());
                 }
                 Err(err) => {
-                    toast_api.error(
-                        "Error".into(),
-                        ToastOptions::new()
-                            .description(format!(
-                                "Error occurred that requires developer attention: {err}"
-                            ))
-                            .permanent(true),
-                    );
+                    toast_api.error(err.title().into(), error_options(&err));
                 }
             }
         });
@@ -91,6 +100,33 @@ pub fn NewPasswordEntry() -> Element {
                                 value: new_raw_password().to_string(),
                                 value_changed: move |evt: FormEvent| new_raw_password.set(Zeroizing::new(evt.value())),
                             }
+                            StrengthMeter { password: new_raw_password() }
+                        }
+                        Field { label: "URLs (one per line)",
+                            TextArea {
+                                name: "urls",
+                                placeholder: "https://example.com/login",
+                                rows: "2",
+                                value: new_urls(),
+                                value_changed: move |evt: FormEvent| new_urls.set(evt.value()),
+                            }
+                        }
+                        Field { label: "Tags (comma separated)",
+                            Input {
+                                name: "tags",
+                                placeholder: "work, email",
+                                value: new_tags(),
+                                value_changed: move |evt: FormEvent| new_tags.set(evt.value()),
+                            }
+                        }
+                        Field { label: "Notes",
+                            TextArea {
+                                name: "notes",
+                                placeholder: "Notes",
+                                rows: "3",
+                                value: new_notes(),
+                                value_changed: move |evt: FormEvent| new_notes.set(evt.value()),
+                            }
                         }
                     }
                 }
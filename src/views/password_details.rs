@@ -6,13 +6,19 @@ use zeroize::Zeroizing;
 
 use crate::{
     components::{
-        AlertDialogAction, AlertDialogActions, AlertDialogCancel, AlertDialogContent,
-        AlertDialogDescription, AlertDialogRoot, AlertDialogTitle, Button, ButtonVariant, Card,
-        Field, FieldGroup, Input, PasswordInput,
+        error_options, AlertDialogAction, AlertDialogActions, AlertDialogCancel,
+        AlertDialogContent, AlertDialogDescription, AlertDialogRoot, AlertDialogTitle, Button,
+        ButtonVariant, Card, Field, FieldGroup, Input, PasswordInput, StrengthMeter, TextArea,
     },
-    models::{AuthState, PasswordEntryRaw},
+    models::{unix_now, AuthState, EntryExtras, PasswordEntryRaw, SshKey},
     routes::Route,
-    services::{database::DatabaseService, password_entry},
+    services::{
+        audit::{self, AuditAction, AuditEvent},
+        database::DatabaseService,
+        error::ServiceError,
+        password_entry::{self, EntryError},
+        ssh_agent,
+    },
 };
 
 #[component]
@@ -31,11 +37,28 @@ pub fn PasswordDetails(id: i32) -> Element {
     let mut site = use_signal(|| "".to_string());
     let mut username = use_signal(|| "".to_string());
     let mut raw_password = use_signal(|| Zeroizing::new(String::new()));
+    let mut urls = use_signal(|| "".to_string());
+    let mut tags = use_signal(|| "".to_string());
+    let mut notes = use_signal(|| "".to_string());
+    let mut created_at = use_signal(|| 0);
+    let mut password_changed_at = use_signal(|| 0);
+    // Details from other password managers that can't be edited here but are kept when saving.
+    let mut extras = use_signal(EntryExtras::default);
 
     // The values of the fields when editing and viewing.
     let mut new_site = use_signal(|| "".to_string());
     let mut new_username = use_signal(|| "".to_string());
     let mut new_raw_password = use_signal(|| Zeroizing::new(String::new()));
+    let mut new_urls = use_signal(|| "".to_string());
+    let mut new_tags = use_signal(|| "".to_string());
+    let mut new_notes = use_signal(|| "".to_string());
+    // A private key pasted in to replace the entry's SSH key, empty to keep it.
+    let mut new_ssh_key = use_signal(|| Zeroizing::new(String::new()));
+    let mut ssh_key_passphrase = use_signal(|| Zeroizing::new(String::new()));
+    let mut ssh_confirm_use = use_signal(|| false);
+    let mut remove_ssh_key = use_signal(|| false);
+
+    let mut editing_password = use_signal(|| false);
 
     // Used for the delete confirmation dialog
     let mut confirmation_open = use_signal(|| false);
@@ -45,76 +68,141 @@ pub fn PasswordDetails(id: i32) -> Element {
             .await
         {
             Ok(pw) => {
+                // The page shows the password, e.g. with the field's show button.
+                let event = AuditEvent::for_entry(AuditAction::Reveal, id, &pw.site)
+                    .with_detail("Opened in the app");
+                let _ = audit::record(event, &auth_state(), db_service().as_ref()).await;
+
                 site.set(pw.site.clone());
                 username.set(pw.username.clone());
                 raw_password.set(pw.raw_password.clone());
+                urls.set(password_entry::join_urls(&pw.urls));
+                tags.set(password_entry::join_tags(&pw.tags));
+                notes.set(pw.notes.clone());
+                new_urls.set(password_entry::join_urls(&pw.urls));
+                new_tags.set(password_entry::join_tags(&pw.tags));
+                new_notes.set(pw.notes.clone());
+                created_at.set(pw.created_at);
+                password_changed_at.set(pw.password_changed_at);
+                ssh_confirm_use.set(
+                    pw.extras
+                        .ssh_key
+                        .as_ref()
+                        .is_some_and(|ssh_key| ssh_key.confirm_use),
+                );
+                extras.set(pw.extras.clone());
                 new_site.set(pw.site);
                 new_username.set(pw.username);
                 new_raw_password.set(pw.raw_password);
             }
-            Err(err) => {
+            Err(EntryError::NotSignedIn) => {
+                navigator.replace(Route::home());
+            }
+            Err(err @ EntryError::NotFound(_)) => {
                 toast_api.error(
-                    "Error".to_string(),
-                    ToastOptions::new()
-                        .description(format!(
-                            "Unexpected error occurred while getting password entries: {err}"
-                        ))
-                        .permanent(true),
+                    err.title().into(),
+                    ToastOptions::new().description(err.to_string()),
                 );
+                navigator.replace(Route::vault());
             }
+            // The parts of the entry that can still be read are shown on their own page.
+            Err(EntryError::WrongKey { .. } | EntryError::Corrupt { .. }) => {
+                navigator.replace(Route::damaged_entry(id));
+            }
+            Err(err) => toast_api.error(err.title().into(), error_options(&err)),
         }
     });
 
     let save_pw = move || {
         spawn(async move {
+            let mut new_extras = extras();
+            if remove_ssh_key() {
+                new_extras.ssh_key = None;
+            } else if !new_ssh_key().trim().is_empty() {
+                match ssh_agent::import_private_key(&new_ssh_key(), &ssh_key_passphrase()) {
+                    Ok(private_key) => {
+                        new_extras.ssh_key = Some(SshKey {
+                            private_key,
+                            confirm_use: ssh_confirm_use(),
+                        })
+                    }
+                    Err(err) => {
+                        toast_api.error(
+                            "Error".into(),
+                            ToastOptions::new()
+                                .description(format!("The SSH key couldn't be imported: {err}")),
+                        );
+                        return;
+                    }
+                }
+            } else if let Some(ssh_key) = new_extras.ssh_key.as_mut() {
+                ssh_key.confirm_use = ssh_confirm_use();
+            }
+
+            let now = unix_now();
             let password = PasswordEntryRaw {
                 id,
+                uuid: String::new(),
+                revision: 0,
                 site: new_site(),
                 username: new_username(),
                 raw_password: new_raw_password(),
+                urls: password_entry::parse_urls(&new_urls()),
+                notes: new_notes(),
+                tags: password_entry::parse_tags(&new_tags()),
+                created_at: created_at(),
+                updated_at: now,
+                password_changed_at: if new_raw_password() != raw_password() {
+                    now
+                } else {
+                    password_changed_at()
+                },
+                extras: new_extras.clone(),
             };
 
-            if let Err(err) = password_entry::save_updated_password(
+            match password_entry::save_updated_password(
                 id,
                 password,
                 &auth_state(),
-                &db_service().as_ref().pool,
+                db_service().as_ref(),
             )
             .await
             {
-                toast_api.error(
-                    "Error".into(),
-                    ToastOptions::new()
-                        .description(format!(
-                            "Error occurred that requires developer attention: {err}"
-                        ))
-                        .permanent(true),
-                )
+                Ok(()) => {
+                    extras.set(new_extras);
+                    new_ssh_key.set(Zeroizing::new(String::new()));
+                    ssh_key_passphrase.set(Zeroizing::new(String::new()));
+                    remove_ssh_key.set(false);
+                    editing_password.set(false);
+                }
+                Err(err) => toast_api.error(err.title().into(), error_options(&err)),
             }
         });
     };
 
     let delete_pw = move |id: i32| {
         spawn(async move {
-            match password_entry::delete_password(id, db_service().as_ref()).await {
+            match password_entry::delete_password(id, &auth_state(), db_service().as_ref()).await {
                 Ok(()) => {
                     navigator.replace(Route::vault());
                 }
-                Err(e) => {
-                    toast_api.error(
-                        "Error".into(),
-                        ToastOptions::new()
-                            .description(format!(
-                                "Error occurred that requires developer attention: {e}"
-                            ))
-                            .permanent(true),
-                    );
+                // Deleted in the meantime, e.g. from the CLI.
+                Err(EntryError::NotFound(_)) => {
+                    navigator.replace(Route::vault());
                 }
+                Err(err) => toast_api.error(err.title().into(), error_options(&err)),
             }
         });
     };
 
-    let mut editing_password = use_signal(|| false);
+    let ssh_key_description = extras()
+        .ssh_key
+        .filter(|_| !remove_ssh_key())
+        .map(|ssh_key| {
+            ssh_agent::describe_key(&ssh_key.private_key)
+                .unwrap_or_else(|err| format!("The key couldn't be read: {err}"))
+        });
+    let has_ssh_key = ssh_key_description.is_some();
 
     rsx! {
         div { style: "display: flex; justify-content: center; padding: 0;",
@@ -150,6 +238,131 @@ pub fn PasswordDetails(id: i32) -> Element {
                                 value_changed: move |evt: FormEvent| new_raw_password.set(Zeroizing::new(evt.value())),
                                 readonly: !editing_password(),
                             }
+                            if editing_password() {
+                                StrengthMeter { password: new_raw_password() }
+                            }
+                        }
+                        Field { label: "URLs (one per line)",
+                            TextArea {
+                                name: "urls",
+                                placeholder: "https://example.com/login",
+                                rows: "2",
+                                value: new_urls(),
+                                value_changed: move |evt: FormEvent| new_urls.set(evt.value()),
+                                readonly: !editing_password(),
+                            }
+                        }
+                        Field { label: "Tags (comma separated)",
+                            Input {
+                                name: "tags",
+                                placeholder: "work, email",
+                                value: new_tags(),
+                                value_changed: move |evt: FormEvent| new_tags.set(evt.value()),
+                                readonly: !editing_password(),
+                            }
+                        }
+                        Field { label: "Notes",
+                            TextArea {
+                                name: "notes",
+                                placeholder: "Notes",
+                                rows: "3",
+                                value: new_notes(),
+                                value_changed: move |evt: FormEvent| new_notes.set(evt.value()),
+                                readonly: !editing_password(),
+                            }
+                        }
+
+                        if !extras().group.is_empty() {
+                            Field { label: "Group",
+                                Input {
+                                    name: "group",
+                                    value: extras().group.join(" / "),
+                                    readonly: true,
+                                }
+                            }
+                        }
+                        for field in extras().custom_fields {
+                            Field { label: "{field.name}",
+                                if field.protected {
+                                    PasswordInput {
+                                        value: field.value.to_string(),
+                                        readonly: true,
+                                    }
+                                } else {
+                                    Input {
+                                        value: field.value.to_string(),
+                                        readonly: true,
+                                    }
+                                }
+                            }
+                        }
+                        if !extras().attachments.is_empty() {
+                            Field { label: "Attachments",
+                                for attachment in extras().attachments {
+                                    div { style: "font-size: 0.9rem; color: #ccc;",
+                                        "{attachment.name} "
+                                        span { style: "color: #888;", "({attachment.data.len()} bytes)" }
+                                    }
+                                }
+                            }
+                        }
+                        if has_ssh_key || editing_password() {
+                            Field { label: "SSH key",
+                                if let Some(description) = ssh_key_description {
+                                    Input {
+                                        name: "ssh_key",
+                                        value: description,
+                                        readonly: true,
+                                    }
+                                }
+                                if editing_password() {
+                                    TextArea {
+                                        name: "new_ssh_key",
+                                        placeholder: "Paste a private key in OpenSSH format to use it for this entry",
+                                        rows: "3",
+                                        value: new_ssh_key().to_string(),
+                                        value_changed: move |evt: FormEvent| {
+                                            new_ssh_key.set(Zeroizing::new(evt.value()));
+                                            remove_ssh_key.set(false);
+                                        },
+                                    }
+                                    if !new_ssh_key().is_empty() {
+                                        PasswordInput {
+                                            name: "ssh_key_passphrase",
+                                            placeholder: "Passphrase of the key, if it has one",
+                                            value: ssh_key_passphrase().to_string(),
+                                            value_changed: move |evt: FormEvent| ssh_key_passphrase.set(Zeroizing::new(evt.value())),
+                                        }
+                                    }
+                                }
+                                if has_ssh_key || !new_ssh_key().is_empty() {
+                                    label { style: "display: flex; align-items: center; gap: 0.5rem; font-size: 0.9rem; color: #ccc;",
+                                        input {
+                                            r#type: "checkbox",
+                                            checked: ssh_confirm_use(),
+                                            disabled: !editing_password(),
+                                            onchange: move |evt: FormEvent| ssh_confirm_use.set(evt.checked()),
+                                        }
+                                        "Ask before each use"
+                                    }
+                                }
+                                if editing_password() && has_ssh_key {
+                                    Button {
+                                        r#type: "button",
+                                        variant: ButtonVariant::Ghost,
+                                        onclick: move |_| {
+                                            remove_ssh_key.set(true);
+                                            new_ssh_key.set(Zeroizing::new(String::new()));
+                                        },
+                                        "Remove SSH key"
+                                    }
+                                }
+                            }
+                        }
+                        if !extras().history.is_empty() {
+                            small { style: "color: #aaa;",
+                                "{extras().history.len()} earlier versions of this entry are kept for exporting."
+                            }
                         }
                     }
                 }
@@ -192,7 +405,6 @@ pub fn PasswordDetails(id: i32) -> Element {
                             variant: ButtonVariant::Ghost,
                             onclick: move |_| {
                                 save_pw();
-                                editing_password.set(false);
                             },
                             "Save"
                         }
@@ -202,6 +414,17 @@ pub fn PasswordDetails(id: i32) -> Element {
                                 new_site.set(site());
                                 new_username.set(username());
                                 new_raw_password.set(raw_password());
+                                new_urls.set(urls());
+                                new_tags.set(tags());
+                                new_notes.set(notes());
+                                new_ssh_key.set(Zeroizing::new(String::new()));
+                                ssh_key_passphrase.set(Zeroizing::new(String::new()));
+                                ssh_confirm_use.set(
+                                    extras()
+                                        .ssh_key
+                                        .is_some_and(|ssh_key| ssh_key.confirm_use),
+                                );
+                                remove_ssh_key.set(false);
                                 editing_password.set(false);
                             },
                             "Cancel"
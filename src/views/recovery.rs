@@ -0,0 +1,303 @@
+use std::{path::PathBuf, sync::Arc};
+
+use dioxus::prelude::*;
+use dioxus_primitives::toast::{use_toast, ToastOptions};
+
+use crate::{
+    components::{
+        AlertDialogAction, AlertDialogActions, AlertDialogCancel, AlertDialogContent,
+        AlertDialogDescription, AlertDialogRoot, AlertDialogTitle, Button, ButtonVariant, Card,
+        Field, FieldGroup, Input,
+    },
+    services::{
+        backup,
+        database::{self, DatabaseError, DatabaseService},
+        error::ServiceError,
+        vaults::{self, VaultInfo, VaultRegistry},
+    },
+    views::BackupRow,
+    DATA_DIR,
+};
+
+/// Why a vault couldn't be opened when the app started.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StartupFailure {
+    pub vault: VaultInfo,
+    pub error: DatabaseError,
+}
+
+/// Shown instead of the app when the vault can't be opened, with the ways to get a vault open:
+/// trying again, opening another vault, putting a backup in its place and the details of what
+/// went wrong.
+#[component]
+pub fn Recovery(failure: StartupFailure) -> Element {
+    let mut vault = use_context::<Signal<Option<Arc<DatabaseService>>>>();
+    let toast_api = use_toast();
+
+    let mut failure = use_signal(|| failure);
+    let mut working = use_signal(|| false);
+    let mut other_vault = use_signal(String::new);
+    let mut other_path = use_signal(String::new);
+    let mut selected_backup = use_signal(|| None::<PathBuf>);
+    let mut confirmation_open = use_signal(|| false);
+    let mut show_diagnostics = use_signal(|| false);
+
+    let data_dir = DATA_DIR.get().unwrap();
+    let mut registry = use_signal(|| {
+        VaultRegistry::load(data_dir).unwrap_or_else(|_| VaultRegistry::new(data_dir))
+    });
+
+    let backups = use_resource(move || async move {
+        let path = failure.read().vault.path.clone();
+        backup::list_backups_of(&path).await.unwrap_or_default()
+    });
+
+    let show_error = move |message: String| {
+        toast_api.error(
+            "Error".into(),
+            ToastOptions::new().description(message).permanent(true),
+        );
+    };
+
+    // Open a vault on the list, which replaces this screen with the app when it works.
+    let mut open = move |info: VaultInfo| {
+        spawn(async move {
+            working.set(true);
+            let listed = registry.peek().clone();
+            match vaults::open_listed_vault(&listed, &info).await {
+                Ok(db_service) => {
+                    registry.write().last_used = Some(info.name);
+                    if let Err(err) = registry.peek().save(data_dir) {
+                        show_error(format!("The vault list couldn't be saved: {err}"));
+                    }
+                    vault.set(Some(Arc::new(db_service)));
+                }
+                Err(error) => failure.set(StartupFailure { vault: info, error }),
+            }
+            working.set(false);
+        });
+    };
+
+    let mut open_file = move || {
+        let path = PathBuf::from(other_path().trim());
+        let added = registry.write().find_or_add_file(&path);
+        match added {
+            Ok(info) => open(info),
+            Err(err) => show_error(err),
+        }
+    };
+
+    let mut restore = move || {
+        let Some(backup_path) = selected_backup() else {
+            return;
+        };
+        let info = failure.peek().vault.clone();
+
+        match backup::replace_with_backup(&backup_path, &info.path) {
+            Ok(moved_to) => {
+                let description = match moved_to {
+                    Some(moved_to) => format!(
+                        "The backup is in place of the vault. The old file was kept as {}.",
+                        moved_to.display()
+                    ),
+                    None => "The backup is in place of the vault.".to_string(),
+                };
+                toast_api.success(
+                    "Restored".into(),
+                    ToastOptions::new().description(description),
+                );
+                selected_backup.set(None);
+                open(info);
+            }
+            Err(err) => show_error(format!("The backup couldn't be restored: {err}")),
+        }
+    };
+
+    let current = failure();
+    let other_vaults: Vec<String> = registry
+        .read()
+        .vaults
+        .iter()
+        .filter(|info| info.path != current.vault.path)
+        .map(|info| info.name.clone())
+        .collect();
+    let backups = backups().unwrap_or_default();
+    let diagnostics = if show_diagnostics() {
+        let mut facts = vec![
+            ("Vault", current.vault.name.clone()),
+            ("Error", format!("{:?}", current.error)),
+        ];
+        facts.extend(database::diagnose(&current.vault.path));
+        facts.push(("Data directory", data_dir.display().to_string()));
+        facts
+            .into_iter()
+            .map(|(label, value)| format!("{label}: {value}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    } else {
+        String::new()
+    };
+
+    rsx! {
+        div { style: "width: 100%; display: flex; justify-content: center; padding: 1rem 0;",
+            Card { title: "{current.error.title()}",
+                div { style: "color: #ccc; font-size: 0.9rem;", "{current.error}" }
+                div { style: "color: #aaa; font-size: 0.85rem; margin-bottom: 0.5rem;",
+                    {hint(&current.error)}
+                }
+
+                div { style: "display: flex; justify-content: flex-end; gap: 0.3rem; margin: 0;",
+                    Button {
+                        variant: ButtonVariant::Ghost,
+                        onclick: move |_| show_diagnostics.set(!show_diagnostics()),
+                        if show_diagnostics() {
+                            "Hide Details"
+                        } else {
+                            "Show Details"
+                        }
+                    }
+                    Button {
+                        variant: ButtonVariant::Secondary,
+                        disabled: working(),
+                        onclick: move |_| open(failure.peek().vault.clone()),
+                        if working() {
+                            "Opening..."
+                        } else {
+                            "Try Again"
+                        }
+                    }
+                }
+
+                if show_diagnostics() {
+                    pre { style: "
+                            background: #151515;
+                            border: 1px solid #2a2a2a;
+                            border-radius: 8px;
+                            padding: 0.6rem;
+                            font-size: 0.8rem;
+                            color: #ccc;
+                            white-space: pre-wrap;
+                            user-select: text;
+                        ",
+                        "{diagnostics}"
+                    }
+                }
+
+                h3 { style: "color: #f0f0f0; font-size: 1rem;", "Open another vault" }
+                FieldGroup {
+                    if !other_vaults.is_empty() {
+                        Field { label: "Vault",
+                            div { style: "display: flex; gap: 0.3rem;",
+                                select {
+                                    style: "flex: 1",
+                                    name: "other_vault",
+                                    onchange: move |evt: FormEvent| other_vault.set(evt.value()),
+                                    option { value: "", "Choose a vault" }
+                                    for name in other_vaults {
+                                        option { value: "{name}", selected: other_vault() == name, "{name}" }
+                                    }
+                                }
+                                Button {
+                                    variant: ButtonVariant::Ghost,
+                                    disabled: working() || other_vault().is_empty(),
+                                    onclick: move |_| {
+                                        let info = registry.peek().find(&other_vault()).cloned();
+                                        if let Some(info) = info {
+                                            open(info);
+                                        }
+                                    },
+                                    "Open"
+                                }
+                            }
+                        }
+                    }
+                    Field { label: "Vault file",
+                        div { style: "display: flex; gap: 0.3rem;",
+                            Input {
+                                style: "flex: 1",
+                                name: "other_path",
+                                placeholder: "/path/to/vault.sqlite",
+                                value: other_path(),
+                                value_changed: move |evt: FormEvent| other_path.set(evt.value()),
+                            }
+                            Button {
+                                variant: ButtonVariant::Ghost,
+                                disabled: working() || other_path().trim().is_empty(),
+                                onclick: move |_| open_file(),
+                                "Open"
+                            }
+                        }
+                    }
+                }
+
+                h3 { style: "color: #f0f0f0; font-size: 1rem;", "Restore a backup" }
+                if backups.is_empty() {
+                    div { style: "color: #aaa; font-size: 0.9rem;", "There are no backups of this vault." }
+                } else {
+                    div { style: "color: #aaa; font-size: 0.85rem;",
+                        "The backup takes the place of the vault file, with the master password it was taken with."
+                    }
+                    for info in backups {
+                        BackupRow {
+                            key: "{info.path.display()}",
+                            selected: selected_backup().as_ref() == Some(&info.path),
+                            on_select: move |path: PathBuf| selected_backup.set(Some(path)),
+                            info: info.clone(),
+                        }
+                    }
+                    div { style: "display: flex; justify-content: flex-end; gap: 0.3rem; margin: 0;",
+                        Button {
+                            variant: ButtonVariant::Destructive,
+                            disabled: working() || selected_backup().is_none(),
+                            onclick: move |_| confirmation_open.set(true),
+                            "Restore"
+                        }
+                    }
+                }
+                AlertDialogRoot {
+                    open: confirmation_open(),
+                    on_open_change: move |v| confirmation_open.set(v),
+                    AlertDialogContent {
+                        AlertDialogTitle { "Restore backup" }
+                        AlertDialogDescription {
+                            "The vault file is replaced with the backup. The current file is kept next to it under another name."
+                        }
+                        AlertDialogActions {
+                            AlertDialogCancel { "Cancel" }
+                            AlertDialogAction {
+                                on_click: move |_| {
+                                    restore();
+                                },
+                                "Restore"
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// What to try for each kind of failure.
+fn hint(error: &DatabaseError) -> &'static str {
+    match error {
+        DatabaseError::Locked { .. } => {
+            "Close any other program using the vault, such as another window of the app, and try again."
+        }
+        DatabaseError::NotFound { .. } => {
+            "If the vault was moved or is on a drive that isn't connected, open it from where it is now or connect the drive and try again."
+        }
+        DatabaseError::Corrupt { .. } => {
+            "Restore one of the vault's backups, or open another vault."
+        }
+        DatabaseError::AccessDenied { .. } | DatabaseError::CreateDirectory { .. } => {
+            "Check that the file and its folder can be read and written, then try again."
+        }
+        DatabaseError::NewerVersion { .. } => {
+            "Update the app to open this vault, or open another vault."
+        }
+        DatabaseError::Migration { .. } | DatabaseError::Other { .. } => {
+            "Try again, restore one of the vault's backups or open another vault. The details help with reporting the problem."
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use std::{path::PathBuf, sync::Arc};
+
+use dioxus::prelude::*;
+use dioxus_primitives::toast::{use_toast, ToastOptions};
+use zeroize::Zeroizing;
+
+use crate::{
+    components::{
+        AlertDialogAction, AlertDialogActions, AlertDialogCancel, AlertDialogContent,
+        AlertDialogDescription, AlertDialogRoot, AlertDialogTitle, Button, ButtonVariant, Card,
+        Field, FieldGroup, PasswordInput,
+    },
+    models::{format_timestamp, AuthState},
+    routes::Route,
+    services::{
+        backup::{self, BackupInfo},
+        database::DatabaseService,
+    },
+};
+
+/// The page listing the vault's automatic backups, rendered when the current route is
+/// `[Route::RestoreBackup]`. A backup is checked with the master password it was taken with
+/// before the vault is replaced with it.
+#[component]
+pub fn RestoreBackup() -> Element {
+    let auth_state = use_context::<Signal<AuthState>>();
+    let db_service = use_context::<Arc<DatabaseService>>();
+    let db_service = use_signal(|| db_service.clone());
+    let navigator = use_navigator();
+    let toast_api = use_toast();
+
+    if !auth_state().signed_in {
+        navigator.replace(Route::home());
+    }
+
+    let mut backups = use_signal(Vec::<BackupInfo>::new);
+    let mut selected = use_signal(|| None::<PathBuf>);
+    let mut backup_password = use_signal(|| Zeroizing::new(String::new()));
+    // How many entries the selected backup decrypted to, once it's been checked.
+    let mut checked_count = use_signal(|| None::<usize>);
+    let mut working = use_signal(|| false);
+    let mut confirmation_open = use_signal(|| false);
+
+    let show_error = move |message: String| {
+        toast_api.error(
+            "Error".into(),
+            ToastOptions::new().description(message).permanent(true),
+        );
+    };
+
+    let mut load_backups = move || {
+        spawn(async move {
+            match backup::list_backups(db_service().as_ref()).await {
+                Ok(loaded) => backups.set(loaded),
+                Err(err) => show_error(format!("Could not list the backups: {err}")),
+            }
+        });
+    };
+
+    use_hook(move || load_backups());
+
+    let check = move || {
+        spawn(async move {
+            let Some(path) = selected() else {
+                return;
+            };
+            working.set(true);
+
+            match backup::read_backup(&path, backup_password()).await {
+                Ok(entries) => checked_count.set(Some(entries.len())),
+                Err(err) => show_error(format!("The backup couldn't be opened: {err}")),
+            }
+            working.set(false);
+        });
+    };
+
+    let restore = move || {
+        spawn(async move {
+            let Some(path) = selected() else {
+                return;
+            };
+            working.set(true);
+
+            match backup::restore_backup(
+                &path,
+                backup_password(),
+                &auth_state(),
+                db_service().as_ref(),
+            )
+            .await
+            {
+                Ok(summary) => {
+                    toast_api.success(
+                        "Restored".into(),
+                        ToastOptions::new().description(format!("{summary}.")),
+                    );
+                    navigator.replace(Route::vault());
+                }
+                Err(err) => {
+                    show_error(format!(
+                        "The vault wasn't changed because the backup couldn't be restored: {err}"
+                    ));
+                    load_backups();
+                }
+            }
+            working.set(false);
+        });
+    };
+
+    let check_result = checked_count()
+        .map(|count| format!("The password is correct and the backup holds {count} entries."));
+
+    rsx! {
+        div { style: "width: 100%; display: flex; justify-content: center;",
+            Card { title: "Backups",
+                div { style: "color: #ccc; font-size: 0.9rem; margin-bottom: 0.5rem;",
+                    "The vault is backed up when it's unlocked, before the master password is changed and before imports. How many backups are kept can be changed in Settings."
+                }
+
+                if backups().is_empty() {
+                    div { style: "color: #aaa; font-size: 0.9rem;", "There are no backups yet." }
+                }
+
+                for info in backups() {
+                    BackupRow {
+                        key: "{info.path.display()}",
+                        selected: selected().as_ref() == Some(&info.path),
+                        on_select: move |path: PathBuf| {
+                            selected.set(Some(path));
+                            checked_count.set(None);
+                        },
+                        info: info.clone(),
+                    }
+                }
+
+                if selected().is_some() {
+                    FieldGroup {
+                        Field { label: "Master password when the backup was taken",
+                            PasswordInput {
+                                name: "backup_password",
+                                placeholder: "Master password",
+                                value: backup_password().to_string(),
+                                value_changed: move |evt: FormEvent| {
+                                    backup_password.set(Zeroizing::new(evt.value()));
+                                    checked_count.set(None);
+                                },
+                            }
+                            if let Some(check_result) = check_result {
+                                small { style: "color: #aaa;", "{check_result}" }
+                            }
+                        }
+                    }
+                }
+
+                div { style: "display: flex; justify-content: flex-end; gap: 0.3rem; margin: 0;",
+                    Button {
+                        variant: ButtonVariant::Ghost,
+                        disabled: working() || selected().is_none() || backup_password().is_empty(),
+                        onclick: move |_| check(),
+                        if working() {
+                            "Checking..."
+                        } else {
+                            "Check"
+                        }
+                    }
+                    Button {
+                        variant: ButtonVariant::Destructive,
+                        disabled: working() || checked_count().is_none(),
+                        onclick: move |_| confirmation_open.set(true),
+                        "Restore"
+                    }
+                    AlertDialogRoot {
+                        open: confirmation_open(),
+                        on_open_change: move |v| confirmation_open.set(v),
+                        AlertDialogContent {
+                            AlertDialogTitle { "Restore backup" }
+                            AlertDialogDescription {
+                                "Every entry in the vault will be replaced with the entries in the backup. The vault is backed up first, so this can be undone by restoring that backup."
+                            }
+                            AlertDialogActions {
+                                AlertDialogCancel { "Cancel" }
+                                AlertDialogAction {
+                                    on_click: move |_| {
+                                        restore();
+                                    },
+                                    "Restore"
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+pub fn BackupRow(info: BackupInfo, selected: bool, on_select: EventHandler<PathBuf>) -> Element {
+    let created_at = format_timestamp(info.created_at);
+    let entry_count = match info.entry_count {
+        Some(count) => format!("{count} entries"),
+        None => "Couldn't be read".to_string(),
+    };
+    let path = info.path.clone();
+
+    rsx! {
+        label {
+            style: "
+                display: flex;
+                align-items: center;
+                gap: 0.8rem;
+                background: #1e1e1e;
+                border: 1px solid #2a2a2a;
+                border-radius: 12px;
+                padding: 0.6rem 1rem;
+                margin-bottom: 0.5rem;
+                cursor: pointer;
+            ",
+            input {
+                r#type: "radio",
+                name: "backup",
+                checked: selected,
+                onchange: move |_| on_select.call(path.clone()),
+            }
+            div { style: "display: flex; flex-direction: column; flex: 1; overflow: hidden;",
+                strong { style: "font-size: 0.95rem; color: #f0f0f0;", "{created_at}" }
+                div { style: "font-size: 0.85rem; color: #ccc;",
+                    "{info.reason.label()}"
+                    span { style: "color: #888;", " · {entry_count}" }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use std::sync::Arc;
+
+use dioxus::prelude::*;
+use dioxus_primitives::{
+    scroll_area::ScrollDirection,
+    toast::{use_toast, ToastOptions},
+};
+
+use crate::{
+    components::{Card, ScrollArea},
+    models::AuthState,
+    routes::Route,
+    services::{
+        database::DatabaseService,
+        password_strength,
+        security_report::{self, ReportEntry},
+    },
+};
+
+/// The SecurityReport page component that will be rendered when the current route is `[Route::SecurityReport]`
+#[component]
+pub fn SecurityReport() -> Element {
+    let auth_state = use_context::<Signal<AuthState>>();
+    let db_service = use_context::<Arc<DatabaseService>>();
+    let db_service = use_signal(|| db_service.clone());
+    let navigator = use_navigator();
+    let toast_api = use_toast();
+
+    if !auth_state().signed_in {
+        navigator.replace(Route::home());
+    }
+
+    let mut report = use_signal(|| None::<security_report::SecurityReport>);
+
+    use_future(move || async move {
+        match security_report::generate_report(&auth_state(), db_service().as_ref()).await {
+            Ok(generated) => report.set(Some(generated)),
+            Err(err) => toast_api.error(
+                "Error".to_string(),
+                ToastOptions::new()
+                    .description(format!(
+                        "Unexpected error occurred while generating the security report: {err}"
+                    ))
+                    .permanent(true),
+            ),
+        }
+    });
+
+    rsx! {
+        div { style: "width: 100%; display: flex; justify-content: center;",
+            Card {
+                title: "Security Report",
+                width: "100%",
+                height: "calc(100vh - 110px)",
+
+                match report() {
+                    None => rsx! {
+                        div { style: "color: #aaa; text-align: center;", "Analyzing vault..." }
+                    },
+                    Some(report) => rsx! {
+                        div { style: "color: #ccc; font-size: 0.9rem; margin-bottom: 0.5rem;",
+                            "{report.issue_count()} issue(s) found across {report.total_entries} entries."
+                        }
+
+                        ScrollArea {
+                            height: "calc(100vh - 250px)",
+                            min_height: "200px",
+                            padding: "0 1.2em 1.2em 1.2em",
+                            direction: ScrollDirection::Vertical,
+                            tabindex: "0",
+                            style: "
+                                border: 1px solid #444;
+                                border-radius: 12px;
+                                background-color: #1b1b1b;
+                            ",
+                            div { class: "scroll-content", style: "padding-top: 15px;",
+                                ReportSection {
+                                    title: "Reused passwords",
+                                    description: "These entries share a password. A breach of one site exposes the others.",
+                                    is_empty: report.reused.is_empty(),
+                                    for (index, group) in report.reused.iter().enumerate() {
+                                        div { style: "margin-bottom: 0.6rem;",
+                                            div { style: "font-size: 0.8rem; color: #aaa; margin-bottom: 0.3rem;",
+                                                "Group {index + 1} ({group.len()} entries)"
+                                            }
+                                            for entry in group.iter() {
+                                                ReportEntryRow { entry: entry.clone(), detail: "" }
+                                            }
+                                        }
+                                    }
+                                }
+
+                                ReportSection {
+                                    title: "Weak passwords",
+                                    description: "These passwords are easy to guess.",
+                                    is_empty: report.weak.is_empty(),
+                                    for weak in report.weak.iter() {
+                                        ReportEntryRow {
+                                            entry: weak.entry.clone(),
+                                            detail: password_strength::score_label(weak.score).to_string(),
+                                        }
+                                    }
+                                }
+
+                                ReportSection {
+                                    title: "Old passwords",
+                                    description: format!(
+                                        "These passwords haven't been changed in {} months or more.",
+                                        report.max_password_age_months,
+                                    ),
+                                    is_empty: report.old.is_empty(),
+                                    for old in report.old.iter() {
+                                        ReportEntryRow {
+                                            entry: old.entry.clone(),
+                                            detail: format!("{} months", old.months_since_changed),
+                                        }
+                                    }
+                                }
+
+                                match report.breached.as_ref() {
+                                    Some(breached) => rsx! {
+                                        ReportSection {
+                                            title: "Breached passwords",
+                                            description: "These passwords appear in known data breaches and should be changed.",
+                                            is_empty: breached.is_empty(),
+                                            for found in breached.iter() {
+                                                ReportEntryRow {
+                                                    entry: found.entry.clone(),
+                                                    detail: format!("Seen {} times", found.count),
+                                                }
+                                            }
+                                        }
+                                    },
+                                    None => rsx! {
+                                        ReportSection {
+                                            title: "Breached passwords",
+                                            description: "Breach checking is not configured.",
+                                            is_empty: false,
+                                            div { style: "font-size: 0.9rem; color: #ccc;",
+                                                "Build a breach index from a Pwned Passwords dataset in Settings to check for breached passwords."
+                                            }
+                                        }
+                                    },
+                                }
+
+                                ReportSection {
+                                    title: "Missing passwords",
+                                    description: "These entries have no password set.",
+                                    is_empty: report.empty.is_empty(),
+                                    for entry in report.empty.iter() {
+                                        ReportEntryRow { entry: entry.clone(), detail: "" }
+                                    }
+                                }
+
+                                if !report.unreadable.is_empty() {
+                                    ReportSection {
+                                        title: "Unreadable entries",
+                                        description: "These entries couldn't be decrypted, so they weren't checked.",
+                                        is_empty: false,
+                                        for entry in report.unreadable.iter() {
+                                            ReportEntryRow { entry: entry.clone(), detail: "" }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    },
+                }
+            }
+        }
+    }
+}
+
+#[component]
+fn ReportSection(title: String, description: String, is_empty: bool, children: Element) -> Element {
+    rsx! {
+        div { style: "margin-bottom: 1.2rem;",
+            h3 { style: "margin: 0 0 0.2rem 0; font-size: 1rem; color: #f0f0f0;", "{title}" }
+            div { style: "font-size: 0.8rem; color: #aaa; margin-bottom: 0.5rem;", "{description}" }
+            if is_empty {
+                div { style: "font-size: 0.9rem; color: var(--secondary-success-color);",
+                    "No problems found."
+                }
+            } else {
+                {children}
+            }
+        }
+    }
+}
+
+#[component]
+fn ReportEntryRow(entry: ReportEntry, detail: String) -> Element {
+    let mut state = use_context::<Signal<AuthState>>();
+    let navigator = use_navigator();
+
+    rsx! {
+        div {
+            style: "
+                background: #1e1e1e;
+                border: 1px solid #2a2a2a;
+                border-radius: 12px;
+                padding: 0.6rem 1.2rem;
+                margin-bottom: 0.4rem;
+                display: flex;
+                justify-content: space-between;
+                align-items: center;
+                cursor: pointer;
+            ",
+            onclick: move |_| {
+                state.write().reset_idle_timer();
+                navigator.push(Route::password_details(entry.id));
+            },
+            div {
+                strong { style: "font-size: 0.95rem; color: #f0f0f0;", "{entry.site}" }
+                div { style: "font-size: 0.85rem; color: #ccc;", "{entry.username}" }
+            }
+            if !detail.is_empty() {
+                div { style: "font-size: 0.8rem; color: #aaa;", "{detail}" }
+            }
+        }
+    }
+}
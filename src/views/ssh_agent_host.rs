@@ -0,0 +1,112 @@
+use std::sync::{Arc, Mutex};
+
+use dioxus::prelude::*;
+use dioxus_primitives::toast::{use_toast, ToastOptions};
+use tokio::sync::mpsc;
+use zeroize::Zeroizing;
+
+use crate::{
+    components::{
+        AlertDialogAction, AlertDialogActions, AlertDialogCancel, AlertDialogContent,
+        AlertDialogDescription, AlertDialogRoot, AlertDialogTitle,
+    },
+    models::AuthState,
+    services::{
+        database::DatabaseService,
+        ssh_agent::{self, ConfirmRequest, SharedAuthState},
+    },
+};
+
+/// Runs the SSH agent for as long as the app is open and asks whether keys flagged that way may
+/// be used. Rendered by the layout so that it's on every page.
+#[component]
+pub fn SshAgentHost() -> Element {
+    let auth_state = use_context::<Signal<AuthState>>();
+    let db_service = use_context::<Arc<DatabaseService>>();
+    let db_service = use_signal(|| db_service);
+    let toast_api = use_toast();
+
+    let shared: SharedAuthState = use_hook(|| Arc::new(Mutex::new(AuthState::default())));
+    let mut pending = use_signal(|| None::<ConfirmRequest>);
+
+    // The agent only needs the key, and only has to change when the app is unlocked or locked or
+    // the master password changes, not on every reset of the idle timer.
+    let unlocked = use_memo(move || {
+        let state = auth_state.read();
+        state.signed_in.then(|| state.salt.clone())
+    });
+    let agent_state = shared.clone();
+    use_effect(move || {
+        let signed_in = unlocked().is_some();
+        let mut agent_state = agent_state.lock().unwrap();
+        *agent_state = if signed_in {
+            AuthState::from_key_material(Zeroizing::new(auth_state.peek().get_key_material()))
+        } else {
+            AuthState::default()
+        };
+    });
+
+    use_future(move || {
+        let shared = shared.clone();
+        async move {
+            let (confirm, mut requests) = mpsc::unbounded_channel();
+            let socket_path = ssh_agent::socket_path(&db_service());
+            let agent = ssh_agent::run_ssh_agent(&socket_path, shared, db_service(), confirm);
+            let prompts = async {
+                while let Some(request) = requests.recv().await {
+                    pending.set(Some(request));
+                }
+            };
+
+            tokio::select! {
+                result = agent => if let Err(err) = result {
+                    toast_api.warning(
+                        "SSH agent".into(),
+                        ToastOptions::new()
+                            .description(format!("The SSH agent couldn't be started: {err}")),
+                    );
+                },
+                _ = prompts => {}
+            }
+        }
+    });
+
+    let mut answer = move |allowed: bool| {
+        if let Some(request) = pending.write().take() {
+            let _ = request.respond.send(allowed);
+        }
+    };
+
+    let description = pending.read().as_ref().map(|request| {
+        format!(
+            "An SSH client wants to use the key of {} ({}).",
+            request.site, request.fingerprint
+        )
+    });
+
+    rsx! {
+        if let Some(description) = description {
+            // Stays open until the use is allowed or denied.
+            AlertDialogRoot { open: true,
+                AlertDialogContent {
+                    AlertDialogTitle { "Allow SSH key use?" }
+                    AlertDialogDescription { "{description}" }
+                    AlertDialogActions {
+                        AlertDialogCancel {
+                            on_click: move |_| {
+                                answer(false);
+                            },
+                            "Deny"
+                        }
+                        AlertDialogAction {
+                            on_click: move |_| {
+                                answer(true);
+                            },
+                            "Allow"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,447 @@
+use std::{path::Path, sync::Arc};
+
+use dioxus::prelude::*;
+use dioxus_primitives::{
+    scroll_area::ScrollDirection,
+    toast::{use_toast, ToastOptions},
+};
+use zeroize::Zeroizing;
+
+use crate::{
+    components::{
+        error_options, Button, ButtonVariant, Card, Field, FieldGroup, Input, PasswordInput,
+        ScrollArea,
+    },
+    models::{format_timestamp, AuthState, PasswordEntryRaw},
+    routes::Route,
+    services::{
+        audit::{self, AuditAction, AuditEvent},
+        database::DatabaseService,
+        error::ServiceError,
+        settings,
+        sync::{self, SyncConflict, SyncField, SyncState, SyncSummary},
+        sync_client::{self, ServerConnection},
+    },
+};
+
+/// The page for syncing the vault with other devices through a shared folder or a sync server,
+/// rendered when the current route is `[Route::SyncVault]`. Entries changed differently on both
+/// sides are listed after a sync to choose which side's fields to keep.
+#[component]
+pub fn SyncVault() -> Element {
+    let auth_state = use_context::<Signal<AuthState>>();
+    let db_service = use_context::<Arc<DatabaseService>>();
+    let db_service = use_signal(|| db_service.clone());
+    let navigator = use_navigator();
+    let toast_api = use_toast();
+
+    if !auth_state().signed_in {
+        navigator.replace(Route::home());
+    }
+
+    let mut folder = use_signal(|| "".to_string());
+    let mut state = use_signal(SyncState::default);
+    let mut summary = use_signal(|| None::<SyncSummary>);
+    let mut working = use_signal(|| false);
+
+    let mut connection = use_signal(|| None::<ServerConnection>);
+    let mut server_url = use_signal(|| "".to_string());
+    let mut server_token = use_signal(|| "".to_string());
+    let mut team_passphrase = use_signal(|| Zeroizing::new(String::new()));
+
+    use_future(move || async move {
+        if let Ok(settings) = settings::get_settings(db_service().as_ref()).await {
+            folder.set(settings.sync_folder);
+        }
+        match sync::get_state(db_service().as_ref()).await {
+            Ok(loaded) => state.set(loaded),
+            Err(err) => toast_api.error(err.title().into(), error_options(&err)),
+        }
+        match sync_client::get_connection(db_service().as_ref()).await {
+            Ok(loaded) => connection.set(loaded),
+            Err(err) => toast_api.error(err.title().into(), error_options(&err)),
+        }
+    });
+
+    let mut run_sync = move || {
+        spawn(async move {
+            working.set(true);
+            let chosen = folder().trim().to_string();
+
+            // Remembered for next time, and for `pw sync`.
+            if let Ok(mut settings) = settings::get_settings(db_service().as_ref()).await {
+                if settings.sync_folder != chosen {
+                    settings.sync_folder = chosen.clone();
+                    let _ = settings::save_settings(&settings, db_service().as_ref()).await;
+                }
+            }
+
+            let mut synced = Vec::new();
+            if !chosen.is_empty() {
+                match sync::sync_folder(Path::new(&chosen), &auth_state(), db_service().as_ref())
+                    .await
+                {
+                    Ok(folder_summary) => synced.push(folder_summary),
+                    Err(err) => toast_api.error(err.title().into(), error_options(&err)),
+                }
+            }
+            if connection().is_some() {
+                match sync_client::sync_server(&auth_state(), db_service().as_ref()).await {
+                    Ok(server_summary) => synced.push(server_summary),
+                    Err(err) => toast_api.error(err.title().into(), error_options(&err)),
+                }
+            }
+
+            if !synced.is_empty() {
+                let combined = combine_summaries(synced);
+                toast_api.success(
+                    "Synced".into(),
+                    ToastOptions::new().description(combined.to_string()),
+                );
+                summary.set(Some(combined));
+            }
+
+            if let Ok(loaded) = sync::get_state(db_service().as_ref()).await {
+                state.set(loaded);
+            }
+            if let Ok(loaded) = sync_client::get_connection(db_service().as_ref()).await {
+                connection.set(loaded);
+            }
+            working.set(false);
+        });
+    };
+
+    let connect = move || {
+        spawn(async move {
+            working.set(true);
+            match sync_client::connect(
+                &server_url(),
+                &server_token(),
+                &team_passphrase(),
+                &auth_state(),
+                db_service().as_ref(),
+            )
+            .await
+            {
+                Ok(connected) => {
+                    toast_api.success(
+                        "Connected".into(),
+                        ToastOptions::new().description(format!(
+                            "Connected to {} as {}, sync to share the vault with the team.",
+                            connected.url, connected.device
+                        )),
+                    );
+                    server_token.set(String::new());
+                    team_passphrase.set(Zeroizing::new(String::new()));
+                    connection.set(Some(connected));
+                }
+                Err(err) => toast_api.error(err.title().into(), error_options(&err)),
+            }
+            working.set(false);
+        });
+    };
+
+    let disconnect = move || {
+        spawn(async move {
+            match sync_client::disconnect(&auth_state(), db_service().as_ref()).await {
+                Ok(()) => connection.set(None),
+                Err(err) => toast_api.error(err.title().into(), error_options(&err)),
+            }
+        });
+    };
+
+    let conflicts = summary
+        .read()
+        .as_ref()
+        .map(|synced| synced.conflicts.clone())
+        .unwrap_or_default();
+    let skipped = summary
+        .read()
+        .as_ref()
+        .map(|synced| synced.skipped.clone())
+        .unwrap_or_default();
+    let last_synced = match state().synced_at {
+        0 => "never".to_string(),
+        synced_at => format_timestamp(synced_at),
+    };
+
+    rsx! {
+        div { style: "width: 100%; display: flex; justify-content: center;",
+            Card { title: "Sync", width: "100%",
+                FieldGroup {
+                    Field { label: "Sync folder",
+                        Input {
+                            name: "sync_folder",
+                            placeholder: "/path/to/synced/folder",
+                            value: folder(),
+                            value_changed: move |evt: FormEvent| folder.set(evt.value()),
+                        }
+                        small { style: "color: #aaa;",
+                            "A folder every device can reach, e.g. one kept in step by Syncthing. Each device writes its own file there, encrypted with the master password, so it must be the same on every device."
+                        }
+                    }
+                }
+
+                div { style: "font-size: 0.85rem; color: #aaa; margin: 0.5rem 0;",
+                    "This device is {state().device} in the folder, last synced {last_synced}."
+                }
+
+                FieldGroup {
+                    Field { label: "Sync server",
+                        match connection() {
+                            Some(connected) => rsx! {
+                                div { style: "display: flex; align-items: center; justify-content: space-between; gap: 1rem;",
+                                    div { style: "font-size: 0.85rem; color: #aaa;",
+                                        "Connected to {connected.url} as {connected.device}, last synced {server_synced(&connected)}."
+                                    }
+                                    Button {
+                                        variant: ButtonVariant::Ghost,
+                                        disabled: working(),
+                                        onclick: move |_| disconnect(),
+                                        "Disconnect"
+                                    }
+                                }
+                            },
+                            None => rsx! {
+                                Input {
+                                    name: "server_url",
+                                    placeholder: "http://sync.example.lan:8787",
+                                    value: server_url(),
+                                    value_changed: move |evt: FormEvent| server_url.set(evt.value()),
+                                }
+                                PasswordInput {
+                                    name: "server_token",
+                                    placeholder: "This device's token",
+                                    value: server_token(),
+                                    value_changed: move |evt: FormEvent| server_token.set(evt.value()),
+                                }
+                                div { style: "display: flex; align-items: center; gap: 0.3rem;",
+                                    PasswordInput {
+                                        name: "team_passphrase",
+                                        placeholder: "The team's passphrase",
+                                        value: team_passphrase().to_string(),
+                                        value_changed: move |evt: FormEvent| {
+                                            team_passphrase.set(Zeroizing::new(evt.value()));
+                                        },
+                                    }
+                                    Button {
+                                        variant: ButtonVariant::Ghost,
+                                        disabled: working() || server_url().trim().is_empty()
+                                            || server_token().trim().is_empty() || team_passphrase().is_empty(),
+                                        onclick: move |_| connect(),
+                                        "Connect"
+                                    }
+                                }
+                                small { style: "color: #aaa;",
+                                    "A server the team runs with pw-sync-server, which gives each device a token. Entries are encrypted with the team's passphrase before they're sent, the server can't read them."
+                                }
+                            },
+                        }
+                    }
+                }
+
+                for file in skipped.iter() {
+                    div { style: "font-size: 0.85rem; color: var(--primary-error-color);",
+                        "Skipped {file}"
+                    }
+                }
+
+                if !conflicts.is_empty() {
+                    div { style: "font-size: 0.9rem; color: #f0f0f0; margin: 0.5rem 0;",
+                        "These entries were changed differently on another device. Choose which fields to keep, the others' changes are already merged."
+                    }
+                    ScrollArea {
+                        height: "calc(100vh - 420px)",
+                        min_height: "200px",
+                        padding: "0 1.2em 1.2em 1.2em",
+                        direction: ScrollDirection::Vertical,
+                        tabindex: "0",
+                        style: "
+                            border: 1px solid #444;
+                            border-radius: 12px;
+                            background-color: #1b1b1b;
+                        ",
+                        div { class: "scroll-content", style: "padding-top: 15px;",
+                            for conflict in conflicts.iter() {
+                                ConflictCard {
+                                    key: "{conflict.local.uuid}",
+                                    conflict: conflict.clone(),
+                                    on_resolved: move |uuid: String| {
+                                        let mut remaining = 0;
+                                        if let Some(synced) = summary.write().as_mut() {
+                                            synced.conflicts.retain(|conflict| conflict.local.uuid != uuid);
+                                            remaining = synced.conflicts.len();
+                                        }
+                                        // Share the resolved entries with the other devices.
+                                        if remaining == 0 {
+                                            run_sync();
+                                        }
+                                    },
+                                }
+                            }
+                        }
+                    }
+                }
+
+                div { style: "display: flex; justify-content: flex-end; gap: 0.3rem; margin: 0;",
+                    Button {
+                        variant: ButtonVariant::Ghost,
+                        onclick: move |_| {
+                            navigator.push(Route::vault());
+                        },
+                        "Back"
+                    }
+                    Button {
+                        variant: ButtonVariant::Ghost,
+                        disabled: working() || (folder().trim().is_empty() && connection().is_none()),
+                        onclick: move |_| run_sync(),
+                        if working() {
+                            "Syncing..."
+                        } else {
+                            "Sync Now"
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// An entry changed differently on both sides, with a choice for each field changed on both.
+#[component]
+fn ConflictCard(conflict: SyncConflict, on_resolved: EventHandler<String>) -> Element {
+    let auth_state = use_context::<Signal<AuthState>>();
+    let db_service = use_context::<Arc<DatabaseService>>();
+    let db_service = use_signal(|| db_service.clone());
+    let toast_api = use_toast();
+
+    let mut take_remote = use_signal(Vec::<SyncField>::new);
+    let mut show_passwords = use_signal(|| false);
+
+    let resolve = {
+        let conflict = conflict.clone();
+        move || {
+            let conflict = conflict.clone();
+            spawn(async move {
+                match sync::resolve_conflict(
+                    &conflict,
+                    &take_remote(),
+                    &auth_state(),
+                    db_service().as_ref(),
+                )
+                .await
+                {
+                    Ok(()) => on_resolved.call(conflict.local.uuid.clone()),
+                    Err(err) => toast_api.error(err.title().into(), error_options(&err)),
+                }
+            });
+        }
+    };
+
+    let title = if conflict.local.username.is_empty() {
+        conflict.local.site.clone()
+    } else {
+        format!("{} ({})", conflict.local.site, conflict.local.username)
+    };
+    let has_password = conflict.fields.contains(&SyncField::Password);
+    let entry_id = conflict.local.id;
+    let site = use_signal(|| conflict.local.site.clone());
+
+    rsx! {
+        div {
+            style: "
+                background: #1e1e1e;
+                border: 1px solid #2a2a2a;
+                border-radius: 12px;
+                padding: 0.6rem 1.2rem;
+                margin-bottom: 0.6rem;
+            ",
+            strong { style: "font-size: 0.95rem; color: #f0f0f0;", "{title}" }
+            div { style: "font-size: 0.8rem; color: #aaa;", "Also changed on {conflict.device}" }
+
+            for field in conflict.fields.iter().copied() {
+                div { style: "margin-top: 0.5rem;",
+                    div { style: "display: flex; align-items: center; gap: 1rem;",
+                        span { style: "font-size: 0.9rem; color: #ccc; width: 110px;", {field.label()} }
+                        select {
+                            name: "{field.label()}",
+                            onchange: move |evt: FormEvent| {
+                                take_remote.write().retain(|taken| *taken != field);
+                                if evt.value() == "remote" {
+                                    take_remote.write().push(field);
+                                }
+                            },
+                            option { value: "local", selected: !take_remote.read().contains(&field), "Keep this device's" }
+                            option { value: "remote", selected: take_remote.read().contains(&field), "Use {conflict.device}'s" }
+                        }
+                    }
+                    div { style: "display: flex; gap: 1rem; font-size: 0.8rem; color: #aaa; margin-top: 0.2rem;",
+                        div { style: "flex: 1; white-space: pre-wrap;",
+                            "This device: {shown_value(field, &conflict.local, show_passwords())}"
+                        }
+                        div { style: "flex: 1; white-space: pre-wrap;",
+                            "{conflict.device}: {shown_value(field, &conflict.remote, show_passwords())}"
+                        }
+                    }
+                }
+            }
+
+            div { style: "display: flex; justify-content: flex-end; gap: 0.3rem; margin-top: 0.5rem;",
+                if has_password {
+                    Button {
+                        variant: ButtonVariant::Ghost,
+                        onclick: move |_| {
+                            show_passwords.set(!show_passwords());
+                            if show_passwords() {
+                                spawn(async move {
+                                    let event = AuditEvent::for_entry(AuditAction::Reveal, entry_id, &site())
+                                        .with_detail("To resolve a sync conflict");
+                                    let _ = audit::record(event, &auth_state(), db_service().as_ref()).await;
+                                });
+                            }
+                        },
+                        if show_passwords() {
+                            "Hide Passwords"
+                        } else {
+                            "Show Passwords"
+                        }
+                    }
+                }
+                Button {
+                    variant: ButtonVariant::Ghost,
+                    onclick: move |_| resolve(),
+                    "Apply"
+                }
+            }
+        }
+    }
+}
+
+/// What syncing through the folder and with the server changed, together.
+fn combine_summaries(summaries: Vec<SyncSummary>) -> SyncSummary {
+    summaries
+        .into_iter()
+        .fold(SyncSummary::default(), |mut combined, summary| {
+            combined.devices += summary.devices;
+            combined.added += summary.added;
+            combined.updated += summary.updated;
+            combined.deleted += summary.deleted;
+            combined.skipped.extend(summary.skipped);
+            combined.conflicts.extend(summary.conflicts);
+            combined
+        })
+}
+
+fn server_synced(connection: &ServerConnection) -> String {
+    match connection.synced_at {
+        0 => "never".to_string(),
+        synced_at => format_timestamp(synced_at),
+    }
+}
+
+fn shown_value(field: SyncField, entry: &PasswordEntryRaw, show_passwords: bool) -> String {
+    match field {
+        SyncField::Password if show_passwords => entry.raw_password.to_string(),
+        field => field.describe(entry),
+    }
+}
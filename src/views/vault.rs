@@ -1,19 +1,44 @@
-use std::{sync::Arc, time::Duration};
+use std::{rc::Rc, sync::Arc, time::Duration};
 
 use dioxus::prelude::*;
-use dioxus_primitives::{
-    scroll_area::ScrollDirection,
-    toast::{use_toast, ToastOptions},
-};
+use dioxus_primitives::toast::{use_toast, ToastOptions};
 use zeroize::Zeroizing;
 
 use crate::{
-    components::{Button, ButtonVariant, Card, Input, ScrollArea},
-    models::{AuthState, PasswordEntryRaw},
+    components::{
+        error_options, virtual_list, Button, ButtonVariant, Card, HighlightedText, Input,
+        VirtualList,
+    },
+    models::{AuthState, PasswordEntrySafe},
     routes::Route,
-    services::{clipboard, database::DatabaseService, password_entry},
+    services::{
+        audit::{self, AuditAction, AuditEvent},
+        clipboard,
+        database::DatabaseService,
+        error::ServiceError,
+        password_entry::{self, EntryProblem},
+        query::{self, QueryContext},
+        search::{SearchField, SearchResult},
+    },
 };
 
+/// How many entries are loaded from the database at a time, the next page is loaded when the list
+/// is scrolled near its end.
+const PAGE_SIZE: u32 = 500;
+/// The height of a row in the vault list in pixels, including the gap below each card.
+const ROW_HEIGHT: f64 = 190.0;
+const LIST_ID: &str = "vault-list";
+
+/// What the vault list looked like when the user last left it, so that coming back from an
+/// entry's details shows the same search and position. The layout provides it, so it belongs to
+/// the open vault, and resets it when the vault is locked.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VaultViewState {
+    search: String,
+    scroll_top: f64,
+    selected_id: Option<i32>,
+}
+
 #[component]
 pub fn Vault() -> Element {
     let auth_state = use_context::<Signal<AuthState>>();
@@ -26,32 +51,114 @@ pub fn Vault() -> Element {
         navigator.replace(Route::home());
     }
 
-    let mut entries: Signal<Vec<PasswordEntryRaw>> = use_signal(Vec::new);
-    let mut search_string = use_signal(|| "".to_string());
-
-    let search = move || async move {
-        match password_entry::get_all_password_entries(
-            &auth_state(),
-            db_service().as_ref(),
-            search_string(),
-        )
-        .await
-        {
-            Ok(pws) => entries.set(pws),
-            Err(err) => {
-                toast_api.error(
-                    "Error".to_string(),
-                    ToastOptions::new()
-                        .description(format!(
-                            "Unexpected error occurred while getting password entries: {err}"
-                        ))
-                        .permanent(true),
-                );
+    let mut view_state = use_context::<Signal<VaultViewState>>();
+
+    // Passwords stay encrypted in the list and are only decrypted when shown or copied.
+    let mut entries: Signal<Vec<PasswordEntrySafe>> = use_signal(Vec::new);
+    // Entries that can't be read, shown apart so that the rest of the vault loads.
+    let mut damaged: Signal<Vec<EntryProblem>> = use_signal(Vec::new);
+    let mut loading = use_signal(|| false);
+    let mut all_loaded = use_signal(|| false);
+    let mut after_id = use_signal(|| None::<i32>);
+    let mut search_string = use_signal(|| view_state.peek().search.clone());
+    let mut selected_id = use_signal(|| view_state.peek().selected_id);
+    let mut pending_scroll_top = use_signal(|| view_state.peek().scroll_top);
+    // Entries are only changed on other pages, which load the vault again, so the flags and notes
+    // titles worked out for searching are kept for as long as the vault is shown.
+    let query_context = use_hook(|| Rc::new(QueryContext::default()));
+
+    // Load the vault a page at a time so the first entries show up straight away.
+    let mut load_page = move || {
+        if *loading.peek() || *all_loaded.peek() {
+            return;
+        }
+        loading.set(true);
+        spawn(async move {
+            match password_entry::get_password_entry_summaries_page(
+                &auth_state(),
+                db_service().as_ref(),
+                *after_id.peek(),
+                PAGE_SIZE,
+            )
+            .await
+            {
+                Ok(page) => {
+                    all_loaded.set(page.len() < PAGE_SIZE as usize);
+                    if let Some(last) = page.last() {
+                        after_id.set(Some(match last {
+                            Ok(entry) => entry.id,
+                            Err(problem) => problem.id,
+                        }));
+                    }
+                    for result in page {
+                        match result {
+                            Ok(entry) => entries.write().push(entry),
+                            Err(problem) => damaged.write().push(problem),
+                        }
+                    }
+                }
+                Err(err) => {
+                    toast_api.error(err.title().into(), error_options(&err));
+                    // Show what loaded instead of trying again on every scroll.
+                    all_loaded.set(true);
+                }
             }
+            loading.set(false);
+        });
+    };
+
+    use_hook(move || load_page());
+
+    // Searching happens in memory so results update as the user types.
+    let parsed_query = use_memo(move || query::parse(&search_string()));
+    let results = use_memo(move || {
+        // An invalid query shows the whole vault while it is being fixed.
+        let parsed = parsed_query().unwrap_or_default();
+        query::execute(&parsed, &entries.read(), &query_context, &auth_state.peek())
+    });
+
+    // Searching covers the whole vault and going back to where the list was scrolled to may need
+    // more than the first page, so keep loading pages until they're there.
+    use_effect(move || {
+        let searching = parsed_query().is_ok_and(|query| !query.is_empty());
+        let loaded_height = entries.read().len() as f64 * ROW_HEIGHT;
+        let restoring = pending_scroll_top() > loaded_height;
+        if (searching || restoring) && !loading() && !all_loaded() {
+            load_page();
+        }
+    });
+
+    // Go back to where the list was scrolled to once enough of the vault has loaded.
+    use_effect(move || {
+        let loaded_height = results.read().len() as f64 * ROW_HEIGHT;
+        let target = *pending_scroll_top.peek();
+        if target > 0.0 && (loaded_height >= target || all_loaded()) {
+            virtual_list::scroll_to(LIST_ID, target);
+            pending_scroll_top.set(0.0);
         }
+    });
+
+    let selected_index = use_memo(move || {
+        let id = selected_id()?;
+        let entries = entries.read();
+        results.read().iter().position(|result| {
+            entries
+                .get(result.index)
+                .is_some_and(|entry| entry.id == id)
+        })
+    });
+
+    let entry_id_at = move |row: usize| -> Option<i32> {
+        let result_index = results.read().get(row)?.index;
+        entries.read().get(result_index).map(|entry| entry.id)
     };
 
-    use_future(search);
+    // Make room for the damaged entries above the list.
+    let list_height = if damaged.read().is_empty() {
+        "calc(100vh - 250px)"
+    } else {
+        "calc(100vh - 395px)"
+    };
 
     rsx! {
         div { style: "width: 100%; display: flex; justify-content: center;",
@@ -70,73 +177,202 @@ pub fn Vault() -> Element {
                         "Add Password"
                     }
 
-                    // Right: Search input
-                    form {
-                        style: "display: flex; margin-left: auto;",
-                        onsubmit: move |_| {
-                            spawn(async move { search().await });
+                    Button {
+                        variant: ButtonVariant::Ghost,
+                        onclick: move |_| {
+                            navigator.push(Route::import());
+                        },
+                        "Import"
+                    }
+
+                    Button {
+                        variant: ButtonVariant::Ghost,
+                        onclick: move |_| {
+                            navigator.push(Route::export());
                         },
+                        "Export"
+                    }
+
+                    // Right: Search input
+                    div { style: "display: flex; flex-direction: column; margin-left: auto;",
                         Input {
                             name: "search",
-                            placeholder: "Search",
+                            placeholder: "Search, e.g. tag:work -weak",
+                            title: "Filter with site:, user:, url:, tag:, notes:, created:, modified:, changed: and is:weak/reused/empty. Prefix a term with - to exclude it.",
                             value: search_string(),
                             value_changed: move |evt: FormEvent| {
                                 search_string.set(evt.value());
+                                view_state.write().search = evt.value();
                             },
-                            style: "width: 200px;",
+                            style: "width: 280px;",
+                        }
+                        if let Err(err) = parsed_query() {
+                            small { style: "margin-left: 5px; color: var(--primary-error-color)",
+                                "{err}"
+                            }
+                        }
+                    }
+                }
+
+                if !damaged.read().is_empty() {
+                    div { style: "
+                            max-height: 130px;
+                            overflow-y: auto;
+                            margin-bottom: 0.5rem;
+                            border: 1px solid var(--primary-error-color);
+                            border-radius: 12px;
+                            padding: 0.4rem 1.2em;
+                        ",
+                        div { style: "font-size: 0.85rem; color: #ccc; margin-bottom: 0.3rem;",
+                            "{damaged.read().len()} entries can't be read. Open one to see why, or check the vault."
+                        }
+                        for problem in damaged.read().iter() {
+                            DamagedEntryCard { key: "{problem.id}", problem: problem.clone() }
                         }
                     }
                 }
 
-                ScrollArea {
-                    height: "calc(100vh - 250px)",
-                    min_height: "200px",
-                    padding: "0 1.2em 1.2em 1.2em",
-                    direction: ScrollDirection::Vertical,
-                    tabindex: "0",
+                VirtualList {
+                    id: LIST_ID,
+                    row_count: results.read().len(),
+                    row_height: ROW_HEIGHT,
+                    selected: selected_index(),
+                    on_select: move |row: usize| {
+                        let id = entry_id_at(row);
+                        selected_id.set(id);
+                        view_state.write().selected_id = id;
+                    },
+                    on_activate: move |row: usize| {
+                        if let Some(id) = entry_id_at(row) {
+                            navigator.push(Route::password_details(id));
+                        }
+                    },
+                    on_scroll: move |top: f64| {
+                        view_state.write().scroll_top = top;
+                    },
+                    on_end_reached: move |_| load_page(),
+                    render_row: move |row: usize| {
+                        let Some(result) = results.read().get(row).cloned() else {
+                            return rsx! {};
+                        };
+                        let Some(entry) = entries.read().get(result.index).cloned() else {
+                            return rsx! {};
+                        };
+                        let selected = selected_index() == Some(row);
+                        rsx! {
+                            PasswordEntryCard {
+                                key: "{entry.id}",
+                                entry,
+                                search_result: result,
+                                selected,
+                            }
+                        }
+                    },
                     style: "
+                        height: {list_height};
+                        min-height: 200px;
+                        padding: 15px 1.2em 0 1.2em;
                         border: 1px solid #444;
                         border-radius: 12px;
                         background-color: #1b1b1b;
                     ",
-                    div { class: "scroll-content", style: "padding-top: 15px;",
-                        for entry in entries().iter() {
-                            PasswordEntryCard {
-                                id: entry.id,
-                                site: entry.site.clone(),
-                                username: entry.username.clone(),
-                                password: entry.raw_password.clone(),
-                            }
-                        }
+                }
+            }
+        }
+    }
+}
+
+/// A placeholder in the vault list for an entry that can't be read.
+#[component]
+fn DamagedEntryCard(problem: EntryProblem) -> Element {
+    let mut state = use_context::<Signal<AuthState>>();
+    let navigator = use_navigator();
 
+    let id = problem.id;
+    let name = if problem.site.is_empty() {
+        format!("Entry {id}")
+    } else {
+        problem.site.clone()
+    };
+
+    rsx! {
+        div {
+            style: "
+                display: flex;
+                justify-content: space-between;
+                align-items: center;
+                gap: 1rem;
+                padding: 0.3rem 0;
+                cursor: pointer;
+            ",
+            onclick: move |_| {
+                state.write().reset_idle_timer();
+                navigator.push(Route::damaged_entry(id));
+            },
+            div { style: "overflow: hidden; text-overflow: ellipsis; white-space: nowrap;",
+                strong { style: "font-size: 0.9rem; color: #f0f0f0;", "{name}" }
+                if !problem.username.is_empty() {
+                    span { style: "font-size: 0.85rem; color: #ccc; margin-left: 0.5rem;",
+                        "{problem.username}"
                     }
                 }
             }
+            span { style: "font-size: 0.8rem; color: var(--primary-error-color); white-space: nowrap;",
+                "{problem.error.title()}"
+            }
         }
     }
 }
 
 #[component]
 fn PasswordEntryCard(
-    id: i32,
-    site: String,
-    username: String,
-    password: Zeroizing<String>,
+    entry: ReadSignal<PasswordEntrySafe>,
+    search_result: SearchResult,
+    selected: bool,
 ) -> Element {
     let mut state = use_context::<Signal<AuthState>>();
+    let db_service = use_context::<Arc<DatabaseService>>();
+    let db_service = use_signal(|| db_service.clone());
     let navigator = use_navigator();
-    let mut show_password = use_signal(|| false);
-    let password = use_signal(|| password);
+    // Only set while the password is shown, dropping it zeroizes the plaintext.
+    let mut shown_password: Signal<Option<Zeroizing<String>>> = use_signal(|| None);
     let toast_api = use_toast();
 
+    let id = entry.read().id;
+    let site = entry.read().site.clone();
+    let username = entry.read().username.clone();
+    let border_color = if selected {
+        "var(--focused-border-color)"
+    } else {
+        "#2a2a2a"
+    };
+    let decrypt_password = move || -> Option<Zeroizing<String>> {
+        match entry.read().decrypt_password(&state.read()) {
+            Ok(password) => Some(password),
+            Err(err) => {
+                toast_api.error(err.title().into(), error_options(&err));
+                None
+            }
+        }
+    };
+    let record = move |action: AuditAction| {
+        let event = AuditEvent::for_entry(action, id, &entry.read().site);
+        spawn(async move {
+            let _ = audit::record(event, &state(), db_service().as_ref()).await;
+        });
+    };
+
     rsx! {
         div {
             style: "
                 background: #1e1e1e;
-                border: 1px solid #2a2a2a;
+                border: 1px solid {border_color};
                 border-radius: 12px;
                 padding: 0.8rem 1.2rem;
                 margin-bottom: 0.7rem;
+                box-sizing: border-box;
+                height: calc(100% - 0.7rem);
+                overflow: hidden;
                 display: flex;
                 flex-direction: column;
                 gap: 0.4rem;
@@ -151,14 +387,41 @@ fn PasswordEntryCard(
 
             // Title (site name)
             div { style: "display: flex; justify-content: space-between; align-items: center;",
-                strong { style: "font-size: 1rem; color: #f0f0f0;", "{site}" }
+                strong { style: "font-size: 1rem; color: #f0f0f0;",
+                    HighlightedText {
+                        text: site.clone(),
+                        positions: search_result.positions(SearchField::Site),
+                    }
+                }
             }
 
             // Username row
             div { style: "display: flex; justify-content: space-between; align-items: center; font-size: 0.9rem; color: #ccc;",
                 div { style: "font-weight: 500; min-width: 80px;", "Username:" }
                 div { style: "flex: 1; overflow: hidden; text-overflow: ellipsis;",
-                    "{username}"
+                    HighlightedText {
+                        text: username.clone(),
+                        positions: search_result.positions(SearchField::Username),
+                    }
+                }
+            }
+
+            // Show matches in fields that aren't otherwise displayed on the card
+            if let Some(other) = search_result
+                .matches
+                .first()
+                .filter(|m| !matches!(m.field, SearchField::Site | SearchField::Username))
+            {
+                div { style: "display: flex; justify-content: space-between; align-items: center; font-size: 0.8rem; color: #aaa;",
+                    div { style: "font-weight: 500; min-width: 80px; text-transform: capitalize;",
+                        "{other.field.label()}:"
+                    }
+                    div { style: "flex: 1; overflow: hidden; text-overflow: ellipsis; white-space: nowrap;",
+                        HighlightedText {
+                            text: other.text.clone(),
+                            positions: other.positions.clone(),
+                        }
+                    }
                 }
             }
 
@@ -166,24 +429,34 @@ fn PasswordEntryCard(
             div { style: "display: flex; justify-content: space-between; align-items: center; font-size: 0.9rem; color: #ccc;",
                 div { style: "font-weight: 500; min-width: 80px;", "Password:" }
                 div { style: "flex: 1; overflow: hidden; text-overflow: ellipsis;",
-                    if show_password() {
-                        {password().to_string()}
+                    if let Some(password) = shown_password.read().as_ref() {
+                        {password.as_str()}
                     } else {
                         "••••••••"
                     }
                 }
             }
 
-            // Buttons row
-            div { style: "display: flex; gap: 0.4rem; justify-content: flex-end;",
+            // Buttons row, keys pressed on the buttons shouldn't also move through the list
+            div {
+                style: "display: flex; gap: 0.4rem; justify-content: flex-end;",
+                onkeydown: move |evt: KeyboardEvent| evt.stop_propagation(),
                 Button {
                     variant: ButtonVariant::Ghost,
                     style: "width: 70px; min-width: 70px;",
                     onclick: move |evt: Event<MouseData>| {
                         evt.stop_propagation();
-                        show_password.set(!show_password());
+                        state.write().reset_idle_timer();
+                        if shown_password.read().is_some() {
+                            shown_password.set(None);
+                        } else {
+                            shown_password.set(decrypt_password());
+                            if shown_password.read().is_some() {
+                                record(AuditAction::Reveal);
+                            }
+                        }
                     },
-                    if show_password() {
+                    if shown_password.read().is_some() {
                         "Hide"
                     } else {
                         "Show"
@@ -194,7 +467,12 @@ fn PasswordEntryCard(
                     style: "width: 70px; min-width: 70px;",
                     onclick: move |evt: Event<MouseData>| {
                         evt.stop_propagation();
-                        let message = clipboard::copy_with_timeout(password().clone(), 5);
+                        state.write().reset_idle_timer();
+                        let Some(password) = decrypt_password() else {
+                            return;
+                        };
+                        record(AuditAction::Copy);
+                        let message = clipboard::copy_with_timeout(password, 5);
                         toast_api
                             .success(
                                 "Copied!".into(),
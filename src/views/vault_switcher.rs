@@ -0,0 +1,200 @@
+use std::{path::PathBuf, sync::Arc};
+
+use dioxus::prelude::*;
+use dioxus_primitives::toast::{use_toast, ToastOptions};
+
+use crate::{
+    components::{Button, ButtonVariant, Card, Field, FieldGroup, Input},
+    models::AuthState,
+    routes::Route,
+    services::{
+        database::DatabaseService,
+        vaults::{self, VaultInfo, VaultRegistry},
+    },
+    DATA_DIR,
+};
+
+/// Chooses which vault the sign-in pages open. Rendered above their forms, so the app is always
+/// locked when the vault changes.
+#[component]
+pub fn VaultSwitcher() -> Element {
+    let db_service = use_context::<Arc<DatabaseService>>();
+    let vault = use_context::<Signal<Option<Arc<DatabaseService>>>>();
+    let auth_state = use_context::<Signal<AuthState>>();
+    let navigator = use_navigator();
+    let toast_api = use_toast();
+
+    let registry = use_hook(|| VaultRegistry::load(DATA_DIR.get().unwrap()));
+    let registry = match registry {
+        Ok(registry) => registry,
+        Err(err) => {
+            return rsx! {
+                small { style: "color: var(--primary-error-color)", "{err}" }
+            };
+        }
+    };
+    let names: Vec<String> = registry
+        .vaults
+        .iter()
+        .map(|vault| vault.name.clone())
+        .collect();
+    let current = registry
+        .vaults
+        .iter()
+        .find(|vault| vault.path == db_service.path)
+        .map(|vault| vault.name.clone())
+        .unwrap_or_default();
+
+    rsx! {
+        div { style: "display: flex; flex-direction: column; gap: 0.25rem; width: 200px;",
+            select {
+                style: "width: 200px",
+                name: "vault",
+                onchange: move |evt: FormEvent| {
+                    let Some(info) = registry.find(&evt.value()).cloned() else {
+                        return;
+                    };
+                    let registry = registry.clone();
+                    spawn(async move {
+                        let result =
+                            switch_vault(info, registry, false, vault, auth_state, navigator).await;
+                        if let Err(err) = result {
+                            toast_api.error(
+                                "Error".into(),
+                                ToastOptions::new().description(err).permanent(true),
+                            );
+                        }
+                    });
+                },
+                for name in names {
+                    option { value: "{name}", selected: name == current, "{name}" }
+                }
+            }
+            Button {
+                style: "width: 200px",
+                r#type: "button",
+                variant: ButtonVariant::Ghost,
+                onclick: move |_| {
+                    navigator.push(Route::new_vault());
+                },
+                "Add Vault"
+            }
+        }
+    }
+}
+
+/// The page for adding a vault, rendered when the current route is `[Route::NewVault]`. A vault
+/// is created at the chosen path, or the vault already there is added to the list.
+#[component]
+pub fn NewVault() -> Element {
+    let vault = use_context::<Signal<Option<Arc<DatabaseService>>>>();
+    let auth_state = use_context::<Signal<AuthState>>();
+    let navigator = use_navigator();
+    let toast_api = use_toast();
+
+    let mut name = use_signal(|| "".to_string());
+    let mut path = use_signal(|| "".to_string());
+    let mut working = use_signal(|| false);
+
+    let data_dir = DATA_DIR.get().unwrap();
+    let path_placeholder = vaults::suggested_path(data_dir, &name())
+        .display()
+        .to_string();
+
+    let add = move || {
+        spawn(async move {
+            working.set(true);
+            let path = match path().trim() {
+                "" => vaults::suggested_path(data_dir, &name()),
+                path => PathBuf::from(path),
+            };
+
+            let result = match VaultRegistry::load(data_dir) {
+                Ok(mut registry) => match registry.add(&name(), &path) {
+                    Ok(info) => {
+                        switch_vault(info, registry, true, vault, auth_state, navigator).await
+                    }
+                    Err(err) => Err(err),
+                },
+                Err(err) => Err(err),
+            };
+            if let Err(err) = result {
+                toast_api.error(
+                    "Error".into(),
+                    ToastOptions::new().description(err).permanent(true),
+                );
+            }
+            working.set(false);
+        });
+    };
+
+    rsx! {
+        div { style: "width: 100%; display: flex; justify-content: center;",
+            Card { title: "Add Vault",
+                FieldGroup {
+                    Field { label: "Name",
+                        Input {
+                            name: "vault_name",
+                            placeholder: "e.g. Work",
+                            value: name(),
+                            value_changed: move |evt: FormEvent| name.set(evt.value()),
+                        }
+                    }
+
+                    Field { label: "File",
+                        Input {
+                            name: "vault_path",
+                            placeholder: path_placeholder,
+                            value: path(),
+                            value_changed: move |evt: FormEvent| path.set(evt.value()),
+                        }
+                        small { style: "color: #aaa;",
+                            "A new vault with its own master password is created there. Choose the file of an existing vault to add it to the list instead."
+                        }
+                    }
+                }
+
+                div { style: "display: flex; justify-content: flex-end; gap: 0.3rem; margin: 0;",
+                    Button {
+                        variant: ButtonVariant::Ghost,
+                        onclick: move |_| {
+                            navigator.push(Route::home());
+                        },
+                        "Cancel"
+                    }
+                    Button {
+                        variant: ButtonVariant::Secondary,
+                        disabled: working() || name().trim().is_empty(),
+                        onclick: move |_| add(),
+                        "Add"
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Open another vault in place of the current one and remember it for the next start. The app
+/// starts over on the sign-in page of that vault.
+async fn switch_vault(
+    info: VaultInfo,
+    mut registry: VaultRegistry,
+    create: bool,
+    mut vault: Signal<Option<Arc<DatabaseService>>>,
+    mut auth_state: Signal<AuthState>,
+    navigator: Navigator,
+) -> Result<(), String> {
+    let db_service = if create {
+        vaults::create_vault(&info).await?
+    } else {
+        vaults::open_vault(&info).await?
+    };
+
+    registry.last_used = Some(info.name);
+    registry.save(DATA_DIR.get().unwrap())?;
+
+    auth_state.set(AuthState::default());
+    navigator.replace(Route::home());
+    vault.set(Some(Arc::new(db_service)));
+    Ok(())
+}
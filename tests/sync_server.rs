@@ -0,0 +1,153 @@
+//! Two vaults syncing through a `pw-sync-server` on an ephemeral port.
+
+use std::sync::Arc;
+
+use password_manager::{
+    models::{AuthState, EntryExtras, PasswordEntryRaw},
+    services::{
+        database::{DatabaseService, DATABASE_FILE_NAME},
+        http::{self, ServerUrl},
+        password_entry,
+        sync::SyncError,
+        sync_client,
+        sync_server::{self, SyncServer},
+    },
+};
+use tempfile::TempDir;
+use tokio::net::TcpListener;
+use zeroize::Zeroizing;
+
+const PASSPHRASE: &str = "team passphrase";
+
+/// Start a server with a new database, returning it and its address. The
+/// database is deleted when the returned directory is dropped.
+async fn start_server() -> (TempDir, Arc<SyncServer>, String) {
+    let dir = tempfile::tempdir().unwrap();
+    let server = Arc::new(
+        SyncServer::open(&dir.path().join(sync_server::DEFAULT_DATABASE_FILE_NAME))
+            .await
+            .unwrap(),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(sync_server::serve(server.clone(), listener));
+    (dir, server, url)
+}
+
+/// A new vault, signed in with a key of its own. The vault is deleted when
+/// the returned directory is dropped.
+async fn vault(key: u8) -> (TempDir, AuthState, DatabaseService) {
+    let dir = tempfile::tempdir().unwrap();
+    let db_service = DatabaseService::create(dir.path().join(DATABASE_FILE_NAME))
+        .await
+        .unwrap();
+    (
+        dir,
+        AuthState::from_key_material(Zeroizing::new([key; 32])),
+        db_service,
+    )
+}
+
+async fn entries(auth_state: &AuthState, db_service: &DatabaseService) -> Vec<PasswordEntryRaw> {
+    password_entry::get_all_password_entries(auth_state, db_service, String::new())
+        .await
+        .unwrap()
+}
+
+async fn status(url: &str, token: &str) -> u16 {
+    let server = ServerUrl::parse(url).unwrap();
+    http::send(&server, "GET", "/v1/team", token, None)
+        .await
+        .unwrap()
+        .status
+}
+
+#[tokio::test]
+async fn vaults_sync_through_the_server() {
+    let (_server_dir, server, url) = start_server().await;
+    let laptop_token = server.add_device("laptop").await.unwrap();
+    let phone_token = server.add_device("phone").await.unwrap();
+
+    let (_laptop_dir, laptop, laptop_db) = vault(1).await;
+    let (_phone_dir, phone, phone_db) = vault(2).await;
+    let connection = sync_client::connect(&url, &laptop_token, PASSPHRASE, &laptop, &laptop_db)
+        .await
+        .unwrap();
+    assert_eq!(connection.device, "laptop");
+    sync_client::connect(&url, &phone_token, PASSPHRASE, &phone, &phone_db)
+        .await
+        .unwrap();
+
+    let id = password_entry::create_password_entry(
+        PasswordEntryRaw {
+            id: 0,
+            uuid: String::new(),
+            revision: 0,
+            site: "example.com".into(),
+            username: "me".into(),
+            raw_password: Zeroizing::new("hunter2".into()),
+            urls: vec!["https://example.com".into()],
+            notes: "shared".into(),
+            tags: vec!["team".into()],
+            created_at: 100,
+            updated_at: 100,
+            password_changed_at: 100,
+            extras: EntryExtras::default(),
+        },
+        &laptop,
+        &laptop_db,
+    )
+    .await
+    .unwrap();
+    sync_client::sync_server(&laptop, &laptop_db).await.unwrap();
+
+    let summary = sync_client::sync_server(&phone, &phone_db).await.unwrap();
+    assert_eq!(summary.added, 1);
+    let pushed = password_entry::get_password_entry_by_id(id, &laptop, &laptop_db)
+        .await
+        .unwrap();
+    let [pulled] = &entries(&phone, &phone_db).await[..] else {
+        panic!("the phone didn't get the laptop's entry");
+    };
+    assert_eq!(pulled.uuid, pushed.uuid);
+    assert_eq!(*pulled.raw_password, "hunter2");
+    assert_eq!(pulled.notes, "shared");
+    assert_eq!(pulled.tags, ["team"]);
+
+    // A deletion on the phone reaches the laptop.
+    password_entry::delete_password(pulled.id, &phone, &phone_db)
+        .await
+        .unwrap();
+    sync_client::sync_server(&phone, &phone_db).await.unwrap();
+    let summary = sync_client::sync_server(&laptop, &laptop_db).await.unwrap();
+    assert_eq!(summary.deleted, 1);
+    assert!(entries(&laptop, &laptop_db).await.is_empty());
+}
+
+#[tokio::test]
+async fn wrong_and_revoked_tokens_are_turned_away() {
+    let (_server_dir, server, url) = start_server().await;
+    let token = server.add_device("laptop").await.unwrap();
+    assert_eq!(status(&url, &token).await, 200);
+    assert_eq!(status(&url, "not a token").await, 401);
+    assert_eq!(status(&url, "").await, 401);
+
+    let (_laptop_dir, laptop, laptop_db) = vault(1).await;
+    let err = sync_client::connect(&url, "not a token", PASSPHRASE, &laptop, &laptop_db)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, SyncError::Server(_)), "{err}");
+    sync_client::connect(&url, &token, PASSPHRASE, &laptop, &laptop_db)
+        .await
+        .unwrap();
+
+    server.revoke_device("laptop").await.unwrap();
+    assert_eq!(status(&url, &token).await, 401);
+    let err = sync_client::sync_server(&laptop, &laptop_db)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err,
+        SyncError::Server("The device's token is missing, wrong or revoked".into())
+    );
+}